@@ -5,6 +5,10 @@ PLUGIN_LIB_NAME=spacy_plugin
 mkdir container
 
 cargo build
+cargo build --release --examples
 
 cp target/debug/lib$PLUGIN_LIB_NAME.so container/
 mv container/lib$PLUGIN_LIB_NAME.so container/$PLUGIN_LIB_NAME.so
+
+# Native plugins are shipped as executables, they're built for speed
+cp target/release/examples/primes plugins/primes/
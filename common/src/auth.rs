@@ -0,0 +1,68 @@
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const NONCE_LEN: usize = 32;
+
+// Domain separation, so tags can't be reused in other protocols with the same key
+const JOIN_CONTEXT: &[u8] = b"spacy-cluster-join-v1";
+
+pub fn generate_nonce() -> Vec<u8> {
+    let mut nonce = vec![0u8; NONCE_LEN];
+    getrandom::fill(&mut nonce).expect("OS random number generator is unavailable");
+    nonce
+}
+
+// Token, that plugin manager gives to the plugin it launches, plugin presents it,
+// when it connects back. It's passed through the environment, so it's hex encoded
+pub fn generate_token() -> String {
+    generate_nonce().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Side of the join handshake, that signs. It's a part of the tag, so node's own tag
+// can't be reflected back to it as the other side's one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinRole {
+    Initiator,
+    Responder
+}
+
+// Everything both sides of the join handshake have agreed on, tags are bound to all of it,
+// so a tag can't be replayed on another connection or relayed between other nodes
+pub struct JoinTranscript<'a> {
+    pub initiator_nonce: &'a [u8],
+    pub responder_nonce: &'a [u8],
+    pub initiator_id: u128,
+    pub responder_id: u128
+}
+
+// Proves knowledge of the cluster key: HMAC over the role of the signer and the transcript
+pub fn sign_join(key: &[u8], role: JoinRole, transcript: &JoinTranscript) -> Vec<u8> {
+    join_mac(key, role, transcript).finalize().into_bytes().to_vec()
+}
+
+pub fn verify_join(key: &[u8], role: JoinRole, transcript: &JoinTranscript, tag: &[u8]) -> bool {
+    // Nonces have fixed length, so their concatenation is unambiguous
+    if transcript.initiator_nonce.len() != NONCE_LEN || transcript.responder_nonce.len() != NONCE_LEN {
+        return false;
+    }
+
+    join_mac(key, role, transcript).verify_slice(tag).is_ok()
+}
+
+fn join_mac(key: &[u8], role: JoinRole, transcript: &JoinTranscript) -> HmacSha256 {
+    let role: &[u8] = match role {
+        JoinRole::Initiator => b"initiator",
+        JoinRole::Responder => b"responder"
+    };
+
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(JOIN_CONTEXT);
+    mac.update(role);
+    mac.update(transcript.initiator_nonce);
+    mac.update(transcript.responder_nonce);
+    mac.update(&transcript.initiator_id.to_ne_bytes());
+    mac.update(&transcript.responder_id.to_ne_bytes());
+    mac
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, Condvar, Mutex, RwLock},
+    time::{Duration, Instant}
+};
+use crate::{
+    event::proto_msg::{self, event::Dest},
+    reactor::Notifier
+};
+
+// How many undeliverable events are kept for inspection
+pub const DEAD_LETTERS_CAPACITY: usize = 1024;
+
+// Routes events between subsystems by their destination.
+// Every subsystem registers a bounded inbox, full inboxes either
+// make senders wait or drop events depending on the policy.
+// Subsystems send from their own event loops, so by default nobody waits:
+// events, that don't fit, are dropped and kept as dead letters
+#[derive(Clone)]
+pub struct Bus {
+    inner: Arc<BusInner>
+}
+
+// Receiving side of the subsystem's queue
+pub struct Inbox {
+    dest: Dest,
+    queue: Arc<Queue>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    // Sender waits for free space, event is dropped after the timeout
+    Block(Duration),
+    // Event is dropped right away
+    Drop
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub policy: Policy
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub sent: u64,
+    pub received: u64,
+    pub dropped: u64,
+    // Sends, that had to wait for free space
+    pub blocked: u64,
+    pub depth: usize,
+    pub max_depth: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeadLetterReason {
+    NoDestination,
+    UnknownDestination(i32),
+    QueueFull(Dest),
+    DestinationGone(Dest)
+}
+
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub event: proto_msg::Event,
+    pub reason: DeadLetterReason
+}
+
+#[derive(Debug)]
+pub enum BusError {
+    AlreadyRegistered(Dest)
+}
+
+struct BusInner {
+    queues: RwLock<HashMap<Dest, Arc<Queue>>>,
+    dead_letters: Mutex<VecDeque<DeadLetter>>
+}
+
+struct Queue {
+    config: QueueConfig,
+    state: Mutex<QueueState>,
+    not_full: Condvar,
+    notifier: Notifier
+}
+
+struct QueueState {
+    events: VecDeque<proto_msg::Event>,
+    metrics: Metrics,
+    closed: bool
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(BusInner {
+                queues: RwLock::new(HashMap::new()),
+                dead_letters: Mutex::new(VecDeque::new())
+            })
+        }
+    }
+
+    // Creates subsystem's inbox. `notifier` wakes the subsystem up, when events arrive
+    pub fn register(&self, dest: Dest, config: QueueConfig, notifier: Notifier) -> Result<Inbox, BusError> {
+        let mut queues = self.inner.queues.write().unwrap();
+
+        // Subsystem, that has stopped, may be replaced
+        if let Some(queue) = queues.get(&dest) {
+            if !queue.state.lock().unwrap().closed {
+                return Err(BusError::AlreadyRegistered(dest));
+            }
+        }
+
+        let queue = Arc::new(Queue {
+            config,
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                metrics: Metrics::default(),
+                closed: false
+            }),
+            not_full: Condvar::new(),
+            notifier
+        });
+        queues.insert(dest, queue.clone());
+
+        Ok(Inbox { dest, queue })
+    }
+
+    // Delivers event to its destination. Undeliverable events end up in the dead letters
+    pub fn send(&self, event: proto_msg::Event) {
+        let dest = match event.dest {
+            Some(dest) => match Dest::try_from(dest) {
+                Ok(dest) => dest,
+                Err(_) => return self.dead_letter(event, DeadLetterReason::UnknownDestination(dest))
+            },
+            None => return self.dead_letter(event, DeadLetterReason::NoDestination)
+        };
+
+        let queue = match self.inner.queues.read().unwrap().get(&dest) {
+            Some(queue) => queue.clone(),
+            None => return self.dead_letter(event, DeadLetterReason::UnknownDestination(dest as i32))
+        };
+
+        let wait = match queue.config.policy {
+            Policy::Block(timeout) => Some(timeout),
+            Policy::Drop => None
+        };
+        if let Err((event, reason)) = queue.push(dest, event, wait) {
+            self.dead_letter(event, reason);
+        }
+    }
+
+    // Waits for free space up to the timeout, whatever the policy of the destination is.
+    // It's for senders, that aren't event loops, e.g. the one, that stops subsystems
+    pub fn send_waiting(&self, event: proto_msg::Event, timeout: Duration) {
+        let queue = event.dest
+            .and_then(|dest| Dest::try_from(dest).ok())
+            .and_then(|dest| self.inner.queues.read().unwrap().get(&dest).map(|queue| (dest, queue.clone())));
+
+        match queue {
+            Some((dest, queue)) => {
+                if let Err((event, reason)) = queue.push(dest, event, Some(timeout)) {
+                    self.dead_letter(event, reason);
+                }
+            },
+            // Reason is the same, as if it was sent without waiting
+            None => self.send(event)
+        }
+    }
+
+    pub fn metrics(&self) -> HashMap<Dest, Metrics> {
+        let queues = self.inner.queues.read().unwrap();
+
+        queues.iter()
+            .map(|(dest, queue)| (*dest, queue.state.lock().unwrap().metrics.clone()))
+            .collect()
+    }
+
+    // Takes undeliverable events out of the bus
+    pub fn take_dead_letters(&self) -> Vec<DeadLetter> {
+        self.inner.dead_letters.lock().unwrap().drain(..).collect()
+    }
+
+    fn dead_letter(&self, event: proto_msg::Event, reason: DeadLetterReason) {
+        log::warn!("Event of kind {} is undeliverable: {}", event.kind, reason);
+
+        let mut dead_letters = self.inner.dead_letters.lock().unwrap();
+        if dead_letters.len() == DEAD_LETTERS_CAPACITY {
+            dead_letters.pop_front();
+        }
+        dead_letters.push_back(DeadLetter { event, reason });
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Queue {
+    // Gives the event back, if it can't be delivered. Sender waits for free space, if `wait` is given
+    fn push(&self, dest: Dest, event: proto_msg::Event, wait: Option<Duration>) -> Result<(), (proto_msg::Event, DeadLetterReason)> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.events.len() >= self.config.capacity && !state.closed {
+            if let Some(timeout) = wait {
+                state.metrics.blocked += 1;
+
+                let deadline = Instant::now() + timeout;
+                while state.events.len() >= self.config.capacity && !state.closed {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+
+                    state = self.not_full.wait_timeout(state, deadline - now).unwrap().0;
+                }
+            }
+        }
+
+        if state.closed {
+            state.metrics.dropped += 1;
+            return Err((event, DeadLetterReason::DestinationGone(dest)));
+        }
+
+        if state.events.len() >= self.config.capacity {
+            state.metrics.dropped += 1;
+            return Err((event, DeadLetterReason::QueueFull(dest)));
+        }
+
+        state.events.push_back(event);
+        state.metrics.sent += 1;
+        state.metrics.depth = state.events.len();
+        state.metrics.max_depth = state.metrics.max_depth.max(state.metrics.depth);
+        drop(state);
+
+        self.notifier.notify();
+
+        Ok(())
+    }
+}
+
+impl Inbox {
+    pub fn dest(&self) -> Dest {
+        self.dest
+    }
+
+    pub fn try_recv(&self) -> Option<proto_msg::Event> {
+        let mut state = self.queue.state.lock().unwrap();
+
+        let event = state.events.pop_front()?;
+        state.metrics.received += 1;
+        state.metrics.depth = state.events.len();
+        drop(state);
+
+        self.queue.not_full.notify_one();
+
+        Some(event)
+    }
+}
+
+impl Drop for Inbox {
+    fn drop(&mut self) {
+        // Waking up senders, so they don't wait for nothing
+        self.queue.state.lock().unwrap().closed = true;
+        self.queue.not_full.notify_all();
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 4096,
+            policy: Policy::Drop
+        }
+    }
+}
+
+impl fmt::Display for DeadLetterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeadLetterReason::NoDestination => write!(f, "no destination"),
+            DeadLetterReason::UnknownDestination(dest) => write!(f, "unknown destination {}", dest),
+            DeadLetterReason::QueueFull(dest) => write!(f, "queue of {:?} is full", dest),
+            DeadLetterReason::DestinationGone(dest) => write!(f, "{:?} is gone", dest)
+        }
+    }
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::AlreadyRegistered(dest) => write!(f, "{:?} is already registered", dest)
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::{sync::Mutex, time};
+
+// Source of time for everything, that has to be reproducible in simulation.
+// Time is counted in nanoseconds since the unix epoch
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u128;
+}
+
+pub struct SystemClock;
+
+// Clock, that moves only when it's told to
+pub struct ManualClock {
+    now: Mutex<u128>
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_nanos()
+    }
+}
+
+impl ManualClock {
+    pub fn new(now: u128) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    pub fn advance(&self, duration: time::Duration) {
+        *self.now.lock().unwrap() += duration.as_nanos();
+    }
+
+    pub fn set(&self, now: u128) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u128 {
+        *self.now.lock().unwrap()
+    }
+}
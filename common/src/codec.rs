@@ -0,0 +1,235 @@
+use std::{
+    fmt,
+    io::{self, Read, Write}
+};
+use prost::Message;
+use crate::event::proto_msg;
+
+// Frames are events prefixed with their length encoded as a varint
+// (same as `Message::encode_length_delimited`)
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+const MAX_VARINT_LEN: usize = 10;
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+// Connection with its own read and write buffers.
+// Works both with blocking and non-blocking streams
+pub struct Connection<S> {
+    stream: S,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    max_frame_size: usize
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Received {
+    // Events in the order they were sent
+    pub events: Vec<proto_msg::Event>,
+    // Remote side closed the connection
+    pub closed: bool
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Io(io::Error),
+    FrameTooLarge(usize),
+    MalformedFrame(String)
+}
+
+enum Frame {
+    Complete(proto_msg::Event, usize),
+    Incomplete
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            read_buf: vec![],
+            write_buf: vec![],
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    // Reads everything, that is available right now, without blocking.
+    // Stream must be in non-blocking mode
+    pub fn read_events(&mut self) -> Result<Received, CodecError> {
+        let mut received = Received::default();
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    received.closed = true;
+                    break;
+                },
+                Ok(bytes_num) => {
+                    self.read_buf.extend_from_slice(&chunk[0..bytes_num]);
+                    self.decode_frames(&mut received.events)?;
+                },
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(CodecError::Io(error))
+            }
+        }
+
+        Ok(received)
+    }
+
+    // Blocks until at least one event is received or connection is closed.
+    // Stream must be in blocking mode
+    pub fn wait_events(&mut self) -> Result<Received, CodecError> {
+        let mut received = Received::default();
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+
+        // Events might have been left from the previous read
+        self.decode_frames(&mut received.events)?;
+
+        while received.events.is_empty() {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    received.closed = true;
+                    break;
+                },
+                Ok(bytes_num) => {
+                    self.read_buf.extend_from_slice(&chunk[0..bytes_num]);
+                    self.decode_frames(&mut received.events)?;
+                },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(CodecError::Io(error))
+            }
+        }
+
+        Ok(received)
+    }
+
+    // Queues event and writes as much as stream accepts
+    pub fn send(&mut self, event: proto_msg::Event) -> Result<(), CodecError> {
+        let frame_size = event.encoded_len();
+        if frame_size > self.max_frame_size {
+            return Err(CodecError::FrameTooLarge(frame_size));
+        }
+
+        event.encode_length_delimited(&mut self.write_buf)
+            .map_err(|error| CodecError::MalformedFrame(error.to_string()))?;
+        self.flush()?;
+
+        Ok(())
+    }
+
+    // Writes queued data, returns `true` if nothing is left
+    pub fn flush(&mut self) -> Result<bool, CodecError> {
+        let mut written = 0;
+
+        while written < self.write_buf.len() {
+            match self.stream.write(&self.write_buf[written..]) {
+                Ok(0) => {
+                    self.write_buf.drain(0..written);
+                    return Err(CodecError::Io(io::Error::from(io::ErrorKind::WriteZero)));
+                },
+                Ok(bytes_num) => written += bytes_num,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.write_buf.drain(0..written);
+                    return Err(CodecError::Io(error));
+                }
+            }
+        }
+
+        self.write_buf.drain(0..written);
+
+        if self.write_buf.is_empty() {
+            match self.stream.flush() {
+                Ok(_) => {},
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(error) => return Err(CodecError::Io(error))
+            }
+        }
+
+        Ok(self.write_buf.is_empty())
+    }
+
+    pub fn has_pending_writes(&self) -> bool {
+        !self.write_buf.is_empty()
+    }
+
+    fn decode_frames(&mut self, events: &mut Vec<proto_msg::Event>) -> Result<(), CodecError> {
+        let mut consumed = 0;
+
+        while let Frame::Complete(event, frame_len) = decode_frame(&self.read_buf[consumed..], self.max_frame_size)? {
+            events.push(event);
+            consumed += frame_len;
+        }
+
+        self.read_buf.drain(0..consumed);
+
+        Ok(())
+    }
+}
+
+fn decode_frame(buf: &[u8], max_frame_size: usize) -> Result<Frame, CodecError> {
+    // Decoding length prefix
+    let mut length: u64 = 0;
+    let mut header_len = 0;
+    loop {
+        let byte = match buf.get(header_len) {
+            Some(byte) => *byte,
+            None => return Ok(Frame::Incomplete)
+        };
+
+        length |= ((byte & 0x7f) as u64) << (7 * header_len);
+        header_len += 1;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+
+        if header_len == MAX_VARINT_LEN {
+            return Err(CodecError::MalformedFrame("invalid length prefix".to_string()));
+        }
+    }
+
+    if length > max_frame_size as u64 {
+        return Err(CodecError::FrameTooLarge(length as usize));
+    }
+
+    let frame_len = header_len + length as usize;
+    if buf.len() < frame_len {
+        return Ok(Frame::Incomplete);
+    }
+
+    let event = proto_msg::Event::decode(&buf[header_len..frame_len])
+        .map_err(|error| CodecError::MalformedFrame(error.to_string()))?;
+
+    Ok(Frame::Complete(event, frame_len))
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Io(error) => write!(f, "{}", error),
+            CodecError::FrameTooLarge(size) => write!(f, "frame of {} bytes is too large", size),
+            CodecError::MalformedFrame(error) => write!(f, "malformed frame: {}", error)
+        }
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(error: io::Error) -> Self {
+        CodecError::Io(error)
+    }
+}
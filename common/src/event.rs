@@ -1,36 +1,86 @@
+use std::fmt;
 use prost::Message;
+use crate::utils;
 
 pub mod proto_msg {
   include!(concat!(env!("OUT_DIR"), "/common.event.rs"));
 }
 
+// Why a field of event's `data` or `meta` couldn't be read
+#[derive(Debug, PartialEq)]
+pub enum FieldError {
+    Missing(usize),
+    Malformed(usize)
+}
+
+pub fn get_field(fields: &[Vec<u8>], index: usize) -> Result<&[u8], FieldError> {
+    fields.get(index)
+        .map(|field| field.as_slice())
+        .ok_or(FieldError::Missing(index))
+}
+
+pub fn get_i32(fields: &[Vec<u8>], index: usize) -> Result<i32, FieldError> {
+    utils::i32_from_ne_bytes(get_field(fields, index)?).map_err(|_| FieldError::Malformed(index))
+}
+
+pub fn get_u64(fields: &[Vec<u8>], index: usize) -> Result<u64, FieldError> {
+    utils::u64_from_ne_bytes(get_field(fields, index)?).map_err(|_| FieldError::Malformed(index))
+}
+
+pub fn get_u128(fields: &[Vec<u8>], index: usize) -> Result<u128, FieldError> {
+    utils::u128_from_ne_bytes(get_field(fields, index)?).map_err(|_| FieldError::Malformed(index))
+}
+
+// Request, that passes several events to a plugin, gets a response to each of them under its id.
+// Every response, but the last one, carries this marker first in its meta
+pub const PARTIAL_RESPONSE: &[u8] = b"partial";
+
+pub fn is_partial_response(event: &proto_msg::Event) -> bool {
+    event.meta.first().is_some_and(|marker| marker == PARTIAL_RESPONSE)
+}
+
+// Used by state machines to check, where the queued event goes
+pub fn has_dir(event: Option<&proto_msg::Event>, dir: proto_msg::event::Dir) -> bool {
+    event.is_some_and(|event| event.dir == Some(dir as i32))
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Missing(index) => write!(f, "field {} is missing", index),
+            FieldError::Malformed(index) => write!(f, "field {} is malformed", index)
+        }
+    }
+}
+
 pub fn serialize(event: proto_msg::Event) -> Vec<u8> {
     event.encode_length_delimited_to_vec()
 }
 
+// Events are taken one after another, until buffer ends with incomplete or malformed event,
+// which is returned together with everything after it
 pub fn deserialize(mut buf: &[u8]) -> (Vec<proto_msg::Event>, &[u8]) {
-	let mut events = vec![];
-
-	// In system without acknowledgment events may pile up,
-	// so we trying to take as much as we can
-
-	loop {
-		let event = match proto_msg::Event::decode_length_delimited(buf) {
-			Ok(event) => event,
-			Err(_) => {
-				return (events, buf);
-			}
-		};
-		events.push(event.clone());
-
-		let serialized_event = serialize(event);
-		let read_bytes = serialized_event.len();
-
-		buf = &buf[read_bytes..];
-		if buf.len() == 0 {
-			break;
-		}
-	}
-
-	(events, buf)
+    let mut events = vec![];
+
+    while !buf.is_empty() {
+        let event_len = match prost::decode_length_delimiter(buf) {
+            Ok(event_len) => event_len,
+            Err(_) => break
+        };
+
+        let event_start = prost::length_delimiter_len(event_len);
+        let event_end = match event_start.checked_add(event_len) {
+            Some(event_end) if event_end <= buf.len() => event_end,
+            _ => break
+        };
+
+        match proto_msg::Event::decode(&buf[event_start..event_end]) {
+            Ok(event) => events.push(event),
+            Err(_) => break
+        }
+
+        buf = &buf[event_end..];
+    }
+
+    (events, buf)
 }
@@ -1,49 +1,309 @@
-use std::collections::{LinkedList, HashMap};
-use crate::event::proto_msg;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+    time::{Duration, Instant}
+};
 
-pub struct FSM {
-    pub state: u8,
-    queue: LinkedList<proto_msg::Event>,
-    transition_table: HashMap<u8, Vec<u8>>
+// State of the machine, name is used in traces, errors and graphs
+pub trait State: Copy + Eq + Hash + fmt::Debug {
+    fn name(&self) -> &'static str;
 }
 
+// Class of the queued event, higher classes are always handled first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    // Cluster's own traffic: consensus, connections, shutdown
+    Control,
+    Client,
+    Plugin
+}
+
+const PRIORITIES: [Priority; 3] = [Priority::Control, Priority::Client, Priority::Plugin];
+
+// What happens with the event, that doesn't fit into the queue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    // New event is dropped
+    DropNewest,
+    // Oldest event of the lowest class makes room, unless that class is more important
+    DropLowest
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventQueueConfig {
+    // Total number of events in all classes, reliable ones aren't counted
+    pub capacity: usize,
+    pub overflow: Overflow
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueueStats {
+    pub pushed: u64,
+    pub popped: u64,
+    pub dropped: u64,
+    pub depth: usize,
+    pub max_depth: usize,
+    // Time events have spent in the queue until they were taken
+    pub total_wait: Duration,
+    pub max_wait: Duration
+}
+
+// Guard looks at the event, that is going to be handled next
+type Guard<E> = Box<dyn Fn(Option<&E>) -> bool + Send>;
+// Hook gets the state machine is coming from or going to
+type Hook<S> = Box<dyn FnMut(S) + Send>;
+
+struct Transition<S, E> {
+    from: S,
+    to: S,
+    guard: Option<(&'static str, Guard<E>)>
+}
+
+pub struct Fsm<S: State, E> {
+    name: &'static str,
+    state: S,
+    queue_config: EventQueueConfig,
+    // One queue per class, events remember when they were queued and whether they may be dropped
+    queues: [VecDeque<(Instant, bool, E)>; 3],
+    queues_stats: [QueueStats; 3],
+    // Number of queued events, that may be dropped
+    droppable_depth: usize,
+    // Transitions are kept in the declaration order, so graphs are stable
+    states: Vec<S>,
+    transitions: Vec<Transition<S, E>>,
+    entry_hooks: Vec<(S, Hook<S>)>,
+    exit_hooks: Vec<(S, Hook<S>)>
+}
+
+#[derive(Debug, PartialEq)]
 pub enum FSMError {
-    TransitionError
+    TransitionError { from: &'static str, to: &'static str },
+    GuardRejected { from: &'static str, to: &'static str, guard: &'static str }
 }
 
-impl FSM {
-    pub fn new(init_state: u8, transition_table: HashMap<u8, Vec<u8>>) -> Self {
+impl<S: State, E> Fsm<S, E> {
+    pub fn new(name: &'static str, init_state: S, transition_table: &[(S, &[S])]) -> Self {
+        let mut states = vec![];
+        let mut transitions = vec![];
+        for (from, to_states) in transition_table.iter() {
+            if !states.contains(from) {
+                states.push(*from);
+            }
+
+            for to in to_states.iter() {
+                if !states.contains(to) {
+                    states.push(*to);
+                }
+
+                transitions.push(Transition { from: *from, to: *to, guard: None });
+            }
+        }
+
         Self {
+            name,
             state: init_state,
-            queue: LinkedList::new(),
-            transition_table
+            queue_config: EventQueueConfig::default(),
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            queues_stats: Default::default(),
+            droppable_depth: 0,
+            states,
+            transitions,
+            entry_hooks: vec![],
+            exit_hooks: vec![]
         }
     }
 
-    pub fn transition(&mut self, next_state: u8) -> Result<(), FSMError> {
-        if let Some(states) = self.transition_table.get(&self.state) {
-            if states.contains(&next_state) {
-                self.state = next_state;
-                return Ok(());
+    // Allows the transition only if the guard passes, transition must be in the table
+    pub fn guard(&mut self, from: S, to: S, name: &'static str, guard: impl Fn(Option<&E>) -> bool + Send + 'static) {
+        let transition = self.transitions.iter_mut()
+            .find(|transition| transition.from == from && transition.to == to)
+            .unwrap_or_else(|| panic!("There is no transition from {:?} to {:?}", from, to));
+
+        transition.guard = Some((name, Box::new(guard)));
+    }
+
+    // Called with the previous state, when the machine enters the state
+    pub fn on_entry(&mut self, state: S, hook: impl FnMut(S) + Send + 'static) {
+        self.entry_hooks.push((state, Box::new(hook)));
+    }
+
+    // Called with the next state, when the machine leaves the state
+    pub fn on_exit(&mut self, state: S, hook: impl FnMut(S) + Send + 'static) {
+        self.exit_hooks.push((state, Box::new(hook)));
+    }
+
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    pub fn transition(&mut self, next_state: S) -> Result<(), FSMError> {
+        let from = self.state;
+
+        let transition = match self.transitions.iter().find(|transition| transition.from == from && transition.to == next_state) {
+            Some(transition) => transition,
+            None => return Err(FSMError::TransitionError { from: from.name(), to: next_state.name() })
+        };
+
+        if let Some((guard_name, guard)) = &transition.guard {
+            if !guard(self.front_event()) {
+                return Err(FSMError::GuardRejected { from: from.name(), to: next_state.name(), guard: guard_name });
             }
         }
 
-        Err(FSMError::TransitionError)
+        for (_, hook) in self.exit_hooks.iter_mut().filter(|(state, _)| *state == from) {
+            hook(next_state);
+        }
+
+        self.state = next_state;
+        log::trace!("{}: `{}` -> `{}`", self.name, from.name(), next_state.name());
+
+        for (_, hook) in self.entry_hooks.iter_mut().filter(|(state, _)| *state == next_state) {
+            hook(from);
+        }
+
+        Ok(())
     }
 
-    pub fn push_event(&mut self, event: proto_msg::Event) {
-        self.queue.push_back(event);
+    // Graph of the machine in Graphviz format, final states are drawn with double circles
+    pub fn to_dot(&self) -> String {
+        let mut dot = format!("digraph {} {{\n", self.name);
+
+        for state in self.states.iter() {
+            let is_final = !self.transitions.iter().any(|transition| transition.from == *state);
+            let shape = if is_final { "doublecircle" } else { "circle" };
+            let style = if *state == self.state { ", style=bold" } else { "" };
+
+            dot += &format!("    \"{}\" [shape={}{}];\n", state.name(), shape, style);
+        }
+
+        for transition in self.transitions.iter() {
+            dot += &format!("    \"{}\" -> \"{}\"", transition.from.name(), transition.to.name());
+            if let Some((guard_name, _)) = &transition.guard {
+                dot += &format!(" [label=\"{}\"]", guard_name);
+            }
+            dot += ";\n";
+        }
+
+        dot += "}\n";
+        dot
     }
 
-    pub fn push_front_event(&mut self, event: proto_msg::Event) {
-        self.queue.push_front(event);
+    pub fn set_queue_config(&mut self, config: EventQueueConfig) {
+        self.queue_config = config;
     }
 
-    pub fn pop_front_event(&mut self) -> Option<proto_msg::Event> {
-        self.queue.pop_front()
+    // Returns the event, that was dropped to keep the queue in its capacity, so the caller can
+    // answer whoever waits for it. It's either the oldest event of some class or the given one
+    pub fn push_event(&mut self, event: E, priority: Priority) -> Option<E> {
+        let mut dropped = None;
+
+        if self.droppable_depth >= self.queue_config.capacity {
+            // Class, that gives up its oldest event
+            let victim = match self.queue_config.overflow {
+                Overflow::DropNewest => None,
+                Overflow::DropLowest => PRIORITIES.iter()
+                    .rev()
+                    .find(|victim| self.queues[**victim as usize].iter().any(|(_, droppable, _)| *droppable))
+                    .filter(|victim| **victim >= priority)
+            };
+
+            match victim {
+                Some(victim) => {
+                    let queue = &mut self.queues[*victim as usize];
+                    if let Some(index) = queue.iter().position(|(_, droppable, _)| *droppable) {
+                        dropped = queue.remove(index).map(|(_, _, event)| event);
+                        self.droppable_depth -= 1;
+                    }
+                    self.record_drop(*victim);
+                },
+                None => {
+                    self.record_drop(priority);
+                    return Some(event);
+                }
+            }
+        }
+
+        self.droppable_depth += 1;
+        self.enqueue(event, priority, true);
+        dropped
+    }
+
+    // Event, that must not be lost, e.g. readiness of the edge-triggered socket. It's queued
+    // whatever the capacity is and never makes room, so the caller has to bound the number of them
+    pub fn push_reliable_event(&mut self, event: E, priority: Priority) {
+        self.enqueue(event, priority, false);
+    }
+
+    fn enqueue(&mut self, event: E, priority: Priority, droppable: bool) {
+        let queue = &mut self.queues[priority as usize];
+        queue.push_back((Instant::now(), droppable, event));
+
+        let stats = &mut self.queues_stats[priority as usize];
+        stats.pushed += 1;
+        stats.depth = queue.len();
+        stats.max_depth = stats.max_depth.max(stats.depth);
+    }
+
+    // Event, that is going to be taken next
+    pub fn front_event(&self) -> Option<&E> {
+        self.queues.iter()
+            .find_map(|queue| queue.front())
+            .map(|(_, _, event)| event)
+    }
+
+    pub fn pop_front_event(&mut self) -> Option<E> {
+        let priority = *PRIORITIES.iter().find(|priority| !self.queues[**priority as usize].is_empty())?;
+
+        let queue = &mut self.queues[priority as usize];
+        let (queued_at, droppable, event) = queue.pop_front()?;
+        if droppable {
+            self.droppable_depth -= 1;
+        }
+
+        let wait = queued_at.elapsed();
+        let stats = &mut self.queues_stats[priority as usize];
+        stats.popped += 1;
+        stats.depth = queue.len();
+        stats.total_wait += wait;
+        stats.max_wait = stats.max_wait.max(wait);
+
+        Some(event)
     }
 
     pub fn is_queue_empty(&mut self) -> bool {
-        self.queue.is_empty()
+        self.queues.iter().all(|queue| queue.is_empty())
+    }
+
+    pub fn queue_stats(&self) -> HashMap<Priority, QueueStats> {
+        PRIORITIES.iter()
+            .map(|priority| (*priority, self.queues_stats[*priority as usize].clone()))
+            .collect()
+    }
+
+    fn record_drop(&mut self, priority: Priority) {
+        log::warn!("{}: queue is full, dropping {:?} event", self.name, priority);
+
+        let stats = &mut self.queues_stats[priority as usize];
+        stats.dropped += 1;
+        stats.depth = self.queues[priority as usize].len();
+    }
+}
+
+impl Default for EventQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: usize::MAX,
+            overflow: Overflow::DropNewest
+        }
+    }
+}
+
+impl fmt::Display for FSMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FSMError::TransitionError { from, to } => write!(f, "transition from `{}` to `{}` is not allowed", from, to),
+            FSMError::GuardRejected { from, to, guard } => write!(f, "transition from `{}` to `{}` is rejected by `{}`", from, to, guard)
+        }
     }
 }
@@ -1,3 +1,13 @@
+pub mod auth;
+pub mod bus;
+pub mod clock;
+pub mod codec;
 pub mod fsm;
+pub mod reactor;
+pub mod requests;
+pub mod package;
+pub mod plugin_protocol;
 pub mod utils;
 pub mod event;
+pub mod stream;
+pub mod tls;
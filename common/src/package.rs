@@ -0,0 +1,238 @@
+use std::{
+    fmt, fs, io,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path}
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::Deserialize;
+
+// Plugin package is a gzipped tar archive with `manifest.toml` at its root:
+//
+//   name = "simple_db"
+//   version = "0.1.0"
+//   entrypoint = "main.py"
+//   runtime = "python"
+//   capabilities = ["shared_memory"]
+//   dependencies = ["numpy"]
+//   restart = "on-failure"
+//   max_restarts = 5
+//
+// Everything else in the archive is plugin's own files and resources.
+// Python plugin's entrypoint is its main module, native plugin's one is an executable,
+// that is made executable, when the package is unpacked, WASM plugin's one is a module,
+// that the plugin manager runs itself.
+// Dependencies are Python modules, that must be importable by the plugin,
+// they can be shipped in the `deps` directory of the package. They are imported in the
+// plugin's process before its entrypoint, plugin, that can't import them, isn't started.
+// Plugin, that exits, is restarted according to `restart`
+// (`never`, `on-failure` or `always`) at most `max_restarts` times in a row
+
+pub const MANIFEST_FILE: &str = "manifest.toml";
+pub const DEPS_DIR: &str = "deps";
+
+// Unpacked package can't be larger than this
+pub const MAX_UNPACKED_SIZE: u64 = 64 * 1024 * 1024;
+
+pub const DEFAULT_MAX_RESTARTS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Runtime {
+    Python,
+    // Executable, that speaks to the plugin manager itself, e.g. built with `spacy_plugin_sdk`
+    Native,
+    // WebAssembly module, that runs inside the plugin manager
+    Wasm
+}
+
+// What plugin is allowed to do besides answering clients
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    SharedMemory
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    Never,
+    // Plugin is restarted, unless it has exited with zero code
+    #[default]
+    OnFailure,
+    Always
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    pub entrypoint: String,
+    pub runtime: Runtime,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32
+}
+
+#[derive(Debug)]
+pub enum PackageError {
+    Io(io::Error),
+    // Entry would end up outside of the plugin's directory or isn't a plain file
+    InvalidEntry(String),
+    TooLarge,
+    InvalidManifest(String)
+}
+
+impl Manifest {
+    pub fn parse(content: &str) -> Result<Self, PackageError> {
+        let manifest: Manifest = toml::from_str(content)
+            .map_err(|error| PackageError::InvalidManifest(error.message().to_string()))?;
+
+        let valid_name = !manifest.name.is_empty() && manifest.name.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            return Err(PackageError::InvalidManifest(format!("invalid name `{}`", manifest.name)));
+        }
+
+        if manifest.runtime != Runtime::Python && !manifest.dependencies.is_empty() {
+            return Err(PackageError::InvalidManifest("only Python plugin can have dependencies".to_string()));
+        }
+
+        for dependency in manifest.dependencies.iter() {
+            let valid_dependency = !dependency.is_empty() && dependency.split('.')
+                .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+            if !valid_dependency {
+                return Err(PackageError::InvalidManifest(format!("invalid dependency `{}`", dependency)));
+            }
+        }
+
+        if !is_inside(Path::new(&manifest.entrypoint)) {
+            return Err(PackageError::InvalidManifest(format!("invalid entrypoint `{}`", manifest.entrypoint)));
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+fn default_max_restarts() -> u32 {
+    DEFAULT_MAX_RESTARTS
+}
+
+// Packs the directory, it must contain the manifest
+pub fn pack(dir: &Path) -> Result<Vec<u8>, PackageError> {
+    Manifest::parse(&fs::read_to_string(dir.join(MANIFEST_FILE))?)?;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", dir)?;
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+// Unpacks the package into the directory and returns its manifest
+pub fn unpack(package: &[u8], dir: &Path) -> Result<Manifest, PackageError> {
+    fs::create_dir_all(dir)?;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(package));
+    archive.set_preserve_permissions(false);
+
+    let mut unpacked_size = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            return Err(PackageError::InvalidEntry(path.display().to_string()));
+        }
+        if !is_inside(&path) {
+            return Err(PackageError::InvalidEntry(path.display().to_string()));
+        }
+
+        if entry_type.is_dir() {
+            fs::create_dir_all(dir.join(&path))?;
+            continue;
+        }
+
+        if let Some(parent) = dir.join(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Header's size can't be trusted, so what is actually read is counted
+        let mut content = vec![];
+        (&mut entry).take(MAX_UNPACKED_SIZE - unpacked_size + 1).read_to_end(&mut content)?;
+        unpacked_size += content.len() as u64;
+        if unpacked_size > MAX_UNPACKED_SIZE {
+            return Err(PackageError::TooLarge);
+        }
+        fs::write(dir.join(&path), content)?;
+    }
+
+    let manifest_content = fs::read_to_string(dir.join(MANIFEST_FILE))
+        .map_err(|_| PackageError::InvalidManifest(format!("`{}` is missing", MANIFEST_FILE)))?;
+    let manifest = Manifest::parse(&manifest_content)?;
+
+    let entrypoint = dir.join(&manifest.entrypoint);
+    if !entrypoint.is_file() {
+        return Err(PackageError::InvalidManifest(format!("entrypoint `{}` is missing", manifest.entrypoint)));
+    }
+
+    // Permissions of the archive aren't kept
+    if manifest.runtime == Runtime::Native {
+        fs::set_permissions(&entrypoint, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(manifest)
+}
+
+// Reads the manifest without unpacking the package
+pub fn read_manifest(package: &[u8]) -> Result<Manifest, PackageError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(package));
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        if path.components().filter(|component| *component != Component::CurDir).ne(Path::new(MANIFEST_FILE).components()) {
+            continue;
+        }
+
+        let mut content = String::new();
+        entry.take(MAX_UNPACKED_SIZE).read_to_string(&mut content)?;
+        return Manifest::parse(&content);
+    }
+
+    Err(PackageError::InvalidManifest(format!("`{}` is missing", MANIFEST_FILE)))
+}
+
+// Relative path, that doesn't go up
+fn is_inside(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+impl From<io::Error> for PackageError {
+    fn from(error: io::Error) -> Self {
+        PackageError::Io(error)
+    }
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageError::Io(error) => write!(f, "{}", error),
+            PackageError::InvalidEntry(path) => write!(f, "invalid entry `{}`", path),
+            PackageError::TooLarge => write!(f, "package is too large"),
+            PackageError::InvalidManifest(error) => write!(f, "invalid manifest: {}", error)
+        }
+    }
+}
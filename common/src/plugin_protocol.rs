@@ -0,0 +1,133 @@
+use std::fmt;
+use crate::event::proto_msg;
+
+// Plugin protocol, version 1. Plugin might be written in any language,
+// this is everything it has to know about the plugin manager.
+//
+// Launch
+//
+//   Plugin manager starts the plugin's process with these variables set:
+//
+//     SPACY_PLUGIN_MAN_ADDR   `host:port`, that plugin connects to, `127.0.0.1:32002`, if it's absent
+//     SPACY_PLUGIN_TOKEN      launch token, that plugin presents in the handshake
+//     SPACY_PLUGIN_NAME       plugin's name from its manifest
+//
+//   Plugin must connect within 10 seconds, otherwise it's killed
+//
+// Framing
+//
+//   Plugin and the manager exchange `common.event.Event` protobuf messages
+//   (`common/src/protobuf/event.proto`) over a single TCP connection.
+//   Every message is prefixed with its length encoded as a varint, that's what
+//   `writeDelimitedTo`/`encode_length_delimited` produce. Message can't be larger than 16 MiB
+//
+// Handshake
+//
+//   Plugin's first message is `MARK_ME_PLUGIN` with data `[token, version]`, where version
+//   is the protocol version, that plugin speaks, as 4 bytes. Plugin manager doesn't answer,
+//   it closes the connection, if the token isn't the one of the launch or version isn't supported.
+//   Handshake without version is taken as version 1
+//
+// Messages to the plugin
+//
+//   Client's event      kind, that client has chosen, its data, meta `[client's name]`
+//                       and correlation id of the client's request
+//   Node's response     `GET_FROM_SHARED_MEMORY` `[value]`, data is empty, if key isn't set,
+//                       `TRANSACTION_SUCCEEDED` or `TRANSACTION_FAILED` without data,
+//                       they carry correlation id of the plugin's request
+//   Timeout             `REQUEST_TIMED_OUT` with correlation id of the plugin's request,
+//                       that wasn't answered in 60 seconds
+//
+//   Plugin must ignore kinds and correlation ids, it doesn't know, more of them might be added
+//
+// Messages from the plugin
+//
+//   `UPDATE_SHARED_MEMORY`    `[key, value]`    correlation id is chosen by the plugin
+//   `GET_FROM_SHARED_MEMORY`  `[key]`           correlation id is chosen by the plugin
+//   `RESPOND_CLIENT`          response's data   correlation id of the client's request
+//
+//   Plugin must not reuse ids of its requests, that are still waiting for responses.
+//   Shared memory is available only to plugins, which manifests declare `shared_memory` capability,
+//   other requests are dropped. Client's request can be answered once for every event, that it has
+//   brought to the plugin, other responses are dropped.
+//   `dir`, `dest` and `meta` of plugin's messages are ignored
+//
+// Numbers
+//
+//   Keys, version and other numbers in data are little endian, `i32` keys take 4 bytes
+//
+// Stop
+//
+//   Plugin manager closes the connection, when the plugin is stopped or removed.
+//   Plugin must exit then, it's killed, if it's still running 2 seconds later
+//
+// Upgrade
+//
+//   New version of the plugin is started, while the previous one runs. Previous version
+//   must have loaded its files, they are moved elsewhere. After the new version's handshake
+//   clients' events go to it, the previous one gets only responses to its requests.
+//   Its connection is closed, when it has answered clients' requests, that it has taken,
+//   and its own requests are answered, or after 60 seconds. If the new version doesn't
+//   connect, it's killed and the previous one keeps running
+
+pub const VERSION: u32 = 1;
+
+// Where the plugin manager accepts plugins
+pub const DEFAULT_PLUGIN_MAN_ADDR: &str = "127.0.0.1:32002";
+
+// Variables, that plugin is launched with
+pub const ADDR_VAR: &str = "SPACY_PLUGIN_MAN_ADDR";
+pub const TOKEN_VAR: &str = "SPACY_PLUGIN_TOKEN";
+pub const NAME_VAR: &str = "SPACY_PLUGIN_NAME";
+
+// Why the plugin's handshake isn't accepted
+#[derive(Debug, PartialEq)]
+pub enum HandshakeError {
+    // First message isn't `MARK_ME_PLUGIN`
+    NotHandshake(i32),
+    MissingToken,
+    MalformedVersion,
+    UnsupportedVersion(u32)
+}
+
+// First message of the plugin
+pub fn handshake(token: &str) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind: proto_msg::event::Kind::MarkMePlugin as i32,
+        data: vec![token.as_bytes().to_vec(), VERSION.to_le_bytes().to_vec()],
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+// Returns token, that plugin has presented, and the version it speaks
+pub fn parse_handshake(event: &proto_msg::Event) -> Result<(&[u8], u32), HandshakeError> {
+    if event.kind != proto_msg::event::Kind::MarkMePlugin as i32 {
+        return Err(HandshakeError::NotHandshake(event.kind));
+    }
+
+    let token = event.data.first().ok_or(HandshakeError::MissingToken)?;
+    let version = match event.data.get(1) {
+        Some(version) => u32::from_le_bytes(version.as_slice().try_into().map_err(|_| HandshakeError::MalformedVersion)?),
+        None => 1
+    };
+
+    if version != VERSION {
+        return Err(HandshakeError::UnsupportedVersion(version));
+    }
+
+    Ok((token, version))
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::NotHandshake(kind) => write!(f, "first event has kind {} instead of handshake", kind),
+            HandshakeError::MissingToken => write!(f, "token is missing"),
+            HandshakeError::MalformedVersion => write!(f, "version is malformed"),
+            HandshakeError::UnsupportedVersion(version) => write!(f, "version {} isn't supported", version)
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    io,
+    os::unix::io::RawFd,
+    sync::{mpsc, Arc},
+    time::Duration
+};
+use mio::{unix::SourceFd, Events, Interest, Poll, Token, Waker};
+
+// Fds are used as tokens, so waker gets the one that can't be an fd
+const WAKER_TOKEN: Token = Token(usize::MAX);
+const EVENTS_CAPACITY: usize = 1024;
+
+// Readiness notification (epoll) for FSM loops.
+// Sockets are registered by their fds, channels wake the loop through `EventSender`
+pub struct Reactor {
+    poll: Poll,
+    events: Events,
+    waker: Arc<Waker>
+}
+
+// Wakes the `Reactor` up from other threads
+#[derive(Clone)]
+pub struct Notifier {
+    waker: Arc<Waker>
+}
+
+// Channel sender, that wakes the receiving `Reactor` up
+pub struct EventSender<T> {
+    tx: mpsc::Sender<T>,
+    notifier: Notifier
+}
+
+impl Reactor {
+    pub fn new() -> io::Result<Self> {
+        let poll = Poll::new()?;
+        let waker = Waker::new(poll.registry(), WAKER_TOKEN)?;
+
+        Ok(Self {
+            poll,
+            events: Events::with_capacity(EVENTS_CAPACITY),
+            waker: Arc::new(waker)
+        })
+    }
+
+    // Creates a channel, sending to which wakes the reactor up
+    pub fn channel<T>(&self) -> (EventSender<T>, mpsc::Receiver<T>) {
+        let (tx, rx) = mpsc::channel();
+
+        (EventSender { tx, notifier: self.notifier() }, rx)
+    }
+
+    pub fn notifier(&self) -> Notifier {
+        Notifier { waker: self.waker.clone() }
+    }
+
+    // Notifications are edge-triggered, so fd must be read (written) until `WouldBlock`
+    pub fn register(&self, fd: RawFd) -> io::Result<()> {
+        self.poll.registry().register(
+            &mut SourceFd(&fd),
+            Token(fd as usize),
+            Interest::READABLE | Interest::WRITABLE
+        )
+    }
+
+    pub fn deregister(&self, fd: RawFd) -> io::Result<()> {
+        self.poll.registry().deregister(&mut SourceFd(&fd))
+    }
+
+    // Blocks until some of registered fds are ready, channel receives
+    // something or timeout expires. Returns ready fds
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<Vec<RawFd>> {
+        match self.poll.poll(&mut self.events, timeout) {
+            Ok(_) => {},
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => return Ok(vec![]),
+            Err(error) => return Err(error)
+        }
+
+        let fds = self.events.iter()
+            .filter(|event| event.token() != WAKER_TOKEN)
+            .map(|event| event.token().0 as RawFd)
+            .collect();
+
+        Ok(fds)
+    }
+}
+
+impl<T> EventSender<T> {
+    pub fn send(&self, value: T) -> Result<(), mpsc::SendError<T>> {
+        self.tx.send(value)?;
+        self.notifier.notify();
+
+        Ok(())
+    }
+}
+
+impl Notifier {
+    pub fn notify(&self) {
+        // Reactor may be already gone, there is nobody to notify then
+        let _ = self.waker.wake();
+    }
+}
+
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            notifier: self.notifier.clone()
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant}
+};
+
+// Requests, that wait for responses. Ids are never reused, so a late response
+// can't reach someone, who has taken the place of the original requester
+pub struct Requests<T> {
+    next_id: u64,
+    timeout: Duration,
+    pending: HashMap<u64, (Instant, T)>
+}
+
+impl<T> Requests<T> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            next_id: 1,
+            timeout,
+            pending: HashMap::new()
+        }
+    }
+
+    // Remembers the request, returns its id
+    pub fn insert(&mut self, value: T) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.insert_with_id(id, value);
+        id
+    }
+
+    // Remembers the request, that was given an id by someone else
+    pub fn insert_with_id(&mut self, id: u64, value: T) {
+        self.pending.insert(id, (Instant::now() + self.timeout, value));
+    }
+
+    pub fn get(&self, id: u64) -> Option<&T> {
+        self.pending.get(&id).map(|(_, value)| value)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut T> {
+        self.pending.get_mut(&id).map(|(_, value)| value)
+    }
+
+    // Request is answered, it's forgotten
+    pub fn take(&mut self, id: u64) -> Option<T> {
+        self.pending.remove(&id).map(|(_, value)| value)
+    }
+
+    // Takes requests, that weren't answered in time
+    pub fn take_expired(&mut self) -> Vec<(u64, T)> {
+        let now = Instant::now();
+
+        let expired: Vec<u64> = self.pending.iter()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired.into_iter()
+            .filter_map(|id| self.pending.remove(&id).map(|(_, value)| (id, value)))
+            .collect()
+    }
+
+    // Forgets requests, e.g. when the requester is gone
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.pending.retain(|_, (_, value)| f(value));
+    }
+
+    // Whether some request matches, e.g. whether the requester still waits for something
+    pub fn any(&self, mut f: impl FnMut(&T) -> bool) -> bool {
+        self.pending.values().any(|(_, value)| f(value))
+    }
+
+    // How long until the next request expires
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+
+        self.pending.values()
+            .map(|(deadline, _)| deadline.saturating_duration_since(now))
+            .min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    os::unix::prelude::{AsRawFd, RawFd},
+    sync::Arc,
+    time::Duration
+};
+use rustls::{pki_types::CertificateDer, ClientConnection, ServerConfig, ServerConnection, StreamOwned};
+use crate::tls::ClientContext;
+
+// Connection, that may or may not be protected by TLS.
+// TLS handshake is performed lazily on the first read or write
+pub enum Stream {
+    Plain(TcpStream),
+    TlsServer(Box<StreamOwned<ServerConnection, TcpStream>>),
+    TlsClient(Box<StreamOwned<ClientConnection, TcpStream>>)
+}
+
+impl Stream {
+    pub fn accept(stream: TcpStream, config: Option<&Arc<ServerConfig>>) -> io::Result<Self> {
+        match config {
+            Some(config) => {
+                let connection = ServerConnection::new(config.clone())
+                    .map_err(io::Error::other)?;
+                Ok(Stream::TlsServer(Box::new(StreamOwned::new(connection, stream))))
+            },
+            None => Ok(Stream::Plain(stream))
+        }
+    }
+
+    pub fn connect(stream: TcpStream, context: Option<&ClientContext>) -> io::Result<Self> {
+        match context {
+            Some(context) => {
+                let connection = ClientConnection::new(context.config.clone(), context.server_name.clone())
+                    .map_err(io::Error::other)?;
+                Ok(Stream::TlsClient(Box::new(StreamOwned::new(connection, stream))))
+            },
+            None => Ok(Stream::Plain(stream))
+        }
+    }
+
+    // Certificate, that the peer has presented, it's known once TLS handshake is finished
+    pub fn peer_certificate(&self) -> Option<&CertificateDer<'static>> {
+        match self {
+            Stream::Plain(_) => None,
+            Stream::TlsServer(stream) => stream.conn.peer_certificates()?.first(),
+            Stream::TlsClient(stream) => stream.conn.peer_certificates()?.first()
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().peer_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.tcp().shutdown(how)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.tcp().set_nonblocking(nonblocking)
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(stream) => stream,
+            Stream::TlsServer(stream) => stream.get_ref(),
+            Stream::TlsClient(stream) => stream.get_ref()
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::TlsServer(stream) => stream.read(buf),
+            Stream::TlsClient(stream) => stream.read(buf)
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::TlsServer(stream) => stream.write(buf),
+            Stream::TlsClient(stream) => stream.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::TlsServer(stream) => stream.flush(),
+            Stream::TlsClient(stream) => stream.flush()
+        }
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.tcp().as_raw_fd()
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    env, fmt,
+    path::PathBuf,
+    sync::Arc
+};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig
+};
+
+// Name that every node's certificate must contain as a subject alternative name.
+// Nodes are found by scanning, so their ips can't be put into certificates.
+// Clients' certificates are signed by the same CA, they are named by other names
+pub const DEFAULT_SERVER_NAME: &str = "spacy-node";
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub ca: PathBuf,
+    pub server_name: String
+}
+
+// Everything needed for establishing outgoing connections
+#[derive(Clone)]
+pub struct ClientContext {
+    pub config: Arc<ClientConfig>,
+    pub server_name: ServerName<'static>
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    MissingVariable(&'static str),
+    Pem(PathBuf, String),
+    Config(rustls::Error),
+    Verifier(String),
+    InvalidServerName(String)
+}
+
+impl TlsConfig {
+    // TLS is enabled only if all of `SPACY_TLS_CERT`, `SPACY_TLS_KEY`
+    // and `SPACY_TLS_CA` are set, `SPACY_TLS_SERVER_NAME` is optional
+    pub fn from_env() -> Result<Option<Self>, TlsError> {
+        let cert = env::var_os("SPACY_TLS_CERT");
+        let key = env::var_os("SPACY_TLS_KEY");
+        let ca = env::var_os("SPACY_TLS_CA");
+
+        if cert.is_none() && key.is_none() && ca.is_none() {
+            return Ok(None);
+        }
+
+        let cert = cert.ok_or(TlsError::MissingVariable("SPACY_TLS_CERT"))?;
+        let key = key.ok_or(TlsError::MissingVariable("SPACY_TLS_KEY"))?;
+        let ca = ca.ok_or(TlsError::MissingVariable("SPACY_TLS_CA"))?;
+        let server_name = env::var("SPACY_TLS_SERVER_NAME")
+            .unwrap_or_else(|_| DEFAULT_SERVER_NAME.to_string());
+
+        Ok(Some(Self {
+            cert: cert.into(),
+            key: key.into(),
+            ca: ca.into(),
+            server_name
+        }))
+    }
+
+    // Config for accepting connections: peers must present a certificate signed by the cluster CA
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, TlsError> {
+        let roots = Arc::new(self.load_roots()?);
+        let verifier = WebPkiClientVerifier::builder(roots)
+            .build()
+            .map_err(|error| TlsError::Verifier(error.to_string()))?;
+
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(self.load_certs()?, self.load_key()?)
+            .map_err(TlsError::Config)?;
+
+        Ok(Arc::new(config))
+    }
+
+    // Config for outgoing connections: authenticating with own certificate
+    // and verifying that remote node is signed by the cluster CA
+    pub fn client_context(&self) -> Result<ClientContext, TlsError> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(self.load_roots()?)
+            .with_client_auth_cert(self.load_certs()?, self.load_key()?)
+            .map_err(TlsError::Config)?;
+
+        let server_name = ServerName::try_from(self.server_name.clone())
+            .map_err(|_| TlsError::InvalidServerName(self.server_name.clone()))?;
+
+        Ok(ClientContext {
+            config: Arc::new(config),
+            server_name
+        })
+    }
+
+    fn load_certs(&self) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+        CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|error| TlsError::Pem(self.cert.clone(), error.to_string()))
+    }
+
+    fn load_key(&self) -> Result<PrivateKeyDer<'static>, TlsError> {
+        PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|error| TlsError::Pem(self.key.clone(), error.to_string()))
+    }
+
+    fn load_roots(&self) -> Result<RootCertStore, TlsError> {
+        let mut roots = RootCertStore::empty();
+        let certs = CertificateDer::pem_file_iter(&self.ca)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|error| TlsError::Pem(self.ca.clone(), error.to_string()))?;
+
+        for cert in certs {
+            roots.add(cert).map_err(TlsError::Config)?;
+        }
+
+        Ok(roots)
+    }
+}
+
+// Certificate, that is valid for the nodes' server name, belongs to a node
+pub fn is_node_certificate(cert: &CertificateDer, server_name: &ServerName) -> bool {
+    webpki::EndEntityCert::try_from(cert)
+        .is_ok_and(|cert| cert.verify_is_valid_for_subject_name(server_name).is_ok())
+}
+
+// Client is named by the first DNS name of its certificate. Node's certificate doesn't name a client
+pub fn client_name(cert: &CertificateDer, server_name: &ServerName) -> Option<String> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    if cert.verify_is_valid_for_subject_name(server_name).is_ok() {
+        return None;
+    }
+
+    cert.valid_dns_names().next().map(str::to_string)
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::MissingVariable(name) => write!(f, "`{}` is not set", name),
+            TlsError::Pem(path, error) => write!(f, "couldn't load {}: {}", path.display(), error),
+            TlsError::Config(error) => write!(f, "{}", error),
+            TlsError::Verifier(error) => write!(f, "{}", error),
+            TlsError::InvalidServerName(name) => write!(f, "invalid server name `{}`", name)
+        }
+    }
+}
@@ -1,27 +1,76 @@
-use std::io::Read;
-
-use crate::event::{self, proto_msg};
-
-fn get_interfaces() -> Vec<pnet::ipnetwork::IpNetwork> {
-    let interfaces: Vec<pnet::ipnetwork::IpNetwork> = pnet::datalink::interfaces()
+fn get_interfaces() -> Vec<pnet::datalink::NetworkInterface> {
+    pnet::datalink::interfaces()
         .into_iter()
         .filter(|iface| iface.is_up() && !iface.is_loopback() && !iface.ips.is_empty())
-        .flat_map(|iface| iface.ips.into_iter())
-        .collect();
+        .collect()
+}
 
-    interfaces
+fn get_networks() -> Vec<pnet::ipnetwork::IpNetwork> {
+    get_interfaces()
+        .into_iter()
+        .flat_map(|iface| iface.ips.into_iter())
+        .collect()
 }
 
 pub fn get_ipv4_ips() -> Vec<std::net::IpAddr> {
-    get_interfaces()
+    get_networks()
         .into_iter()
         .filter(|iface| iface.is_ipv4())
         .map(|iface| iface.ip())
         .collect()
 }
 
-pub fn get_networks_and_masks() -> Vec<(std::net::IpAddr, std::net::IpAddr)> {
+pub fn get_ipv6_ips() -> Vec<std::net::IpAddr> {
+    get_networks()
+        .into_iter()
+        .filter(|iface| iface.is_ipv6())
+        .map(|iface| iface.ip())
+        .collect()
+}
+
+pub fn get_ips() -> Vec<std::net::IpAddr> {
+    let mut ips = get_ipv4_ips();
+    ips.extend(get_ipv6_ips());
+    ips
+}
+
+// Link-local ipv6 addresses are only valid together with the interface index,
+// so this returns ready to use socket addresses instead of plain ips
+pub fn get_socket_addrs(port: u16) -> Vec<std::net::SocketAddr> {
+    let mut addrs = vec![];
+    for iface in get_interfaces() {
+        for network in iface.ips {
+            let addr = match network.ip() {
+                std::net::IpAddr::V4(ip) => {
+                    std::net::SocketAddr::V4(std::net::SocketAddrV4::new(ip, port))
+                },
+                std::net::IpAddr::V6(ip) => {
+                    let scope_id = if is_ipv6_link_local(&ip) { iface.index } else { 0 };
+                    std::net::SocketAddr::V6(std::net::SocketAddrV6::new(ip, port, 0, scope_id))
+                }
+            };
+            addrs.push(addr);
+        }
+    }
+
+    addrs
+}
+
+// Indexes of the interfaces, that can be used for ipv6 multicast
+pub fn get_ipv6_interfaces() -> Vec<u32> {
     get_interfaces()
+        .into_iter()
+        .filter(|iface| iface.is_multicast() && iface.ips.iter().any(|ip| ip.is_ipv6()))
+        .map(|iface| iface.index)
+        .collect()
+}
+
+pub fn is_ipv6_link_local(address: &std::net::Ipv6Addr) -> bool {
+    (address.segments()[0] & 0xffc0) == 0xfe80
+}
+
+pub fn get_networks_and_masks() -> Vec<(std::net::IpAddr, std::net::IpAddr)> {
+    get_networks()
         .into_iter()
         .map(|iface| (iface.network(), iface.mask()))
         .collect()
@@ -34,10 +83,27 @@ pub fn get_octets(address: &std::net::IpAddr) -> Result<[u8; 4], std::net::AddrP
     Ok(octets)
 }
 
+// Raises the soft limit of open fds up to the hard one, so more connections
+// can be served. Returns the new limit
+pub fn raise_fd_limit() -> nix::Result<u64> {
+    use nix::sys::resource::{getrlimit, setrlimit, Resource};
+
+    let (soft_limit, hard_limit) = getrlimit(Resource::RLIMIT_NOFILE)?;
+    if soft_limit < hard_limit {
+        setrlimit(Resource::RLIMIT_NOFILE, hard_limit, hard_limit)?;
+    }
+
+    Ok(hard_limit)
+}
+
 pub fn u8_from_ne_bytes(bytes: &[u8]) -> Result<u8, std::array::TryFromSliceError> {
     Ok(u8::from_ne_bytes(bytes[0..bytes.len()].try_into()?))
 }
 
+pub fn u64_from_ne_bytes(bytes: &[u8]) -> Result<u64, std::array::TryFromSliceError> {
+    Ok(u64::from_ne_bytes(bytes[0..bytes.len()].try_into()?))
+}
+
 pub fn u128_from_ne_bytes(bytes: &[u8]) -> Result<u128, std::array::TryFromSliceError> {
     Ok(u128::from_ne_bytes(bytes[0..bytes.len()].try_into()?))
 }
@@ -50,28 +116,3 @@ pub fn usize_from_ne_bytes(bytes: &[u8]) -> Result<usize, std::array::TryFromSli
     Ok(usize::from_ne_bytes(bytes[0..bytes.len()].try_into()?))
 }
 
-pub fn read_events(stream: &mut std::net::TcpStream) -> Result<Vec<proto_msg::Event>, std::io::Error> {
-    let mut message = vec![];
-    let mut buf = [0u8; 1024];
-
-    let mut events = vec![];
-
-    loop {
-        let bytes_num = stream.read(&mut buf).unwrap();
-        message.extend(&buf[0..bytes_num]);
-
-        let (new_events, rem) = event::deserialize(&message);
-        events.extend(new_events);
-        message = rem.to_vec();
-
-        if message.len() == 0 {
-            break;
-        }
-
-        buf = [0u8; 1024];
-    }
-
-    events.reverse();
-
-    Ok(events)
-}
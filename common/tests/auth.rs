@@ -0,0 +1,46 @@
+use common::auth::{self, JoinRole, JoinTranscript};
+
+fn transcript<'a>(initiator_nonce: &'a [u8], responder_nonce: &'a [u8]) -> JoinTranscript<'a> {
+    JoinTranscript { initiator_nonce, responder_nonce, initiator_id: 42, responder_id: 7 }
+}
+
+#[test]
+fn join_signed_with_cluster_key_is_accepted() {
+    let (initiator_nonce, responder_nonce) = (auth::generate_nonce(), auth::generate_nonce());
+    let transcript = transcript(&initiator_nonce, &responder_nonce);
+    let tag = auth::sign_join(b"cluster key", JoinRole::Responder, &transcript);
+
+    assert!(auth::verify_join(b"cluster key", JoinRole::Responder, &transcript, &tag));
+}
+
+#[test]
+fn join_signed_with_other_key_is_rejected() {
+    let (initiator_nonce, responder_nonce) = (auth::generate_nonce(), auth::generate_nonce());
+    let transcript = transcript(&initiator_nonce, &responder_nonce);
+    let tag = auth::sign_join(b"dev laptop key", JoinRole::Responder, &transcript);
+
+    assert!(!auth::verify_join(b"cluster key", JoinRole::Responder, &transcript, &tag));
+}
+
+#[test]
+fn tag_is_bound_to_transcript() {
+    let (initiator_nonce, responder_nonce) = (auth::generate_nonce(), auth::generate_nonce());
+    let tag = auth::sign_join(b"cluster key", JoinRole::Initiator, &transcript(&initiator_nonce, &responder_nonce));
+
+    let other_nonce = auth::generate_nonce();
+    assert!(!auth::verify_join(b"cluster key", JoinRole::Initiator, &transcript(&other_nonce, &responder_nonce), &tag));
+    assert!(!auth::verify_join(b"cluster key", JoinRole::Initiator, &transcript(&initiator_nonce, &other_nonce), &tag));
+    assert!(!auth::verify_join(b"cluster key", JoinRole::Initiator, &transcript(&initiator_nonce[1..], &responder_nonce), &tag));
+
+    let swapped = JoinTranscript { initiator_id: 7, responder_id: 42, ..transcript(&initiator_nonce, &responder_nonce) };
+    assert!(!auth::verify_join(b"cluster key", JoinRole::Initiator, &swapped, &tag));
+}
+
+#[test]
+fn tag_of_one_side_cant_be_reflected_as_other_side() {
+    let (initiator_nonce, responder_nonce) = (auth::generate_nonce(), auth::generate_nonce());
+    let transcript = transcript(&initiator_nonce, &responder_nonce);
+    let tag = auth::sign_join(b"cluster key", JoinRole::Responder, &transcript);
+
+    assert!(!auth::verify_join(b"cluster key", JoinRole::Initiator, &transcript, &tag));
+}
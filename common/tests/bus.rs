@@ -0,0 +1,191 @@
+use std::{
+    thread,
+    time::{Duration, Instant}
+};
+use common::{
+    bus::{Bus, DeadLetterReason, Policy, QueueConfig},
+    event::proto_msg::{self, event::Dest},
+    reactor::Reactor
+};
+
+fn make_event(dest: Option<i32>, kind: i32) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest,
+        kind,
+        data: vec![],
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+fn config(capacity: usize, policy: Policy) -> QueueConfig {
+    QueueConfig { capacity, policy }
+}
+
+#[test]
+fn events_are_routed_by_destination() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let node = bus.register(Dest::Node, QueueConfig::default(), reactor.notifier()).unwrap();
+    let server = bus.register(Dest::Server, QueueConfig::default(), reactor.notifier()).unwrap();
+
+    bus.send(make_event(Some(Dest::Node as i32), 1));
+    bus.send(make_event(Some(Dest::Server as i32), 2));
+    bus.send(make_event(Some(Dest::Node as i32), 3));
+
+    assert_eq!(node.try_recv().map(|event| event.kind), Some(1));
+    assert_eq!(node.try_recv().map(|event| event.kind), Some(3));
+    assert_eq!(node.try_recv(), None);
+    assert_eq!(server.try_recv().map(|event| event.kind), Some(2));
+
+    let metrics = bus.metrics();
+    assert_eq!(metrics[&Dest::Node].sent, 2);
+    assert_eq!(metrics[&Dest::Node].received, 2);
+    assert_eq!(metrics[&Dest::Node].max_depth, 2);
+    assert!(bus.take_dead_letters().is_empty());
+}
+
+#[test]
+fn same_destination_cannot_be_registered_twice() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let inbox = bus.register(Dest::Node, QueueConfig::default(), reactor.notifier()).unwrap();
+
+    assert!(bus.register(Dest::Node, QueueConfig::default(), reactor.notifier()).is_err());
+
+    // Stopped subsystem may be replaced
+    drop(inbox);
+    assert!(bus.register(Dest::Node, QueueConfig::default(), reactor.notifier()).is_ok());
+}
+
+#[test]
+fn undeliverable_events_become_dead_letters() {
+    let bus = Bus::new();
+
+    bus.send(make_event(None, 1));
+    bus.send(make_event(Some(Dest::Node as i32), 2));
+    bus.send(make_event(Some(42), 3));
+
+    let reasons: Vec<_> = bus.take_dead_letters().into_iter().map(|letter| letter.reason).collect();
+    assert_eq!(reasons, vec![
+        DeadLetterReason::NoDestination,
+        DeadLetterReason::UnknownDestination(Dest::Node as i32),
+        DeadLetterReason::UnknownDestination(42)
+    ]);
+}
+
+#[test]
+fn full_queue_drops_events_with_drop_policy() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let inbox = bus.register(Dest::Node, config(2, Policy::Drop), reactor.notifier()).unwrap();
+
+    for kind in 0..3 {
+        bus.send(make_event(Some(Dest::Node as i32), kind));
+    }
+
+    let dead_letters = bus.take_dead_letters();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].event.kind, 2);
+    assert_eq!(dead_letters[0].reason, DeadLetterReason::QueueFull(Dest::Node));
+    assert_eq!(bus.metrics()[&Dest::Node].dropped, 1);
+
+    assert_eq!(inbox.try_recv().map(|event| event.kind), Some(0));
+}
+
+#[test]
+fn full_queue_blocks_sender_until_there_is_space() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let inbox = bus.register(Dest::Node, config(1, Policy::Block(Duration::from_secs(10))), reactor.notifier()).unwrap();
+
+    bus.send(make_event(Some(Dest::Node as i32), 0));
+
+    let sender_bus = bus.clone();
+    let sender = thread::spawn(move || {
+        sender_bus.send(make_event(Some(Dest::Node as i32), 1));
+    });
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(!sender.is_finished());
+
+    assert_eq!(inbox.try_recv().map(|event| event.kind), Some(0));
+    sender.join().unwrap();
+
+    assert_eq!(inbox.try_recv().map(|event| event.kind), Some(1));
+    assert_eq!(bus.metrics()[&Dest::Node].blocked, 1);
+    assert!(bus.take_dead_letters().is_empty());
+}
+
+#[test]
+fn blocked_sender_gives_up_after_timeout() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let _inbox = bus.register(Dest::Node, config(1, Policy::Block(Duration::from_millis(50))), reactor.notifier()).unwrap();
+
+    bus.send(make_event(Some(Dest::Node as i32), 0));
+
+    let started_at = Instant::now();
+    bus.send(make_event(Some(Dest::Node as i32), 1));
+    assert!(started_at.elapsed() >= Duration::from_millis(50));
+
+    let dead_letters = bus.take_dead_letters();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].reason, DeadLetterReason::QueueFull(Dest::Node));
+}
+
+#[test]
+fn events_to_stopped_subsystem_become_dead_letters() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let inbox = bus.register(Dest::PluginMan, QueueConfig::default(), reactor.notifier()).unwrap();
+    drop(inbox);
+
+    bus.send(make_event(Some(Dest::PluginMan as i32), 0));
+
+    let dead_letters = bus.take_dead_letters();
+    assert_eq!(dead_letters[0].reason, DeadLetterReason::DestinationGone(Dest::PluginMan));
+}
+
+#[test]
+fn default_queue_never_blocks_sender() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let config = QueueConfig { capacity: 1, ..QueueConfig::default() };
+    let _inbox = bus.register(Dest::Node, config, reactor.notifier()).unwrap();
+
+    bus.send(make_event(Some(Dest::Node as i32), 0));
+
+    let started_at = Instant::now();
+    bus.send(make_event(Some(Dest::Node as i32), 1));
+    assert!(started_at.elapsed() < Duration::from_millis(50));
+
+    let dead_letters = bus.take_dead_letters();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].reason, DeadLetterReason::QueueFull(Dest::Node));
+    assert_eq!(bus.metrics()[&Dest::Node].blocked, 0);
+}
+
+#[test]
+fn waiting_sender_gets_through_dropping_queue() {
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let inbox = bus.register(Dest::Node, config(1, Policy::Drop), reactor.notifier()).unwrap();
+
+    bus.send(make_event(Some(Dest::Node as i32), 0));
+
+    let sender_bus = bus.clone();
+    let sender = thread::spawn(move || {
+        sender_bus.send_waiting(make_event(Some(Dest::Node as i32), 1), Duration::from_secs(10));
+    });
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(!sender.is_finished());
+
+    assert_eq!(inbox.try_recv().map(|event| event.kind), Some(0));
+    sender.join().unwrap();
+
+    assert_eq!(inbox.try_recv().map(|event| event.kind), Some(1));
+    assert!(bus.take_dead_letters().is_empty());
+}
@@ -0,0 +1,97 @@
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream}
+};
+use common::{
+    codec::{Connection, CodecError},
+    event::proto_msg
+};
+use prost::Message;
+
+fn make_event(kind: i32, size: usize) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind,
+        data: vec![vec![kind as u8; size]],
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+fn connected_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _addr) = listener.accept().unwrap();
+
+    (client, server)
+}
+
+#[test]
+fn events_are_delivered_in_order() {
+    let (client, server) = connected_pair();
+    let mut sender = Connection::new(client);
+    let mut receiver = Connection::new(server);
+
+    let events: Vec<_> = (0..10).map(|kind| make_event(kind, 100)).collect();
+    for event in events.iter() {
+        sender.send(event.clone()).unwrap();
+    }
+
+    let mut received = vec![];
+    while received.len() < events.len() {
+        received.extend(receiver.wait_events().unwrap().events);
+    }
+
+    assert_eq!(received, events);
+}
+
+#[test]
+fn partial_frame_does_not_block() {
+    let (mut client, server) = connected_pair();
+    server.set_nonblocking(true).unwrap();
+    let mut receiver = Connection::new(server);
+
+    let event = make_event(1, 1000);
+    let frame = event.encode_length_delimited_to_vec();
+
+    // Only half of the frame arrives
+    client.write_all(&frame[0..frame.len() / 2]).unwrap();
+    client.flush().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    let received = receiver.read_events().unwrap();
+    assert!(received.events.is_empty());
+    assert!(!received.closed);
+
+    // The rest of it
+    client.write_all(&frame[frame.len() / 2..]).unwrap();
+    drop(client);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    let received = receiver.read_events().unwrap();
+    assert_eq!(received.events, vec![event]);
+    assert!(received.closed);
+}
+
+#[test]
+fn oversized_frame_is_rejected() {
+    let (client, server) = connected_pair();
+    let mut sender = Connection::new(client);
+    let mut receiver = Connection::new(server).with_max_frame_size(64);
+
+    sender.send(make_event(1, 1000)).unwrap();
+
+    assert!(matches!(receiver.wait_events(), Err(CodecError::FrameTooLarge(_))));
+}
+
+#[test]
+fn malformed_frame_is_rejected() {
+    let (mut client, server) = connected_pair();
+    let mut receiver = Connection::new(server);
+
+    // Length prefix is never terminated
+    client.write_all(&[0xff; 16]).unwrap();
+
+    assert!(matches!(receiver.wait_events(), Err(CodecError::MalformedFrame(_))));
+}
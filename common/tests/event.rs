@@ -0,0 +1,54 @@
+use common::event::{self, proto_msg, FieldError};
+
+fn make_event(kind: i32, data: Vec<Vec<u8>>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind,
+        data,
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+#[test]
+fn incomplete_event_is_returned() {
+    let events = vec![make_event(1, vec![vec![1; 10]]), make_event(2, vec![vec![2; 300]])];
+
+    let mut buf = vec![];
+    for event in events.iter() {
+        buf.extend(event::serialize(event.clone()));
+    }
+    let partial = event::serialize(make_event(3, vec![vec![3; 10]]));
+    buf.extend_from_slice(&partial[0..5]);
+
+    let (decoded, rem) = event::deserialize(&buf);
+    assert_eq!(decoded, events);
+    assert_eq!(rem, &partial[0..5]);
+}
+
+#[test]
+fn unknown_fields_do_not_break_framing() {
+    // Event from a newer peer with field 15 (varint), that this version doesn't know
+    let mut body = event::serialize(make_event(1, vec![vec![1; 4]]));
+    body.remove(0);
+    body.extend_from_slice(&[15 << 3, 1]);
+
+    let mut buf = vec![body.len() as u8];
+    buf.extend(body);
+    buf.extend(event::serialize(make_event(2, vec![])));
+
+    let (decoded, rem) = event::deserialize(&buf);
+    assert_eq!(decoded, vec![make_event(1, vec![vec![1; 4]]), make_event(2, vec![])]);
+    assert!(rem.is_empty());
+}
+
+#[test]
+fn fields_are_checked() {
+    let data = vec![7i32.to_ne_bytes().to_vec(), vec![1, 2, 3]];
+
+    assert_eq!(event::get_i32(&data, 0), Ok(7));
+    assert_eq!(event::get_i32(&data, 1), Err(FieldError::Malformed(1)));
+    assert_eq!(event::get_u128(&data, 2), Err(FieldError::Missing(2)));
+    assert_eq!(event::get_field(&data, 1), Ok(&[1u8, 2, 3][..]));
+}
@@ -0,0 +1,180 @@
+use std::sync::{Arc, Mutex};
+use common::fsm::{EventQueueConfig, Fsm, FSMError, Overflow, Priority, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Light {
+    Off,
+    On,
+    Broken
+}
+
+impl State for Light {
+    fn name(&self) -> &'static str {
+        match self {
+            Light::Off => "off",
+            Light::On => "on",
+            Light::Broken => "broken"
+        }
+    }
+}
+
+fn make_fsm() -> Fsm<Light, i32> {
+    Fsm::new("light", Light::Off, &[
+        (Light::Off, &[Light::On, Light::Broken]),
+        (Light::On, &[Light::Off, Light::Broken]),
+        (Light::Broken, &[])
+    ])
+}
+
+#[test]
+fn transitions_follow_the_table() {
+    let mut fsm = make_fsm();
+
+    fsm.transition(Light::On).unwrap();
+    fsm.transition(Light::Broken).unwrap();
+    assert_eq!(fsm.state(), Light::Broken);
+
+    assert_eq!(fsm.transition(Light::On), Err(FSMError::TransitionError { from: "broken", to: "on" }));
+    assert_eq!(fsm.state(), Light::Broken);
+}
+
+#[test]
+fn guards_look_at_the_next_event() {
+    let mut fsm = make_fsm();
+    fsm.guard(Light::Off, Light::On, "positive", |event| event.is_some_and(|value| *value > 0));
+
+    assert_eq!(fsm.transition(Light::On), Err(FSMError::GuardRejected { from: "off", to: "on", guard: "positive" }));
+
+    fsm.push_event(-1, Priority::Client);
+    assert!(fsm.transition(Light::On).is_err());
+
+    fsm.push_event(1, Priority::Control);
+    fsm.transition(Light::On).unwrap();
+    assert_eq!(fsm.pop_front_event(), Some(1));
+}
+
+#[test]
+fn hooks_are_called_on_entry_and_exit() {
+    let calls = Arc::new(Mutex::new(vec![]));
+    let mut fsm = make_fsm();
+
+    let exit_calls = calls.clone();
+    fsm.on_exit(Light::Off, move |next| exit_calls.lock().unwrap().push(format!("exit off to {}", next.name())));
+    let entry_calls = calls.clone();
+    fsm.on_entry(Light::On, move |prev| entry_calls.lock().unwrap().push(format!("enter on from {}", prev.name())));
+
+    fsm.transition(Light::On).unwrap();
+    fsm.transition(Light::Off).unwrap();
+    let _ = fsm.transition(Light::Off);
+
+    assert_eq!(*calls.lock().unwrap(), vec!["exit off to on", "enter on from off"]);
+}
+
+#[test]
+fn graph_is_exported() {
+    let mut fsm = make_fsm();
+    fsm.guard(Light::Off, Light::On, "has_power", |_| true);
+
+    let expected = "\
+digraph light {
+    \"off\" [shape=circle, style=bold];
+    \"on\" [shape=circle];
+    \"broken\" [shape=doublecircle];
+    \"off\" -> \"on\" [label=\"has_power\"];
+    \"off\" -> \"broken\";
+    \"on\" -> \"off\";
+    \"on\" -> \"broken\";
+}
+";
+    assert_eq!(fsm.to_dot(), expected);
+}
+
+#[test]
+fn higher_priorities_go_first() {
+    let mut fsm = make_fsm();
+
+    fsm.push_event(1, Priority::Plugin);
+    fsm.push_event(2, Priority::Client);
+    fsm.push_event(3, Priority::Control);
+    fsm.push_event(4, Priority::Client);
+    fsm.push_event(5, Priority::Control);
+
+    assert_eq!(fsm.front_event(), Some(&3));
+
+    let order: Vec<i32> = std::iter::from_fn(|| fsm.pop_front_event()).collect();
+    assert_eq!(order, vec![3, 5, 2, 4, 1]);
+    assert!(fsm.is_queue_empty());
+
+    let stats = fsm.queue_stats();
+    assert_eq!(stats[&Priority::Control].pushed, 2);
+    assert_eq!(stats[&Priority::Client].popped, 2);
+    assert_eq!(stats[&Priority::Client].max_depth, 2);
+    assert_eq!(stats[&Priority::Plugin].depth, 0);
+}
+
+#[test]
+fn newest_event_is_dropped_on_overflow() {
+    let mut fsm = make_fsm();
+    fsm.set_queue_config(EventQueueConfig { capacity: 2, overflow: Overflow::DropNewest });
+
+    assert_eq!(fsm.push_event(1, Priority::Plugin), None);
+    assert_eq!(fsm.push_event(2, Priority::Plugin), None);
+    // Dropped event is given back
+    assert_eq!(fsm.push_event(3, Priority::Control), Some(3));
+
+    assert_eq!(fsm.pop_front_event(), Some(1));
+    assert_eq!(fsm.pop_front_event(), Some(2));
+    assert_eq!(fsm.pop_front_event(), None);
+    assert_eq!(fsm.queue_stats()[&Priority::Control].dropped, 1);
+}
+
+#[test]
+fn lowest_priority_makes_room_on_overflow() {
+    let mut fsm = make_fsm();
+    fsm.set_queue_config(EventQueueConfig { capacity: 3, overflow: Overflow::DropLowest });
+
+    fsm.push_event(1, Priority::Client);
+    fsm.push_event(2, Priority::Plugin);
+    fsm.push_event(3, Priority::Plugin);
+
+    // Oldest plugin's event is dropped for the control one
+    assert_eq!(fsm.push_event(4, Priority::Control), Some(2));
+    assert_eq!(fsm.push_event(5, Priority::Control), Some(3));
+    // Newer client's event replaces the older one
+    assert_eq!(fsm.push_event(6, Priority::Client), Some(1));
+    assert_eq!(fsm.push_event(7, Priority::Client), Some(6));
+    // Less important event doesn't push out others
+    assert_eq!(fsm.push_event(8, Priority::Plugin), Some(8));
+
+    let order: Vec<i32> = std::iter::from_fn(|| fsm.pop_front_event()).collect();
+    assert_eq!(order, vec![4, 5, 7]);
+
+    let stats = fsm.queue_stats();
+    assert_eq!(stats[&Priority::Plugin].dropped, 3);
+    assert_eq!(stats[&Priority::Client].dropped, 2);
+    assert_eq!(stats[&Priority::Control].dropped, 0);
+}
+
+#[test]
+fn reliable_events_are_never_dropped() {
+    let mut fsm = make_fsm();
+    fsm.set_queue_config(EventQueueConfig { capacity: 2, overflow: Overflow::DropLowest });
+
+    fsm.push_reliable_event(1, Priority::Plugin);
+    fsm.push_event(2, Priority::Plugin);
+    fsm.push_event(3, Priority::Plugin);
+    // Reliable event doesn't count against the capacity
+    fsm.push_reliable_event(4, Priority::Client);
+
+    // Room is made by the droppable event, though the reliable one is older
+    fsm.push_event(5, Priority::Control);
+
+    let order: Vec<i32> = std::iter::from_fn(|| fsm.pop_front_event()).collect();
+    assert_eq!(order, vec![5, 4, 1, 3]);
+    assert_eq!(fsm.queue_stats()[&Priority::Plugin].dropped, 1);
+
+    // Capacity is free again, once droppable events are taken
+    fsm.push_event(6, Priority::Plugin);
+    fsm.push_event(7, Priority::Plugin);
+    assert_eq!(fsm.queue_stats()[&Priority::Plugin].dropped, 1);
+}
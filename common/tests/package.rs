@@ -0,0 +1,130 @@
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+use common::package::{self, Capability, PackageError, RestartPolicy, Runtime};
+use flate2::{write::GzEncoder, Compression};
+
+const MANIFEST: &str = r#"
+name = "simple_db"
+version = "0.1.0"
+entrypoint = "main.py"
+runtime = "python"
+capabilities = ["shared_memory"]
+dependencies = ["json", "xml.dom"]
+"#;
+
+fn make_plugin_dir(dir: &Path, manifest: &str) {
+    fs::write(dir.join("manifest.toml"), manifest).unwrap();
+    fs::write(dir.join("main.py"), "import helpers\n").unwrap();
+    fs::create_dir_all(dir.join("deps/helpers")).unwrap();
+    fs::write(dir.join("deps/helpers/__init__.py"), "").unwrap();
+}
+
+// Archive with a single entry, which path isn't checked by the builder
+fn make_raw_package(path: &[u8], entry_type: tar::EntryType) -> Vec<u8> {
+    let mut header = tar::Header::new_old();
+    header.as_old_mut().name[..path.len()].copy_from_slice(path);
+    header.set_entry_type(entry_type);
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    builder.append(&header, &b"data"[..]).unwrap();
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+#[test]
+fn package_is_unpacked_with_its_files() {
+    let source = tempfile::tempdir().unwrap();
+    make_plugin_dir(source.path(), MANIFEST);
+    let package = package::pack(source.path()).unwrap();
+
+    let target = tempfile::tempdir().unwrap();
+    let manifest = package::unpack(&package, &target.path().join("simple_db")).unwrap();
+
+    assert_eq!(manifest.name, "simple_db");
+    assert_eq!(manifest.runtime, Runtime::Python);
+    assert!(manifest.has_capability(Capability::SharedMemory));
+    assert_eq!(manifest.dependencies, vec!["json", "xml.dom"]);
+    assert_eq!(manifest.restart, RestartPolicy::OnFailure);
+    assert_eq!(manifest.max_restarts, package::DEFAULT_MAX_RESTARTS);
+    assert!(target.path().join("simple_db/main.py").is_file());
+    assert!(target.path().join("simple_db/deps/helpers/__init__.py").is_file());
+}
+
+#[test]
+fn restart_policy_is_read() {
+    let manifest = package::Manifest::parse(&format!("{}restart = \"always\"\nmax_restarts = 2\n", MANIFEST)).unwrap();
+    assert_eq!(manifest.restart, RestartPolicy::Always);
+    assert_eq!(manifest.max_restarts, 2);
+
+    let manifest = package::Manifest::parse(&format!("{}restart = \"never\"\n", MANIFEST)).unwrap();
+    assert_eq!(manifest.restart, RestartPolicy::Never);
+
+    assert!(package::Manifest::parse(&format!("{}restart = \"sometimes\"\n", MANIFEST)).is_err());
+}
+
+#[test]
+fn invalid_manifests_are_rejected() {
+    let invalid = [
+        "name = \"db\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"ruby\"",
+        "name = \"../db\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"python\"",
+        "name = \"db\"\nversion = \"1\"\nentrypoint = \"/etc/passwd\"\nruntime = \"python\"",
+        "name = \"db\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"python\"\ncapabilities = [\"root\"]",
+        "name = \"db\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"python\"\ndependencies = [\"os; import sys\"]",
+        "name = \"db\"\nentrypoint = \"main.py\"\nruntime = \"python\"",
+        "name = \"db\"\nversion = \"1\"\nentrypoint = \"db\"\nruntime = \"native\"\ndependencies = [\"json\"]",
+        "name = \"db\"\nversion = \"1\"\nentrypoint = \"db.wasm\"\nruntime = \"wasm\"\ndependencies = [\"json\"]"
+    ];
+
+    for manifest in invalid {
+        assert!(matches!(package::Manifest::parse(manifest), Err(PackageError::InvalidManifest(_))), "{}", manifest);
+    }
+}
+
+#[test]
+fn native_entrypoint_is_executable() {
+    let source = tempfile::tempdir().unwrap();
+    fs::write(source.path().join("manifest.toml"), "name = \"primes\"\nversion = \"1\"\nentrypoint = \"bin/primes\"\nruntime = \"native\"").unwrap();
+    fs::create_dir_all(source.path().join("bin")).unwrap();
+    fs::write(source.path().join("bin/primes"), "#!/bin/sh\n").unwrap();
+    let package = package::pack(source.path()).unwrap();
+
+    let target = tempfile::tempdir().unwrap();
+    let manifest = package::unpack(&package, target.path()).unwrap();
+
+    assert_eq!(manifest.runtime, Runtime::Native);
+    let mode = fs::metadata(target.path().join("bin/primes")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o111, 0o111);
+}
+
+#[test]
+fn entrypoint_must_be_in_the_package() {
+    let source = tempfile::tempdir().unwrap();
+    make_plugin_dir(source.path(), &MANIFEST.replace("main.py", "missing.py"));
+    let package = package::pack(source.path()).unwrap();
+
+    let target = tempfile::tempdir().unwrap();
+    assert!(matches!(package::unpack(&package, target.path()), Err(PackageError::InvalidManifest(_))));
+}
+
+#[test]
+fn entries_cant_escape_plugin_dir() {
+    let target = tempfile::tempdir().unwrap();
+    let plugin_dir = target.path().join("plugin");
+
+    let package = make_raw_package(b"../escaped.py", tar::EntryType::Regular);
+    assert!(matches!(package::unpack(&package, &plugin_dir), Err(PackageError::InvalidEntry(_))));
+    assert!(!target.path().join("escaped.py").exists());
+
+    let package = make_raw_package(b"/tmp/escaped.py", tar::EntryType::Regular);
+    assert!(matches!(package::unpack(&package, &plugin_dir), Err(PackageError::InvalidEntry(_))));
+
+    let package = make_raw_package(b"link", tar::EntryType::Symlink);
+    assert!(matches!(package::unpack(&package, &plugin_dir), Err(PackageError::InvalidEntry(_))));
+}
+
+#[test]
+fn garbage_isnt_a_package() {
+    let target = tempfile::tempdir().unwrap();
+    assert!(package::unpack(b"definitely not a package", target.path()).is_err());
+}
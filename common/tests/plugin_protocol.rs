@@ -0,0 +1,43 @@
+use common::{
+    event::proto_msg::{self, event::Kind},
+    plugin_protocol::{self, HandshakeError}
+};
+
+fn make_handshake(kind: Kind, data: Vec<Vec<u8>>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind: kind as i32,
+        data,
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+#[test]
+fn handshake_carries_token_and_version() {
+    let handshake = plugin_protocol::handshake("token");
+
+    assert_eq!(plugin_protocol::parse_handshake(&handshake), Ok((&b"token"[..], plugin_protocol::VERSION)));
+}
+
+#[test]
+fn handshake_without_version_is_first_version() {
+    let handshake = make_handshake(Kind::MarkMePlugin, vec![b"token".to_vec()]);
+
+    assert_eq!(plugin_protocol::parse_handshake(&handshake), Ok((&b"token"[..], 1)));
+}
+
+#[test]
+fn invalid_handshakes_are_rejected() {
+    let invalid = [
+        (make_handshake(Kind::RespondClient, vec![b"token".to_vec()]), HandshakeError::NotHandshake(Kind::RespondClient as i32)),
+        (make_handshake(Kind::MarkMePlugin, vec![]), HandshakeError::MissingToken),
+        (make_handshake(Kind::MarkMePlugin, vec![b"token".to_vec(), vec![1]]), HandshakeError::MalformedVersion),
+        (make_handshake(Kind::MarkMePlugin, vec![b"token".to_vec(), 2u32.to_le_bytes().to_vec()]), HandshakeError::UnsupportedVersion(2))
+    ];
+
+    for (handshake, error) in invalid {
+        assert_eq!(plugin_protocol::parse_handshake(&handshake), Err(error));
+    }
+}
@@ -0,0 +1,48 @@
+use std::{thread, time::Duration};
+use common::requests::Requests;
+
+#[test]
+fn ids_are_unique_and_responses_are_taken_once() {
+    let mut requests = Requests::new(Duration::from_secs(60));
+
+    let first = requests.insert(10);
+    let second = requests.insert(10);
+    assert_ne!(first, second);
+
+    assert_eq!(requests.take(first), Some(10));
+    assert_eq!(requests.take(first), None);
+
+    // Id of the answered request isn't given out again
+    let third = requests.insert(10);
+    assert!(third != first && third != second);
+    assert_eq!(requests.len(), 2);
+}
+
+#[test]
+fn unanswered_requests_expire() {
+    let mut requests = Requests::new(Duration::from_millis(20));
+
+    let id = requests.insert("client");
+    assert!(requests.take_expired().is_empty());
+    assert!(requests.next_timeout().unwrap() <= Duration::from_millis(20));
+
+    thread::sleep(Duration::from_millis(30));
+    assert_eq!(requests.next_timeout(), Some(Duration::ZERO));
+    assert_eq!(requests.take_expired(), vec![(id, "client")]);
+    assert!(requests.is_empty());
+    assert_eq!(requests.next_timeout(), None);
+}
+
+#[test]
+fn requests_of_gone_requester_are_forgotten() {
+    let mut requests = Requests::new(Duration::from_secs(60));
+
+    requests.insert_with_id(7, 1);
+    let other = requests.insert(2);
+    requests.retain(|fd| *fd != 1);
+
+    assert_eq!(requests.get(7), None);
+    assert_eq!(requests.get(other), Some(&2));
+    assert!(!requests.any(|fd| *fd == 1));
+    assert!(requests.any(|fd| *fd == 2));
+}
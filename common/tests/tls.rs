@@ -0,0 +1,133 @@
+use std::{
+    fs,
+    net::{TcpListener, TcpStream},
+    path::Path,
+    thread
+};
+use common::{
+    codec::Connection,
+    event::{self, proto_msg},
+    stream::Stream,
+    tls::{TlsConfig, DEFAULT_SERVER_NAME}
+};
+use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+use std::io::Write;
+
+struct Authority {
+    issuer: Issuer<'static, KeyPair>,
+    pem: String
+}
+
+fn generate_ca() -> Authority {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec![]).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let cert = params.self_signed(&key).unwrap();
+
+    Authority {
+        issuer: Issuer::new(params, key),
+        pem: cert.pem()
+    }
+}
+
+// Writes certificate signed by `ca` together with its key and CA into `dir`
+fn generate_config(dir: &Path, name: &str, ca: &Authority) -> TlsConfig {
+    let key = KeyPair::generate().unwrap();
+    let params = CertificateParams::new(vec![DEFAULT_SERVER_NAME.to_string()]).unwrap();
+    let cert = params.signed_by(&key, &ca.issuer).unwrap();
+
+    let config = TlsConfig {
+        cert: dir.join(format!("{}.crt", name)),
+        key: dir.join(format!("{}.key", name)),
+        ca: dir.join(format!("{}-ca.crt", name)),
+        server_name: DEFAULT_SERVER_NAME.to_string()
+    };
+    fs::write(&config.cert, cert.pem()).unwrap();
+    fs::write(&config.key, key.serialize_pem()).unwrap();
+    fs::write(&config.ca, &ca.pem).unwrap();
+
+    config
+}
+
+fn mark_me_node(node_id: u128) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind: proto_msg::event::Kind::MarkMeNode as i32,
+        data: vec![node_id.to_ne_bytes().to_vec()],
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+// Accepts one connection and echoes back the first received event
+fn spawn_server(config: TlsConfig) -> (u16, thread::JoinHandle<Result<proto_msg::Event, String>>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server_config = config.server_config().unwrap();
+
+    let handle = thread::spawn(move || {
+        let (stream, _addr) = listener.accept().unwrap();
+        let stream = Stream::accept(stream, Some(&server_config)).unwrap();
+        let mut connection = Connection::new(stream);
+
+        let received = connection.wait_events().map_err(|error| error.to_string())?;
+        let event = received.events.first().cloned().ok_or("connection closed")?;
+        connection.send(event.clone()).map_err(|error| error.to_string())?;
+
+        Ok(event)
+    });
+
+    (port, handle)
+}
+
+#[test]
+fn nodes_signed_by_cluster_ca_exchange_events() {
+    let dir = tempfile::tempdir().unwrap();
+    let ca = generate_ca();
+    let server_config = generate_config(dir.path(), "server", &ca);
+    let client_config = generate_config(dir.path(), "client", &ca);
+
+    let (port, handle) = spawn_server(server_config);
+
+    let context = client_config.client_context().unwrap();
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let stream = Stream::connect(stream, Some(&context)).unwrap();
+    let mut connection = Connection::new(stream);
+    connection.send(mark_me_node(42)).unwrap();
+
+    let received = connection.wait_events().unwrap();
+    assert_eq!(received.events, vec![mark_me_node(42)]);
+    assert_eq!(handle.join().unwrap(), Ok(mark_me_node(42)));
+}
+
+#[test]
+fn peer_signed_by_foreign_ca_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let server_config = generate_config(dir.path(), "server", &generate_ca());
+    let client_config = generate_config(dir.path(), "client", &generate_ca());
+
+    let (port, handle) = spawn_server(server_config);
+
+    let context = client_config.client_context().unwrap();
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let stream = Stream::connect(stream, Some(&context)).unwrap();
+    let mut connection = Connection::new(stream);
+    let _ = connection.send(mark_me_node(42));
+
+    assert!(connection.wait_events().is_err());
+    assert!(handle.join().unwrap().is_err());
+}
+
+#[test]
+fn plaintext_peer_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let server_config = generate_config(dir.path(), "server", &generate_ca());
+
+    let (port, handle) = spawn_server(server_config);
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.write_all(&event::serialize(mark_me_node(42))).unwrap();
+
+    assert!(handle.join().unwrap().is_err());
+}
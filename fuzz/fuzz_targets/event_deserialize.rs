@@ -0,0 +1,20 @@
+#![no_main]
+
+use common::event;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (events, rem) = event::deserialize(data);
+
+    // Remainder is always the tail of the input
+    assert!(data.ends_with(rem));
+
+    // Decoded events survive the round trip
+    let mut buf = vec![];
+    for event in events.iter() {
+        buf.extend(event::serialize(event.clone()));
+    }
+    let (decoded, rem) = event::deserialize(&buf);
+    assert!(rem.is_empty());
+    assert_eq!(decoded, events);
+});
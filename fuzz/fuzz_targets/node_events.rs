@@ -0,0 +1,21 @@
+#![no_main]
+
+use common::{bus::Bus, event::proto_msg::event::Dest};
+use libfuzzer_sys::fuzz_target;
+use spacy::node::Node;
+use spacy_fuzz::{FuzzEvent, Sinks};
+
+fuzz_target!(|events: Vec<FuzzEvent>| {
+    let bus = Bus::new();
+    let sinks = Sinks::new(&bus, Dest::Node);
+    let mut node = Node::new(bus);
+
+    for event in events {
+        // Node, that has failed, would be stopped, there is no point to go on
+        if node.process_event(event.into_event(Dest::Node)).is_err() {
+            break;
+        }
+
+        sinks.drain();
+    }
+});
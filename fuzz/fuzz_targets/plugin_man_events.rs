@@ -0,0 +1,23 @@
+#![no_main]
+
+use common::{bus::Bus, event::proto_msg::event::Dest};
+use libfuzzer_sys::fuzz_target;
+use spacy::{config::Config, plugin_man::PluginMan};
+use spacy_fuzz::{FuzzEvent, Sinks};
+
+fuzz_target!(|events: Vec<FuzzEvent>| {
+    let bus = Bus::new();
+    let sinks = Sinks::new(&bus, Dest::PluginMan);
+
+    // Plugin manager isn't started, so plugins are never spawned
+    let mut plugin_man = PluginMan::new(bus, &Config::default());
+
+    for event in events {
+        // Plugin manager, that has failed, would be stopped, there is no point to go on
+        if plugin_man.process_event(event.into_event(Dest::PluginMan)).is_err() {
+            break;
+        }
+
+        sinks.drain();
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use common::{bus::Bus, event::proto_msg::event::Dest};
+use libfuzzer_sys::fuzz_target;
+use spacy::{config::Config, server::Server};
+use spacy_fuzz::{FuzzEvent, Sinks};
+
+fuzz_target!(|events: Vec<FuzzEvent>| {
+    let bus = Bus::new();
+    let sinks = Sinks::new(&bus, Dest::Server);
+
+    // Server isn't started, so it doesn't listen and doesn't look for nodes
+    let mut server = Server::new(bus, 1, &Config::default());
+
+    for event in events {
+        // Server, that has failed, would be stopped, there is no point to go on
+        if server.process_event(event.into_event(Dest::Server)).is_err() {
+            break;
+        }
+
+        sinks.drain();
+    }
+});
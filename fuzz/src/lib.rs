@@ -0,0 +1,84 @@
+use arbitrary::Arbitrary;
+use common::{
+    bus::{Bus, Inbox, Policy, QueueConfig},
+    event::proto_msg::{self, event::Dest},
+    reactor::Reactor
+};
+
+// Fields are mostly generated in shapes, that handlers try to read,
+// otherwise almost every event would be rejected on the first field
+#[derive(Arbitrary, Debug)]
+pub enum Field {
+    I32(i32),
+    U128(u128),
+    // Few ids, so fuzzer can refer to the nodes it has connected
+    NodeId(u8),
+    // Few fds, so fuzzer can refer to the clients and plugins
+    Fd(u8),
+    Bytes(Vec<u8>)
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct FuzzEvent {
+    dir: Option<u8>,
+    kind: u8,
+    data: Vec<Field>,
+    meta: Vec<Field>
+}
+
+impl Field {
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Field::I32(value) => value.to_ne_bytes().to_vec(),
+            Field::U128(value) => value.to_ne_bytes().to_vec(),
+            Field::NodeId(id) => (id as u128 % 4 + 1).to_ne_bytes().to_vec(),
+            Field::Fd(fd) => (fd as i32 % 8).to_ne_bytes().to_vec(),
+            Field::Bytes(bytes) => bytes
+        }
+    }
+}
+
+impl FuzzEvent {
+    pub fn into_event(self, dest: Dest) -> proto_msg::Event {
+        let dir = self.dir.map(|dir| match dir {
+            0 => proto_msg::event::Dir::Incoming as i32,
+            1 => proto_msg::event::Dir::Outcoming as i32,
+            dir => dir as i32
+        });
+
+        proto_msg::Event {
+            dir,
+            dest: Some(dest as i32),
+            kind: self.kind as i32 % 32,
+            data: self.data.into_iter().map(Field::into_bytes).collect(),
+            meta: self.meta.into_iter().map(Field::into_bytes).collect(),
+            correlation_id: None
+        }
+    }
+}
+
+// Takes whatever subsystem under test sends to others, so the bus never blocks
+pub struct Sinks {
+    _reactor: Reactor,
+    inboxes: Vec<Inbox>
+}
+
+impl Sinks {
+    pub fn new(bus: &Bus, tested: Dest) -> Self {
+        let reactor = Reactor::new().unwrap();
+        let config = QueueConfig { capacity: 1024, policy: Policy::Drop };
+
+        let inboxes = [Dest::Server, Dest::PluginMan, Dest::Node].into_iter()
+            .filter(|dest| *dest != tested)
+            .map(|dest| bus.register(dest, config, reactor.notifier()).unwrap())
+            .collect();
+
+        Self { _reactor: reactor, inboxes }
+    }
+
+    pub fn drain(&self) {
+        for inbox in self.inboxes.iter() {
+            while inbox.try_recv().is_some() {}
+        }
+    }
+}
@@ -1,8 +1,3 @@
-import sys
-from os import path
-container_dir = path.abspath(path.join(path.dirname(__name__), './container'))
-sys.path.append(container_dir)
-
 import spacy_plugin
 import pickle
 import time
@@ -42,19 +37,19 @@ class BasicPlugin(spacy_plugin.SpacyPlugin):
             if self.state == 0:
                 if event.kind == self.COUNT_PRIMES:
                     if len(event.data) != 1:
-                        self.respond_client([b"Wrong number of argumets"], event.meta)
+                        self.respond_client([b"Wrong number of argumets"], event.correlation_id)
                         return
                     elif self.target_result_cnt != 0:
-                        self.respond_client([b"Already computing"], event.meta)
+                        self.respond_client([b"Already computing"], event.correlation_id)
                         return
                     else:
                         try:
                             n = int(bytes_to_str(event.data[0]))
                         except Exception:
-                            self.respond_client([b"Failed to parse arguments"], event.meta)
+                            self.respond_client([b"Failed to parse arguments"], event.correlation_id)
                             return
 
-                        self.queue.append(("", event.meta))
+                        self.queue.append(("", event.correlation_id))
 
                         tasks = []
                         tasks_num = math.ceil(n / 10000)
@@ -141,7 +136,7 @@ class BasicPlugin(spacy_plugin.SpacyPlugin):
                     return
 
                 elif event.kind == self.COUNT_PRIMES:
-                    self.respond_client([b"Already computing"], event.meta)
+                    self.respond_client([b"Already computing"], event.correlation_id)
                     return
 
                 return
@@ -151,7 +146,7 @@ class BasicPlugin(spacy_plugin.SpacyPlugin):
             print(traceback.print_exc())
 
 plugin = BasicPlugin()
-while True:
+while plugin.is_running():
     plugin.step()
     plugin.execute()
     time.sleep(0.001)
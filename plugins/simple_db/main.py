@@ -1,8 +1,3 @@
-import sys
-from os import path
-container_dir = path.abspath(path.join(path.dirname(__name__), './container'))
-sys.path.append(container_dir)
-
 import spacy_plugin
 import time
 
@@ -19,7 +14,8 @@ class BasicPlugin(spacy_plugin.SpacyPlugin):
     def __init__(self):
         super().__init__()
         self.kinds = spacy_plugin.SpacyKinds()
-        self.queue = []
+        # Client requests, waiting for shared memory, by the plugin request id
+        self.pending = {}
 
     def update(self):
         try:
@@ -27,50 +23,54 @@ class BasicPlugin(spacy_plugin.SpacyPlugin):
             if not event: return
 
             if event.kind == self.kinds.kind_transaction_succeeded:
-                response = self.queue.pop(0)
+                response = self.pending.pop(event.correlation_id)
                 self.respond_client([bytes("Your data was saved", encoding="utf-8")], response[1])
 
             elif event.kind == self.kinds.kind_transaction_failed:
-                response = self.queue.pop(0)
+                response = self.pending.pop(event.correlation_id)
                 self.respond_client([bytes("Failed to process request", encoding="utf-8")], response[1])
 
             elif event.kind == self.kinds.kind_get_from_shared_memory:
                 data = event.data[0]
-                response = self.queue.pop(0)
+                response = self.pending.pop(event.correlation_id)
                 message = response[0].format(bytes_to_str(data))
                 self.respond_client([bytes(message, encoding="utf-8")], response[1])
 
+            elif event.kind == self.kinds.kind_request_timed_out:
+                response = self.pending.pop(event.correlation_id)
+                self.respond_client([b"Request timed out"], response[1])
+
 
             elif event.kind == self.SAVE_DATA:
                 if len(event.data) != 2:
-                    self.respond_client([b"Wrong number of arguments"], event.meta)
+                    self.respond_client([b"Wrong number of arguments"], event.correlation_id)
                     self.waiting_for_data = True
                 else:
                     key, value = event.data
 
                     int_key = int(bytes_to_str(key))
 
-                    self.shared_memory_push(int_key, value)
-                    self.queue.append(("Save data: {}", event.meta))
+                    request_id = self.shared_memory_push(int_key, value)
+                    self.pending[request_id] = ("Save data: {}", event.correlation_id)
 
             elif event.kind == self.GET_DATA:
                 if len(event.data) != 1:
-                    self.respond_client([b"Wrong number of arguments"], event.meta)
+                    self.respond_client([b"Wrong number of arguments"], event.correlation_id)
                 else:
                     key = event.data[0]
 
                     int_key = int(bytes_to_str(key))
 
-                    self.shared_memory_get(int_key)
-                    self.queue.append(("Get data: {}", event.meta))
+                    request_id = self.shared_memory_get(int_key)
+                    self.pending[request_id] = ("Get data: {}", event.correlation_id)
 
             else:
-                self.respond_client([b"Unknown command!"], event.meta)
+                self.respond_client([b"Unknown command!"], event.correlation_id)
         except Exception as e:
             print(e)
 
 plugin = BasicPlugin()
-while True:
+while plugin.is_running():
     plugin.step()
     plugin.execute()
     time.sleep(0.001)
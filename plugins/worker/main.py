@@ -1,8 +1,3 @@
-import sys
-from os import path
-container_dir = path.abspath(path.join(path.dirname(__name__), './container'))
-sys.path.append(container_dir)
-
 import spacy_plugin
 import pickle
 import time
@@ -115,7 +110,7 @@ class BasicPlugin(spacy_plugin.SpacyPlugin):
             print(traceback.print_exc())
 
 plugin = BasicPlugin()
-while True:
+while plugin.is_running():
     plugin.step()
     plugin.execute()
     time.sleep(0.001)
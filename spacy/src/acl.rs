@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::Path
+};
+
+// Access control list for clients.
+// It's loaded from a file with the following format:
+//
+//   # role <name> <permission>...
+//   role admin *
+//   role calc list send:simple_calc
+//
+//   # client <name> <token> <role>
+//   client alice 9c1e6f0a admin
+//   client bob 41d7a2b3 calc
+//
+// Permissions are `deploy`, `remove`, `list`, `send:<plugin>`, `send:*` and `*`
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Permission {
+    All,
+    Deploy,
+    Remove,
+    List,
+    Send(Option<Vec<u8>>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action<'a> {
+    Deploy,
+    Remove,
+    List,
+    Send(&'a [u8])
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub name: String,
+    pub role: String
+}
+
+pub struct Acl {
+    roles: HashMap<String, Vec<Permission>>,
+    clients: HashMap<String, Client>,
+    tokens: HashMap<Vec<u8>, String>
+}
+
+#[derive(Debug)]
+pub enum AclError {
+    Io(io::Error),
+    Parse(usize, String)
+}
+
+impl Permission {
+    fn parse(string: &str) -> Option<Self> {
+        match string {
+            "*" => Some(Permission::All),
+            "deploy" => Some(Permission::Deploy),
+            "remove" => Some(Permission::Remove),
+            "list" => Some(Permission::List),
+            "send:*" => Some(Permission::Send(None)),
+            _ => string.strip_prefix("send:")
+                .filter(|plugin| !plugin.is_empty())
+                .map(|plugin| Permission::Send(Some(plugin.as_bytes().to_vec())))
+        }
+    }
+
+    fn allows(&self, action: &Action) -> bool {
+        match (self, action) {
+            (Permission::All, _) => true,
+            (Permission::Deploy, Action::Deploy) => true,
+            (Permission::Remove, Action::Remove) => true,
+            (Permission::List, Action::List) => true,
+            (Permission::Send(None), Action::Send(_)) => true,
+            (Permission::Send(Some(allowed)), Action::Send(plugin)) => allowed == plugin,
+            _ => false
+        }
+    }
+}
+
+impl Acl {
+    pub fn load(path: &Path) -> Result<Self, AclError> {
+        let content = fs::read_to_string(path).map_err(AclError::Io)?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, AclError> {
+        let mut roles = HashMap::new();
+        let mut clients = HashMap::new();
+        let mut tokens = HashMap::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line_num = index + 1;
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                None => continue,
+                Some(word) if word.starts_with('#') => continue,
+                Some("role") => {
+                    let name = words.next()
+                        .ok_or_else(|| AclError::Parse(line_num, "role name is missing".to_string()))?;
+
+                    let mut permissions = vec![];
+                    for word in words {
+                        let permission = Permission::parse(word)
+                            .ok_or_else(|| AclError::Parse(line_num, format!("unknown permission `{}`", word)))?;
+                        permissions.push(permission);
+                    }
+
+                    if roles.insert(name.to_string(), permissions).is_some() {
+                        return Err(AclError::Parse(line_num, format!("role `{}` is already declared", name)));
+                    }
+                },
+                Some("client") => {
+                    let (name, token, role) = match (words.next(), words.next(), words.next()) {
+                        (Some(name), Some(token), Some(role)) => (name, token, role),
+                        _ => return Err(AclError::Parse(line_num, "expected `client <name> <token> <role>`".to_string()))
+                    };
+
+                    if tokens.insert(token.as_bytes().to_vec(), name.to_string()).is_some() {
+                        return Err(AclError::Parse(line_num, "token is already in use".to_string()));
+                    }
+
+                    let client = Client {
+                        name: name.to_string(),
+                        role: role.to_string()
+                    };
+                    if clients.insert(name.to_string(), client).is_some() {
+                        return Err(AclError::Parse(line_num, format!("client `{}` is already declared", name)));
+                    }
+                },
+                Some(word) => {
+                    return Err(AclError::Parse(line_num, format!("unknown directive `{}`", word)));
+                }
+            }
+        }
+
+        // Roles may be declared after clients, so checking only at the end
+        for client in clients.values() {
+            if !roles.contains_key(&client.role) {
+                return Err(AclError::Parse(0, format!("client `{}` has unknown role `{}`", client.name, client.role)));
+            }
+        }
+
+        Ok(Self { roles, clients, tokens })
+    }
+
+    pub fn authenticate(&self, token: &[u8]) -> Option<&Client> {
+        self.tokens.get(token).and_then(|name| self.clients.get(name))
+    }
+
+    pub fn is_allowed(&self, client_name: &[u8], action: &Action) -> bool {
+        let client = match std::str::from_utf8(client_name).ok().and_then(|name| self.clients.get(name)) {
+            Some(client) => client,
+            None => return false
+        };
+
+        match self.roles.get(&client.role) {
+            Some(permissions) => permissions.iter().any(|permission| permission.allows(action)),
+            None => false
+        }
+    }
+}
+
+impl fmt::Display for AclError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AclError::Io(error) => write!(f, "{}", error),
+            AclError::Parse(0, error) => write!(f, "{}", error),
+            AclError::Parse(line, error) => write!(f, "line {}: {}", line, error)
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{env, fmt, fs, io, net::SocketAddr, path::PathBuf, sync::Arc};
+use common::tls::{ClientContext, TlsConfig, TlsError};
+use rustls::ServerConfig;
+use crate::{
+    acl::{Acl, AclError},
+    sandbox::Sandbox,
+    wasm_plugin::WasmLimits
+};
+
+// Limits of plugins, that operator hasn't changed
+const DEFAULT_PLUGIN_MEMORY_MB: u64 = 1024;
+const DEFAULT_PLUGIN_MAX_FDS: u64 = 256;
+const DEFAULT_PLUGIN_WASM_FUEL: u64 = 100_000_000;
+
+#[derive(Default)]
+pub struct Config {
+    pub tls_server: Option<Arc<ServerConfig>>,
+    pub tls_client: Option<ClientContext>,
+    pub cluster_key: Option<Vec<u8>>,
+    pub acl: Option<Arc<Acl>>,
+    // Where server accepts nodes and clients, every interface's address on port 32000, if it's empty
+    pub listen_addrs: Vec<SocketAddr>,
+    // Where packages are unpacked, each plugin gets its own directory
+    pub plugins_dir: PathBuf,
+    // Where `spacy_plugin` module is, plugins get it on their path
+    pub plugin_sdk_dir: PathBuf,
+    // Where output of plugins is written, each plugin gets its own file
+    pub plugin_logs_dir: PathBuf,
+    // Limits, that plugins are started with
+    pub plugin_sandbox: Sandbox,
+    // Limits of plugins, that run inside the plugin manager
+    pub plugin_wasm: WasmLimits
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Tls(TlsError),
+    ClusterKey(io::Error),
+    Acl(AclError),
+    // Variable, that configures plugins' sandbox, has an invalid value
+    Sandbox(&'static str)
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let plugin_sandbox = sandbox_from_env()?;
+        let mut config = Self {
+            tls_server: None,
+            tls_client: None,
+            cluster_key: None,
+            acl: None,
+            listen_addrs: vec![],
+            plugins_dir: env::var_os("SPACY_PLUGINS_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("run/plugins")),
+            plugin_sdk_dir: env::var_os("SPACY_PLUGIN_SDK_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("container")),
+            plugin_logs_dir: env::var_os("SPACY_PLUGIN_LOGS_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("run/logs")),
+            plugin_wasm: WasmLimits {
+                fuel: limit_from_env("SPACY_PLUGIN_WASM_FUEL", DEFAULT_PLUGIN_WASM_FUEL)?,
+                memory_bytes: plugin_sandbox.memory_bytes
+            },
+            plugin_sandbox
+        };
+
+        if let Some(tls) = TlsConfig::from_env()? {
+            config.tls_server = Some(tls.server_config()?);
+            config.tls_client = Some(tls.client_context()?);
+        } else {
+            log::warn!("TLS is not configured, all cluster traffic is plaintext");
+        }
+
+        // Key, that nodes use to prove that they belong to the same cluster
+        if let Some(path) = env::var_os("SPACY_CLUSTER_KEY_FILE") {
+            let key = fs::read(path).map_err(ConfigError::ClusterKey)?;
+            config.cluster_key = Some(key.trim_ascii().to_vec());
+        } else if let Some(key) = env::var_os("SPACY_CLUSTER_KEY") {
+            config.cluster_key = Some(key.into_encoded_bytes());
+        } else {
+            log::warn!("Cluster key is not configured, any node is allowed to join");
+        }
+
+        if let Some(key) = &config.cluster_key {
+            if key.is_empty() {
+                return Err(ConfigError::ClusterKey(io::Error::other("cluster key is empty")));
+            }
+        }
+
+        // Client identities and their permissions
+        if let Some(path) = env::var_os("SPACY_CLIENTS_FILE") {
+            let acl = Acl::load(path.as_ref()).map_err(ConfigError::Acl)?;
+            config.acl = Some(Arc::new(acl));
+        } else {
+            log::warn!("Clients file is not configured, any client is allowed to manage plugins");
+        }
+
+        Ok(config)
+    }
+}
+
+// Plugins are limited by default, `0` lifts a limit
+fn sandbox_from_env() -> Result<Sandbox, ConfigError> {
+    let memory_mb = limit_from_env("SPACY_PLUGIN_MEMORY_MB", DEFAULT_PLUGIN_MEMORY_MB)?;
+
+    let user = match env::var("SPACY_PLUGIN_USER") {
+        Ok(user) => {
+            let (uid, gid) = user.split_once(':').unwrap_or((&user, &user));
+            match (uid.parse(), gid.parse()) {
+                (Ok(uid), Ok(gid)) => Some((uid, gid)),
+                _ => return Err(ConfigError::Sandbox("SPACY_PLUGIN_USER"))
+            }
+        },
+        Err(_) => None
+    };
+
+    Ok(Sandbox {
+        cpu_seconds: limit_from_env("SPACY_PLUGIN_CPU_SECONDS", 0)?,
+        memory_bytes: memory_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+        max_fds: limit_from_env("SPACY_PLUGIN_MAX_FDS", DEFAULT_PLUGIN_MAX_FDS)?,
+        user,
+        namespaces: flag_from_env("SPACY_PLUGIN_NAMESPACES", false)?,
+        seccomp: flag_from_env("SPACY_PLUGIN_SECCOMP", true)?
+    })
+}
+
+fn limit_from_env(name: &'static str, default: u64) -> Result<Option<u64>, ConfigError> {
+    let limit = match env::var(name) {
+        Ok(value) => value.parse().map_err(|_| ConfigError::Sandbox(name))?,
+        Err(_) => default
+    };
+
+    Ok(Some(limit).filter(|limit| *limit != 0))
+}
+
+fn flag_from_env(name: &'static str, default: bool) -> Result<bool, ConfigError> {
+    match env::var(name).as_deref() {
+        Ok("1") => Ok(true),
+        Ok("0") => Ok(false),
+        Ok(_) => Err(ConfigError::Sandbox(name)),
+        Err(_) => Ok(default)
+    }
+}
+
+impl From<TlsError> for ConfigError {
+    fn from(error: TlsError) -> Self {
+        ConfigError::Tls(error)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Tls(error) => write!(f, "TLS configuration error: {}", error),
+            ConfigError::ClusterKey(error) => write!(f, "Couldn't load cluster key: {}", error),
+            ConfigError::Acl(error) => write!(f, "Couldn't load clients file: {}", error),
+            ConfigError::Sandbox(name) => write!(f, "Invalid value of {}", name)
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+// Where the plugin, that is deployed to the cluster, runs:
+//
+//   all               on every node
+//   replicas=<n>      on n nodes, they are picked by the plugin's name
+//   nodes=<id>,<id>   on the listed nodes, if they are in the cluster
+//
+// Every node decides on its own, so placement depends only on the cluster's members
+
+// Statuses, that client receives, if deployment isn't changed
+pub const STATUS_UNKNOWN_DEPLOYMENT: i32 = -1;
+pub const STATUS_NAME_IN_USE: i32 = -3;
+// Cluster is busy with other transactions, request can be repeated
+pub const STATUS_CLUSTER_BUSY: i32 = -7;
+pub const STATUS_INVALID_PLACEMENT: i32 = -8;
+// Node doesn't reach a majority of the cluster's members, e.g. during a partition
+pub const STATUS_NO_QUORUM: i32 = -14;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placement {
+    All,
+    Replicas(usize),
+    Nodes(Vec<u128>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deployment {
+    pub placement: Placement,
+    pub package: Vec<u8>
+}
+
+impl Placement {
+    pub fn parse(string: &str) -> Option<Self> {
+        if string == "all" {
+            return Some(Placement::All);
+        }
+
+        if let Some(replicas) = string.strip_prefix("replicas=") {
+            return replicas.parse().ok()
+                .filter(|replicas| *replicas > 0)
+                .map(Placement::Replicas);
+        }
+
+        if let Some(nodes) = string.strip_prefix("nodes=") {
+            let nodes: Option<Vec<u128>> = nodes.split(',')
+                .map(|node| node.trim().parse().ok())
+                .collect();
+            return nodes.filter(|nodes| !nodes.is_empty()).map(Placement::Nodes);
+        }
+
+        None
+    }
+
+    // Whether the node runs the plugin, members include the node itself
+    pub fn places(&self, name: &[u8], node_id: u128, members: &[u128]) -> bool {
+        match self {
+            Placement::All => true,
+            Placement::Nodes(nodes) => nodes.contains(&node_id),
+            Placement::Replicas(replicas) => {
+                // Rendezvous hashing, so only replicas of the gone node move
+                let mut scores: Vec<(u64, u128)> = members.iter()
+                    .map(|member| (score(name, *member), *member))
+                    .collect();
+                scores.sort_unstable_by(|a, b| b.cmp(a));
+
+                scores.iter()
+                    .take(*replicas)
+                    .any(|(_, member)| *member == node_id)
+            }
+        }
+    }
+}
+
+// FNV-1a, it must be the same on every node whatever they are built with
+fn score(name: &[u8], node_id: u128) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in name.iter().chain(node_id.to_le_bytes().iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Placement::All => write!(f, "all"),
+            Placement::Replicas(replicas) => write!(f, "replicas={}", replicas),
+            Placement::Nodes(nodes) => {
+                let nodes: Vec<String> = nodes.iter().map(|node| node.to_string()).collect();
+                write!(f, "nodes={}", nodes.join(","))
+            }
+        }
+    }
+}
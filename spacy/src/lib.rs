@@ -0,0 +1,10 @@
+pub mod acl;
+pub mod config;
+pub mod deployment;
+pub mod node;
+pub mod server;
+pub mod supervision;
+pub mod sandbox;
+pub mod wasm_plugin;
+pub mod plugin_logs;
+pub mod plugin_man;
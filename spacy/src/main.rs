@@ -1,76 +1,117 @@
-mod node;
-mod server;
-mod plugin_man;
-
-use node::Node;
-use plugin_man::PluginMan;
-use server::Server;
 use std::{
-    sync::mpsc,
+    collections::HashMap,
+    fmt,
+    process,
     thread,
     time
 };
-use common::event::proto_msg;
+use common::{
+    bus::Bus,
+    event::proto_msg::{self, event::Dest},
+    utils
+};
+use signal_hook::{consts::{SIGINT, SIGTERM}, iterator::Signals};
+use spacy::{
+    config::Config,
+    node::Node,
+    plugin_man::PluginMan,
+    server::Server
+};
+
+// How often bus metrics are reported
+const METRICS_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+// How long stopping waits for the room in subsystem's inbox
+const SHUTDOWN_SEND_TIMEOUT: time::Duration = time::Duration::from_secs(10);
 
 fn main() {
     env_logger::init();
 
-    let (main_event_channel_tx, main_event_channel_rx) = mpsc::channel();
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(error) => {
+            log::error!("{}", error);
+            process::exit(1);
+        }
+    };
 
-    let main_event_channel_tx_clone = main_event_channel_tx.clone();
-    let mut node = Node::new(main_event_channel_tx_clone);
-    let node_event_channel_tx = node.start();
+    // Every client, node and plugin takes an fd
+    match utils::raise_fd_limit() {
+        Ok(limit) => log::debug!("Open files limit: {}", limit),
+        Err(error) => log::warn!("Couldn't raise open files limit: {}", error)
+    }
 
-    let main_event_channel_tx_clone = main_event_channel_tx.clone();
-    let server = Server::new(main_event_channel_tx_clone, node.get_node_id());
-    let (server_event_channel_tx, _server_handle) = server.start();
+    // Termination signals are handled from now on
+    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
 
-    let main_event_channel_tx_clone = main_event_channel_tx.clone();
-    let mut plugin_man = PluginMan::new(main_event_channel_tx_clone);
-    let plugin_man_event_channel_tx = plugin_man.start();
+    // Subsystems register themselves on the bus and send events to each other through it
+    let bus = Bus::new();
 
-    loop {
-        let event_res = main_event_channel_rx.try_recv();
-        if let Ok(event) = event_res {
-            if let Some(dest) = event.dest {
-                if dest == proto_msg::event::Dest::PluginMan as i32 {
-                    log::debug!("Received `plugin_manager` event");
+    let mut node = Node::new(bus.clone());
+    let node_id = node.get_node_id();
+    let node_handle = node.start();
 
-                    // Sending an event to plugin manager
-                    plugin_man_event_channel_tx.send(event).unwrap();
-                }
+    let server = Server::new(bus.clone(), node_id, &config);
+    let server_handle = server.start();
 
-                else if dest == proto_msg::event::Dest::Node as i32 {
-                    log::debug!("Recevied `node` event");
+    let plugin_man = PluginMan::new(bus.clone(), &config);
+    let plugin_man_handle = plugin_man.start();
 
-                    // Sending an event to node
-                    node_event_channel_tx.send(event).unwrap();
-                }
+    let metrics_bus = bus.clone();
+    thread::spawn(move || loop {
+        thread::sleep(METRICS_INTERVAL);
 
-                else if dest == proto_msg::event::Dest::Server as i32 {
-                    log::debug!("Recevied `server` event");
+        for (dest, metrics) in metrics_bus.metrics() {
+            log::debug!("Bus {:?}: {:?}", dest, metrics);
+        }
 
-                    // Sending an event to server
-                    server_event_channel_tx.send(event).unwrap();
-                }
+        // Each one is logged, when it's dropped, here they are summed up
+        let mut dead_letters = HashMap::new();
+        for letter in metrics_bus.take_dead_letters() {
+            *dead_letters.entry(letter.reason.to_string()).or_insert(0) += 1;
+        }
+        for (reason, count) in dead_letters {
+            log::warn!("Bus dropped {} events since the last report: {}", count, reason);
+        }
+    });
 
-                else {
-                    log::warn!("Received event with unknown destination: {:?}", event.dest);
-                }
+    // Waiting for termination
+    if let Some(signal) = signals.forever().next() {
+        log::info!("Received signal {}, shutting down", signal);
+    }
 
-            } else {
-                log::warn!("Received event without destination");
-            }
+    // Second signal terminates the node right away
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            log::warn!("Forced shutdown");
+            process::exit(1);
         }
+    });
 
-        node.step().unwrap();
-        plugin_man.step().unwrap();
+    // Plugins stop first, so no new transactions are requested
+    stop_subsystem(&bus, Dest::PluginMan, plugin_man_handle);
+    // Node finishes transactions and announces its departure
+    stop_subsystem(&bus, Dest::Node, node_handle);
+    // Server delivers what's left and closes connections
+    stop_subsystem(&bus, Dest::Server, server_handle);
 
-        thread::sleep(time::Duration::from_millis(1));
-    }
+    log::info!("Stopped");
+}
 
-    // match server_handle.join() {
-    //     Ok(_) => {},
-    //     Err(_) => {}
-    // };
+fn stop_subsystem<E: fmt::Debug>(bus: &Bus, dest: Dest, handle: thread::JoinHandle<Result<(), E>>) {
+    // Subsystem, that hasn't got the event, would never stop
+    bus.send_waiting(proto_msg::Event {
+        dir: Some(proto_msg::event::Dir::Incoming as i32),
+        dest: Some(dest as i32),
+        kind: proto_msg::event::Kind::Shutdown as i32,
+        data: vec![],
+        meta: vec![],
+        correlation_id: None
+    }, SHUTDOWN_SEND_TIMEOUT);
+
+    match handle.join() {
+        Ok(Ok(_)) => log::debug!("{:?} stopped", dest),
+        Ok(Err(error)) => log::error!("{:?} stopped with error: {:?}", dest, error),
+        Err(_) => log::error!("{:?} panicked", dest)
+    }
 }
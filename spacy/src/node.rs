@@ -1,151 +1,295 @@
 use std::{
-    collections::HashMap,
-    sync::mpsc, time
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    fmt, thread, time
 };
 use common::{
-    fsm::{FSM, FSMError},
-    event::{proto_msg, self},
-    utils
+    bus::{Bus, Inbox, QueueConfig},
+    clock::{Clock, SystemClock},
+    fsm::{Fsm, FSMError, Priority, State},
+    event::{proto_msg, self, FieldError},
+    reactor::Reactor
 };
+use crate::deployment::{self, Deployment, Placement};
 
 pub struct Node {
-    fsm: FSM,
-    event_channel_tx: mpsc::Sender<proto_msg::Event>,
-    event_channel_rx: mpsc::Receiver<proto_msg::Event>,
+    fsm: Fsm<NodeState, proto_msg::Event>,
+    inbox: Inbox,
+    reactor: Reactor,
     shared_memory: HashMap<i32, Vec<u8>>,
     shared_memory_version: u128,
+    // Plugins, that are deployed to the cluster, by their names.
+    // They are replicated together with shared memory and share its version
+    deployments: HashMap<Vec<u8>, Deployment>,
+    // Deployed plugins, that plugin manager was asked to run on this node
+    running_plugins: HashSet<Vec<u8>>,
 
     node_id: u128,
     nodes: Vec<u128>,
+    // Nodes, that have joined the cluster and haven't left it, reachable or not.
+    // Changes are committed only by a majority of them, so two sides of a partition can't both commit
+    members: HashSet<u128>,
 
     is_transaction: bool,
     is_transaction_master: bool,
     transaction_kind: i32,
     transaction_queue: Vec<proto_msg::Event>,
     transaction_approvals: usize,
+    // Clock's time, when the transaction is given up
+    transaction_deadline: u128,
 
-    main_event_channel_tx: mpsc::Sender<proto_msg::Event>
+    // Nodes, that have announced their departure
+    left_nodes: HashSet<u128>,
+    // Node leaves the cluster, when transactions are finished or deadline is reached
+    shutdown_deadline: Option<time::Instant>,
+
+    // Node ids and shared memory versions are taken from it
+    clock: Arc<dyn Clock>,
+
+    bus: Bus
 }
 
+// How long in-flight transactions may take during shutdown
+const SHUTDOWN_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+// Transaction, that isn't finished in time, is given up, some of its messages are lost.
+// It's shorter than plugin manager's request timeout, so plugins get the result from the node
+const TRANSACTION_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
 #[derive(Debug)]
 pub enum NodeError {
+    // Event doesn't carry what its kind requires
+    MalformedEvent(FieldError),
+    // Event doesn't match the state of the transaction
+    UnexpectedEvent(&'static str),
+    // FSM was asked for an impossible transition
     InternalError
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NodeState {
+    Init,
+    WaitEvent,
+    HandleIncomingEvent,
+    HandleOutcomingEvent,
+    Stop
+}
+
+impl State for NodeState {
+    fn name(&self) -> &'static str {
+        match self {
+            NodeState::Init => "init",
+            NodeState::WaitEvent => "wait_event",
+            NodeState::HandleIncomingEvent => "handle_incoming_event",
+            NodeState::HandleOutcomingEvent => "handle_outcoming_event",
+            NodeState::Stop => "stop"
+        }
+    }
+}
+
 impl Node {
-    // 0 - initialization
-    // 1 - waiting for events
-    // 2 - handling incoming event
-    // 3 - handling outcoming event
-    // 4 - stop
-
-    pub fn new(main_event_channel_tx: mpsc::Sender<proto_msg::Event>) -> Self {
-        let fsm = FSM::new(0, HashMap::from([
-            (0, vec![1, 4]),
-            (1, vec![2, 3, 4]),
-            (2, vec![1, 4]),
-            (3, vec![1, 4]),
-            (4, vec![])
-        ]));
-
-        // Creating main event communication channel
-        let (event_channel_tx, event_channel_rx) = mpsc::channel();
+    pub fn new(bus: Bus) -> Self {
+        Self::with_clock(bus, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(bus: Bus, clock: Arc<dyn Clock>) -> Self {
+        let mut fsm = Fsm::new("node", NodeState::Init, &[
+            (NodeState::Init, &[NodeState::WaitEvent, NodeState::Stop]),
+            (NodeState::WaitEvent, &[NodeState::HandleIncomingEvent, NodeState::HandleOutcomingEvent, NodeState::Stop]),
+            (NodeState::HandleIncomingEvent, &[NodeState::WaitEvent, NodeState::Stop]),
+            (NodeState::HandleOutcomingEvent, &[NodeState::WaitEvent, NodeState::Stop]),
+            (NodeState::Stop, &[])
+        ]);
+        fsm.guard(NodeState::WaitEvent, NodeState::HandleIncomingEvent, "incoming", |event| event::has_dir(event, proto_msg::event::Dir::Incoming));
+        fsm.guard(NodeState::WaitEvent, NodeState::HandleOutcomingEvent, "outcoming", |event| event::has_dir(event, proto_msg::event::Dir::Outcoming));
+
+        // Registering on the bus, arriving events wake the node up
+        let reactor = Reactor::new().unwrap();
+        let inbox = bus.register(proto_msg::event::Dest::Node, QueueConfig::default(), reactor.notifier()).unwrap();
 
         // Creating a shared_memory instance
         let shared_memory = HashMap::new();
         let shared_memory_version = 0;
 
         // Generating node id
-        let node_id = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_nanos();
+        let node_id = clock.now();
         log::debug!("Node_id: {}", node_id);
 
         Self {
             fsm,
-            event_channel_tx,
-            event_channel_rx,
+            inbox,
+            reactor,
             shared_memory,
             shared_memory_version,
+            deployments: HashMap::new(),
+            running_plugins: HashSet::new(),
 
             node_id,
             nodes: vec![],
+            members: HashSet::new(),
 
             is_transaction: false,
             is_transaction_master: false,
             transaction_kind: 0,
             transaction_queue: vec![],
             transaction_approvals: 0,
+            transaction_deadline: 0,
 
-            main_event_channel_tx
-        }
-    }
+            left_nodes: HashSet::new(),
+            shutdown_deadline: None,
 
-    pub fn start(&mut self) -> mpsc::Sender<proto_msg::Event> {
-        let event_channel_tx_clone = self.event_channel_tx.clone();
+            clock,
 
-        event_channel_tx_clone
+            bus
+        }
     }
 
-    pub fn step(&mut self) -> Result<(), NodeError> {
-        match self.fsm.state {
-            0 => self.init(),
-            1 => self.wait_event(),
-            2 => self.handle_incoming_event(),
-            3 => self.handle_outcoming_event(),
-            4 => {
-                self.stop()?;
-                return Ok(());
-            },
-            _ => unreachable!()
-        }
+    pub fn start(mut self) -> thread::JoinHandle<Result<(), NodeError>> {
+        // Starting FSM loop
+        thread::spawn(move || loop {
+            match self.fsm.state() {
+                NodeState::Init => self.init()?,
+                NodeState::WaitEvent => self.wait_event()?,
+                NodeState::HandleIncomingEvent => self.handle_incoming_event()?,
+                NodeState::HandleOutcomingEvent => self.handle_outcoming_event()?,
+                NodeState::Stop => {
+                    self.stop()?;
+                    return Ok(());
+                },
+            }
+        })
     }
 
     pub fn get_node_id(&mut self) -> u128 {
         self.node_id
     }
 
+    pub fn get_shared_memory(&self) -> &HashMap<i32, Vec<u8>> {
+        &self.shared_memory
+    }
+
+    pub fn get_deployments(&self) -> &HashMap<Vec<u8>, Deployment> {
+        &self.deployments
+    }
+
     fn init(&mut self) -> Result<(), NodeError> {
         log::debug!("State `init`");
 
-        self.fsm.transition(1)?;
+        self.fsm.transition(NodeState::WaitEvent)?;
         Ok(())
     }
 
     fn wait_event(&mut self) -> Result<(), NodeError> {
         // log::debug!("State `wait_event`");
 
-        let event = match self.event_channel_rx.try_recv() {
-            Ok(event) => event,
-            Err(_) => {
-                if !self.is_transaction {
-                    if self.fsm.is_queue_empty() {
-                        if !self.transaction_queue.is_empty() {
-                            let transaction_event = self.transaction_queue.get(0).unwrap();
-                            let transaction_kind = transaction_event.kind;
+        let event = match self.inbox.try_recv() {
+            Some(event) => event,
+            None => {
+                self.expire_transaction();
+                if self.try_start_transaction()? {
+                    return Ok(());
+                }
 
-                            self.handle_request_transaction_outcoming(transaction_kind, vec![])?;
+                // Leaving the cluster, when there is nothing left to do
+                if let Some(deadline) = self.shutdown_deadline {
+                    let is_drained = !self.is_transaction && self.transaction_queue.is_empty();
+                    if is_drained || time::Instant::now() >= deadline {
+                        if !is_drained {
+                            log::warn!("Leaving the cluster with unfinished transactions");
                         }
+
+                        self.leave_cluster();
+                        self.fsm.transition(NodeState::Stop)?;
+                        return Ok(());
                     }
                 }
 
+                // Nothing to do, sleeping until new events arrive
+                let shutdown_timeout = self.shutdown_deadline
+                    .map(|deadline| deadline.saturating_duration_since(time::Instant::now()));
+                let transaction_timeout = Some(self.transaction_deadline)
+                    .filter(|_| self.is_transaction)
+                    .map(|deadline| time::Duration::from_nanos(deadline.saturating_sub(self.clock.now()) as u64));
+                let timeout = shutdown_timeout.into_iter().chain(transaction_timeout).min();
+                if let Err(error) = self.reactor.wait(timeout) {
+                    log::warn!("Waiting for events failed: {}", error);
+                }
+
                 return Ok(());
             }
         };
 
-        let event_direction = event.dir;
-        self.fsm.push_event(event);
+        self.dispatch_event(event)
+    }
+
+    // Handles a single event right away, as if it was taken from the bus
+    pub fn process_event(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
+        if self.fsm.state() == NodeState::Init {
+            self.init()?;
+        }
+
+        if self.fsm.state() == NodeState::WaitEvent {
+            self.dispatch_event(event)?;
+        }
+
+        loop {
+            match self.fsm.state() {
+                NodeState::WaitEvent | NodeState::Stop => return Ok(()),
+                NodeState::HandleIncomingEvent => self.handle_incoming_event()?,
+                NodeState::HandleOutcomingEvent => self.handle_outcoming_event()?,
+                _ => unreachable!()
+            }
+        }
+    }
+
+    // Does what the node does, when there are no events to handle
+    pub fn process_idle(&mut self) -> Result<(), NodeError> {
+        if self.fsm.state() == NodeState::WaitEvent {
+            self.expire_transaction();
+            self.try_start_transaction()?;
+        }
+
+        Ok(())
+    }
+
+    // Requests the queued transaction, if there is nothing else going on.
+    // Returns `true` if transaction is requested
+    fn try_start_transaction(&mut self) -> Result<bool, NodeError> {
+        if self.is_transaction || !self.fsm.is_queue_empty() {
+            return Ok(false);
+        }
+
+        let transaction_kind = match self.transaction_queue.first() {
+            Some(transaction_event) => transaction_event.kind,
+            None => return Ok(false)
+        };
+
+        // Shared memory sync is decided by versions in case of collision
+        let additional_data = if transaction_kind == 2 {
+            vec![self.shared_memory_version.to_ne_bytes().to_vec()]
+        } else {
+            vec![]
+        };
+
+        self.handle_request_transaction_outcoming(transaction_kind, additional_data)?;
+        Ok(true)
+    }
 
-        // Handling event based on it's direction
-        if let Some(dir) = event_direction {
+    fn dispatch_event(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
+        // Handling event based on it's direction, events without one are dropped
+        if let Some(dir) = event.dir {
             if dir == proto_msg::event::Dir::Incoming as i32 {
                 log::debug!("Received `incoming` event");
 
-                self.fsm.transition(2)?;
+                self.fsm.push_event(event, Priority::Control);
+                self.fsm.transition(NodeState::HandleIncomingEvent)?;
             }
 
             else if dir == proto_msg::event::Dir::Outcoming as i32 {
                 log::debug!("Received `outcoming` event");
 
-                self.fsm.transition(3)?;
+                self.fsm.push_event(event, Priority::Control);
+                self.fsm.transition(NodeState::HandleOutcomingEvent)?;
             }
 
             else {
@@ -162,57 +306,93 @@ impl Node {
     fn handle_incoming_event(&mut self) -> Result<(), NodeError> {
         log::debug!("State `handle_incoming_event`");
 
-        let event = self.fsm.pop_front_event().unwrap();
+        let event = self.fsm.pop_front_event().ok_or(NodeError::InternalError)?;
+        let kind = event.kind;
 
-        if event.kind == proto_msg::event::Kind::RequestTransaction as i32 {
-            self.handle_request_transaction_incoming(event)?;
+        let result = if event.kind == proto_msg::event::Kind::RequestTransaction as i32 {
+            self.handle_request_transaction_incoming(event)
         }
 
         else if event.kind == proto_msg::event::Kind::ApproveTransaction as i32 {
-            self.handle_approve_transaction_incoming(event)?;
+            self.handle_approve_transaction_incoming(event)
         }
 
         else if event.kind == proto_msg::event::Kind::CommitTransaction as i32 {
-            self.handle_commit_transaction_incoming(event)?;
+            self.handle_commit_transaction_incoming(event)
         }
 
         else if event.kind == proto_msg::event::Kind::NodeConnected as i32 {
-            self.handle_request_new_connection_outcoming(event)?;
+            self.handle_request_new_connection_outcoming(event)
         }
 
         else if event.kind == proto_msg::event::Kind::NodeDisconnected as i32 {
-            self.handle_request_old_connection_outcoming(event)?;
+            self.handle_node_disconnected(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::NodeLeaving as i32 {
+            self.handle_node_leaving(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::Shutdown as i32 {
+            self.handle_shutdown()
         }
 
         else {
             log::warn!("Received event with unknown kind: {}", event.kind);
-        }
+            Ok(())
+        };
 
-        self.fsm.transition(1)?;
+        Self::recover(kind, result)?;
+
+        self.fsm.transition(NodeState::WaitEvent)?;
         Ok(())
     }
 
     fn handle_outcoming_event(&mut self) -> Result<(), NodeError> {
         log::debug!("State `handle_outcoming_event`");
 
-        let event = self.fsm.pop_front_event().unwrap();
+        let event = self.fsm.pop_front_event().ok_or(NodeError::InternalError)?;
+        let kind = event.kind;
 
-        if event.kind == proto_msg::event::Kind::UpdateSharedMemory as i32 {
-            self.handle_request_update_shared_memory_outcoming(event)?;
+        let result = if event.kind == proto_msg::event::Kind::UpdateSharedMemory as i32 {
+            self.handle_request_update_shared_memory_outcoming(event)
         }
 
         else if event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32 {
-            self.handle_request_get_from_shared_memory_outcoming(event)?;
+            self.handle_request_get_from_shared_memory_outcoming(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::DeployPlugin as i32 {
+            self.handle_request_deploy_plugin_outcoming(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::RemoveDeployment as i32 {
+            self.handle_request_remove_deployment_outcoming(event)
         }
 
         else {
             log::warn!("Received event with unknown kind: {}", event.kind);
-        }
+            Ok(())
+        };
 
-        self.fsm.transition(1)?;
+        Self::recover(kind, result)?;
+
+        self.fsm.transition(NodeState::WaitEvent)?;
         Ok(())
     }
 
+    // Event, that can't be handled, is dropped, so a peer or a plugin can't take the node down
+    fn recover(kind: i32, result: Result<(), NodeError>) -> Result<(), NodeError> {
+        match result {
+            Err(NodeError::InternalError) => Err(NodeError::InternalError),
+            Err(error) => {
+                log::warn!("Dropping event of kind {}: {}", kind, error);
+                Ok(())
+            },
+            Ok(_) => Ok(())
+        }
+    }
+
     fn stop(&mut self) -> Result<(), NodeError> {
         log::debug!("State `stop`");
 
@@ -227,14 +407,12 @@ impl Node {
             dest: Some(proto_msg::event::Dest::Server as i32),
             kind: proto_msg::event::Kind::ApproveTransaction as i32,
             data: vec![self.node_id.to_ne_bytes().to_vec()],
-            meta: event.meta
+            meta: event.meta,
+            correlation_id: None
         };
 
-        let bytes = event.data.get(0).unwrap();
-        let transaction_kind = utils::i32_from_ne_bytes(bytes).unwrap();
-
-        let bytes = event.data.get(1).unwrap();
-        let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+        let transaction_kind = event::get_i32(&event.data, 0)?;
+        let node_id = event::get_u128(&event.data, 1)?;
 
         if !self.nodes.contains(&node_id) {
             log::debug!("Ignoring request from non-connected node");
@@ -247,7 +425,7 @@ impl Node {
             self.transaction_kind = transaction_kind;
 
             // Approving transaction
-            self.main_event_channel_tx.send(approve_event).unwrap();
+            self.approve_transaction(approve_event);
         }
 
         // If we are in transaction, then collision occured
@@ -255,8 +433,7 @@ impl Node {
             if self.transaction_kind == transaction_kind {
                 // If transaction is syncing shared memory
                 if self.transaction_kind == 2 {
-                    let bytes = event.data.get(2).unwrap();
-                    let version = utils::u128_from_ne_bytes(bytes).unwrap();
+                    let version = event::get_u128(&event.data, 2)?;
 
                     if self.shared_memory_version == version {
                         if self.node_id > node_id {
@@ -264,26 +441,26 @@ impl Node {
                         } else {
                             log::debug!("Local transaction cancelled");
 
-                            self.transaction_queue.pop();
+                            self.pop_local_transaction();
 
                             self.is_transaction_master = false;
                             self.transaction_approvals = 0;
 
                             // Approving transaction
-                            self.main_event_channel_tx.send(approve_event).unwrap();
+                            self.approve_transaction(approve_event);
                         }
                     } else if self.shared_memory_version > version {
                         log::debug!("Local transaction approved");
                     } else {
                         log::debug!("Local transaction cancelled");
 
-                        self.transaction_queue.pop();
+                        self.pop_local_transaction();
 
                         self.is_transaction_master = false;
                         self.transaction_approvals = 0;
 
                         // Approving transaction
-                        self.main_event_channel_tx.send(approve_event).unwrap();
+                        self.approve_transaction(approve_event);
                     }
                 } else if self.transaction_kind == 3 {
                     if self.node_id > node_id {
@@ -291,24 +468,25 @@ impl Node {
                     } else {
                         log::debug!("Local transaction cancelled");
 
-                        let transaction_event = self.transaction_queue.pop().unwrap();
-                        let meta = transaction_event.meta;
+                        let transaction_event = self.pop_local_transaction()
+                            .ok_or(NodeError::UnexpectedEvent("no local transaction is queued"))?;
 
                         let event = proto_msg::Event {
                             dir: Some(proto_msg::event::Dir::Incoming as i32),
                             dest: Some(proto_msg::event::Dest::PluginMan as i32),
                             kind: proto_msg::event::Kind::TransactionFailed as i32,
                             data: vec![],
-                            meta
+                            meta: vec![],
+                            correlation_id: transaction_event.correlation_id
                         };
 
-                        self.main_event_channel_tx.send(event).unwrap();
+                        self.bus.send(event);
 
                         self.is_transaction_master = false;
                         self.transaction_approvals = 0;
 
                         // Approving transaction
-                        self.main_event_channel_tx.send(approve_event).unwrap();
+                        self.approve_transaction(approve_event);
                     }
                 } else {
                     if self.node_id > node_id {
@@ -321,7 +499,7 @@ impl Node {
                         self.transaction_approvals = 0;
 
                         // Approving transaction
-                        self.main_event_channel_tx.send(approve_event).unwrap();
+                        self.approve_transaction(approve_event);
                     }
                 }
             } else if self.transaction_kind < transaction_kind {
@@ -330,8 +508,8 @@ impl Node {
                 if self.transaction_kind == 3 {
                     log::debug!("Local transaction failed");
 
-                    let transaction_event = self.transaction_queue.pop().unwrap();
-                    let meta = transaction_event.meta;
+                    let transaction_event = self.pop_local_transaction()
+                        .ok_or(NodeError::UnexpectedEvent("no local transaction is queued"))?;
 
                     self.is_transaction_master = false;
 
@@ -340,10 +518,11 @@ impl Node {
                         dest: Some(proto_msg::event::Dest::PluginMan as i32),
                         kind: proto_msg::event::Kind::TransactionFailed as i32,
                         data: vec![],
-                        meta
+                        meta: vec![],
+                        correlation_id: transaction_event.correlation_id
                     };
 
-                    self.main_event_channel_tx.send(event).unwrap();
+                    self.bus.send(event);
                 } else {
                     log::debug!("Local transaction held");
 
@@ -351,7 +530,7 @@ impl Node {
                     self.transaction_kind = transaction_kind;
 
                     // Approving transaction
-                    self.main_event_channel_tx.send(approve_event).unwrap();
+                    self.approve_transaction(approve_event);
                 }
             }
         }
@@ -360,7 +539,7 @@ impl Node {
             self.transaction_kind = transaction_kind;
 
             // Approving transaction
-            self.main_event_channel_tx.send(approve_event).unwrap();
+            self.approve_transaction(approve_event);
         }
 
         Ok(())
@@ -379,7 +558,8 @@ impl Node {
             dest: None,
             kind: proto_msg::event::Kind::RequestTransaction as i32,
             data,
-            meta: vec![]
+            meta: vec![],
+            correlation_id: None
         };
 
         // Propagating to other nodes
@@ -395,14 +575,16 @@ impl Node {
             dest: Some(proto_msg::event::Dest::Server as i32),
             kind: proto_msg::event::Kind::BroadcastEvent as i32,
             data,
-            meta: vec![]
+            meta: vec![],
+            correlation_id: None
         };
 
-        self.main_event_channel_tx.send(broadcast_event).unwrap();
+        self.bus.send(broadcast_event);
 
         self.is_transaction = true;
         self.is_transaction_master = true;
         self.transaction_kind = transaction_kind;
+        self.transaction_deadline = self.clock.now() + TRANSACTION_TIMEOUT.as_nanos();
 
         if self.nodes.len() == 0 {
             self.handle_try_perform_transaction()?;
@@ -411,11 +593,53 @@ impl Node {
         Ok(())
     }
 
+    // Approved transaction of other node holds this one, until it's committed or times out
+    fn approve_transaction(&mut self, approve_event: proto_msg::Event) {
+        self.transaction_deadline = self.clock.now() + TRANSACTION_TIMEOUT.as_nanos();
+        self.bus.send(approve_event);
+    }
+
+    // Transaction, whose messages were lost, would hold the node forever. Requested changes of
+    // shared memory and deployments fail, membership changes and syncs stay queued and are retried
+    fn expire_transaction(&mut self) {
+        if !self.is_transaction || self.clock.now() < self.transaction_deadline {
+            return;
+        }
+
+        log::warn!("Transaction of kind {} has timed out", self.transaction_kind);
+
+        if self.is_transaction_master {
+            if self.transaction_kind == 3 {
+                if let Some(transaction_event) = self.pop_local_transaction() {
+                    let event = proto_msg::Event {
+                        dir: Some(proto_msg::event::Dir::Incoming as i32),
+                        dest: Some(proto_msg::event::Dest::PluginMan as i32),
+                        kind: proto_msg::event::Kind::TransactionFailed as i32,
+                        data: vec![],
+                        meta: vec![],
+                        correlation_id: transaction_event.correlation_id
+                    };
+
+                    self.bus.send(event);
+                }
+            }
+
+            else if self.transaction_kind == 4 || self.transaction_kind == 5 {
+                if let Some(transaction_event) = self.pop_local_transaction() {
+                    self.reply_deployment(transaction_event.correlation_id, deployment::STATUS_CLUSTER_BUSY);
+                }
+            }
+        }
+
+        self.is_transaction = false;
+        self.is_transaction_master = false;
+        self.transaction_approvals = 0;
+    }
+
     fn handle_approve_transaction_incoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
         log::debug!("Handling `approve_transaction`");
 
-        let bytes = event.data.get(0).unwrap();
-        let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+        let node_id = event::get_u128(&event.data, 0)?;
 
         if !self.nodes.contains(&node_id) {
             log::debug!("Ignoring request from non-connected node");
@@ -436,8 +660,7 @@ impl Node {
     fn handle_commit_transaction_incoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
         log::debug!("Handling `commit_transaction`");
 
-        let bytes = event.data.get(0).unwrap();
-        let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+        let node_id = event::get_u128(&event.data, 0)?;
 
         if !self.nodes.contains(&node_id) {
             log::debug!("Ignoring request from non-connected node");
@@ -457,7 +680,8 @@ impl Node {
             dest: None,
             kind: proto_msg::event::Kind::CommitTransaction as i32,
             data: event.data,
-            meta: vec![]
+            meta: vec![],
+            correlation_id: None
         };
 
         let mut data = vec![];
@@ -472,10 +696,11 @@ impl Node {
             dest: Some(proto_msg::event::Dest::Server as i32),
             kind: proto_msg::event::Kind::BroadcastEvent as i32,
             data,
-            meta: vec![]
+            meta: vec![],
+            correlation_id: None
         };
 
-        self.main_event_channel_tx.send(broadcast_event).unwrap();
+        self.bus.send(broadcast_event);
 
         Ok(())
     }
@@ -488,7 +713,8 @@ impl Node {
             dest: None,
             kind: 0,
             data: event.data,
-            meta: vec![]
+            meta: vec![],
+            correlation_id: None
         };
 
         self.transaction_queue.push(transaction_event);
@@ -502,29 +728,16 @@ impl Node {
     fn handle_request_old_connection_outcoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
         log::debug!("Handling `request old_connection`");
 
-        let bytes = event.data.get(0).unwrap();
-        let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
-        let mut to_del = None;
-        for i in 0..self.nodes.len() {
-            let id = self.nodes.get(i).unwrap();
-
-            if node_id == *id {
-                to_del = Some(i);
-            }
-        }
-
-        if let Some(id) = to_del {
-            self.nodes.remove(id);
-        }
-
-        // self.nodes.remove()
+        let node_id = event::get_u128(&event.data, 0)?;
+        self.nodes.retain(|id| *id != node_id);
 
         let transaction_event = proto_msg::Event {
             dir: None,
             dest: None,
             kind: 1,
             data: event.data,
-            meta: vec![]
+            meta: vec![],
+            correlation_id: None
         };
 
         self.transaction_queue.push(transaction_event);
@@ -535,26 +748,110 @@ impl Node {
         Ok(())
     }
 
+    fn handle_node_disconnected(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
+        log::debug!("Handling `node_disconnected`");
+
+        let node_id = event::get_u128(&event.data, 0)?;
+
+        // Node, that has announced its departure, is already removed
+        if self.left_nodes.remove(&node_id) {
+            return Ok(());
+        }
+
+        self.handle_request_old_connection_outcoming(event)
+    }
+
+    fn handle_node_leaving(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
+        log::debug!("Handling `node_leaving`");
+
+        let node_id = event::get_u128(&event.data, 0)?;
+
+        if !self.nodes.contains(&node_id) {
+            return Ok(());
+        }
+
+        log::info!("Node {} is leaving the cluster", node_id);
+        self.left_nodes.insert(node_id);
+        // Node, that has left, doesn't count towards the majority
+        self.members.remove(&node_id);
+
+        // Leaving node is removed the same way as the disconnected one
+        let event = proto_msg::Event {
+            dir: None,
+            dest: None,
+            kind: 0,
+            data: vec![node_id.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: None
+        };
+
+        self.handle_request_old_connection_outcoming(event)
+    }
+
+    fn handle_shutdown(&mut self) -> Result<(), NodeError> {
+        log::debug!("Handling `shutdown`");
+
+        if self.shutdown_deadline.is_none() {
+            log::info!("Finishing transactions before leaving the cluster");
+            self.shutdown_deadline = Some(time::Instant::now() + SHUTDOWN_TIMEOUT);
+        }
+
+        Ok(())
+    }
+
+    fn leave_cluster(&mut self) {
+        log::info!("Leaving the cluster");
+
+        let leaving_event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: None,
+            kind: proto_msg::event::Kind::NodeLeaving as i32,
+            data: vec![self.node_id.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: None
+        };
+
+        // Announcing departure to other nodes
+        let mut data = vec![];
+        data.push(event::serialize(leaving_event));
+        data.push((self.nodes.len() as i32).to_ne_bytes().to_vec());
+        for node in self.nodes.iter() {
+            data.push(node.to_ne_bytes().to_vec());
+        }
+
+        let broadcast_event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: Some(proto_msg::event::Dest::Server as i32),
+            kind: proto_msg::event::Kind::BroadcastEvent as i32,
+            data,
+            meta: vec![],
+            correlation_id: None
+        };
+
+        self.bus.send(broadcast_event);
+    }
+
     fn handle_request_update_shared_memory_outcoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
         log::debug!("Handling `request update_shared_memory`");
 
-        if self.is_transaction {
+        if self.is_transaction || !self.has_quorum() {
             let event = proto_msg::Event {
                 dir: Some(proto_msg::event::Dir::Incoming as i32),
                 dest: Some(proto_msg::event::Dest::PluginMan as i32),
                 kind: proto_msg::event::Kind::TransactionFailed as i32,
                 data: vec![],
-                meta: event.meta
+                meta: vec![],
+                correlation_id: event.correlation_id
             };
 
-            self.main_event_channel_tx.send(event).unwrap();
+            self.bus.send(event);
 
             return Ok(());
         }
 
-        let version = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_nanos();
-        let key = utils::i32_from_ne_bytes(event.data.get(0).unwrap()).unwrap();
-        let value = event.data.get(1).unwrap().to_vec();
+        let key = event::get_i32(&event.data, 0)?;
+        let value = event::get_field(&event.data, 1)?.to_vec();
+        let version = self.clock.now();
 
         let mut data = vec![];
         data.push(version.to_ne_bytes().to_vec());
@@ -566,7 +863,8 @@ impl Node {
             dest: None,
             kind: 3,
             data,
-            meta: event.meta
+            meta: vec![],
+            correlation_id: event.correlation_id
         };
 
         self.transaction_queue.push(transaction_event);
@@ -580,27 +878,24 @@ impl Node {
     fn handle_request_get_from_shared_memory_outcoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
         log::debug!("Handling `request get_from_shared_memory`");
 
-        let bytes = event.data.get(0).unwrap();
-        let key = utils::i32_from_ne_bytes(bytes).unwrap();
+        let key = event::get_i32(&event.data, 0)?;
 
         // Returning value if key is valid
-        let data;
-        if self.shared_memory.contains_key(&key) {
-            let value = self.shared_memory.get(&key).unwrap();
-            data = vec![value.to_vec()];
-        } else {
-            data = vec![];
-        }
+        let data = match self.shared_memory.get(&key) {
+            Some(value) => vec![value.to_vec()],
+            None => vec![]
+        };
 
         let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Incoming as i32),
             dest: Some(proto_msg::event::Dest::PluginMan as i32),
             kind: proto_msg::event::Kind::GetFromSharedMemory as i32,
             data,
-            meta: event.meta
+            meta: vec![],
+            correlation_id: event.correlation_id
         };
 
-        self.main_event_channel_tx.send(event).unwrap();
+        self.bus.send(event);
 
         Ok(())
     }
@@ -611,7 +906,18 @@ impl Node {
         if self.transaction_approvals == self.nodes.len() {
             log::debug!("Transaction approved by all nodes");
 
-            let mut transaction_event = self.transaction_queue.pop().unwrap();
+            // Nodes might have become unreachable, while the transaction was requested.
+            // Membership changes and syncs don't need a majority, so the rest can rejoin
+            if self.transaction_kind >= 3 && !self.has_quorum() {
+                log::warn!("Transaction of kind {} is given up, majority of the cluster is unreachable", self.transaction_kind);
+                return self.fail_without_quorum();
+            }
+
+            let mut transaction_event = self.pop_local_transaction()
+                .ok_or(NodeError::UnexpectedEvent("no local transaction is queued"))?;
+            if self.transaction_kind == 2 {
+                transaction_event.data = self.snapshot();
+            }
             transaction_event.data.insert(0, self.node_id.to_ne_bytes().to_vec());
             transaction_event.data.insert(1, self.transaction_kind.to_ne_bytes().to_vec());
 
@@ -632,43 +938,39 @@ impl Node {
     fn handle_perform_transaction(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
         log::debug!("Handling `perform_transaction`");
 
-        // Unused
-        // let bytes = event.data.get(0).unwrap();
-        // let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
-
-        let bytes = event.data.get(1).unwrap();
-        let transaction_kind = utils::i32_from_ne_bytes(bytes).unwrap();
+        let master_id = event::get_u128(&event.data, 0)?;
+        let transaction_kind = event::get_i32(&event.data, 1)?;
 
         // Node connected
         if transaction_kind == 0 {
             log::debug!("Transaction kind: `node_connected`");
 
-            let bytes = event.data.get(2).unwrap();
-            let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+            let node_id = event::get_u128(&event.data, 2)?;
 
-            if node_id != self.node_id {
+            // Node, that is connected to several members, joins once
+            if node_id != self.node_id && !self.nodes.contains(&node_id) {
                 self.nodes.push(node_id);
             }
+            if node_id != self.node_id {
+                self.members.insert(node_id);
+            }
 
             log::debug!("Node id: {}", node_id);
 
             self.is_transaction = false;
             self.is_transaction_master = false;
 
-            // Syncing shared memory
-            let mut data = vec![];
-            data.push(self.shared_memory_version.to_ne_bytes().to_vec());
-            for (key, value) in self.shared_memory.iter() {
-                data.push(key.to_ne_bytes().to_vec());
-                data.push(value.to_vec());
-            }
+            // Node's placement might have changed with the cluster
+            self.reconcile_plugins();
 
+            // Snapshot is taken, when the sync is performed, other transactions can come first
             let transaction_event = proto_msg::Event {
                 dir: None,
                 dest: None,
                 kind: 2,
-                data,
-                meta: vec![]
+                data: vec![],
+                meta: vec![],
+                correlation_id: None
             };
 
             self.transaction_queue.push(transaction_event);
@@ -682,77 +984,121 @@ impl Node {
         else if transaction_kind == 1 {
             log::debug!("Transaction kind: `node_disconnected`");
 
-            let bytes = event.data.get(2).unwrap();
-            let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+            let node_id = event::get_u128(&event.data, 2)?;
 
             if node_id != self.node_id {
-                let mut to_del = None;
-                for i in 0..self.nodes.len() {
-                    let id = self.nodes.get(i).unwrap();
-
-                    if node_id == *id {
-                        to_del = Some(i);
-                    }
-                }
-
-                if let Some(id) = to_del {
-                    self.nodes.remove(id);
-                }
+                self.nodes.retain(|id| *id != node_id);
             }
 
             log::debug!("Node id: {}", node_id);
+
+            // Replicas of the gone node move to the rest
+            self.reconcile_plugins();
         }
 
         else if transaction_kind == 2 {
             log::debug!("Transaction kind: `sync_shared_memory`");
 
-            let bytes = event.data.get(2).unwrap();
-            let version = utils::u128_from_ne_bytes(bytes).unwrap();
-            self.shared_memory_version = version;
+            let version = event::get_u128(&event.data, 2)?;
+            let fields_num = usize::try_from(event::get_i32(&event.data, 3)?)
+                .map_err(|_| NodeError::UnexpectedEvent("negative number of shared memory fields"))?;
 
-            self.shared_memory.clear();
-            let fields_num = (event.data.len() - 3) / 2;
+            // Whole snapshot is checked before replacing the local one
+            let mut shared_memory = HashMap::new();
             for i in 0..fields_num {
-                let index = (2 * i + 3) as usize;
+                let index = 2 * i + 4;
+
+                let key = event::get_i32(&event.data, index)?;
+                let value = event::get_field(&event.data, index + 1)?.to_vec();
 
-                let key_bytes = event.data.get(index + 0).unwrap();
-                let value_bytes = event.data.get(index + 1).unwrap();
+                shared_memory.insert(key, value);
+            }
 
-                let key = utils::i32_from_ne_bytes(key_bytes).unwrap();
-                let value = value_bytes.to_vec();
+            // Deployments follow shared memory
+            let mut deployments = HashMap::new();
+            let deployments_start = 2 * fields_num + 4;
+            let deployments_num = event.data.len().saturating_sub(deployments_start) / 3;
+            for i in 0..deployments_num {
+                let index = 3 * i + deployments_start;
 
-                self.shared_memory.insert(key, value);
+                let name = event::get_field(&event.data, index)?.to_vec();
+                let deployment = Self::parse_deployment(&event.data, index + 1)?;
+
+                deployments.insert(name, deployment);
             }
 
+            self.shared_memory_version = version;
+            self.shared_memory = shared_memory;
+            self.deployments = deployments;
+
             log::info!("Shared memory synced");
+
+            self.reconcile_plugins();
         }
 
         // Update shared memory
         else if transaction_kind == 3 {
             log::debug!("Transaction kind: `update_shared_memory`");
 
-            let version_bytes = event.data.get(2).unwrap();
-            let key_bytes = event.data.get(3).unwrap();
-            let value_bytes = event.data.get(4).unwrap();
-
-            let version = utils::u128_from_ne_bytes(version_bytes).unwrap();
-            let key = utils::i32_from_ne_bytes(key_bytes).unwrap();
-            let value = value_bytes.to_vec();
+            let version = event::get_u128(&event.data, 2)?;
+            let key = event::get_i32(&event.data, 3)?;
+            let value = event::get_field(&event.data, 4)?.to_vec();
 
             self.shared_memory_version = version;
             self.shared_memory.insert(key, value);
 
-            if self.is_transaction_master {
+            // Commit of other node's transaction can arrive, while local one is requested
+            if master_id == self.node_id {
                 let event = proto_msg::Event {
                     dir: Some(proto_msg::event::Dir::Incoming as i32),
                     dest: Some(proto_msg::event::Dest::PluginMan as i32),
                     kind: proto_msg::event::Kind::TransactionSucceeded as i32,
                     data: vec![],
-                    meta: event.meta
+                    meta: vec![],
+                    correlation_id: event.correlation_id
                 };
 
-                self.main_event_channel_tx.send(event).unwrap();
+                self.bus.send(event);
+            }
+        }
+
+        // Deploy plugin
+        else if transaction_kind == 4 {
+            log::debug!("Transaction kind: `deploy_plugin`");
+
+            let version = event::get_u128(&event.data, 2)?;
+            let name = event::get_field(&event.data, 3)?.to_vec();
+            let deployment = Self::parse_deployment(&event.data, 4)?;
+
+            log::info!("Plugin `{}` is deployed to {}", String::from_utf8_lossy(&name), deployment.placement);
+
+            self.shared_memory_version = version;
+            self.deployments.insert(name, deployment);
+
+            if master_id == self.node_id {
+                self.reply_deployment(event.correlation_id, 0);
             }
+
+            self.reconcile_plugins();
+        }
+
+        // Remove deployment
+        else if transaction_kind == 5 {
+            log::debug!("Transaction kind: `remove_deployment`");
+
+            let version = event::get_u128(&event.data, 2)?;
+            let name = event::get_field(&event.data, 3)?.to_vec();
+
+            log::info!("Plugin `{}` is removed from the cluster", String::from_utf8_lossy(&name));
+
+            self.shared_memory_version = version;
+            self.deployments.remove(&name);
+
+            if master_id == self.node_id {
+                self.reply_deployment(event.correlation_id, 0);
+            }
+
+            self.reconcile_plugins();
         }
 
         else {
@@ -764,10 +1110,222 @@ impl Node {
 
         Ok(())
     }
+
+    fn handle_request_deploy_plugin_outcoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
+        log::debug!("Handling `request deploy_plugin`");
+
+        let name = event::get_field(&event.data, 0)?.to_vec();
+        Self::parse_deployment(&event.data, 1)?;
+
+        if self.deployments.contains_key(&name) {
+            self.reply_deployment(event.correlation_id, deployment::STATUS_NAME_IN_USE);
+            return Ok(());
+        }
+
+        self.request_deployment_transaction(4, event)
+    }
+
+    fn handle_request_remove_deployment_outcoming(&mut self, event: proto_msg::Event) -> Result<(), NodeError> {
+        log::debug!("Handling `request remove_deployment`");
+
+        let name = event::get_field(&event.data, 0)?;
+
+        if !self.deployments.contains_key(name) {
+            self.reply_deployment(event.correlation_id, deployment::STATUS_UNKNOWN_DEPLOYMENT);
+            return Ok(());
+        }
+
+        self.request_deployment_transaction(5, event)
+    }
+
+    fn request_deployment_transaction(&mut self, transaction_kind: i32, event: proto_msg::Event) -> Result<(), NodeError> {
+        // Like shared memory updates, deployments aren't queued behind other transactions
+        if self.is_transaction || !self.transaction_queue.is_empty() {
+            self.reply_deployment(event.correlation_id, deployment::STATUS_CLUSTER_BUSY);
+            return Ok(());
+        }
+
+        if !self.has_quorum() {
+            self.reply_deployment(event.correlation_id, deployment::STATUS_NO_QUORUM);
+            return Ok(());
+        }
+
+        let mut data = event.data;
+        data.insert(0, self.clock.now().to_ne_bytes().to_vec());
+
+        let transaction_event = proto_msg::Event {
+            dir: None,
+            dest: None,
+            kind: transaction_kind,
+            data,
+            meta: vec![],
+            correlation_id: event.correlation_id
+        };
+
+        self.transaction_queue.push(transaction_event);
+
+        // Requesting transaction
+        self.handle_request_transaction_outcoming(transaction_kind, vec![])?;
+
+        Ok(())
+    }
+
+    // Shared memory with its version, followed by deployments
+    fn snapshot(&self) -> Vec<Vec<u8>> {
+        let mut data = vec![];
+        data.push(self.shared_memory_version.to_ne_bytes().to_vec());
+        data.push((self.shared_memory.len() as i32).to_ne_bytes().to_vec());
+        for (key, value) in self.shared_memory.iter() {
+            data.push(key.to_ne_bytes().to_vec());
+            data.push(value.to_vec());
+        }
+        for (name, deployment) in self.deployments.iter() {
+            data.push(name.to_vec());
+            data.push(deployment.placement.to_string().into_bytes());
+            data.push(deployment.package.to_vec());
+        }
+
+        data
+    }
+
+    // Several local transactions can be queued, while one of them is held,
+    // the one, that is requested now, is the latest of the requested kind
+    fn pop_local_transaction(&mut self) -> Option<proto_msg::Event> {
+        let kind = self.transaction_kind;
+        let index = self.transaction_queue.iter().rposition(|transaction_event| transaction_event.kind == kind)?;
+
+        Some(self.transaction_queue.remove(index))
+    }
+
+    // Reachable nodes, this one included, are a majority of the cluster's members
+    fn has_quorum(&self) -> bool {
+        2 * (self.nodes.len() + 1) > self.members.len() + 1
+    }
+
+    // Approved transaction isn't committed, nodes, that have approved it, hold until it times out
+    fn fail_without_quorum(&mut self) -> Result<(), NodeError> {
+        let transaction_event = self.pop_local_transaction()
+            .ok_or(NodeError::UnexpectedEvent("no local transaction is queued"))?;
+
+        if self.transaction_kind == 3 {
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Incoming as i32),
+                dest: Some(proto_msg::event::Dest::PluginMan as i32),
+                kind: proto_msg::event::Kind::TransactionFailed as i32,
+                data: vec![],
+                meta: vec![],
+                correlation_id: transaction_event.correlation_id
+            };
+
+            self.bus.send(event);
+        } else {
+            self.reply_deployment(transaction_event.correlation_id, deployment::STATUS_NO_QUORUM);
+        }
+
+        self.is_transaction = false;
+        self.is_transaction_master = false;
+        self.transaction_approvals = 0;
+
+        Ok(())
+    }
+
+    // Plugin manager tells the client, whether deployment has changed
+    fn reply_deployment(&self, correlation_id: Option<u64>, status: i32) {
+        let kind = if status == 0 {
+            proto_msg::event::Kind::TransactionSucceeded
+        } else {
+            proto_msg::event::Kind::TransactionFailed
+        };
+
+        let event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: Some(proto_msg::event::Dest::PluginMan as i32),
+            kind: kind as i32,
+            data: vec![status.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id
+        };
+
+        self.bus.send(event);
+    }
+
+    // Placement and package are stored next to each other
+    fn parse_deployment(data: &[Vec<u8>], index: usize) -> Result<Deployment, NodeError> {
+        let placement = String::from_utf8_lossy(event::get_field(data, index)?).to_string();
+        let placement = Placement::parse(&placement)
+            .ok_or(NodeError::UnexpectedEvent("invalid placement"))?;
+        let package = event::get_field(data, index + 1)?.to_vec();
+
+        Ok(Deployment { placement, package })
+    }
+
+    // Starts deployed plugins, that belong to this node, and stops the rest
+    fn reconcile_plugins(&mut self) {
+        let mut members = self.nodes.clone();
+        members.push(self.node_id);
+
+        for (name, deployment) in self.deployments.iter() {
+            let is_placed = deployment.placement.places(name, self.node_id, &members);
+            let is_running = self.running_plugins.contains(name);
+
+            if is_placed && !is_running {
+                log::info!("Starting deployed plugin `{}`", String::from_utf8_lossy(name));
+
+                self.running_plugins.insert(name.clone());
+                self.send_plugin_command(proto_msg::event::Kind::StartPlugin, vec![name.clone(), deployment.package.clone()]);
+            } else if !is_placed && is_running {
+                log::info!("Stopping deployed plugin `{}`, it's placed elsewhere", String::from_utf8_lossy(name));
+
+                self.running_plugins.remove(name);
+                self.send_plugin_command(proto_msg::event::Kind::StopPlugin, vec![name.clone()]);
+            }
+        }
+
+        let removed: Vec<Vec<u8>> = self.running_plugins.iter()
+            .filter(|name| !self.deployments.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            log::info!("Stopping removed plugin `{}`", String::from_utf8_lossy(&name));
+
+            self.running_plugins.remove(&name);
+            self.send_plugin_command(proto_msg::event::Kind::StopPlugin, vec![name]);
+        }
+    }
+
+    fn send_plugin_command(&self, kind: proto_msg::event::Kind, data: Vec<Vec<u8>>) {
+        let event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: Some(proto_msg::event::Dest::PluginMan as i32),
+            kind: kind as i32,
+            data,
+            meta: vec![],
+            correlation_id: None
+        };
+
+        self.bus.send(event);
+    }
 }
 
 impl From<FSMError> for NodeError {
-    fn from(_: FSMError) -> Self {
+    fn from(error: FSMError) -> Self {
+        log::error!("State machine failed: {}", error);
         NodeError::InternalError
     }
 }
+
+impl From<FieldError> for NodeError {
+    fn from(error: FieldError) -> Self {
+        NodeError::MalformedEvent(error)
+    }
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::MalformedEvent(error) => write!(f, "malformed event: {}", error),
+            NodeError::UnexpectedEvent(reason) => write!(f, "unexpected event: {}", reason),
+            NodeError::InternalError => write!(f, "internal error")
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::VecDeque,
+    fmt, fs, io,
+    io::Write,
+    path::PathBuf
+};
+
+// Output of a plugin. Last lines are kept in memory, so clients can read them,
+// and everything is appended to the plugin's log file, which is rotated once it grows too big.
+// Every line gets a sequence number, clients follow the output by asking for lines after the last one they've got
+
+// Longer lines are split, so plugin can't make the manager buffer without limit
+pub const MAX_LINE_LENGTH: usize = 4096;
+
+// Log file is moved to `<name>.log.1`, when it grows larger than this
+const MAX_LOG_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogStream {
+    Stdout,
+    Stderr
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub seq: u64,
+    pub stream: LogStream,
+    pub line: Vec<u8>
+}
+
+pub struct PluginLogs {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+    next_seq: u64,
+    // Unfinished lines of stdout and stderr
+    partial: [Vec<u8>; 2],
+    path: Option<PathBuf>,
+    file: Option<fs::File>,
+    file_size: u64
+}
+
+impl PluginLogs {
+    // Lines are written to the file, if path is given
+    pub fn new(capacity: usize, path: Option<PathBuf>) -> Self {
+        let mut logs = Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            next_seq: 0,
+            partial: [vec![], vec![]],
+            path,
+            file: None,
+            file_size: 0
+        };
+
+        if let Err(error) = logs.open_file() {
+            log::warn!("Couldn't open plugin's log file: {}", error);
+        }
+
+        logs
+    }
+
+    // Takes whatever plugin has written, unfinished line waits for the rest
+    pub fn push(&mut self, stream: LogStream, mut bytes: &[u8]) {
+        while let Some(end) = bytes.iter().position(|byte| *byte == b'\n') {
+            self.extend_partial(stream, &bytes[..end]);
+            self.finish(stream);
+            bytes = &bytes[end + 1..];
+        }
+
+        self.extend_partial(stream, bytes);
+    }
+
+    // Stream is closed, its unfinished line is the last one
+    pub fn close(&mut self, stream: LogStream) {
+        if !self.partial[stream as usize].is_empty() {
+            self.finish(stream);
+        }
+    }
+
+    // Last `count` lines
+    pub fn tail(&self, count: usize) -> impl Iterator<Item = &LogLine> {
+        self.lines.iter().skip(self.lines.len().saturating_sub(count))
+    }
+
+    // Lines starting with the sequence number, lines, that are already dropped, are skipped
+    pub fn since(&self, seq: u64) -> impl Iterator<Item = &LogLine> {
+        self.lines.iter().filter(move |line| line.seq >= seq)
+    }
+
+    // Sequence number of the next line
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    fn finish(&mut self, stream: LogStream) {
+        let line = std::mem::take(&mut self.partial[stream as usize]);
+        self.add_line(stream, line);
+    }
+
+    fn extend_partial(&mut self, stream: LogStream, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let partial = &mut self.partial[stream as usize];
+            let taken = bytes.len().min(MAX_LINE_LENGTH - partial.len());
+            partial.extend_from_slice(&bytes[..taken]);
+            bytes = &bytes[taken..];
+
+            if self.partial[stream as usize].len() == MAX_LINE_LENGTH {
+                self.finish(stream);
+            }
+        }
+    }
+
+    fn add_line(&mut self, stream: LogStream, line: Vec<u8>) {
+        if let Err(error) = self.write_to_file(stream, &line) {
+            log::warn!("Couldn't write plugin's log file: {}", error);
+            self.file = None;
+            self.file_size = 0;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(LogLine { seq: self.next_seq, stream, line });
+        self.next_seq += 1;
+    }
+
+    fn write_to_file(&mut self, stream: LogStream, line: &[u8]) -> io::Result<()> {
+        if self.file_size >= MAX_LOG_FILE_SIZE {
+            self.rotate()?;
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(())
+        };
+
+        let prefix = format!("{}: ", stream);
+        file.write_all(prefix.as_bytes())?;
+        file.write_all(line)?;
+        file.write_all(b"\n")?;
+        self.file_size += (prefix.len() + line.len() + 1) as u64;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        let mut rotated = path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(path, rotated)?;
+
+        self.open_file()
+    }
+
+    fn open_file(&mut self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        self.file_size = file.metadata()?.len();
+        self.file = Some(file);
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for LogStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogStream::Stdout => write!(f, "stdout"),
+            LogStream::Stderr => write!(f, "stderr")
+        }
+    }
+}
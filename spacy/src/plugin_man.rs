@@ -1,188 +1,650 @@
 use std::{
-    collections::HashMap,
-    sync::mpsc,
-    process::Command,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    fmt, fs, io, thread, time,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
     net::{TcpListener, TcpStream, Shutdown},
-    os::unix::prelude::AsRawFd,
-    io::Write
+    os::unix::prelude::AsRawFd
 };
+use mio::unix::pipe;
 use common::{
-    fsm::{FSM, FSMError},
-    event::{proto_msg, self}, utils
+    bus::{Bus, Inbox, QueueConfig},
+    codec::{Connection, Received},
+    fsm::{EventQueueConfig, Fsm, FSMError, Overflow, Priority, State},
+    event::{proto_msg, self, FieldError},
+    auth,
+    package::{self, Capability, Manifest, Runtime},
+    plugin_protocol,
+    reactor::Reactor,
+    requests::Requests
 };
-use nix::sys::{
-    select::{select, FdSet},
-    time::{TimeVal, TimeValLike}
+use crate::{
+    acl::{Acl, Action},
+    config::Config,
+    deployment::{self, Placement},
+    plugin_logs::{LogStream, PluginLogs},
+    sandbox::{self, Sandbox},
+    supervision::{PluginStatus, Supervision},
+    wasm_plugin::{WasmError, WasmPlugin, WasmRuntime}
 };
 
 pub struct PluginMan {
-    fsm: FSM,
-    event_channel_tx: mpsc::Sender<proto_msg::Event>,
-    event_channel_rx: mpsc::Receiver<proto_msg::Event>,
-    listener: TcpListener,
+    fsm: Fsm<PluginManState, proto_msg::Event>,
+    inbox: Inbox,
+    reactor: Reactor,
+    listener: Option<TcpListener>,
     plugins: HashMap<u32, i32>,
     plugins_names: HashMap<Vec<u8>, u32>,
-    plugins_streams: HashMap<i32, TcpStream>,
-    plugins_processes: HashMap<u32, std::process::Child>,
+    plugins_streams: HashMap<i32, Connection<TcpStream>>,
+    plugins_processes: HashMap<u32, Child>,
+    // Manifests of running plugins by their fds
+    plugins_manifests: HashMap<i32, Manifest>,
+    // Plugins, that were started, because they are deployed to the cluster
+    deployed_plugins: HashSet<Vec<u8>>,
+    // Every installed plugin by its name, whether it runs or not
+    plugins_supervision: HashMap<Vec<u8>, Supervision>,
+    // Output of plugins by their names, it outlives plugins' restarts
+    plugins_logs: HashMap<Vec<u8>, PluginLogs>,
+    // Plugins' stdout and stderr by their fds
+    plugins_outputs: HashMap<i32, PluginOutput>,
+    // Plugins, that are started, but haven't connected yet, by their names
+    launches: HashMap<Vec<u8>, Launch>,
+    // Accepted connections, that haven't presented a launch token yet, by their fds
+    unidentified: HashMap<i32, UnidentifiedPlugin>,
+    // Previous versions of upgraded plugins by their fds, they answer what they were asked
+    // before the upgrade, new versions take everything else
+    draining: HashMap<i32, Draining>,
+    // Processes of previous versions, that have drained and are told to stop
+    retired: Vec<RetiredPlugin>,
+    // Plugins, that run inside the manager, by ids, that take place of fds. Ids are negative,
+    // so they never match fds of connections
+    wasm_plugins: HashMap<i32, WasmPlugin>,
+    wasm_plugins_names: HashMap<Vec<u8>, i32>,
+    next_wasm_id: i32,
+    wasm_runtime: WasmRuntime,
+    // Clients' requests, that were passed to plugins, by ids given by the server
+    clients_requests: Requests<ClientRequest>,
+    // Plugins' and clients' requests, that were passed to the node
+    node_requests: Requests<NodeRequest>,
+    acl: Option<Arc<Acl>>,
+    plugins_dir: PathBuf,
+    plugin_sdk_dir: PathBuf,
+    plugin_logs_dir: PathBuf,
+    sandbox: Sandbox,
+
+    bus: Bus
+}
+
+// Pipe, that plugin writes its output to
+struct PluginOutput {
+    name: Vec<u8>,
+    stream: LogStream,
+    pipe: pipe::Receiver
+}
+
+// Plugin's process, that is started, but hasn't connected back yet
+struct Launch {
+    manifest: Manifest,
+    child: Child,
+    // Plugin presents it, when it connects, so other processes can't pose as the plugin
+    token: String,
+    started_at: time::Instant,
+    origin: LaunchOrigin
+}
+
+// Who waits for the plugin to start
+#[derive(Clone, Copy)]
+enum LaunchOrigin {
+    // Client's `new_plugin` with the id, that server has given to it
+    Client(Option<u64>),
+    // Node has placed the deployed plugin on this node
+    Deployment,
+    // Supervision restarts the plugin
+    Restart,
+    // Client's `upgrade_plugin`, previous version runs, until the new one connects
+    Upgrade(Option<u64>)
+}
+
+// Connection, that hasn't told, which plugin it is
+struct UnidentifiedPlugin {
+    connection: Connection<TcpStream>,
+    accepted_at: time::Instant
+}
+
+// Previous version of the upgraded plugin
+struct Draining {
+    name: Vec<u8>,
+    // WASM plugin has no process
+    process: Option<u32>,
+    started_at: time::Instant
+}
+
+// Previous version's process, which connection is closed
+struct RetiredPlugin {
+    name: Vec<u8>,
+    child: Child,
+    stopped_at: time::Instant
+}
+
+// Client's request, that waits for plugin's response
+struct ClientRequest {
+    // Plugin, that may answer it
+    fd: i32,
+    // Request is answered once for every event, that was passed to the plugin
+    responses_left: usize,
+    // Responses, that are queued already, are never dropped, ones beyond those expected are
+    responses_queued: usize
+}
 
-    main_event_channel_tx: mpsc::Sender<proto_msg::Event>
+// Request, that waits for node's response
+enum NodeRequest {
+    // Plugin's one with the id, that plugin has given to it
+    Plugin { fd: i32, id: Option<u64> },
+    // Client's deployment with the id, that server has given to it
+    Deployment(Option<u64>)
 }
 
+// How long requests wait for responses
+const REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(60);
+
+// How long plugin may take to connect and present its token, after it's started
+const PLUGIN_STARTUP_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+// Exit code of the Python plugin's process, which dependencies can't be imported
+const MISSING_DEPENDENCY_EXIT_CODE: i32 = 86;
+
+// How long plugins may take to exit after their connections are closed
+const PLUGIN_STOP_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+// How often processes of disconnected plugins are checked for exit
+const REAP_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+// Lines of every plugin's output, that are kept in memory
+const PLUGIN_LOGS_CAPACITY: usize = 1000;
+// Lines, that client gets, if it doesn't say how many
+const DEFAULT_LOGS_TAIL: usize = 100;
+
+// Events, that the plugin manager keeps before dropping plugins' ones
+const EVENT_QUEUE_CAPACITY: usize = 4096;
+
+// Status, that client receives, if it's not allowed to perform a request
+const STATUS_PERMISSION_DENIED: i32 = -4;
+// Statuses of `new_plugin`, that client receives, if package can't be deployed
+const STATUS_INVALID_PACKAGE: i32 = -5;
+const STATUS_MISSING_DEPENDENCY: i32 = -6;
+// Statuses of `new_plugin`, that client receives, if plugin is started, but doesn't connect
+const STATUS_STARTUP_TIMEOUT: i32 = -9;
+const STATUS_STARTUP_FAILED: i32 = -10;
+// Status of `upgrade_plugin`, that client receives, if plugin's previous upgrade hasn't finished
+const STATUS_UPGRADE_IN_PROGRESS: i32 = -11;
+// Status, that client receives, if its request is dropped, because the manager's queue is full
+const STATUS_OVERLOADED: i32 = -12;
+// Status of `new_plugin_event`, that client receives, if it carries no events for the plugin
+const STATUS_NO_EVENTS: i32 = -13;
+
+// Directory inside `plugins_dir`, where packages are unpacked before their names are known
+const INCOMING_DIR: &str = ".incoming";
+// Directory inside `plugins_dir`, where previous versions of upgraded plugins run from
+const PREVIOUS_DIR: &str = ".previous";
+
 #[derive(Debug)]
 pub enum PluginManError {
+    // Event doesn't carry what its kind requires
+    MalformedEvent(FieldError),
+    // Plugin, that event is addressed to, has already disconnected
+    PluginGone(i32),
+    // Response doesn't match any request, that is waiting for it
+    UnknownRequest(Option<u64>),
+    // Plugin's manifest doesn't grant what plugin has asked for
+    MissingCapability(i32, Capability),
+    // Response, that only the node gives, came from a client
+    NotFromNode(i32),
+    Io(io::Error),
+    // FSM was asked for an impossible transition
     InternalError
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PluginManState {
+    Init,
+    WaitEvent,
+    HandleEvent,
+    HandleIncomingEvent,
+    HandleOutcomingEvent,
+    Stop
+}
+
+impl State for PluginManState {
+    fn name(&self) -> &'static str {
+        match self {
+            PluginManState::Init => "init",
+            PluginManState::WaitEvent => "wait_event",
+            PluginManState::HandleEvent => "handle_event",
+            PluginManState::HandleIncomingEvent => "handle_incoming_event",
+            PluginManState::HandleOutcomingEvent => "handle_outcoming_event",
+            PluginManState::Stop => "stop"
+        }
+    }
+}
+
 impl PluginMan {
-    // 0 - initialization
-    // 1 - waiting for events
-    // 2 - handle event
-    // 3 - handle incoming event
-    // 4 - handle outcoming event
-    // 5 - stop
-
-    pub fn new(main_event_channel_tx: mpsc::Sender<proto_msg::Event>) -> Self {
-        let fsm = FSM::new(0, HashMap::from([
-            (0, vec![1, 5]),
-            (1, vec![2, 5]),
-            (2, vec![1, 3, 4, 5]),
-            (3, vec![2, 5]),
-            (4, vec![2, 5]),
-            (5, vec![])
-        ]));
-
-        // Creating main event communication channel
-        let (event_channel_tx, event_channel_rx) = mpsc::channel();
-
-        // Creating listener for communication with plugins
-        let listener = TcpListener::bind(("127.0.0.1", 32002)).unwrap();
+    pub fn new(bus: Bus, config: &Config) -> Self {
+        let mut fsm = Fsm::new("plugin_man", PluginManState::Init, &[
+            (PluginManState::Init, &[PluginManState::WaitEvent, PluginManState::Stop]),
+            (PluginManState::WaitEvent, &[PluginManState::HandleEvent, PluginManState::Stop]),
+            (PluginManState::HandleEvent, &[PluginManState::WaitEvent, PluginManState::HandleIncomingEvent, PluginManState::HandleOutcomingEvent, PluginManState::Stop]),
+            (PluginManState::HandleIncomingEvent, &[PluginManState::HandleEvent, PluginManState::Stop]),
+            (PluginManState::HandleOutcomingEvent, &[PluginManState::HandleEvent, PluginManState::Stop]),
+            (PluginManState::Stop, &[])
+        ]);
+        fsm.guard(PluginManState::HandleEvent, PluginManState::HandleIncomingEvent, "incoming", |event| event::has_dir(event, proto_msg::event::Dir::Incoming));
+        fsm.guard(PluginManState::HandleEvent, PluginManState::HandleOutcomingEvent, "outcoming", |event| event::has_dir(event, proto_msg::event::Dir::Outcoming));
+        fsm.set_queue_config(EventQueueConfig { capacity: EVENT_QUEUE_CAPACITY, overflow: Overflow::DropLowest });
+
+        // Registering on the bus, arriving events wake the plugin manager up
+        let reactor = Reactor::new().unwrap();
+        let inbox = bus.register(proto_msg::event::Dest::PluginMan, QueueConfig::default(), reactor.notifier()).unwrap();
 
         Self {
             fsm,
-            event_channel_tx,
-            event_channel_rx,
-            listener,
+            inbox,
+            reactor,
+            listener: None,
             plugins: HashMap::new(),
             plugins_names: HashMap::new(),
             plugins_streams: HashMap::new(),
             plugins_processes: HashMap::new(),
-
-            main_event_channel_tx
+            plugins_manifests: HashMap::new(),
+            deployed_plugins: HashSet::new(),
+            plugins_supervision: HashMap::new(),
+            plugins_logs: HashMap::new(),
+            plugins_outputs: HashMap::new(),
+            launches: HashMap::new(),
+            unidentified: HashMap::new(),
+            draining: HashMap::new(),
+            retired: Vec::new(),
+            wasm_plugins: HashMap::new(),
+            wasm_plugins_names: HashMap::new(),
+            next_wasm_id: -1,
+            wasm_runtime: WasmRuntime::new(config.plugin_wasm.clone()),
+            clients_requests: Requests::new(REQUEST_TIMEOUT),
+            node_requests: Requests::new(REQUEST_TIMEOUT),
+            acl: config.acl.clone(),
+            plugins_dir: config.plugins_dir.clone(),
+            plugin_sdk_dir: config.plugin_sdk_dir.clone(),
+            plugin_logs_dir: config.plugin_logs_dir.clone(),
+            sandbox: config.plugin_sandbox.clone(),
+
+            bus
         }
     }
 
-    pub fn start(&mut self) -> mpsc::Sender<proto_msg::Event> {
-        let event_channel_tx_clone = self.event_channel_tx.clone();
-
-        event_channel_tx_clone
+    pub fn start(mut self) -> thread::JoinHandle<Result<(), PluginManError>> {
+        // Starting FSM loop
+        thread::spawn(move || loop {
+            match self.fsm.state() {
+                PluginManState::Init => self.init()?,
+                PluginManState::WaitEvent => self.wait_event()?,
+                PluginManState::HandleEvent => self.handle_event()?,
+                PluginManState::HandleIncomingEvent => self.handle_incoming_event()?,
+                PluginManState::HandleOutcomingEvent => self.handle_outcoming_event()?,
+                PluginManState::Stop => {
+                    self.stop()?;
+                    return Ok(());
+                }
+            }
+        })
     }
 
-    pub fn step(&mut self) -> Result<(), PluginManError> {
-        match self.fsm.state {
-            0 => self.init(),
-            1 => self.wait_event(),
-            2 => self.handle_event(),
-            3 => self.handle_incoming_event(),
-            4 => self.handle_outcoming_event(),
-            5 => {
-                self.stop()?;
-                return Ok(());
+    // Handles a single event right away, as if it was taken from the queue.
+    // Plugin manager, that wasn't started, doesn't accept plugins
+    pub fn process_event(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        if self.fsm.state() == PluginManState::Init {
+            self.fsm.transition(PluginManState::WaitEvent)?;
+        }
+        if self.fsm.state() == PluginManState::WaitEvent {
+            self.fsm.transition(PluginManState::HandleEvent)?;
+        }
+
+        let priority = Self::get_priority(&event);
+        self.queue_event(event, priority);
+
+        loop {
+            match self.fsm.state() {
+                PluginManState::WaitEvent | PluginManState::Stop => return Ok(()),
+                PluginManState::HandleEvent => self.handle_event()?,
+                PluginManState::HandleIncomingEvent => self.handle_incoming_event()?,
+                PluginManState::HandleOutcomingEvent => self.handle_outcoming_event()?,
+                _ => unreachable!()
             }
-            _ => unreachable!()
         }
     }
 
     fn init(&mut self) -> Result<(), PluginManError> {
         log::debug!("State `init`");
 
-        self.fsm.transition(1)?;
+        // Creating listener for communication with plugins, they are accepted, when they connect
+        let listener = TcpListener::bind(plugin_protocol::DEFAULT_PLUGIN_MAN_ADDR)?;
+        listener.set_nonblocking(true)?;
+        self.reactor.register(listener.as_raw_fd())?;
+        self.listener = Some(listener);
+
+        self.fsm.transition(PluginManState::WaitEvent)?;
         Ok(())
     }
 
     fn wait_event(&mut self) -> Result<(), PluginManError> {
         // log::debug!("State `wait_event`");
 
-        // Checking if there anything to read
-        match self.event_channel_rx.try_recv() {
-            Ok(event) => {
-                self.fsm.push_event(event);
-            },
-            Err(_) => {}
-        }
+        self.expire_requests();
+        self.supervise();
+        self.run_wasm_plugins();
 
-        let mut readfds = FdSet::new();
-        for fd in self.plugins.values() {
-            readfds.insert(*fd);
+        // Taking everything, that was sent to the plugin manager
+        while let Some(event) = self.inbox.try_recv() {
+            let priority = Self::get_priority(&event);
+            self.queue_event(event, priority);
         }
 
-        let mut timeout = TimeVal::milliseconds(1);
-        let result = select(None, &mut readfds, None, None, &mut timeout);
-        if result.is_err() {
+        if !self.fsm.is_queue_empty() {
+            self.fsm.transition(PluginManState::HandleEvent)?;
             return Ok(());
         }
 
-        for fd in readfds.fds(None) {
-            let stream = self.plugins_streams.get_mut(&fd).unwrap();
+        // Sleeping until plugins' sockets are ready, some event is sent, request expires
+        // or some plugin has to be restarted
+        let timeout = self.clients_requests.next_timeout().into_iter()
+            .chain(self.node_requests.next_timeout())
+            .chain(self.next_supervision_timeout())
+            .min();
+        let fds = match self.reactor.wait(timeout) {
+            Ok(fds) => fds,
+            Err(error) => {
+                log::warn!("Waiting for events failed: {}", error);
+                vec![]
+            }
+        };
+
+        for fd in fds {
+            if self.listener.as_ref().is_some_and(|listener| listener.as_raw_fd() == fd) {
+                self.accept_plugins();
+                continue;
+            }
 
-            // Reading message until read
-            let events = utils::read_events(stream).unwrap();
+            if self.unidentified.contains_key(&fd) {
+                self.identify_plugin(fd);
+                continue;
+            }
 
-            // If plugin manager disconnected
-            if events.len() == 0 {
-                stream.shutdown(Shutdown::Both).unwrap();
-            } else {
-                for event in events {
-                    // Adding plugin's id to event's meta information
-                    let mut event_meta = event.meta;
-                    event_meta.insert(0, fd.to_ne_bytes().to_vec());
-
-                    let event_with_meta = proto_msg::Event {
-                        dir: event.dir,
-                        dest: event.dest,
-                        kind: event.kind,
-                        data: event.data,
-                        meta: event_meta
-                    };
-
-                    self.fsm.push_event(event_with_meta);
+            if self.plugins_outputs.contains_key(&fd) {
+                self.read_plugin_output(fd);
+                continue;
+            }
+
+            let connection = match self.plugins_streams.get_mut(&fd) {
+                Some(connection) => connection,
+                None => continue
+            };
+
+            // Socket might be ready for writing what it didn't accept previously
+            if connection.has_pending_writes() {
+                if let Err(error) = connection.flush() {
+                    log::debug!("Couldn't flush plugin's connection {}: {}", fd, error);
                 }
             }
+
+            // Reading everything, that is available
+            let received = match connection.read_events() {
+                Ok(received) => received,
+                Err(error) => {
+                    log::warn!("Dropping plugin's connection {}: {}", fd, error);
+                    Received { events: vec![], closed: true }
+                }
+            };
+
+            self.push_plugin_events(fd, received.events);
+            if received.closed {
+                self.plugin_disconnected(fd);
+            }
         }
 
-        self.fsm.transition(2)?;
+        self.fsm.transition(PluginManState::HandleEvent)?;
         Ok(())
     }
 
+    fn push_plugin_events(&mut self, fd: i32, events: Vec<proto_msg::Event>) {
+        for event in events {
+            // Adding plugin's id to event's meta information
+            let mut event_meta = event.meta;
+            event_meta.insert(0, fd.to_ne_bytes().to_vec());
+
+            // Plugins can only send events out, whatever direction they've set
+            let event_with_meta = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Outcoming as i32),
+                dest: event.dest,
+                kind: event.kind,
+                data: event.data,
+                meta: event_meta,
+                correlation_id: event.correlation_id
+            };
+
+            // Response to the client, that waits for it, is queued whatever the load is.
+            // Their number is bounded by the number of events, that were passed to plugins
+            let is_awaited_response = event_with_meta.kind == proto_msg::event::Kind::RespondClient as i32
+                && event_with_meta.correlation_id
+                    .and_then(|id| self.clients_requests.get_mut(id))
+                    .filter(|request| request.fd == fd && request.responses_queued < request.responses_left)
+                    .map(|request| request.responses_queued += 1)
+                    .is_some();
+
+            if is_awaited_response {
+                self.fsm.push_reliable_event(event_with_meta, Priority::Plugin);
+            } else {
+                self.queue_event(event_with_meta, Priority::Plugin);
+            }
+        }
+    }
+
+    // Event, that is dropped to keep the queue in its capacity, is answered, so its requester
+    // doesn't wait for the timeout
+    fn queue_event(&mut self, event: proto_msg::Event, priority: Priority) {
+        let dropped = match self.fsm.push_event(event, priority) {
+            Some(dropped) => dropped,
+            None => return
+        };
+
+        let is_outcoming = dropped.dir == Some(proto_msg::event::Dir::Outcoming as i32);
+
+        if is_outcoming && (dropped.kind == proto_msg::event::Kind::UpdateSharedMemory as i32
+            || dropped.kind == proto_msg::event::Kind::GetFromSharedMemory as i32) {
+            let fd = match event::get_i32(&dropped.meta, 0) {
+                Ok(fd) => fd,
+                Err(_) => return
+            };
+
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Incoming as i32),
+                dest: None,
+                kind: proto_msg::event::Kind::RequestTimedOut as i32,
+                data: vec![],
+                meta: vec![],
+                correlation_id: dropped.correlation_id
+            };
+
+            // Plugin might have gone already
+            let _ = self.send_to_plugin(fd, event);
+        }
+
+        // Client's requests come from the server with ids, node's answers are left to time out
+        else if !is_outcoming && dropped.correlation_id.is_some()
+            && Self::get_priority(&dropped) == Priority::Client {
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Outcoming as i32),
+                dest: Some(proto_msg::event::Dest::Server as i32),
+                kind: proto_msg::event::Kind::RespondClient as i32,
+                data: vec![STATUS_OVERLOADED.to_ne_bytes().to_vec()],
+                meta: vec![],
+                correlation_id: dropped.correlation_id
+            };
+
+            self.bus.send(event);
+        }
+    }
+
+    // Plugin's process is reaped, when it exits
+    fn plugin_disconnected(&mut self, fd: i32) {
+        log::info!("Plugin with fd {} disconnected", fd);
+
+        self.close_plugin_connection(fd);
+
+        let now = time::Instant::now();
+        let name = self.plugins.iter()
+            .find(|(_, plugin_fd)| **plugin_fd == fd)
+            .and_then(|(id, _)| self.plugins_names.iter().find(|(_, child_id)| *child_id == id))
+            .map(|(name, _)| name.clone());
+        if let Some(supervision) = name.and_then(|name| self.plugins_supervision.get_mut(&name)) {
+            supervision.disconnected(now);
+        }
+    }
+
+    // Connections are taken as they come, which plugin it is, is known after it presents its token
+    fn accept_plugins(&mut self) {
+        let listener = match &self.listener {
+            Some(listener) => listener,
+            None => return
+        };
+
+        loop {
+            let stream = match listener.accept() {
+                Ok((stream, _addr)) => stream,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    log::warn!("Error occured while accepting plugin's connection: {}", error);
+                    break;
+                }
+            };
+
+            // Plugin must not be able to block the manager
+            let registered = stream.set_nonblocking(true)
+                .and_then(|_| self.reactor.register(stream.as_raw_fd()));
+            if let Err(error) = registered {
+                log::warn!("Error occured while accepting plugin's connection: {}", error);
+                continue;
+            }
+
+            log::debug!("Accepted plugin's connection {}", stream.as_raw_fd());
+            self.unidentified.insert(stream.as_raw_fd(), UnidentifiedPlugin {
+                connection: Connection::new(stream),
+                accepted_at: time::Instant::now()
+            });
+        }
+    }
+
+    // First event of the plugin's connection must be the handshake with the token of some launch
+    // and the supported protocol version, otherwise connection is dropped
+    fn identify_plugin(&mut self, fd: i32) {
+        let mut unidentified = match self.unidentified.remove(&fd) {
+            Some(unidentified) => unidentified,
+            None => return
+        };
+
+        let mut received = match unidentified.connection.read_events() {
+            Ok(received) => received,
+            Err(error) => {
+                log::warn!("Dropping plugin's connection {}: {}", fd, error);
+                self.drop_unidentified(fd, unidentified);
+                return;
+            }
+        };
+
+        if received.events.is_empty() {
+            if received.closed {
+                log::warn!("Plugin's connection {} was closed before the handshake", fd);
+                self.drop_unidentified(fd, unidentified);
+            } else {
+                self.unidentified.insert(fd, unidentified);
+            }
+            return;
+        }
+
+        let handshake = received.events.remove(0);
+        let token = match plugin_protocol::parse_handshake(&handshake) {
+            Ok((token, _version)) => token,
+            Err(error) => {
+                log::warn!("Dropping plugin's connection {}: {}", fd, error);
+                self.drop_unidentified(fd, unidentified);
+                return;
+            }
+        };
+        let name = self.launches.iter()
+            .find(|(_, launch)| launch.token.as_bytes() == token)
+            .map(|(name, _)| name.clone());
+        let launch = match name.and_then(|name| self.launches.remove(&name)) {
+            Some(launch) => launch,
+            None => {
+                log::warn!("Connection {} didn't present a token of any started plugin", fd);
+                self.drop_unidentified(fd, unidentified);
+                return;
+            }
+        };
+
+        // Clients' events go to the new version from now on
+        let origin = launch.origin;
+        if let LaunchOrigin::Upgrade(_) = origin {
+            self.retire_plugin(&launch.manifest);
+        }
+        self.register_plugin(launch.manifest, launch.child, unidentified.connection);
+        if let LaunchOrigin::Client(correlation_id) | LaunchOrigin::Upgrade(correlation_id) = origin {
+            self.respond_status(correlation_id, 0);
+        }
+
+        // Plugin might have sent something right after the handshake
+        self.push_plugin_events(fd, received.events);
+        if received.closed {
+            self.plugin_disconnected(fd);
+        }
+    }
+
+    fn drop_unidentified(&mut self, fd: i32, unidentified: UnidentifiedPlugin) {
+        let _ = self.reactor.deregister(fd);
+        let _ = unidentified.connection.get_ref().shutdown(Shutdown::Both);
+    }
+
     fn handle_event(&mut self) -> Result<(), PluginManError> {
         // log::debug!("State `handle_event`");
 
-        let event = match self.fsm.pop_front_event() {
-            Some(event) => event,
+        let event_direction = match self.fsm.front_event() {
+            Some(event) => event.dir,
             None => {
-                self.fsm.transition(1)?;
+                self.fsm.transition(PluginManState::WaitEvent)?;
                 return Ok(());
             }
         };
-        let event_direction = event.dir;
-        self.fsm.push_front_event(event);
 
-        // Handling event based on it's direction
+        // Handling event based on it's direction, events without one are dropped
         if let Some(dir) = event_direction {
             if dir == proto_msg::event::Dir::Incoming as i32 {
                 log::debug!("Received `incoming` event");
 
-                self.fsm.transition(3)?;
+                self.fsm.transition(PluginManState::HandleIncomingEvent)?;
             }
 
             else if dir == proto_msg::event::Dir::Outcoming as i32 {
                 log::debug!("Received `outcoming` event");
 
-                self.fsm.transition(4)?;
+                self.fsm.transition(PluginManState::HandleOutcomingEvent)?;
             }
 
             else {
                 log::warn!("Received event with the unknown direction");
+                self.fsm.pop_front_event();
             }
+        } else {
+            log::warn!("Received event without direction");
+            self.fsm.pop_front_event();
         }
 
         Ok(())
@@ -191,356 +653,1441 @@ impl PluginMan {
     fn handle_incoming_event(&mut self) -> Result<(), PluginManError> {
         log::debug!("State `handle_incoming_event`");
 
-        let event = self.fsm.pop_front_event().unwrap();
+        let event = self.fsm.pop_front_event().ok_or(PluginManError::InternalError)?;
+        let kind = event.kind;
 
-        if event.kind == proto_msg::event::Kind::NewPlugin as i32 {
-            self.handle_new_plugin(event)?;
+        let result = if event.kind == proto_msg::event::Kind::NewPlugin as i32 {
+            self.handle_new_plugin(event)
         }
 
         else if event.kind == proto_msg::event::Kind::RemovePlugin as i32 {
-            self.handle_remove_plugin(event)?;
+            self.handle_remove_plugin(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::UpgradePlugin as i32 {
+            self.handle_upgrade_plugin(event)
         }
 
         else if event.kind == proto_msg::event::Kind::GetPluginList as i32 {
-            self.handle_get_plugin_list(event)?;
+            self.handle_get_plugin_list(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::GetPluginStatus as i32 {
+            self.handle_get_plugin_status(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::GetPluginLogs as i32 {
+            self.handle_get_plugin_logs(event)
         }
 
         else if event.kind == proto_msg::event::Kind::NewPluginEvent as i32 {
-            self.handle_new_plugin_event(event)?;
+            self.handle_new_plugin_event(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32
+            || event.kind == proto_msg::event::Kind::TransactionSucceeded as i32
+            || event.kind == proto_msg::event::Kind::TransactionFailed as i32 {
+            self.handle_node_response(event)
         }
 
-        else if event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32 {
-            self.handle_get_from_shared_memory_incoming(event)?;
+        else if event.kind == proto_msg::event::Kind::StartPlugin as i32 {
+            self.handle_start_plugin(event)
         }
 
-        else if event.kind == proto_msg::event::Kind::TransactionSucceeded as i32 {
-            self.handle_transaction_succeeded(event)?;
+        else if event.kind == proto_msg::event::Kind::StopPlugin as i32 {
+            self.handle_stop_plugin(event)
         }
 
-        else if event.kind == proto_msg::event::Kind::TransactionFailed as i32 {
-            self.handle_transaction_failed(event)?;
+        else if event.kind == proto_msg::event::Kind::Shutdown as i32 {
+            self.fsm.transition(PluginManState::Stop)?;
+            return Ok(());
         }
 
         else {
             log::warn!("Received event with unknown kind: {}", event.kind);
-        }
+            Ok(())
+        };
 
-        self.fsm.transition(2)?;
+        Self::recover(kind, result)?;
+
+        self.fsm.transition(PluginManState::HandleEvent)?;
         Ok(())
     }
 
     fn handle_outcoming_event(&mut self) -> Result<(), PluginManError> {
         log::debug!("State `handle_outcoming_event`");
 
-        let event = self.fsm.pop_front_event().unwrap();
-
-        if event.kind == proto_msg::event::Kind::UpdateSharedMemory as i32 {
-            self.handle_update_shared_memory(event)?;
-        }
+        let event = self.fsm.pop_front_event().ok_or(PluginManError::InternalError)?;
+        let kind = event.kind;
 
-        else if event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32 {
-            self.handle_get_from_shared_memory_outcoming(event)?;
+        let result = if event.kind == proto_msg::event::Kind::UpdateSharedMemory as i32
+            || event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32 {
+            self.handle_plugin_request(event)
         }
 
         else if event.kind == proto_msg::event::Kind::RespondClient as i32 {
-            self.handle_respond_client(event)?;
+            self.handle_respond_client(event)
         }
 
         else {
             log::warn!("Received event with unknown kind: {}", event.kind);
-        }
+            Ok(())
+        };
+
+        Self::recover(kind, result)?;
 
-        self.fsm.transition(2)?;
+        self.fsm.transition(PluginManState::HandleEvent)?;
         Ok(())
     }
 
-    fn stop(&mut self) -> Result<(), PluginManError> {
-        log::debug!("State `stop`");
+    // Node's answers go first, so plugins and clients can't hold transactions' results back
+    fn get_priority(event: &proto_msg::Event) -> Priority {
+        if event.dir == Some(proto_msg::event::Dir::Outcoming as i32) {
+            Priority::Plugin
+        }
 
-        Ok(())
+        else if event.kind == proto_msg::event::Kind::TransactionSucceeded as i32
+            || event.kind == proto_msg::event::Kind::TransactionFailed as i32
+            || event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32
+            || event.kind == proto_msg::event::Kind::StartPlugin as i32
+            || event.kind == proto_msg::event::Kind::StopPlugin as i32
+            || event.kind == proto_msg::event::Kind::Shutdown as i32 {
+            Priority::Control
+        }
+
+        else {
+            Priority::Client
+        }
     }
 
-    fn handle_new_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `new_plugin`");
+    // Event, that can't be handled, is dropped, so a client or a plugin can't take the manager down
+    fn recover(kind: i32, result: Result<(), PluginManError>) -> Result<(), PluginManError> {
+        match result {
+            Err(PluginManError::InternalError) => Err(PluginManError::InternalError),
+            Err(error) => {
+                log::warn!("Dropping event of kind {}: {}", kind, error);
+                Ok(())
+            },
+            Ok(_) => Ok(())
+        }
+    }
 
-        // Startup status variable
-        let mut status: i32 = 0;
+    fn stop(&mut self) -> Result<(), PluginManError> {
+        log::debug!("State `stop`");
 
-        let first_arg = event.data.get(0).unwrap();
-        let second_arg = event.data.get(1).unwrap();
+        for (priority, stats) in self.fsm.queue_stats() {
+            log::debug!("Plugin manager's queue {:?}: {:?}", priority, stats);
+        }
 
-        // Parsing event data
-        let plugin_source = String::from_utf8_lossy(&first_arg).to_string();
-        let plugin_name = second_arg.to_vec();
+        // Closed connection tells plugins to stop
+        for (fd, connection) in self.plugins_streams.drain() {
+            let _ = self.reactor.deregister(fd);
+            let _ = connection.get_ref().shutdown(Shutdown::Both);
+        }
+        let unidentified: Vec<(i32, UnidentifiedPlugin)> = self.unidentified.drain().collect();
+        for (fd, unidentified) in unidentified {
+            self.drop_unidentified(fd, unidentified);
+        }
 
-        if self.plugins_names.contains_key(&plugin_name) {
-            status = -3;
+        // Plugins, that haven't connected yet, can't be told to stop
+        for (_, launch) in self.launches.drain() {
+            Self::kill(launch.child);
+        }
+        // Previous versions are told to stop already
+        for retired in self.retired.drain(..) {
+            Self::kill(retired.child);
+        }
 
-            log::info!("Plugin startup rejected. Name is already in use");
-        } else {
-            // Spawning new thread with the plugin
-            let exec_status = Command::new("python3")
-                .arg("-c")
-                .arg(plugin_source)
-                .spawn();
-
-            // Check if child is started successfully
-            match exec_status {
-                Ok(mut child) => {
-                    // Accept plugin's connection
-                    // TODO: somehow make this call non-blocking (timeout)
-                    match self.listener.accept() {
-                        Ok((stream, _addr)) => {
-                            // Add plugin to local structs
-                            log::info!("New plugin started");
-                            log::debug!("Fd: {}", stream.as_raw_fd());
-                            let child_id = child.id();
-                            self.plugins.insert(child_id, stream.as_raw_fd());
-                            self.plugins_names.insert(plugin_name, child_id);
-                            self.plugins_streams.insert(stream.as_raw_fd(), stream);
-                            self.plugins_processes.insert(child_id, child);
-                        },
-                        Err(err) => {
-                            log::warn!("Error occured while accepting plugin's connection: {}", err);
-                            child.kill().unwrap();
-                            status = -2;
-                        }
-                    };
-                },
-                Err(err) => {
-                    log::warn!("Error occured while starting a plugin: {}", err);
-                    status = -1;
-                }
+        // Giving plugins some time to exit on their own
+        let deadline = time::Instant::now() + PLUGIN_STOP_TIMEOUT;
+        while time::Instant::now() < deadline {
+            self.plugins_processes.retain(|_, child| !matches!(child.try_wait(), Ok(Some(_))));
+            if self.plugins_processes.is_empty() {
+                break;
             }
+
+            thread::sleep(time::Duration::from_millis(10));
         }
 
-        // TODO: Notify client about status
-        let response_event = proto_msg::Event {
-            dir: Some(proto_msg::event::Dir::Outcoming as i32),
-            dest: Some(proto_msg::event::Dest::Server as i32),
-            kind: proto_msg::event::Kind::RespondClient as i32,
-            data: vec![status.to_ne_bytes().to_vec()],
-            meta: event.meta
-        };
+        for (id, mut child) in self.plugins_processes.drain() {
+            log::warn!("Plugin {} didn't stop in time, killing it", id);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        self.plugins.clear();
+        self.plugins_names.clear();
+        self.plugins_manifests.clear();
+        self.wasm_plugins.clear();
+        self.wasm_plugins_names.clear();
+        self.draining.clear();
+        self.deployed_plugins.clear();
+        self.plugins_supervision.clear();
+
+        // Whatever plugins have written before exiting is kept
+        let fds: Vec<i32> = self.plugins_outputs.keys().copied().collect();
+        for fd in fds {
+            self.read_plugin_output(fd);
+        }
+        self.plugins_outputs.clear();
+        self.plugins_logs.clear();
 
-        self.main_event_channel_tx.send(response_event).unwrap();
+        log::info!("Plugins stopped");
 
         Ok(())
     }
 
-    fn handle_remove_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `remove_plugin`");
-
-        // Remove status variable
-        let mut status: i32 = 0;
+    fn handle_new_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `new_plugin`");
 
-        let first_arg = event.data.get(0).unwrap();
-        let plugin_name = first_arg.to_vec();
+        if !self.authorize(&event, Action::Deploy) {
+            return Ok(());
+        }
 
-        if !self.plugins_names.contains_key(&plugin_name) {
-            log::info!("Plugin removal rejected. Plugin with this name doesn't exist");
+        // Parsing event data, plugin is deployed to the cluster, if placement is given
+        let package = event::get_field(&event.data, 0)?;
+        let placement = event.data.get(1)
+            .map(|placement| String::from_utf8_lossy(placement).to_string())
+            .filter(|placement| placement != "local");
+
+        // Startup status variable, client is answered, when plugin connects
+        let status = match placement {
+            None => match self.deploy(package, LaunchOrigin::Client(event.correlation_id)) {
+                Ok(_) => return Ok(()),
+                Err(status) => status
+            },
+            // Node responds, when deployment is replicated
+            Some(placement) => match self.request_deployment(package, &placement, event.correlation_id) {
+                Ok(_) => return Ok(()),
+                Err(status) => status
+            }
+        };
 
-            status = -1;
-        } else {
-            let id = self.plugins_names.remove(&plugin_name).unwrap();
-            let fd = self.plugins.remove(&id).unwrap();
-            let stream = self.plugins_streams.remove(&fd).unwrap();
-            stream.shutdown(Shutdown::Both).unwrap();
-            let mut child = self.plugins_processes.remove(&id).unwrap();
-            match child.kill() {
-                Ok(_) => {},
-                Err(_) => {}
-            };
+        self.respond_status(event.correlation_id, status);
 
-            log::info!("Plugin removed");
-        }
+        Ok(())
+    }
 
-        // TODO: Notify client about status
+    // Response, that carries only the status
+    fn respond_status(&mut self, correlation_id: Option<u64>, status: i32) {
         let response_event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
             dest: Some(proto_msg::event::Dest::Server as i32),
             kind: proto_msg::event::Kind::RespondClient as i32,
             data: vec![status.to_ne_bytes().to_vec()],
-            meta: event.meta
+            meta: vec![],
+            correlation_id
         };
 
-        self.main_event_channel_tx.send(response_event).unwrap();
-
-        Ok(())
+        self.bus.send(response_event);
     }
 
-    fn handle_get_plugin_list(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `get_plugin_list`");
+    // Asks the node to replicate the deployment.
+    // Returns status for the client, if request isn't valid
+    fn request_deployment(&mut self, package: &[u8], placement: &str, correlation_id: Option<u64>) -> Result<(), i32> {
+        if Placement::parse(placement).is_none() {
+            log::info!("Plugin deployment rejected. Invalid placement `{}`", placement);
+            return Err(deployment::STATUS_INVALID_PLACEMENT);
+        }
 
-        let mut data = vec![];
-        for name in self.plugins_names.keys() {
-            data.push(name.clone());
+        let manifest = match package::read_manifest(package) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                log::info!("Plugin deployment rejected. Invalid package: {}", error);
+                return Err(STATUS_INVALID_PACKAGE);
+            }
+        };
+
+        if self.plugins_supervision.contains_key(manifest.name.as_bytes()) && !self.deployed_plugins.contains(manifest.name.as_bytes()) {
+            log::info!("Plugin deployment rejected. Name is already in use");
+            return Err(deployment::STATUS_NAME_IN_USE);
         }
 
-        // TODO: Notify client about status
-        let response_event = proto_msg::Event {
+        let request_id = self.node_requests.insert(NodeRequest::Deployment(correlation_id));
+
+        let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
-            dest: Some(proto_msg::event::Dest::Server as i32),
-            kind: proto_msg::event::Kind::RespondClient as i32,
-            data,
-            meta: event.meta
+            dest: Some(proto_msg::event::Dest::Node as i32),
+            kind: proto_msg::event::Kind::DeployPlugin as i32,
+            data: vec![manifest.name.into_bytes(), placement.as_bytes().to_vec(), package.to_vec()],
+            meta: vec![],
+            correlation_id: Some(request_id)
         };
 
-        self.main_event_channel_tx.send(response_event).unwrap();
+        self.bus.send(event);
 
         Ok(())
     }
 
-    fn handle_new_plugin_event(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `new_plugin_event`");
-
-        let first_arg = event.data.get(0).unwrap();
-        let second_arg = event.data.get(1).unwrap();
+    // Unpacks the package and starts the plugin, it's registered, when it connects.
+    // Returns status for the client, if plugin isn't started
+    fn deploy(&mut self, package: &[u8], origin: LaunchOrigin) -> Result<(), i32> {
+        if self.listener.is_none() {
+            log::warn!("Plugin startup rejected. Plugin manager isn't started");
+            return Err(-2);
+        }
 
-        // Parsing event data
-        let plugin_name = first_arg.to_vec();
-        let (events_to_plugin, _rem) = event::deserialize(second_arg);
+        // Name is known only after the package is unpacked
+        let incoming_dir = self.plugins_dir.join(INCOMING_DIR);
+        let _ = fs::remove_dir_all(&incoming_dir);
 
-        if !self.plugins_names.contains_key(&plugin_name) {
-            let status: i32 = -1;
+        let manifest = match package::unpack(package, &incoming_dir) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                log::info!("Plugin startup rejected. Invalid package: {}", error);
+                let _ = fs::remove_dir_all(&incoming_dir);
+                return Err(STATUS_INVALID_PACKAGE);
+            }
+        };
 
-            // TODO: Notify client about status
-            let response_event = proto_msg::Event {
-                dir: Some(proto_msg::event::Dir::Outcoming as i32),
-                dest: Some(proto_msg::event::Dest::Server as i32),
-                kind: proto_msg::event::Kind::RespondClient as i32,
-                data: vec![status.to_ne_bytes().to_vec()],
-                meta: event.meta
-            };
+        if self.plugins_supervision.contains_key(manifest.name.as_bytes()) {
+            // Running WASM plugin is replaced with the new version in place
+            let reloadable = manifest.runtime == Runtime::Wasm
+                && self.wasm_plugins_names.contains_key(manifest.name.as_bytes())
+                && self.deployed_plugins.contains(manifest.name.as_bytes()) == matches!(origin, LaunchOrigin::Deployment);
+            if reloadable {
+                return self.reload_wasm_plugin(manifest, &incoming_dir, origin);
+            }
 
-            self.main_event_channel_tx.send(response_event).unwrap();
+            log::info!("Plugin startup rejected. Name is already in use");
+            let _ = fs::remove_dir_all(&incoming_dir);
+            return Err(-3);
+        }
 
-            log::info!("Plugin with specified name doesn't exist");
-        } else {
-            // Getting plugin's stream from the name
-            let child_id = self.plugins_names.get(&plugin_name).unwrap();
-            let fd = self.plugins.get(&child_id).unwrap();
-            let mut stream = self.plugins_streams.get(&fd).unwrap();
+        // Directory might be left from the plugin, that had the same name
+        let plugin_dir = self.plugins_dir.join(&manifest.name);
+        let _ = fs::remove_dir_all(&plugin_dir);
+        if let Err(error) = fs::rename(&incoming_dir, &plugin_dir) {
+            log::warn!("Error occured while installing a plugin: {}", error);
+            return Err(-1);
+        }
 
-            // Sending an event to plugin
-            let meta = event.meta;
-            for event in events_to_plugin {
-                let meta_clone = meta.clone();
-                let event = proto_msg::Event {
-                    dir: Some(proto_msg::event::Dir::Incoming as i32),
-                    dest: None,
-                    kind: event.kind,
-                    data: event.data,
-                    meta: meta_clone
-                };
+        // WASM plugin runs right away, there is no process to wait for
+        if manifest.runtime == Runtime::Wasm {
+            return match self.start_wasm_plugin(manifest, &plugin_dir) {
+                Ok(_) => {
+                    if let LaunchOrigin::Client(correlation_id) = origin {
+                        self.respond_status(correlation_id, 0);
+                    }
+                    Ok(())
+                },
+                Err(status) => {
+                    let _ = fs::remove_dir_all(&plugin_dir);
+                    Err(status)
+                }
+            };
+        }
 
-                stream.write(&event::serialize(event)).unwrap();
+        let token = auth::generate_token();
+        match Self::start_plugin(&manifest, &plugin_dir, &self.plugin_sdk_dir, &self.sandbox, &token) {
+            Ok(child) => {
+                self.launch(manifest, child, token, origin);
+                Ok(())
+            },
+            Err(status) => {
+                // Plugin, that didn't start, leaves nothing behind
+                let _ = fs::remove_dir_all(&plugin_dir);
+                Err(status)
             }
         }
-
-        Ok(())
     }
 
-    fn handle_get_from_shared_memory_incoming(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `get_from_shared_memory`");
+    fn handle_upgrade_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `upgrade_plugin`");
 
-        // Parsing event data
-        let first_arg = event.meta.get(0).unwrap();
-        let plugin_fd = utils::i32_from_ne_bytes(first_arg).unwrap();
+        if !self.authorize(&event, Action::Deploy) {
+            return Ok(());
+        }
 
-        // Getting plugin's stream
-        if let Some(stream) = self.plugins_streams.get_mut(&plugin_fd) {
-            let event = proto_msg::Event {
-                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                dest: None,
-                kind: proto_msg::event::Kind::GetFromSharedMemory as i32,
-                data: event.data,
-                meta: vec![]
-            };
+        let package = event::get_field(&event.data, 0)?;
 
-            // Sending an event to the plugin
-            stream.write(&event::serialize(event)).unwrap();
+        // Client is answered, when the new version connects
+        if let Err(status) = self.upgrade(package, event.correlation_id) {
+            self.respond_status(event.correlation_id, status);
         }
 
         Ok(())
     }
 
-    fn handle_transaction_succeeded(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `transaction_approved`");
+    // Replaces the running plugin with the new version without losing clients' requests.
+    // Returns status for the client, if upgrade isn't started
+    fn upgrade(&mut self, package: &[u8], correlation_id: Option<u64>) -> Result<(), i32> {
+        if self.listener.is_none() {
+            log::warn!("Plugin upgrade rejected. Plugin manager isn't started");
+            return Err(-2);
+        }
 
-        // Parsing event data
-        let first_arg = event.meta.get(0).unwrap();
-        let plugin_fd = utils::i32_from_ne_bytes(first_arg).unwrap();
+        let incoming_dir = self.plugins_dir.join(INCOMING_DIR);
+        let _ = fs::remove_dir_all(&incoming_dir);
 
-        // Getting plugin's stream
-        let stream = self.plugins_streams.get_mut(&plugin_fd).unwrap();
+        let manifest = match package::unpack(package, &incoming_dir) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                log::info!("Plugin upgrade rejected. Invalid package: {}", error);
+                let _ = fs::remove_dir_all(&incoming_dir);
+                return Err(STATUS_INVALID_PACKAGE);
+            }
+        };
 
-        let event = proto_msg::Event {
-            dir: Some(proto_msg::event::Dir::Incoming as i32),
-            dest: None,
-            kind: proto_msg::event::Kind::TransactionSucceeded as i32,
-            data: event.data,
-            meta: vec![]
+        // Whether the running version is a WASM one
+        let name = manifest.name.as_bytes().to_vec();
+        let running_wasm = self.plugins_names.get(&name)
+            .and_then(|child_id| self.plugins.get(child_id))
+            .or_else(|| self.wasm_plugins_names.get(&name))
+            .and_then(|fd| self.plugins_manifests.get(fd))
+            .map(|running| running.runtime == Runtime::Wasm);
+        let upgrading = self.launches.contains_key(&name)
+            || self.draining.values().any(|draining| draining.name == name)
+            || self.retired.iter().any(|retired| retired.name == name);
+
+        let rejection = if self.deployed_plugins.contains(&name) {
+            Some(("plugin is deployed to the cluster", -1))
+        } else if running_wasm.is_none() {
+            Some(("plugin isn't running", -1))
+        } else if upgrading {
+            Some(("previous upgrade hasn't finished", STATUS_UPGRADE_IN_PROGRESS))
+        } else if running_wasm != Some(manifest.runtime == Runtime::Wasm) {
+            Some(("WASM plugin and plugin with a process can't replace each other", STATUS_INVALID_PACKAGE))
+        } else {
+            None
         };
+        if let Some((reason, status)) = rejection {
+            log::info!("Upgrade of plugin `{}` rejected. {}", manifest.name, reason);
+            let _ = fs::remove_dir_all(&incoming_dir);
+            return Err(status);
+        }
 
-        // Sending an event to the plugin
-        stream.write(&event::serialize(event)).unwrap();
+        if manifest.runtime == Runtime::Wasm {
+            return self.reload_wasm_plugin(manifest, &incoming_dir, LaunchOrigin::Upgrade(correlation_id));
+        }
 
-        Ok(())
+        self.upgrade_process_plugin(manifest, &incoming_dir, correlation_id)
     }
 
-    fn handle_transaction_failed(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `transaction_failed`");
+    // New version runs from the plugin's directory, previous one keeps serving clients from
+    // the directory aside, until the new one connects. Previous version must have loaded
+    // everything it needs, its files are moved under it
+    fn upgrade_process_plugin(&mut self, manifest: Manifest, incoming_dir: &Path, correlation_id: Option<u64>) -> Result<(), i32> {
+        let plugin_dir = self.plugins_dir.join(&manifest.name);
+        let previous_dir = self.plugins_dir.join(PREVIOUS_DIR).join(&manifest.name);
+        let _ = fs::remove_dir_all(&previous_dir);
+
+        let installed = fs::create_dir_all(self.plugins_dir.join(PREVIOUS_DIR))
+            .and_then(|_| fs::rename(&plugin_dir, &previous_dir))
+            .and_then(|_| fs::rename(incoming_dir, &plugin_dir));
+        if let Err(error) = installed {
+            log::warn!("Error occured while installing a plugin: {}", error);
+            let _ = fs::remove_dir_all(incoming_dir);
+            self.restore_previous_version(&manifest.name);
+            return Err(-1);
+        }
 
-        // Parsing event data
-        let first_arg = event.meta.get(0).unwrap();
-        let plugin_fd = utils::i32_from_ne_bytes(first_arg).unwrap();
+        let token = auth::generate_token();
+        match Self::start_plugin(&manifest, &plugin_dir, &self.plugin_sdk_dir, &self.sandbox, &token) {
+            Ok(child) => {
+                self.launch(manifest, child, token, LaunchOrigin::Upgrade(correlation_id));
+                Ok(())
+            },
+            Err(status) => {
+                self.restore_previous_version(&manifest.name);
+                Err(status)
+            }
+        }
+    }
 
-        // Getting plugin's stream
-        let stream = self.plugins_streams.get_mut(&plugin_fd).unwrap();
+    // Starts the installed plugin again, its manifest is read from its directory
+    fn restart_plugin(&mut self, plugin_name: &[u8]) {
+        let name = String::from_utf8_lossy(plugin_name).to_string();
+        log::info!("Restarting plugin `{}`", name);
 
-        let event = proto_msg::Event {
-            dir: Some(proto_msg::event::Dir::Incoming as i32),
-            dest: None,
-            kind: proto_msg::event::Kind::TransactionFailed as i32,
-            data: event.data,
-            meta: vec![]
+        if self.listener.is_none() {
+            return;
+        }
+
+        let plugin_dir = self.plugins_dir.join(&name);
+        let manifest = fs::read_to_string(plugin_dir.join(package::MANIFEST_FILE))
+            .map_err(package::PackageError::from)
+            .and_then(|content| Manifest::parse(&content));
+        let manifest = match manifest {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                log::warn!("Plugin `{}` can't be restarted: {}", name, error);
+                self.plugin_exited(plugin_name, None, "couldn't be restarted".to_string());
+                return;
+            }
+        };
+
+        if manifest.runtime == Runtime::Wasm {
+            if self.start_wasm_plugin(manifest, &plugin_dir).is_err() {
+                self.plugin_exited(plugin_name, None, "couldn't be restarted".to_string());
+            }
+            return;
+        }
+
+        let token = auth::generate_token();
+        match Self::start_plugin(&manifest, &plugin_dir, &self.plugin_sdk_dir, &self.sandbox, &token) {
+            Ok(child) => self.launch(manifest, child, token, LaunchOrigin::Restart),
+            Err(_) => self.plugin_exited(plugin_name, None, "couldn't be restarted".to_string())
+        }
+    }
+
+    // Plugin's output is captured from the start, so it's there, even if plugin fails before connecting
+    fn launch(&mut self, manifest: Manifest, mut child: Child, token: String, origin: LaunchOrigin) {
+        let name = manifest.name.as_bytes().to_vec();
+        self.open_plugin_logs(&manifest.name);
+
+        let outputs = [
+            (LogStream::Stdout, child.stdout.take().map(pipe::Receiver::from)),
+            (LogStream::Stderr, child.stderr.take().map(pipe::Receiver::from))
+        ];
+        for (stream, output) in outputs {
+            if let Some(pipe) = output {
+                self.register_plugin_output(&name, stream, pipe);
+            }
+        }
+
+        // Name is taken, while plugin starts. Upgraded plugin's status is the one of the running version
+        let now = time::Instant::now();
+        let supervision = self.plugins_supervision.entry(name.clone())
+            .or_insert_with(|| Supervision::new(&manifest, now));
+        if !matches!(origin, LaunchOrigin::Upgrade(_)) {
+            supervision.launched(now);
+        }
+
+        self.launches.insert(name, Launch { manifest, child, token, started_at: now, origin });
+    }
+
+    // Plugin, that a client or the node waits for, is removed, restarted one is supervised as usual
+    fn launch_failed(&mut self, plugin_name: &[u8], exit_code: Option<i32>, reason: String, status: i32) {
+        let launch = match self.launches.remove(plugin_name) {
+            Some(launch) => launch,
+            None => return
+        };
+
+        log::warn!("Plugin `{}` failed to start: {}", String::from_utf8_lossy(plugin_name), reason);
+        Self::kill(launch.child);
+
+        match launch.origin {
+            LaunchOrigin::Client(correlation_id) => {
+                self.remove_plugin(plugin_name);
+                self.respond_status(correlation_id, status);
+            },
+            LaunchOrigin::Deployment => {
+                self.deployed_plugins.remove(plugin_name);
+                self.remove_plugin(plugin_name);
+            },
+            LaunchOrigin::Restart => self.plugin_exited(plugin_name, exit_code, reason),
+            // Previous version hasn't stopped running
+            LaunchOrigin::Upgrade(correlation_id) => {
+                log::warn!("Upgrade of plugin `{}` is rolled back", String::from_utf8_lossy(plugin_name));
+                self.restore_previous_version(&String::from_utf8_lossy(plugin_name));
+                self.respond_status(correlation_id, status);
+            }
+        }
+    }
+
+    // Adds plugin, that has connected, to local structs
+    fn register_plugin(&mut self, manifest: Manifest, child: Child, connection: Connection<TcpStream>) {
+        let fd = connection.get_ref().as_raw_fd();
+        log::info!("Plugin `{}` {} started", manifest.name, manifest.version);
+        log::debug!("Fd: {}", fd);
+
+        let name = manifest.name.as_bytes().to_vec();
+        if let Some(supervision) = self.plugins_supervision.get_mut(&name) {
+            supervision.started(time::Instant::now());
+        }
+
+        let child_id = child.id();
+        self.plugins.insert(child_id, fd);
+        self.plugins_names.insert(name, child_id);
+        self.plugins_manifests.insert(fd, manifest);
+        self.plugins_streams.insert(fd, connection);
+        self.plugins_processes.insert(child_id, child);
+    }
+
+    // Running version stops taking clients' events and drains, the new one takes its name.
+    // Restarts are counted from scratch for the new version
+    fn retire_plugin(&mut self, manifest: &Manifest) {
+        let name = manifest.name.as_bytes().to_vec();
+        let now = time::Instant::now();
+
+        let previous = match self.plugins_names.remove(&name) {
+            Some(child_id) => self.plugins.get(&child_id).map(|fd| (*fd, Some(child_id))),
+            None => self.wasm_plugins_names.remove(&name).map(|id| (id, None))
+        };
+        match previous {
+            Some((fd, process)) => {
+                log::info!("Previous version of plugin `{}` is draining", manifest.name);
+                self.draining.insert(fd, Draining { name: name.clone(), process, started_at: now });
+            },
+            // Previous version has exited, while the new one was starting
+            None => self.remove_previous_version(&manifest.name)
+        }
+
+        self.plugins_supervision.insert(name, Supervision::new(manifest, now));
+    }
+
+    // Previous version's files take their place back
+    fn restore_previous_version(&self, name: &str) {
+        let previous_dir = self.plugins_dir.join(PREVIOUS_DIR).join(name);
+        if !previous_dir.exists() {
+            return;
+        }
+
+        let plugin_dir = self.plugins_dir.join(name);
+        let _ = fs::remove_dir_all(&plugin_dir);
+        if let Err(error) = fs::rename(&previous_dir, &plugin_dir) {
+            log::warn!("Couldn't restore previous version of plugin `{}`: {}", name, error);
+        }
+    }
+
+    fn remove_previous_version(&self, name: &str) {
+        let _ = fs::remove_dir_all(self.plugins_dir.join(PREVIOUS_DIR).join(name));
+    }
+
+    // Instantiates the plugin's module, plugin is registered at once
+    fn start_wasm_plugin(&mut self, manifest: Manifest, plugin_dir: &Path) -> Result<(), i32> {
+        let plugin = self.instantiate_wasm_plugin(&manifest, plugin_dir)?;
+        self.register_wasm_plugin(manifest, plugin);
+
+        Ok(())
+    }
+
+    fn register_wasm_plugin(&mut self, manifest: Manifest, plugin: WasmPlugin) {
+        let id = self.next_wasm_id;
+        self.next_wasm_id -= 1;
+        log::info!("Plugin `{}` {} started", manifest.name, manifest.version);
+        log::debug!("Id: {}", id);
+
+        self.open_plugin_logs(&manifest.name);
+        let name = manifest.name.as_bytes().to_vec();
+        self.plugins_supervision.entry(name.clone())
+            .or_insert_with(|| Supervision::new(&manifest, time::Instant::now()))
+            .started(time::Instant::now());
+
+        self.wasm_plugins.insert(id, plugin);
+        self.wasm_plugins_names.insert(name, id);
+        self.plugins_manifests.insert(id, manifest);
+    }
+
+    // New version takes the place of the running one, that keeps running, if the new one fails.
+    // Running one drains, it answers what it was asked before
+    fn reload_wasm_plugin(&mut self, manifest: Manifest, incoming_dir: &Path, origin: LaunchOrigin) -> Result<(), i32> {
+        let plugin = match self.instantiate_wasm_plugin(&manifest, incoming_dir) {
+            Ok(plugin) => plugin,
+            Err(status) => {
+                let _ = fs::remove_dir_all(incoming_dir);
+                return Err(status);
+            }
         };
 
-        // Sending an event to the plugin
-        stream.write(&event::serialize(event)).unwrap();
+        let plugin_dir = self.plugins_dir.join(&manifest.name);
+        let _ = fs::remove_dir_all(&plugin_dir);
+        if let Err(error) = fs::rename(incoming_dir, &plugin_dir) {
+            log::warn!("Error occured while installing a plugin: {}", error);
+            return Err(-1);
+        }
+
+        log::info!("Plugin `{}` reloaded, version {}", manifest.name, manifest.version);
+        self.retire_plugin(&manifest);
+        self.register_wasm_plugin(manifest, plugin);
+
+        if let LaunchOrigin::Client(correlation_id) | LaunchOrigin::Upgrade(correlation_id) = origin {
+            self.respond_status(correlation_id, 0);
+        }
 
         Ok(())
     }
 
-    fn handle_update_shared_memory(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `update_shared_memory`");
+    fn instantiate_wasm_plugin(&self, manifest: &Manifest, plugin_dir: &Path) -> Result<WasmPlugin, i32> {
+        let module = fs::read(plugin_dir.join(&manifest.entrypoint)).map_err(|error| {
+            log::warn!("Error occured while reading plugin's module: {}", error);
+            -1
+        })?;
 
-        let event = proto_msg::Event {
+        self.wasm_runtime.instantiate(&module).map_err(|error| match error {
+            WasmError::InvalidModule(error) => {
+                log::info!("Plugin startup rejected. Invalid module: {}", error);
+                STATUS_INVALID_PACKAGE
+            },
+            WasmError::Trap(reason) => {
+                log::warn!("Plugin `{}` failed to start: {}", manifest.name, reason);
+                STATUS_STARTUP_FAILED
+            }
+        })
+    }
+
+    // WASM plugins take events, that were sent to them, what they send is handled
+    // as if it came from a connection
+    fn run_wasm_plugins(&mut self) {
+        let ids: Vec<i32> = self.wasm_plugins.keys().copied().collect();
+
+        for id in ids {
+            let plugin = match self.wasm_plugins.get_mut(&id) {
+                Some(plugin) => plugin,
+                None => continue
+            };
+
+            let result = plugin.run();
+            let events = plugin.take_sent();
+            let output = plugin.take_output();
+
+            let name = self.plugins_manifests.get(&id)
+                .map(|manifest| manifest.name.as_bytes().to_vec())
+                .unwrap_or_default();
+            if let Some(logs) = self.plugins_logs.get_mut(&name) {
+                logs.push(LogStream::Stdout, &output);
+            }
+            self.push_plugin_events(id, events);
+
+            if let Err(error) = result {
+                self.wasm_plugin_failed(id, &name, error.to_string());
+            }
+        }
+    }
+
+    // Instance, that has trapped, is dropped, plugin is instantiated again according to its policy
+    fn wasm_plugin_failed(&mut self, id: i32, plugin_name: &[u8], reason: String) {
+        self.wasm_plugins.remove(&id);
+        self.close_plugin_connection(id);
+
+        if let Some(logs) = self.plugins_logs.get_mut(plugin_name) {
+            logs.push(LogStream::Stderr, format!("{}\n", reason).as_bytes());
+        }
+
+        // Previous version's failure doesn't concern the new one
+        if self.draining.remove(&id).is_some() {
+            return;
+        }
+        self.wasm_plugins_names.remove(plugin_name);
+        self.plugin_exited(plugin_name, None, reason);
+    }
+
+    // Logs outlive plugin's restarts
+    fn open_plugin_logs(&mut self, name: &str) {
+        let path = self.plugin_logs_dir.join(format!("{}.log", name));
+        self.plugins_logs.entry(name.as_bytes().to_vec())
+            .or_insert_with(|| PluginLogs::new(PLUGIN_LOGS_CAPACITY, Some(path)));
+    }
+
+    fn register_plugin_output(&mut self, name: &[u8], stream: LogStream, pipe: pipe::Receiver) {
+        let registered = pipe.set_nonblocking(true)
+            .and_then(|_| self.reactor.register(pipe.as_raw_fd()));
+        if let Err(error) = registered {
+            log::warn!("Output of plugin `{}` isn't captured: {}", String::from_utf8_lossy(name), error);
+            return;
+        }
+
+        self.plugins_outputs.insert(pipe.as_raw_fd(), PluginOutput { name: name.to_vec(), stream, pipe });
+    }
+
+    // Reads everything, that plugin has written, pipe is closed, when plugin exits
+    fn read_plugin_output(&mut self, fd: i32) {
+        let output = match self.plugins_outputs.get_mut(&fd) {
+            Some(output) => output,
+            None => return
+        };
+
+        let mut buffer = [0; 4096];
+        let closed = loop {
+            match output.pipe.read(&mut buffer) {
+                Ok(0) => break true,
+                Ok(read) => {
+                    if let Some(logs) = self.plugins_logs.get_mut(&output.name) {
+                        logs.push(output.stream, &buffer[..read]);
+                    }
+                },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break false,
+                Err(error) => {
+                    log::debug!("Couldn't read plugin's output: {}", error);
+                    break true;
+                }
+            }
+        };
+
+        if closed {
+            if let Some(output) = self.plugins_outputs.remove(&fd) {
+                let _ = self.reactor.deregister(fd);
+                if let Some(logs) = self.plugins_logs.get_mut(&output.name) {
+                    logs.close(output.stream);
+                }
+            }
+        }
+    }
+
+    // Reaps plugins, that have exited, and restarts them according to their policies
+    fn supervise(&mut self) {
+        let now = time::Instant::now();
+
+        // Plugin, that has closed its connection, but still runs, is of no use
+        for (name, supervision) in self.plugins_supervision.iter() {
+            let child = self.plugins_names.get(name).and_then(|id| self.plugins_processes.get_mut(id));
+            if let Some(child) = child.filter(|_| supervision.is_unresponsive(now)) {
+                log::warn!("Plugin `{}` didn't exit after disconnecting, killing it", String::from_utf8_lossy(name));
+                let _ = child.kill();
+            }
+        }
+
+        self.drain_plugins(now);
+
+        let exited: Vec<(u32, ExitStatus)> = self.plugins_processes.iter_mut()
+            .filter_map(|(id, child)| match child.try_wait() {
+                Ok(Some(status)) => Some((*id, status)),
+                _ => None
+            })
+            .collect();
+
+        for (id, status) in exited {
+            self.plugins_processes.remove(&id);
+            if let Some(fd) = self.plugins.remove(&id) {
+                self.close_plugin_connection(fd);
+
+                // Previous version, that has exited before draining, is of no concern
+                if let Some(draining) = self.draining.remove(&fd) {
+                    self.remove_previous_version(&String::from_utf8_lossy(&draining.name));
+                    continue;
+                }
+            }
+
+            let name = self.plugins_names.iter()
+                .find(|(_, child_id)| **child_id == id)
+                .map(|(name, _)| name.clone());
+            if let Some(name) = name {
+                self.plugins_names.remove(&name);
+                self.plugin_exited(&name, status.code(), sandbox::exit_reason(&status));
+            }
+        }
+
+        // Plugin, that exits or doesn't connect in time, hasn't started
+        let failed: Vec<(Vec<u8>, Option<i32>, String, i32)> = self.launches.iter_mut()
+            .filter_map(|(name, launch)| match launch.child.try_wait() {
+                Ok(Some(status)) if status.code() == Some(MISSING_DEPENDENCY_EXIT_CODE) => Some((name.clone(), status.code(),
+                    "couldn't import its dependencies".to_string(), STATUS_MISSING_DEPENDENCY)),
+                Ok(Some(status)) => Some((name.clone(), status.code(),
+                    format!("{} before connecting", sandbox::exit_reason(&status)), STATUS_STARTUP_FAILED)),
+                _ if now >= launch.started_at + PLUGIN_STARTUP_TIMEOUT => Some((name.clone(), None,
+                    "didn't connect in time".to_string(), STATUS_STARTUP_TIMEOUT)),
+                _ => None
+            })
+            .collect();
+        for (name, exit_code, reason, status) in failed {
+            self.launch_failed(&name, exit_code, reason, status);
+        }
+
+        let expired: Vec<i32> = self.unidentified.iter()
+            .filter(|(_, unidentified)| now >= unidentified.accepted_at + PLUGIN_STARTUP_TIMEOUT)
+            .map(|(fd, _)| *fd)
+            .collect();
+        for fd in expired {
+            if let Some(unidentified) = self.unidentified.remove(&fd) {
+                log::warn!("Connection {} didn't present a token in time", fd);
+                self.drop_unidentified(fd, unidentified);
+            }
+        }
+
+        // Plugin, that is upgraded, is restarted, if the upgrade is rolled back
+        let restarts: Vec<Vec<u8>> = self.plugins_supervision.iter()
+            .filter(|(name, supervision)| supervision.is_restart_due(now) && !self.launches.contains_key(*name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in restarts {
+            self.restart_plugin(&name);
+        }
+    }
+
+    // Previous versions, that have answered what they were asked, are stopped, others
+    // get as long, as requests wait for responses. Process is told to stop by closing
+    // its connection, WASM plugin is just dropped
+    fn drain_plugins(&mut self, now: time::Instant) {
+        let drained: Vec<i32> = self.draining.iter()
+            .filter(|&(&fd, draining)| {
+                let connected = self.plugins_streams.contains_key(&fd) || self.wasm_plugins.contains_key(&fd);
+                let busy = self.clients_requests.any(|request| request.fd == fd)
+                    || self.node_requests.any(|request| matches!(request, NodeRequest::Plugin { fd: plugin_fd, .. } if *plugin_fd == fd));
+                !connected || !busy || now >= draining.started_at + REQUEST_TIMEOUT
+            })
+            .map(|(fd, _)| *fd)
+            .collect();
+
+        for fd in drained {
+            let draining = match self.draining.remove(&fd) {
+                Some(draining) => draining,
+                None => continue
+            };
+            log::info!("Previous version of plugin `{}` has drained", String::from_utf8_lossy(&draining.name));
+
+            self.wasm_plugins.remove(&fd);
+            self.close_plugin_connection(fd);
+
+            // Process isn't known by its fd anymore, the fd might be given to another connection
+            let child = draining.process.and_then(|child_id| {
+                self.plugins.remove(&child_id);
+                self.plugins_processes.remove(&child_id)
+            });
+            if let Some(child) = child {
+                self.retired.push(RetiredPlugin { name: draining.name, child, stopped_at: now });
+            }
+        }
+
+        // Previous version, that doesn't exit, is killed, its files are removed after it
+        let mut exited = vec![];
+        self.retired.retain_mut(|retired| {
+            if let Ok(None) = retired.child.try_wait() {
+                if now < retired.stopped_at + PLUGIN_STOP_TIMEOUT {
+                    return true;
+                }
+
+                log::warn!("Previous version of plugin `{}` didn't exit after draining, killing it", String::from_utf8_lossy(&retired.name));
+                let _ = retired.child.kill();
+                let _ = retired.child.wait();
+            }
+
+            exited.push(retired.name.clone());
+            false
+        });
+        for name in exited {
+            self.remove_previous_version(&String::from_utf8_lossy(&name));
+        }
+    }
+
+    fn plugin_exited(&mut self, plugin_name: &[u8], exit_code: Option<i32>, reason: String) {
+        let supervision = match self.plugins_supervision.get_mut(plugin_name) {
+            Some(supervision) => supervision,
+            None => return
+        };
+
+        let name = String::from_utf8_lossy(plugin_name);
+        match supervision.exited(exit_code, time::Instant::now()) {
+            PluginStatus::Restarting => log::warn!("Plugin `{}` has stopped ({}), restart {} of {}",
+                name, reason, supervision.restarts(), supervision.max_restarts()),
+            PluginStatus::Crashed => log::warn!("Plugin `{}` crashed ({}), it won't be restarted", name, reason),
+            _ => log::info!("Plugin `{}` exited", name)
+        }
+        supervision.set_exit_reason(reason);
+    }
+
+    // Plugin manager must wake up to kill plugins, that are stuck or don't connect, restart and reap them
+    fn next_supervision_timeout(&self) -> Option<time::Duration> {
+        let now = time::Instant::now();
+
+        // Processes, which connections are closed, exit soon, and starting ones might exit,
+        // there is no way to wait for that. Previous versions are checked, until they drain
+        let reaping = (!self.launches.is_empty() || !self.draining.is_empty() || !self.retired.is_empty()
+            || self.plugins_supervision.values().any(|supervision| supervision.is_disconnected()))
+            .then_some(REAP_INTERVAL);
+
+        let startup_deadlines = self.launches.values()
+            .map(|launch| launch.started_at)
+            .chain(self.unidentified.values().map(|unidentified| unidentified.accepted_at))
+            .map(|started_at| started_at + PLUGIN_STARTUP_TIMEOUT);
+
+        self.plugins_supervision.values()
+            .filter_map(|supervision| supervision.next_deadline())
+            .chain(startup_deadlines)
+            .map(|deadline| deadline.saturating_duration_since(now))
+            .chain(reaping)
+            .min()
+    }
+
+    // Spawns process with the plugin, it connects back with the token
+    fn start_plugin(manifest: &Manifest, plugin_dir: &Path, sdk_dir: &Path, sandbox: &Sandbox, token: &str) -> Result<Child, i32> {
+        let mut command = Self::command(manifest, plugin_dir, sdk_dir, sandbox);
+        // Python runs the entrypoint, native entrypoint is the process itself. Dependencies are
+        // imported first, process exits with its own code, if they can't be. Import, that hangs,
+        // is caught by the startup timeout like any plugin, that doesn't connect
+        if manifest.runtime == Runtime::Python && !manifest.dependencies.is_empty() {
+            // Names are checked to be module names, when manifest is parsed
+            command.arg("-c")
+                .arg(format!(
+                    "import runpy, sys\ntry:\n    import {}\nexcept ImportError:\n    sys.exit({})\nsys.argv = sys.argv[1:]\nrunpy.run_path(sys.argv[0], run_name='__main__')",
+                    manifest.dependencies.join(", "), MISSING_DEPENDENCY_EXIT_CODE))
+                .arg(&manifest.entrypoint);
+        } else if manifest.runtime == Runtime::Python {
+            command.arg(&manifest.entrypoint);
+        }
+
+        let spawned = command
+            .env(plugin_protocol::TOKEN_VAR, token)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        spawned.map_err(|error| {
+            log::warn!("Error occured while starting a plugin: {}", error);
+            -1
+        })
+    }
+
+    // Runtime's process, that runs sandboxed in the plugin's directory. Python gets the SDK,
+    // plugin's own modules and vendored dependencies on its path
+    fn command(manifest: &Manifest, plugin_dir: &Path, sdk_dir: &Path, sandbox: &Sandbox) -> Command {
+        let sdk_dir = fs::canonicalize(sdk_dir).unwrap_or_else(|_| sdk_dir.to_path_buf());
+        let plugin_dir = fs::canonicalize(plugin_dir).unwrap_or_else(|_| plugin_dir.to_path_buf());
+
+        match manifest.runtime {
+            Runtime::Python => {
+                let path = std::env::join_paths([sdk_dir, plugin_dir.join(package::DEPS_DIR), plugin_dir.clone()])
+                    .unwrap_or_default();
+
+                let mut command = Command::new("python3");
+                sandbox.apply(&mut command);
+                // Output is piped, it mustn't be held in buffers
+                command.current_dir(&plugin_dir)
+                    .env("HOME", &plugin_dir)
+                    .env("PYTHONPATH", path)
+                    .env("PYTHONUNBUFFERED", "1")
+                    .env(plugin_protocol::ADDR_VAR, plugin_protocol::DEFAULT_PLUGIN_MAN_ADDR)
+                    .env(plugin_protocol::NAME_VAR, &manifest.name);
+                command
+            },
+            Runtime::Native => {
+                let mut command = Command::new(plugin_dir.join(&manifest.entrypoint));
+                sandbox.apply(&mut command);
+                command.current_dir(&plugin_dir)
+                    .env("HOME", &plugin_dir)
+                    .env(plugin_protocol::ADDR_VAR, plugin_protocol::DEFAULT_PLUGIN_MAN_ADDR)
+                    .env(plugin_protocol::NAME_VAR, &manifest.name);
+                command
+            },
+            Runtime::Wasm => unreachable!("WASM plugins run inside the plugin manager")
+        }
+    }
+
+    fn kill(mut child: Child) {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    fn handle_remove_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `remove_plugin`");
+
+        if !self.authorize(&event, Action::Remove) {
+            return Ok(());
+        }
+
+        let plugin_name = event::get_field(&event.data, 0)?.to_vec();
+
+        // Plugin, that isn't local, might be deployed to the cluster, node decides
+        let is_local = self.plugins_supervision.contains_key(&plugin_name) && !self.deployed_plugins.contains(&plugin_name);
+        if !is_local {
+            let request_id = self.node_requests.insert(NodeRequest::Deployment(event.correlation_id));
+
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Outcoming as i32),
+                dest: Some(proto_msg::event::Dest::Node as i32),
+                kind: proto_msg::event::Kind::RemoveDeployment as i32,
+                data: vec![plugin_name],
+                meta: vec![],
+                correlation_id: Some(request_id)
+            };
+
+            self.bus.send(event);
+
+            return Ok(());
+        }
+
+        // Remove status variable
+        let status: i32 = 0;
+        self.remove_plugin(&plugin_name);
+
+        let response_event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
-            dest: Some(proto_msg::event::Dest::Node as i32),
+            dest: Some(proto_msg::event::Dest::Server as i32),
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data: vec![status.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: event.correlation_id
+        };
+
+        self.bus.send(response_event);
+
+        Ok(())
+    }
+
+    // Node has placed deployed plugin on this node
+    fn handle_start_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `start_plugin`");
+
+        let plugin_name = event::get_field(&event.data, 0)?.to_vec();
+        let package = event::get_field(&event.data, 1)?;
+
+        match self.deploy(package, LaunchOrigin::Deployment) {
+            Ok(_) => {
+                self.deployed_plugins.insert(plugin_name);
+            },
+            Err(status) => log::warn!("Deployed plugin `{}` isn't started, status {}", String::from_utf8_lossy(&plugin_name), status)
+        }
+
+        Ok(())
+    }
+
+    // Node has placed deployed plugin elsewhere or it's removed from the cluster
+    fn handle_stop_plugin(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `stop_plugin`");
+
+        let plugin_name = event::get_field(&event.data, 0)?;
+
+        // Local plugin with the same name isn't touched
+        if self.deployed_plugins.remove(plugin_name) {
+            self.remove_plugin(plugin_name);
+        }
+
+        Ok(())
+    }
+
+    // Stops the plugin and removes its files
+    fn remove_plugin(&mut self, plugin_name: &[u8]) {
+        // Plugin, that has crashed or waits for its restart, has no process
+        if self.plugins_supervision.remove(plugin_name).is_none() {
+            return;
+        }
+
+        // Client, that waits for the plugin to start, is told it didn't
+        if let Some(launch) = self.launches.remove(plugin_name) {
+            Self::kill(launch.child);
+            if let LaunchOrigin::Client(correlation_id) | LaunchOrigin::Upgrade(correlation_id) = launch.origin {
+                self.respond_status(correlation_id, STATUS_STARTUP_FAILED);
+            }
+        }
+
+        // Previous versions go with the plugin
+        let draining: Vec<i32> = self.draining.iter()
+            .filter(|(_, draining)| draining.name == plugin_name)
+            .map(|(fd, _)| *fd)
+            .collect();
+        for fd in draining {
+            if let Some(process) = self.draining.remove(&fd).and_then(|draining| draining.process) {
+                self.plugins.remove(&process);
+                if let Some(child) = self.plugins_processes.remove(&process) {
+                    Self::kill(child);
+                }
+            }
+            self.wasm_plugins.remove(&fd);
+            self.close_plugin_connection(fd);
+        }
+        let retired: Vec<RetiredPlugin> = self.retired.extract_if(.., |retired| retired.name == plugin_name).collect();
+        for retired in retired {
+            Self::kill(retired.child);
+        }
+        self.remove_previous_version(&String::from_utf8_lossy(plugin_name));
+
+        if let Some(id) = self.plugins_names.remove(plugin_name) {
+            if let Some(fd) = self.plugins.remove(&id) {
+                self.close_plugin_connection(fd);
+            }
+            if let Some(child) = self.plugins_processes.remove(&id) {
+                Self::kill(child);
+            }
+        }
+        if let Some(id) = self.wasm_plugins_names.remove(plugin_name) {
+            self.wasm_plugins.remove(&id);
+            self.close_plugin_connection(id);
+        }
+
+        // Log file is left, it's the only trace of the plugin
+        let outputs: Vec<i32> = self.plugins_outputs.iter()
+            .filter(|(_, output)| output.name == plugin_name)
+            .map(|(fd, _)| *fd)
+            .collect();
+        for fd in outputs {
+            self.read_plugin_output(fd);
+            if self.plugins_outputs.remove(&fd).is_some() {
+                let _ = self.reactor.deregister(fd);
+            }
+        }
+        self.plugins_logs.remove(plugin_name);
+
+        // Plugin's files are gone with it
+        let plugin_dir = self.plugins_dir.join(String::from_utf8_lossy(plugin_name).as_ref());
+        if let Err(error) = fs::remove_dir_all(&plugin_dir) {
+            log::warn!("Couldn't remove plugin's directory {}: {}", plugin_dir.display(), error);
+        }
+
+        log::info!("Plugin `{}` removed", String::from_utf8_lossy(plugin_name));
+    }
+
+    // Closes connection to the plugin and forgets its requests
+    fn close_plugin_connection(&mut self, fd: i32) {
+        if let Some(connection) = self.plugins_streams.remove(&fd) {
+            let _ = self.reactor.deregister(fd);
+            let _ = connection.get_ref().shutdown(Shutdown::Both);
+        }
+        self.plugins_manifests.remove(&fd);
+        self.forget_plugin_requests(fd);
+    }
+
+    fn handle_get_plugin_list(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `get_plugin_list`");
+
+        if !self.authorize(&event, Action::List) {
+            return Ok(());
+        }
+
+        let mut data = vec![];
+        for name in self.plugins_supervision.keys() {
+            data.push(name.clone());
+        }
+
+        let response_event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: Some(proto_msg::event::Dest::Server as i32),
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data,
+            meta: vec![],
+            correlation_id: event.correlation_id
+        };
+
+        self.bus.send(response_event);
+
+        Ok(())
+    }
+
+    // Status is sent as `[0, status, restarts, exit reason, exit code]`, reason is empty,
+    // if plugin hasn't exited yet, exit code is absent, if it hasn't exited or was killed by a signal
+    fn handle_get_plugin_status(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `get_plugin_status`");
+
+        if !self.authorize(&event, Action::List) {
+            return Ok(());
+        }
+
+        let plugin_name = event::get_field(&event.data, 0)?;
+
+        let data = match self.plugins_supervision.get(plugin_name) {
+            Some(supervision) => {
+                let mut data = vec![
+                    0i32.to_ne_bytes().to_vec(),
+                    supervision.status().to_string().into_bytes(),
+                    supervision.restarts().to_ne_bytes().to_vec(),
+                    supervision.exit_reason().unwrap_or_default().as_bytes().to_vec()
+                ];
+                if let Some(exit_code) = supervision.exit_code() {
+                    data.push(exit_code.to_ne_bytes().to_vec());
+                }
+                data
+            },
+            None => vec![(-1i32).to_ne_bytes().to_vec()]
+        };
+
+        let response_event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: Some(proto_msg::event::Dest::Server as i32),
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data,
+            meta: vec![],
+            correlation_id: event.correlation_id
+        };
+
+        self.bus.send(response_event);
+
+        Ok(())
+    }
+
+    // Request is `[name, tail, since]`, last lines are sent, unless client asks for lines
+    // starting with the sequence number `since`, that's how it follows the output.
+    // Logs are sent as `[0, next sequence number, stream, line, stream, line...]`
+    fn handle_get_plugin_logs(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `get_plugin_logs`");
+
+        if !self.authorize(&event, Action::List) {
+            return Ok(());
+        }
+
+        let plugin_name = event::get_field(&event.data, 0)?;
+        let tail = match event.data.get(1) {
+            Some(_) => usize::try_from(event::get_i32(&event.data, 1)?).unwrap_or(0),
+            None => DEFAULT_LOGS_TAIL
+        };
+        let since = match event.data.get(2) {
+            Some(_) => Some(event::get_u64(&event.data, 2)?),
+            None => None
+        };
+
+        let data = match self.plugins_logs.get(plugin_name) {
+            Some(logs) => {
+                let lines: Vec<_> = match since {
+                    Some(since) => logs.since(since).collect(),
+                    None => logs.tail(tail).collect()
+                };
+
+                let mut data = vec![0i32.to_ne_bytes().to_vec(), logs.next_seq().to_ne_bytes().to_vec()];
+                for line in lines {
+                    data.push(line.stream.to_string().into_bytes());
+                    data.push(line.line.clone());
+                }
+                data
+            },
+            None => vec![(-1i32).to_ne_bytes().to_vec()]
+        };
+
+        let response_event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: Some(proto_msg::event::Dest::Server as i32),
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data,
+            meta: vec![],
+            correlation_id: event.correlation_id
+        };
+
+        self.bus.send(response_event);
+
+        Ok(())
+    }
+
+    fn handle_new_plugin_event(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling `new_plugin_event`");
+
+        // Parsing event data
+        let plugin_name = event::get_field(&event.data, 0)?.to_vec();
+        let (events_to_plugin, _rem) = event::deserialize(event::get_field(&event.data, 1)?);
+
+        if !self.authorize(&event, Action::Send(&plugin_name)) {
+            return Ok(());
+        }
+
+        // Request is answered once for every event, one without events would never be
+        if events_to_plugin.is_empty() {
+            log::info!("Request to the plugin carries no events");
+            self.respond_status(event.correlation_id, STATUS_NO_EVENTS);
+            return Ok(());
+        }
+
+        let plugin_fd = self.plugins_names.get(&plugin_name)
+            .and_then(|child_id| self.plugins.get(child_id))
+            .or_else(|| self.wasm_plugins_names.get(&plugin_name))
+            .copied()
+            .filter(|fd| self.plugins_streams.contains_key(fd) || self.wasm_plugins.contains_key(fd));
+
+        if let Some(fd) = plugin_fd {
+            // Only this plugin may answer the request, once for every event
+            if let Some(request_id) = event.correlation_id {
+                let request = ClientRequest { fd, responses_left: events_to_plugin.len(), responses_queued: 0 };
+                self.clients_requests.insert_with_id(request_id, request);
+            }
+
+            // Sending an event to plugin, client's name is left in meta
+            let meta = event.meta;
+            for plugin_event in events_to_plugin {
+                let plugin_event = proto_msg::Event {
+                    dir: Some(proto_msg::event::Dir::Incoming as i32),
+                    dest: None,
+                    kind: plugin_event.kind,
+                    data: plugin_event.data,
+                    meta: meta.clone(),
+                    correlation_id: event.correlation_id
+                };
+
+                self.send_to_plugin(fd, plugin_event)?;
+            }
+        } else {
+            let status: i32 = -1;
+
+            let response_event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Outcoming as i32),
+                dest: Some(proto_msg::event::Dest::Server as i32),
+                kind: proto_msg::event::Kind::RespondClient as i32,
+                data: vec![status.to_ne_bytes().to_vec()],
+                meta: vec![],
+                correlation_id: event.correlation_id
+            };
+
+            self.bus.send(response_event);
+
+            log::info!("Plugin with specified name doesn't exist");
+        }
+
+        Ok(())
+    }
+
+    // Node's response goes to the plugin, that has made the request
+    fn handle_node_response(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling node's response");
+
+        // Server puts client's name into meta of every client's event, node's events come without meta.
+        // Otherwise a client could answer plugin's or deployment's request in place of the node
+        if !event.meta.is_empty() {
+            return Err(PluginManError::NotFromNode(event.kind));
+        }
+
+        let request = event.correlation_id
+            .and_then(|id| self.node_requests.take(id))
+            .ok_or(PluginManError::UnknownRequest(event.correlation_id))?;
+
+        let (fd, id) = match request {
+            NodeRequest::Plugin { fd, id } => (fd, id),
+            NodeRequest::Deployment(id) => {
+                // Node puts the status first
+                let status = event::get_i32(&event.data, 0)?;
+
+                let response_event = proto_msg::Event {
+                    dir: Some(proto_msg::event::Dir::Outcoming as i32),
+                    dest: Some(proto_msg::event::Dest::Server as i32),
+                    kind: proto_msg::event::Kind::RespondClient as i32,
+                    data: vec![status.to_ne_bytes().to_vec()],
+                    meta: vec![],
+                    correlation_id: id
+                };
+
+                self.bus.send(response_event);
+
+                return Ok(());
+            }
+        };
+
+        // Plugin gets back the id it has given
+        let event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: None,
             kind: event.kind,
             data: event.data,
-            meta: event.meta
+            meta: vec![],
+            correlation_id: id
         };
 
-        self.main_event_channel_tx.send(event).unwrap();
+        self.send_to_plugin(fd, event)
+    }
+
+    // Plugin, that has a connection, gets the event right away, WASM one takes it, when it runs
+    fn send_to_plugin(&mut self, fd: i32, event: proto_msg::Event) -> Result<(), PluginManError> {
+        if let Some(plugin) = self.wasm_plugins.get_mut(&fd) {
+            plugin.push_event(event);
+            return Ok(());
+        }
+
+        let connection = self.plugins_streams.get_mut(&fd)
+            .ok_or(PluginManError::PluginGone(fd))?;
+        if let Err(error) = connection.send(event) {
+            log::warn!("Couldn't send event to the plugin: {}", error);
+        }
 
         Ok(())
     }
 
-    fn handle_get_from_shared_memory_outcoming(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
-        log::debug!("Handling `get_from_shared_memory`");
+    // Plugin's request goes to the node under the id, that is unique across all plugins
+    fn handle_plugin_request(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
+        log::debug!("Handling plugin's request");
+
+        let plugin_fd = event::get_i32(&event.meta, 0)?;
+
+        // Shared memory is available only to plugins, that have declared it
+        let allowed = self.plugins_manifests.get(&plugin_fd)
+            .is_some_and(|manifest| manifest.has_capability(Capability::SharedMemory));
+        if !allowed {
+            return Err(PluginManError::MissingCapability(plugin_fd, Capability::SharedMemory));
+        }
+
+        let request_id = self.node_requests.insert(NodeRequest::Plugin { fd: plugin_fd, id: event.correlation_id });
 
         let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
             dest: Some(proto_msg::event::Dest::Node as i32),
             kind: event.kind,
             data: event.data,
-            meta: event.meta
+            meta: vec![],
+            correlation_id: Some(request_id)
         };
 
-        self.main_event_channel_tx.send(event).unwrap();
+        self.bus.send(event);
 
         Ok(())
     }
@@ -548,25 +2095,131 @@ impl PluginMan {
     fn handle_respond_client(&mut self, event: proto_msg::Event) -> Result<(), PluginManError> {
         log::debug!("Handling `respond_client`");
 
-        // Removing unnecessary meta information
-        let event_meta = &event.meta[1..];
+        // Plugin can answer only requests, that were passed to it
+        let plugin_fd = event::get_i32(&event.meta, 0)?;
+        let request_id = event.correlation_id
+            .filter(|id| self.clients_requests.get(*id).is_some_and(|request| request.fd == plugin_fd))
+            .ok_or(PluginManError::UnknownRequest(event.correlation_id))?;
+
+        // Request is forgotten with its last response, others are marked as partial
+        let mut meta = vec![];
+        if let Some(request) = self.clients_requests.get_mut(request_id) {
+            request.responses_left = request.responses_left.saturating_sub(1);
+            request.responses_queued = request.responses_queued.saturating_sub(1);
+
+            if request.responses_left > 0 {
+                meta.push(event::PARTIAL_RESPONSE.to_vec());
+            } else {
+                self.clients_requests.take(request_id);
+            }
+        }
 
         let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
             dest: Some(proto_msg::event::Dest::Server as i32),
             kind: event.kind,
             data: event.data,
-            meta: event_meta.to_vec()
+            meta,
+            correlation_id: Some(request_id)
         };
 
-        self.main_event_channel_tx.send(event).unwrap();
+        self.bus.send(event);
 
         Ok(())
     }
+
+    // Plugins are told about their requests, that weren't answered in time.
+    // Clients are told about theirs by the server
+    fn expire_requests(&mut self) {
+        self.clients_requests.take_expired();
+
+        for (_, request) in self.node_requests.take_expired() {
+            let (fd, id) = match request {
+                NodeRequest::Plugin { fd, id } => (fd, id),
+                NodeRequest::Deployment(_) => continue
+            };
+
+            log::debug!("Request of plugin with fd {} timed out", fd);
+
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Incoming as i32),
+                dest: None,
+                kind: proto_msg::event::Kind::RequestTimedOut as i32,
+                data: vec![],
+                meta: vec![],
+                correlation_id: id
+            };
+
+            // Plugin might have gone already
+            let _ = self.send_to_plugin(fd, event);
+        }
+    }
+
+    // Requests of the gone plugin won't be answered
+    fn forget_plugin_requests(&mut self, fd: i32) {
+        self.clients_requests.retain(|request| request.fd != fd);
+        self.node_requests.retain(|request| !matches!(request, NodeRequest::Plugin { fd: plugin_fd, .. } if *plugin_fd == fd));
+    }
+
+    // Checks client's permissions, responding with `permission denied` if action isn't allowed.
+    // Client's name is put into meta by the server
+    fn authorize(&self, event: &proto_msg::Event, action: Action) -> bool {
+        let acl = match &self.acl {
+            Some(acl) => acl,
+            None => return true
+        };
+
+        let client_name = event.meta.first().map(|name| name.as_slice()).unwrap_or_default();
+        if acl.is_allowed(client_name, &action) {
+            return true;
+        }
+
+        log::info!("Client `{}` is not allowed to {:?}", String::from_utf8_lossy(client_name), action);
+
+        let response_event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: Some(proto_msg::event::Dest::Server as i32),
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data: vec![STATUS_PERMISSION_DENIED.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: event.correlation_id
+        };
+
+        self.bus.send(response_event);
+
+        false
+    }
 }
 
 impl From<FSMError> for PluginManError {
-    fn from(_: FSMError) -> Self {
+    fn from(error: FSMError) -> Self {
+        log::error!("State machine failed: {}", error);
         PluginManError::InternalError
     }
 }
+
+impl From<FieldError> for PluginManError {
+    fn from(error: FieldError) -> Self {
+        PluginManError::MalformedEvent(error)
+    }
+}
+
+impl From<io::Error> for PluginManError {
+    fn from(error: io::Error) -> Self {
+        PluginManError::Io(error)
+    }
+}
+
+impl fmt::Display for PluginManError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginManError::MalformedEvent(error) => write!(f, "malformed event: {}", error),
+            PluginManError::PluginGone(fd) => write!(f, "plugin with fd {} has disconnected", fd),
+            PluginManError::UnknownRequest(id) => write!(f, "there is no request with id {:?}", id),
+            PluginManError::MissingCapability(fd, capability) => write!(f, "plugin with fd {} doesn't have {:?} capability", fd, capability),
+            PluginManError::NotFromNode(kind) => write!(f, "event of kind {} came from a client, not from the node", kind),
+            PluginManError::Io(error) => write!(f, "{}", error),
+            PluginManError::InternalError => write!(f, "internal error")
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    env, io, ptr,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus}
+};
+
+// Plugins run with limits, that the node's operator configures.
+// Everything but switching the user works without root:
+//
+//   rlimits      CPU time, address space and open files, plugin is killed, when it runs out of CPU time
+//   user         uid and gid, plugins run as
+//   namespaces   own user, mount, IPC and UTS namespaces, network is shared,
+//                because plugins connect to the manager through loopback
+//   seccomp      system calls, that plugins have no business making, kill them
+//
+// Limits are enforced by the kernel with signals, `exit_reason` turns them into crash reasons
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sandbox {
+    pub cpu_seconds: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    pub max_fds: Option<u64>,
+    // Uid and gid
+    pub user: Option<(u32, u32)>,
+    pub namespaces: bool,
+    pub seccomp: bool
+}
+
+// Variables of the node's environment, that plugins get, the rest might hold its secrets
+const INHERITED_VARS: &[&str] = &["PATH", "LANG", "LC_ALL"];
+
+// System calls, that kill the plugin
+const FORBIDDEN_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_userfaultfd,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_setns,
+    libc::SYS_unshare
+];
+
+// Architecture, that the filter is built for, system calls of other ones are numbered differently
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+// x32 system calls on x86_64 have this bit set, they would bypass the filter
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// Offsets of fields of `seccomp_data`
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+impl Sandbox {
+    // Limits are set, when the process is forked, before the runtime is executed
+    pub fn apply(&self, command: &mut Command) {
+        command.env_clear();
+        for (name, value) in env::vars_os() {
+            if INHERITED_VARS.iter().any(|inherited| name == *inherited) {
+                command.env(name, value);
+            }
+        }
+
+        let filter = match (self.seccomp, AUDIT_ARCH) {
+            (true, Some(arch)) => Some(seccomp_filter(arch)),
+            (true, None) => {
+                log::warn!("Seccomp isn't supported on this architecture, plugins' system calls aren't filtered");
+                None
+            },
+            (false, _) => None
+        };
+
+        let sandbox = self.clone();
+        // Closure runs in the forked process, it must not allocate
+        unsafe {
+            command.pre_exec(move || sandbox.enter(filter.as_deref()));
+        }
+    }
+
+    fn enter(&self, filter: Option<&[libc::sock_filter]>) -> io::Result<()> {
+        // User is switched first, new user namespace maps only the current one
+        if let Some((uid, gid)) = self.user {
+            check(unsafe { libc::setgroups(0, ptr::null()) })?;
+            check(unsafe { libc::setgid(gid) })?;
+            check(unsafe { libc::setuid(uid) })?;
+        }
+
+        if self.namespaces {
+            check(unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS) })?;
+        }
+
+        // Soft limit sends SIGXCPU, hard one a second later SIGKILL
+        if let Some(seconds) = self.cpu_seconds {
+            set_limit(libc::RLIMIT_CPU, seconds, seconds.saturating_add(1))?;
+        }
+        if let Some(bytes) = self.memory_bytes {
+            set_limit(libc::RLIMIT_AS, bytes, bytes)?;
+        }
+        if let Some(fds) = self.max_fds {
+            set_limit(libc::RLIMIT_NOFILE, fds, fds)?;
+        }
+        // Core dump of a crashed plugin might be as big as its memory
+        set_limit(libc::RLIMIT_CORE, 0, 0)?;
+
+        if let Some(filter) = filter {
+            let program = libc::sock_fprog {
+                len: filter.len() as libc::c_ushort,
+                filter: filter.as_ptr() as *mut libc::sock_filter
+            };
+
+            check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+            check(unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program as *const libc::sock_fprog) })?;
+        }
+
+        Ok(())
+    }
+}
+
+// Why process has exited, signals, that the kernel sends on limits' violations, are named
+pub fn exit_reason(status: &ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with code {}", code),
+        (None, Some(libc::SIGXCPU)) => "CPU time limit exceeded".to_string(),
+        (None, Some(libc::SIGSYS)) => "forbidden system call".to_string(),
+        (None, Some(libc::SIGXFSZ)) => "file size limit exceeded".to_string(),
+        (None, Some(libc::SIGSEGV)) => "segmentation fault".to_string(),
+        (None, Some(libc::SIGKILL)) => "killed".to_string(),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "exited".to_string()
+    }
+}
+
+// Denylist, architecture is checked first, so numbers of other architectures can't slip through
+fn seccomp_filter(arch: u32) -> Vec<libc::sock_filter> {
+    let mut filter = vec![
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_ARCH),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, arch, 1, 0),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+        jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, X32_SYSCALL_BIT, 0, 1),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS)
+    ];
+
+    for syscall in FORBIDDEN_SYSCALLS {
+        filter.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, *syscall as u32, 0, 1));
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS));
+    }
+
+    filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    filter
+}
+
+fn statement(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+// Limits are only lowered, raising the hard one needs root
+fn set_limit(resource: libc::__rlimit_resource_t, soft: u64, hard: u64) -> io::Result<()> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    check(unsafe { libc::getrlimit(resource, &mut limit) })?;
+
+    limit.rlim_max = limit.rlim_max.min(hard as libc::rlim_t);
+    limit.rlim_cur = limit.rlim_max.min(soft as libc::rlim_t);
+    check(unsafe { libc::setrlimit(resource, &limit) })
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
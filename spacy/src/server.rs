@@ -1,130 +1,307 @@
 use std::{
-    collections::HashMap,
-    net::{TcpListener, TcpStream, IpAddr, Ipv4Addr, SocketAddr, Shutdown},
+    collections::{HashMap, HashSet},
+    net::{TcpListener, TcpStream, UdpSocket, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, Shutdown},
     os::unix::prelude::AsRawFd,
-    sync::{mpsc, Arc, Mutex},
+    sync::{mpsc, Arc, Mutex, atomic::{AtomicBool, Ordering}},
     thread,
-    time, io::Write
-};
-use nix::sys::{
-    select::{select, FdSet},
-    time::{TimeVal, TimeValLike}
+    fmt, time, io
 };
 use common::{
-    fsm::{FSM, FSMError},
-    event::{proto_msg, self},
+    bus::{Bus, Inbox, QueueConfig},
+    fsm::{EventQueueConfig, Fsm, FSMError, Overflow, Priority, State},
+    event::{proto_msg, self, FieldError},
+    auth,
+    codec::{Connection, CodecError, Received},
+    reactor::{EventSender, Reactor},
+    requests::Requests,
+    stream::Stream,
+    tls::{self, ClientContext},
     utils
 };
+use rustls::{pki_types::{CertificateDer, ServerName}, ServerConfig};
+use crate::{acl::Acl, config::Config};
+
+// Link-local multicast group, that nodes use to announce themselves over ipv6
+const DISCOVERY_GROUP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0x7370, 0x6163);
+const DISCOVERY_PORT: u16 = 32001;
+
+// How long peer may take to answer during the join handshake
+const HANDSHAKE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+// How long client waits for the response, before it's told the request has timed out
+const REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(60);
+
+// Events, that the server keeps before dropping clients' ones
+const EVENT_QUEUE_CAPACITY: usize = 4096;
+
+// Requests, that clients may send, they all go to the plugin manager
+const CLIENT_REQUEST_KINDS: [proto_msg::event::Kind; 7] = [
+    proto_msg::event::Kind::NewPlugin,
+    proto_msg::event::Kind::RemovePlugin,
+    proto_msg::event::Kind::UpgradePlugin,
+    proto_msg::event::Kind::GetPluginList,
+    proto_msg::event::Kind::GetPluginStatus,
+    proto_msg::event::Kind::GetPluginLogs,
+    proto_msg::event::Kind::NewPluginEvent
+];
+
+// How long the server tries to deliver what's left, when stopping
+const SHUTDOWN_FLUSH_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+// Accepted connection, that hasn't finished the handshake yet
+struct PendingConnection {
+    connection: Connection<Stream>,
+    addr: SocketAddr,
+    accepted_at: time::Instant,
+    state: HandshakeState
+}
+
+// Client's request, that waits for the response
+#[derive(Clone, Copy)]
+struct ClientRequest {
+    fd: i32,
+    // Id, that client has given to the request
+    id: Option<u64>
+}
+
+enum HandshakeState {
+    Started,
+    Challenged { node_id: u128, node_nonce: Vec<u8>, nonce: Vec<u8> }
+}
+
+enum HandshakeStatus {
+    InProgress,
+    Client(String),
+    Node(u128),
+    Failed
+}
 
 pub struct Server {
-    fsm: FSM,
-    event_channel_tx: mpsc::Sender<proto_msg::Event>,
+    fsm: Fsm<ServerState, proto_msg::Event>,
+    event_channel_tx: EventSender<proto_msg::Event>,
     event_channel_rx: mpsc::Receiver<proto_msg::Event>,
-    stream_channel_rx: mpsc::Receiver<TcpStream>,
+    inbox: Inbox,
+    stream_channel_tx: mpsc::Sender<Connection<Stream>>,
+    stream_channel_rx: mpsc::Receiver<Connection<Stream>>,
     servers: HashMap<i32, TcpListener>,
-    clients: HashMap<i32, TcpStream>,
-    nodes: HashMap<i32, TcpStream>,
+    pending: HashMap<i32, PendingConnection>,
+    clients: HashMap<i32, Connection<Stream>>,
+    clients_names: HashMap<i32, String>,
+    requests: Requests<ClientRequest>,
+    nodes: HashMap<i32, Connection<Stream>>,
     nodes_ips: Arc<Mutex<HashMap<IpAddr, i32>>>,
     nodes_ids: HashMap<u128, i32>,
-    readfds: Vec<i32>,
-    scanner_handle: thread::JoinHandle<()>,
+    // Node connections, that this node has initiated, by their fds
+    outgoing_nodes: HashSet<i32>,
+    reactor: Reactor,
+    // Fds, whose readiness is queued and not handled yet. Reactor is edge-triggered,
+    // so readiness is never dropped, it's queued once per fd instead
+    ready_fds: HashSet<i32>,
+    scanners_handles: Vec<thread::JoinHandle<()>>,
+    scanners_running: Arc<AtomicBool>,
 
     node_id: u128,
+    tls_server: Option<Arc<ServerConfig>>,
+    tls_client: Option<ClientContext>,
+    cluster_key: Option<Vec<u8>>,
+    acl: Option<Arc<Acl>>,
+    listen_addrs: Vec<SocketAddr>,
 
-    main_event_channel_tx: mpsc::Sender<proto_msg::Event>
+    bus: Bus
 }
 
+#[derive(Debug)]
 pub enum ServerError {
+    // Event doesn't carry what its kind requires
+    MalformedEvent(FieldError),
+    // Connection, that event is addressed to, is already closed
+    PeerGone(i32),
+    // Response doesn't match any request, that is waiting for it
+    UnknownRequest(Option<u64>),
+    Io(io::Error),
+    // FSM was asked for an impossible transition or a server's thread died
     InternalError
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ServerState {
+    // Initialization
+    Init,
+    // Waiting for events
+    WaitEvent,
+    // Handling event
+    HandleEvent,
+    // Handling incoming event
+    HandleIncomingEvent,
+    // Handling outcoming event
+    HandleOutcomingEvent,
+    // Stop
+    Stop
+}
+
+impl State for ServerState {
+    fn name(&self) -> &'static str {
+        match self {
+            ServerState::Init => "init",
+            ServerState::WaitEvent => "wait_event",
+            ServerState::HandleEvent => "handle_event",
+            ServerState::HandleIncomingEvent => "handle_incoming_event",
+            ServerState::HandleOutcomingEvent => "handle_outcoming_event",
+            ServerState::Stop => "stop"
+        }
+    }
+}
+
 impl Server {
-    // 0 - initialization
-    // 1 - waiting for events
-    // 2 - handling event
-    // 3 - handling incoming event
-    // 4 - handling outcoming event
-    // 5 - stop
-
-    pub fn new(main_event_channel_tx: mpsc::Sender<proto_msg::Event>, node_id: u128) -> Self {
-        let fsm = FSM::new(0, HashMap::from([
-            (0, vec![1, 5]),
-            (1, vec![2, 5]),
-            (2, vec![1, 3, 4, 5]),
-            (3, vec![2, 5]),
-            (4, vec![2, 5]),
-            (5, vec![])
-        ]));
-
-        // Creating main event communication channel
-        let (event_channel_tx, event_channel_rx) = mpsc::channel();
+    pub fn new(bus: Bus, node_id: u128, config: &Config) -> Self {
+        let mut fsm = Fsm::new("server", ServerState::Init, &[
+            (ServerState::Init, &[ServerState::WaitEvent, ServerState::Stop]),
+            (ServerState::WaitEvent, &[ServerState::HandleEvent, ServerState::Stop]),
+            (ServerState::HandleEvent, &[ServerState::WaitEvent, ServerState::HandleIncomingEvent, ServerState::HandleOutcomingEvent, ServerState::Stop]),
+            (ServerState::HandleIncomingEvent, &[ServerState::HandleEvent, ServerState::Stop]),
+            (ServerState::HandleOutcomingEvent, &[ServerState::HandleEvent, ServerState::Stop]),
+            (ServerState::Stop, &[])
+        ]);
+        fsm.guard(ServerState::HandleEvent, ServerState::HandleIncomingEvent, "incoming", |event| event::has_dir(event, proto_msg::event::Dir::Incoming));
+        fsm.guard(ServerState::HandleEvent, ServerState::HandleOutcomingEvent, "outcoming", |event| event::has_dir(event, proto_msg::event::Dir::Outcoming));
+        fsm.set_queue_config(EventQueueConfig { capacity: EVENT_QUEUE_CAPACITY, overflow: Overflow::DropLowest });
+
+        // Creating channel for server's own threads, that wakes the server up
+        let reactor = Reactor::new().unwrap();
+        let (event_channel_tx, event_channel_rx) = reactor.channel();
+
+        // Registering on the bus for events from other subsystems
+        let inbox = bus.register(proto_msg::event::Dest::Server, QueueConfig::default(), reactor.notifier()).unwrap();
 
 		// Creating communication channel with `scanner`
-		let (scanner_stream_channel_tx, stream_channel_rx) = mpsc::channel();
-        let server_event_channel_tx = event_channel_tx.clone();
-
-		// Spawning `scanner`
-        let nodes_ips = Arc::new(Mutex::new(HashMap::new()));
-        let known_nodes_ips = nodes_ips.clone();
-		let scanner_handle = thread::spawn(move || {
-			Self::t_scanner(server_event_channel_tx,
-                            scanner_stream_channel_tx,
-                            known_nodes_ips,
-                            node_id);
-		});
+		let (stream_channel_tx, stream_channel_rx) = mpsc::channel();
 
         Self {
             fsm,
             event_channel_tx,
             event_channel_rx,
+            inbox,
+            stream_channel_tx,
 			stream_channel_rx,
             servers: HashMap::new(),
+            pending: HashMap::new(),
             clients: HashMap::new(),
+            clients_names: HashMap::new(),
+            requests: Requests::new(REQUEST_TIMEOUT),
             nodes: HashMap::new(),
-            nodes_ips,
+            nodes_ips: Arc::new(Mutex::new(HashMap::new())),
             nodes_ids: HashMap::new(),
-            readfds: vec![],
-			scanner_handle,
+            outgoing_nodes: HashSet::new(),
+            reactor,
+            ready_fds: HashSet::new(),
+            scanners_handles: vec![],
+            scanners_running: Arc::new(AtomicBool::new(true)),
 
             node_id,
+            tls_server: config.tls_server.clone(),
+            tls_client: config.tls_client.clone(),
+            cluster_key: config.cluster_key.clone(),
+            acl: config.acl.clone(),
+            listen_addrs: config.listen_addrs.clone(),
 
-            main_event_channel_tx
+            bus
         }
     }
 
-    pub fn start(mut self) -> (mpsc::Sender<proto_msg::Event>,
-                               thread::JoinHandle<Result<(), ServerError>>) {
-        let event_channel_tx_clone = self.event_channel_tx.clone();
-
+    pub fn start(mut self) -> thread::JoinHandle<Result<(), ServerError>> {
         // Starting FSM loop
-        let handle = thread::spawn(move || loop {
-            match self.fsm.state {
-                0 => self.init()?,
-                1 => self.wait_event()?,
-                2 => self.handle_event()?,
-                3 => self.handle_incoming_event()?,
-                4 => self.handle_outcoming_event()?,
-                5 => {
+        thread::spawn(move || loop {
+            match self.fsm.state() {
+                ServerState::Init => self.init()?,
+                ServerState::WaitEvent => self.wait_event()?,
+                ServerState::HandleEvent => self.handle_event()?,
+                ServerState::HandleIncomingEvent => self.handle_incoming_event()?,
+                ServerState::HandleOutcomingEvent => self.handle_outcoming_event()?,
+                ServerState::Stop => {
                     self.stop()?;
                     return Ok(())
                 },
-                _ => unreachable!()
             }
-        });
+        })
+    }
+
+    // Handles a single event right away, as if it was taken from the queue.
+    // Server, that wasn't started, doesn't listen and doesn't look for nodes
+    pub fn process_event(&mut self, event: proto_msg::Event) -> Result<(), ServerError> {
+        if self.fsm.state() == ServerState::Init {
+            self.fsm.transition(ServerState::WaitEvent)?;
+        }
+        if self.fsm.state() == ServerState::WaitEvent {
+            self.fsm.transition(ServerState::HandleEvent)?;
+        }
+
+        let priority = self.get_priority(&event);
+        self.fsm.push_event(event, priority);
 
-        (event_channel_tx_clone, handle)
+        loop {
+            match self.fsm.state() {
+                ServerState::WaitEvent | ServerState::Stop => return Ok(()),
+                ServerState::HandleEvent => self.handle_event()?,
+                ServerState::HandleIncomingEvent => self.handle_incoming_event()?,
+                ServerState::HandleOutcomingEvent => self.handle_outcoming_event()?,
+                _ => unreachable!()
+            }
+        }
     }
 
     fn init(&mut self) -> Result<(), ServerError> {
         log::debug!("State `init`");
 
-        // For each avaliable ip creating a listener(server)
-        // TODO: Add support for ipv6 ips
+		// Spawning `scanner`
+        let server_event_channel_tx = self.event_channel_tx.clone();
+        let scanner_stream_channel_tx = self.stream_channel_tx.clone();
+        let running = self.scanners_running.clone();
+        let known_nodes_ips = self.nodes_ips.clone();
+        let node_id = self.node_id;
+        let tls_client = self.tls_client.clone();
+        let cluster_key = self.cluster_key.clone();
+		self.scanners_handles.push(thread::spawn(move || {
+			Self::t_scanner(server_event_channel_tx,
+                            scanner_stream_channel_tx,
+                            running,
+                            known_nodes_ips,
+                            node_id,
+                            tls_client,
+                            cluster_key);
+		}));
+
+        // Spawning `multicast scanner` for ipv6 networks
+        let server_event_channel_tx = self.event_channel_tx.clone();
+        let scanner_stream_channel_tx = self.stream_channel_tx.clone();
+        let running = self.scanners_running.clone();
+        let known_nodes_ips = self.nodes_ips.clone();
+        let tls_client = self.tls_client.clone();
+        let cluster_key = self.cluster_key.clone();
+        self.scanners_handles.push(thread::spawn(move || {
+            Self::t_multicast_scanner(server_event_channel_tx,
+                                      scanner_stream_channel_tx,
+                                      running,
+                                      known_nodes_ips,
+                                      node_id,
+                                      tls_client,
+                                      cluster_key);
+        }));
+
+        // For each avaliable ip (both ipv4 and ipv6) creating a listener(server)
+        let addrs = if self.listen_addrs.is_empty() {
+            utils::get_socket_addrs(32000)
+        } else {
+            self.listen_addrs.clone()
+        };
         let mut servers = vec![];
-        for ip in utils::get_ipv4_ips() {
-            match TcpListener::bind((ip, 32000)) {
+        for addr in addrs {
+            match TcpListener::bind(addr).and_then(|listener| {
+                // Connections are accepted until `WouldBlock`
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            }) {
                 Ok(listener) => {
-                    log::info!("Started listener {}", listener.local_addr().unwrap());
+                    log::info!("Started listener {}", addr);
 
                     // Adding a new server to servers-list
                     let fd = listener.as_raw_fd();
@@ -137,112 +314,164 @@ impl Server {
                         dest: None,
                         kind: proto_msg::event::Kind::NewFd as i32,
                         data: vec![fd.to_ne_bytes().to_vec()],
-                        meta: vec![]
+                        meta: vec![],
+                        correlation_id: None
                     };
-                    self.event_channel_tx.send(event).unwrap();
+                    self.event_channel_tx.send(event).map_err(|_| ServerError::InternalError)?;
                 },
                 Err(error) => {
-                    log::warn!("Couldn't start a listener {}: {}", ip, error);
+                    log::warn!("Couldn't start a listener {}: {}", addr, error);
                 }
             }
         }
 
-        self.fsm.transition(1)?;
+        self.fsm.transition(ServerState::WaitEvent)?;
         Ok(())
     }
 
     fn wait_event(&mut self) -> Result<(), ServerError> {
         // log::debug!("State `wait_event`");
 
-        // Waiting for new events
-        match self.event_channel_rx.try_recv() {
-            Ok(event) => {
-                if event.kind == proto_msg::event::Kind::NewFd as i32 {
-                    let bytes = event.data.get(0).unwrap();
-                    let fd = utils::i32_from_ne_bytes(bytes).unwrap();
-                    self.readfds.push(fd);
+        // Taking everything, that other threads have sent
+        while let Ok(event) = self.event_channel_rx.try_recv() {
+            if event.kind == proto_msg::event::Kind::NewFd as i32 {
+                if let Ok(fd) = event::get_i32(&event.data, 0) {
+                    if let Err(error) = self.reactor.register(fd) {
+                        log::warn!("Couldn't watch fd {}: {}", fd, error);
+                    }
                 }
+            }
 
-                else if event.kind == proto_msg::event::Kind::OldFd as i32 {
-                    let bytes = event.data.get(0).unwrap();
-                    let fd = utils::i32_from_ne_bytes(bytes).unwrap();
+            else if event.kind == proto_msg::event::Kind::OldFd as i32 {
+                if let Ok(fd) = event::get_i32(&event.data, 0) {
+                    // Closed fds are removed by epoll itself
+                    let _ = self.reactor.deregister(fd);
+                }
+            }
 
-                    let mut fd_index = 0;
-                    for i in 0..self.readfds.len() {
-                        if *self.readfds.get(i).unwrap() == fd {
-                            fd_index = i;
-                            break;
+            // New connections of the scanners, their streams are already waiting in the channel
+            else {
+                let priority = self.get_priority(&event);
+                self.fsm.push_reliable_event(event, priority);
+            }
+        }
+
+        while let Some(event) = self.inbox.try_recv() {
+            let priority = self.get_priority(&event);
+
+            // Response to the client, that waits for it, is never dropped. Their number is bounded
+            // by the number of requests, as the plugin manager answers only those it has got
+            let is_awaited_response = event.kind == proto_msg::event::Kind::RespondClient as i32
+                && event.correlation_id.is_some_and(|id| self.requests.get(id).is_some());
+
+            if is_awaited_response {
+                self.fsm.push_reliable_event(event, priority);
+            } else {
+                self.fsm.push_event(event, priority);
+            }
+        }
+
+        // Sleeping until sockets are ready, some event is sent or handshake expires
+        if self.fsm.is_queue_empty() {
+            let timeout = self.pending.values()
+                .map(|pending| HANDSHAKE_TIMEOUT.saturating_sub(pending.accepted_at.elapsed()))
+                .chain(self.requests.next_timeout())
+                .min();
+
+            match self.reactor.wait(timeout) {
+                Ok(fds) => {
+                    // Send events to the handler
+                    for fd in fds {
+                        if !self.ready_fds.insert(fd) {
+                            continue;
                         }
-                    }
-                    self.readfds.remove(fd_index);
-                }
 
-                else{
-                    self.fsm.push_event(event);
+                        let event = proto_msg::Event {
+                            dir: Some(proto_msg::event::Dir::Incoming as i32),
+                            dest: None,
+                            kind: proto_msg::event::Kind::NewStreamEvent as i32,
+                            data: vec![fd.to_ne_bytes().to_vec()],
+                            meta: vec![],
+                            correlation_id: None
+                        };
+
+                        let priority = self.get_priority(&event);
+                        self.fsm.push_reliable_event(event, priority);
+                    }
+                },
+                Err(error) => {
+                    log::warn!("Waiting for events failed: {}", error);
                 }
-            },
-            Err(_) => {}
-        };
-
-        let mut readfds = FdSet::new();
-        for fd in self.readfds.iter() {
-            readfds.insert(*fd);
+            }
         }
 
-        // Wait for acitivy on specified fds
-        let mut timeout = TimeVal::milliseconds(1);
-        let result = select(None, &mut readfds, None, None, &mut timeout);
-        if result.is_err() {
-            log::warn!("`select` exited with error: {:?}", result.err());
+        // Dropping connections, that didn't manage to finish the handshake in time
+        let expired_fds: Vec<i32> = self.pending.iter()
+            .filter(|(_, pending)| pending.accepted_at.elapsed() >= HANDSHAKE_TIMEOUT)
+            .map(|(fd, _)| *fd)
+            .collect();
+        for fd in expired_fds {
+            let pending = self.pending.remove(&fd).unwrap();
+            log::warn!("Handshake with {} timed out", pending.addr);
+            self.drop_connection(fd, pending.connection);
         }
 
-        // Send events to the handler
-        for fd in readfds.fds(None) {
-            let event = proto_msg::Event {
-                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                dest: None,
-                kind: proto_msg::event::Kind::NewStreamEvent as i32,
-                data: vec![fd.to_ne_bytes().to_vec()],
-                meta: vec![]
-            };
+        // Telling clients, that their requests won't be answered
+        for (_, request) in self.requests.take_expired() {
+            log::debug!("Request of client with fd {} timed out", request.fd);
 
-            self.fsm.push_event(event);
+            if let Some(connection) = self.clients.get_mut(&request.fd) {
+                let event = proto_msg::Event {
+                    dir: None,
+                    dest: None,
+                    kind: proto_msg::event::Kind::RequestTimedOut as i32,
+                    data: vec![],
+                    meta: vec![],
+                    correlation_id: request.id
+                };
+
+                if let Err(error) = connection.send(event) {
+                    log::warn!("Couldn't respond to the client: {}", error);
+                }
+            }
         }
 
-        self.fsm.transition(2)?;
+        self.fsm.transition(ServerState::HandleEvent)?;
         Ok(())
     }
 
     fn handle_event(&mut self) -> Result<(), ServerError> {
         // log::debug!("State `handle_event`");
 
-        let event = match self.fsm.pop_front_event() {
-            Some(event) => event,
+        let event_direction = match self.fsm.front_event() {
+            Some(event) => event.dir,
             None => {
-                self.fsm.transition(1)?;
+                self.fsm.transition(ServerState::WaitEvent)?;
                 return Ok(());
             }
         };
-        let event_direction = event.dir;
-        self.fsm.push_front_event(event);
 
-        // Handling event based on it's direction
+        // Handling event based on it's direction, events without one are dropped
         if let Some(dir) = event_direction {
             if dir == proto_msg::event::Dir::Incoming as i32 {
                 log::debug!("Received `incoming` event");
 
-                self.fsm.transition(3)?;
+                self.fsm.transition(ServerState::HandleIncomingEvent)?;
             }
 
             else if dir == proto_msg::event::Dir::Outcoming as i32 {
                 log::debug!("Received `outcoming` event");
 
-                self.fsm.transition(4)?;
+                self.fsm.transition(ServerState::HandleOutcomingEvent)?;
             }
 
             else {
                 log::warn!("Received event with the unknown direction");
+                self.fsm.pop_front_event();
             }
+        } else {
+            log::warn!("Received event without direction");
+            self.fsm.pop_front_event();
         }
 
         Ok(())
@@ -251,210 +480,519 @@ impl Server {
     fn handle_incoming_event(&mut self) -> Result<(), ServerError> {
         log::debug!("State `handle_incoming_event`");
 
-        let event = self.fsm.pop_front_event().unwrap();
+        let event = self.fsm.pop_front_event().ok_or(ServerError::InternalError)?;
+        let kind = event.kind;
 
         // If we got new event from `listener` thread
-        if event.kind == proto_msg::event::Kind::NewStreamEvent as i32 {
-            log::debug!("Handling `new_stream_event`");
-
-            // Parsing fd from the event
-            let bytes = event.data.get(0).unwrap();
-            let fd = utils::i32_from_ne_bytes(bytes).unwrap();
-
-            // Matching fd to handler
-            if self.servers.contains_key(&fd) {
-                self.handle_new_stream_event_server(fd)?;
-            }
-
-            else if self.clients.contains_key(&fd) {
-                self.handle_new_stream_event_client(fd)?;
-            }
-
-            else if self.nodes.contains_key(&fd) {
-                self.handle_new_stream_event_node(fd)?;
-            }
-
-            else {
-                log::warn!("There is no handler for this fd: {}", fd);
-            }
+        let result = if event.kind == proto_msg::event::Kind::NewStreamEvent as i32 {
+            self.handle_new_stream_event(event)
         }
 
         // If we got new event from `scanner` thread
         else if event.kind == proto_msg::event::Kind::NewStream as i32 {
-            self.handle_new_stream(event)?;
+            self.handle_new_stream(event)
+        }
+
+        else if event.kind == proto_msg::event::Kind::Shutdown as i32 {
+            self.fsm.transition(ServerState::Stop)?;
+            return Ok(());
         }
 
         else {
             log::warn!("Received event with unknown kind: {}", event.kind);
-        }
+            Ok(())
+        };
 
-        self.fsm.transition(2)?;
+        Self::recover(kind, result)?;
+
+        self.fsm.transition(ServerState::HandleEvent)?;
         Ok(())
     }
 
     fn handle_outcoming_event(&mut self) -> Result<(), ServerError> {
         log::debug!("State `handle_outcoming_event`");
 
-        let event = self.fsm.pop_front_event().unwrap();
+        let event = self.fsm.pop_front_event().ok_or(ServerError::InternalError)?;
+        let kind = event.kind;
 
         // Broadcasting some event
-        if event.kind == proto_msg::event::Kind::BroadcastEvent as i32 {
-            self.handle_broadcast_event(event)?;
+        let result = if event.kind == proto_msg::event::Kind::BroadcastEvent as i32 {
+            self.handle_broadcast_event(event)
         }
 
         // Approving transaction
         else if event.kind == proto_msg::event::Kind::ApproveTransaction as i32 {
-            self.handle_approve_transaction(event)?;
+            self.handle_approve_transaction(event)
         }
 
         // Send a response to a client
         else if event.kind == proto_msg::event::Kind::RespondClient as i32 {
-            self.handle_respond_client(event)?;
+            self.handle_respond_client(event)
         }
 
         else {
             log::warn!("Received event with unknown kind: {}", event.kind);
-        }
+            Ok(())
+        };
+
+        Self::recover(kind, result)?;
 
-        self.fsm.transition(2)?;
+        self.fsm.transition(ServerState::HandleEvent)?;
         Ok(())
     }
 
-    fn stop(self) -> Result<(), ServerError> {
+    // Cluster's own traffic goes first, so clients can't starve transactions
+    fn get_priority(&self, event: &proto_msg::Event) -> Priority {
+        if event.kind == proto_msg::event::Kind::NewStreamEvent as i32 {
+            match event::get_i32(&event.data, 0) {
+                Ok(fd) if self.nodes.contains_key(&fd) => Priority::Control,
+                _ => Priority::Client
+            }
+        }
+
+        else if event.kind == proto_msg::event::Kind::RespondClient as i32 {
+            Priority::Client
+        }
+
+        else {
+            Priority::Control
+        }
+    }
+
+    // Event, that can't be handled, is dropped, so a peer can't take the server down
+    fn recover(kind: i32, result: Result<(), ServerError>) -> Result<(), ServerError> {
+        match result {
+            Err(ServerError::InternalError) => Err(ServerError::InternalError),
+            Err(error) => {
+                log::warn!("Dropping event of kind {}: {}", kind, error);
+                Ok(())
+            },
+            Ok(_) => Ok(())
+        }
+    }
+
+    fn stop(mut self) -> Result<(), ServerError> {
         log::debug!("State `stop`");
 
-        match self.scanner_handle.join() {
-            Ok(_) => Ok(()),
-            Err(_) => Err(ServerError::InternalError)
-        }?;
+        for (priority, stats) in self.fsm.queue_stats() {
+            log::debug!("Server's queue {:?}: {:?}", priority, stats);
+        }
+
+        // Scanners check this flag between connection attempts
+        self.scanners_running.store(false, Ordering::Relaxed);
+
+        // Delivering what sockets didn't accept yet (e.g. departure announcement)
+        let deadline = time::Instant::now() + SHUTDOWN_FLUSH_TIMEOUT;
+        loop {
+            let mut has_pending_writes = false;
+            for (fd, connection) in self.clients.iter_mut().chain(self.nodes.iter_mut()) {
+                if connection.has_pending_writes() {
+                    match connection.flush() {
+                        Ok(flushed) => has_pending_writes |= !flushed,
+                        Err(error) => log::debug!("Couldn't flush connection {}: {}", fd, error)
+                    }
+                }
+            }
+
+            let now = time::Instant::now();
+            if !has_pending_writes || now >= deadline {
+                break;
+            }
+
+            let _ = self.reactor.wait(Some(deadline - now));
+        }
+
+        // Closing all connections
+        let connections = self.clients.drain()
+            .chain(self.nodes.drain())
+            .chain(self.pending.drain().map(|(fd, pending)| (fd, pending.connection)));
+        for (_fd, connection) in connections {
+            let _ = connection.get_ref().shutdown(Shutdown::Both);
+        }
+
+        log::info!("Connections closed");
+
+        for handle in self.scanners_handles.drain(..) {
+            handle.join().map_err(|_| ServerError::InternalError)?;
+        }
 
         Ok(())
     }
 
+    fn handle_new_stream_event(&mut self, event: proto_msg::Event) -> Result<(), ServerError> {
+        log::debug!("Handling `new_stream_event`");
+
+        // Parsing fd from the event
+        let fd = event::get_i32(&event.data, 0)?;
+        self.ready_fds.remove(&fd);
+
+        // Socket might be ready for writing what it didn't accept previously
+        self.flush_connection(fd);
+
+        // Matching fd to handler
+        if self.servers.contains_key(&fd) {
+            self.handle_new_stream_event_server(fd)
+        }
+
+        else if self.pending.contains_key(&fd) {
+            self.handle_new_stream_event_pending(fd)
+        }
+
+        else if self.clients.contains_key(&fd) {
+            self.handle_new_stream_event_client(fd)
+        }
+
+        else if self.nodes.contains_key(&fd) {
+            self.handle_new_stream_event_node(fd)
+        }
+
+        else {
+            log::warn!("There is no handler for this fd: {}", fd);
+            Ok(())
+        }
+    }
+
     fn handle_new_stream_event_server(&mut self, fd: i32) -> Result<(), ServerError> {
         log::debug!("Handling `new_stream_event` from server");
 
-        let listener = self.servers.get(&fd).unwrap();
+        // Accepting everything, that is waiting
+        loop {
+            let listener = self.servers.get(&fd).ok_or(ServerError::PeerGone(fd))?;
+
+            let (stream, addr) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    log::warn!("Couldn't accept new connection: {}", error);
+                    break;
+                }
+            };
 
-        match listener.accept() {
-            Ok((mut stream, addr)) => {
-                // Getting stream's fd
-                let new_fd = stream.as_raw_fd();
+            // Getting stream's fd
+            let new_fd = stream.as_raw_fd();
 
-                // Handshake
-                let events = utils::read_events(&mut stream).unwrap();
-                // Assuming we will get only one event
-                let handshake_event = events.get(0).unwrap();
+            // Nobody should be able to block the server with a partial message
+            if let Err(error) = stream.set_nonblocking(true) {
+                log::warn!("Couldn't set up connection {}: {}", addr, error);
+                continue;
+            }
 
-                let mut handshake_ok = true;
-                if handshake_event.kind == proto_msg::event::Kind::MarkMeClient as i32 {
-                    log::info!("New client connected {}", addr);
-                    self.clients.insert(new_fd, stream);
+            // Wrapping into TLS, if it's enabled
+            let stream = match Stream::accept(stream, self.tls_server.as_ref()) {
+                Ok(stream) => stream,
+                Err(error) => {
+                    log::warn!("Couldn't set up connection {}: {}", addr, error);
+                    continue;
                 }
+            };
 
-                else if handshake_event.kind == proto_msg::event::Kind::MarkMeNode as i32 {
-                    {
-                        let mut nodes_ips = self.nodes_ips.lock().unwrap();
+            // Connection stays pending until the handshake is finished
+            self.pending.insert(new_fd, PendingConnection {
+                connection: Connection::new(stream),
+                addr,
+                accepted_at: time::Instant::now(),
+                state: HandshakeState::Started
+            });
 
-                        if !nodes_ips.contains_key(&addr.ip()) {
-                            log::info!("New node connected {}", addr);
-
-                            // Notify `node` about new connection
-                            self.main_event_channel_tx.send(proto_msg::Event {
-                                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                                dest: Some(proto_msg::event::Dest::Node as i32),
-                                kind: proto_msg::event::Kind::NodeConnected as i32,
-                                data: handshake_event.data.clone(),
-                                meta: vec![]
-                            }).unwrap();
-
-                            // Respond with this node id
-                            let event = proto_msg::Event {
-                                dir: None,
-                                dest: None,
-                                kind: 0,
-                                data: vec![self.node_id.to_ne_bytes().to_vec()],
-                                meta: vec![]
-                            };
-                            stream.write(&event::serialize(event)).unwrap();
-
-                            self.nodes.insert(new_fd, stream);
-                            nodes_ips.insert(addr.ip(), fd);
-
-                            let bytes = handshake_event.data.get(0).unwrap();
-                            let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
-
-                            self.nodes_ids.insert(node_id, new_fd);
-                        } else {
-                            return Ok(());
-                        }
+            // Notify `listener` thread about new fd
+            self.event_channel_tx.send(proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Incoming as i32),
+                dest: None,
+                kind: proto_msg::event::Kind::NewFd as i32,
+                data: vec![new_fd.to_ne_bytes().to_vec()],
+                meta: vec![],
+                correlation_id: None
+            }).map_err(|_| ServerError::InternalError)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_new_stream_event_pending(&mut self, fd: i32) -> Result<(), ServerError> {
+        log::debug!("Handling `new_stream_event` from pending connection");
+
+        let mut pending = self.pending.remove(&fd).ok_or(ServerError::PeerGone(fd))?;
+
+        // TLS handshake, if any, happens on these reads
+        let received = match pending.connection.read_events() {
+            Ok(received) => received,
+            Err(error) => {
+                log::warn!("Handshake with {} failed: {}", pending.addr, error);
+                self.drop_connection(fd, pending.connection);
+                return Ok(());
+            }
+        };
+
+        let mut events = received.events.into_iter();
+        while let Some(event) = events.next() {
+            match self.handle_handshake_event(fd, &mut pending, event) {
+                // Handshake is not finished yet
+                HandshakeStatus::InProgress => continue,
+                HandshakeStatus::Client(client_name) => {
+                    log::info!("New client connected {} {}", pending.addr, client_name);
+                    self.clients.insert(fd, pending.connection);
+                    self.clients_names.insert(fd, client_name);
+
+                    // Client might have sent requests right after the handshake
+                    self.forward_client_events(fd, events.collect());
+                    return Ok(());
+                },
+                HandshakeStatus::Node(node_id) => {
+                    self.register_node(fd, pending.connection, pending.addr, node_id, false);
+
+                    self.forward_node_events(fd, events.collect());
+                    return Ok(());
+                },
+                HandshakeStatus::Failed => {
+                    self.drop_connection(fd, pending.connection);
+                    return Ok(());
+                }
+            }
+        }
+
+        if received.closed {
+            log::warn!("Handshake with {} failed: connection closed", pending.addr);
+            self.drop_connection(fd, pending.connection);
+            return Ok(());
+        }
+
+        self.pending.insert(fd, pending);
+
+        Ok(())
+    }
+
+    // Accepting side of the node's join handshake:
+    // 1. node -> us: `mark_me_node` [node_id, node_nonce]
+    // 2. us -> node: [our_id, our_nonce, hmac("responder", node_nonce, our_nonce, node_id, our_id)]
+    // 3. node -> us: `auth_response` [hmac("initiator", node_nonce, our_nonce, node_id, our_id)]
+    // 4. us -> node: `auth_response` [] (or connection is closed)
+    // Without cluster key only node ids are exchanged.
+    // Clients just send `mark_me_client` [token].
+    // With TLS, only nodes' certificates may join as nodes, and clients are named by their certificates
+    fn handle_handshake_event(&mut self, fd: i32, pending: &mut PendingConnection,
+                              event: proto_msg::Event) -> HandshakeStatus {
+        let addr = pending.addr;
+
+        match &pending.state {
+            HandshakeState::Started => {
+                if event.kind == proto_msg::event::Kind::MarkMeClient as i32 {
+                    // With TLS client is named by its certificate
+                    let certificate_name = match self.peer_certificate(pending) {
+                        Some((cert, server_name)) => match tls::client_name(cert, server_name) {
+                            Some(name) => Some(name),
+                            None => {
+                                log::warn!("Client {} failed authentication: its certificate doesn't name a client", addr);
+                                return HandshakeStatus::Failed;
+                            }
+                        },
+                        None => None
+                    };
+
+                    // Identifying client by its token, it must belong to the client, that certificate names
+                    let client_name = match &self.acl {
+                        Some(acl) => {
+                            let token = event.data.first().map(|token| token.as_slice()).unwrap_or_default();
+                            match acl.authenticate(token) {
+                                Some(client) if certificate_name.as_ref().is_none_or(|name| *name == client.name) => client.name.clone(),
+                                _ => {
+                                    log::warn!("Client {} failed authentication", addr);
+                                    return HandshakeStatus::Failed;
+                                }
+                            }
+                        },
+                        None => certificate_name.unwrap_or_default()
+                    };
+
+                    return HandshakeStatus::Client(client_name);
+                }
+
+                else if event.kind != proto_msg::event::Kind::MarkMeNode as i32 {
+                    log::warn!("Handshake with {} failed: unexpected event {}", addr, event.kind);
+                    return HandshakeStatus::Failed;
+                }
+
+                // Clients' certificates are signed by the same CA, so the certificate must be a node's one
+                if let Some((cert, server_name)) = self.peer_certificate(pending) {
+                    if !tls::is_node_certificate(cert, server_name) {
+                        log::warn!("Handshake with {} failed: its certificate isn't a node's one", addr);
+                        return HandshakeStatus::Failed;
                     }
                 }
 
-                else {
-                    stream.shutdown(Shutdown::Both).unwrap();
-                    handshake_ok = false;
+                let node_id = match event.data.first().and_then(|bytes| utils::u128_from_ne_bytes(bytes).ok()) {
+                    Some(node_id) => node_id,
+                    None => {
+                        log::warn!("Handshake with {} failed: malformed `mark_me_node`", addr);
+                        return HandshakeStatus::Failed;
+                    }
+                };
+
+                // Node, that claims our id, is either us or someone replaying our handshake
+                if node_id == self.node_id {
+                    log::warn!("Handshake with {} failed: it claims id of this node", addr);
+                    return HandshakeStatus::Failed;
                 }
 
-                if handshake_ok {
-                    // Notify `listener` thread about new fd
-                    self.event_channel_tx.send(proto_msg::Event {
-                        dir: Some(proto_msg::event::Dir::Incoming as i32),
-                        dest: None,
-                        kind: proto_msg::event::Kind::NewFd as i32,
-                        data: vec![new_fd.to_ne_bytes().to_vec()],
-                        meta: vec![]
-                    }).unwrap();
-                } else {
-                    log::warn!("Handshake failed");
+                // Same node may be reachable from several ips (e.g. ipv4 and ipv6), while one ip
+                // may belong to several nodes or to the node, that has restarted with a new id.
+                // So duplicates are told by ids only
+                if let Some(&known_fd) = self.nodes_ids.get(&node_id) {
+                    // Connection is incoming, remote node has initiated it
+                    let initiator = node_id;
+                    if !self.replaces_known_connection(node_id, known_fd, initiator) {
+                        log::debug!("Node {} is already connected, dropping connection {}", node_id, addr);
+
+                        let mut nodes_ips = self.nodes_ips.lock().unwrap();
+                        nodes_ips.insert(addr.ip(), known_fd);
+
+                        return HandshakeStatus::Failed;
+                    }
                 }
+
+                let key = match &self.cluster_key {
+                    Some(key) => key,
+                    None => {
+                        // Respond with this node id
+                        let event = proto_msg::Event {
+                            dir: None,
+                            dest: None,
+                            kind: 0,
+                            data: vec![self.node_id.to_ne_bytes().to_vec()],
+                            meta: vec![],
+                            correlation_id: None
+                        };
+
+                        return match pending.connection.send(event) {
+                            Ok(_) => HandshakeStatus::Node(node_id),
+                            Err(_) => HandshakeStatus::Failed
+                        };
+                    }
+                };
+
+                let node_nonce = match event.data.get(1) {
+                    Some(nonce) if nonce.len() == auth::NONCE_LEN => nonce,
+                    _ => {
+                        log::warn!("Node {} failed authentication: it's probably not configured with cluster key", addr);
+                        return HandshakeStatus::Failed;
+                    }
+                };
+
+                // Proving that we know the cluster key and challenging the node
+                let nonce = auth::generate_nonce();
+                let transcript = auth::JoinTranscript {
+                    initiator_nonce: node_nonce,
+                    responder_nonce: &nonce,
+                    initiator_id: node_id,
+                    responder_id: self.node_id
+                };
+                let event = proto_msg::Event {
+                    dir: None,
+                    dest: None,
+                    kind: 0,
+                    data: vec![
+                        self.node_id.to_ne_bytes().to_vec(),
+                        nonce.clone(),
+                        auth::sign_join(key, auth::JoinRole::Responder, &transcript)
+                    ],
+                    meta: vec![],
+                    correlation_id: None
+                };
+
+                if pending.connection.send(event).is_err() {
+                    return HandshakeStatus::Failed;
+                }
+
+                pending.state = HandshakeState::Challenged { node_id, node_nonce: node_nonce.clone(), nonce };
+
+                HandshakeStatus::InProgress
             },
-            Err(error) => {
-                log::warn!("Couldn't accept new connection: {}", error);
+
+            HandshakeState::Challenged { node_id, node_nonce, nonce } => {
+                let node_id = *node_id;
+                let key = self.cluster_key.as_ref().unwrap();
+                let transcript = auth::JoinTranscript {
+                    initiator_nonce: node_nonce,
+                    responder_nonce: nonce,
+                    initiator_id: node_id,
+                    responder_id: self.node_id
+                };
+
+                let authenticated = event.kind == proto_msg::event::Kind::AuthResponse as i32 &&
+                    event.data.first()
+                        .map(|tag| auth::verify_join(key, auth::JoinRole::Initiator, &transcript, tag))
+                        .unwrap_or(false);
+
+                if !authenticated {
+                    log::warn!("Node {} failed authentication", addr);
+                    return HandshakeStatus::Failed;
+                }
+
+                // Node might have connected by other means while we were waiting
+                if let Some(&known_fd) = self.nodes_ids.get(&node_id) {
+                    if !self.replaces_known_connection(node_id, known_fd, node_id) {
+                        log::debug!("Node {} is already connected, dropping connection {}", node_id, addr);
+                        return HandshakeStatus::Failed;
+                    }
+                }
+
+                let event = proto_msg::Event {
+                    dir: None,
+                    dest: None,
+                    kind: proto_msg::event::Kind::AuthResponse as i32,
+                    data: vec![],
+                    meta: vec![],
+                    correlation_id: None
+                };
+
+                log::debug!("Node {} with fd {} authenticated", node_id, fd);
+
+                match pending.connection.send(event) {
+                    Ok(_) => HandshakeStatus::Node(node_id),
+                    Err(_) => HandshakeStatus::Failed
+                }
             }
-        };
+        }
+    }
 
-        Ok(())
+    // Certificate of the peer together with the nodes' server name, if TLS is enabled
+    fn peer_certificate<'a>(&'a self, pending: &'a PendingConnection) -> Option<(&'a CertificateDer<'static>, &'a ServerName<'static>)> {
+        let context = self.tls_client.as_ref()?;
+        let cert = pending.connection.get_ref().peer_certificate()?;
+
+        Some((cert, &context.server_name))
     }
 
     fn handle_new_stream_event_client(&mut self, fd: i32) -> Result<(), ServerError> {
         log::debug!("Handling `new_stream_event` from client");
 
-        let stream = self.clients.get_mut(&fd).unwrap();
+        let connection = self.clients.get_mut(&fd).ok_or(ServerError::PeerGone(fd))?;
 
         // Getting sent events
-        let events = utils::read_events(stream).unwrap();
-        if events.len() != 0 {
-            log::debug!("Received new {}-event message from the client", events.len());
+        let received = match connection.read_events() {
+            Ok(received) => received,
+            Err(error) => {
+                log::warn!("Dropping client {:?}: {}", connection.get_ref().peer_addr(), error);
+                Received { events: vec![], closed: true }
+            }
+        };
 
-            for event in events {
-                let event = proto_msg::Event {
-                    dir: Some(proto_msg::event::Dir::Incoming as i32),
-                    dest: Some(proto_msg::event::Dest::PluginMan as i32),
-                    kind: event.kind,
-                    data: event.data,
-                    meta: vec![fd.to_ne_bytes().to_vec()]
-                };
+        if !received.events.is_empty() {
+            log::debug!("Received new {}-event message from the client", received.events.len());
 
-                self.main_event_channel_tx.send(event).unwrap();
-            }
+            self.forward_client_events(fd, received.events);
         }
 
-        else {
-            log::info!("Client disconnected {}", stream.peer_addr().unwrap());
-
-            // Disconnecting client
-            stream.shutdown(Shutdown::Both).unwrap();
-            self.clients.remove(&fd);
+        if received.closed {
+            self.clients_names.remove(&fd);
+            self.requests.retain(|request| request.fd != fd);
+            if let Some(connection) = self.clients.remove(&fd) {
+                if let Ok(addr) = connection.get_ref().peer_addr() {
+                    log::info!("Client disconnected {}", addr);
+                }
 
-            // Notify `listener` thread about old fd
-            self.event_channel_tx.send(proto_msg::Event {
-                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                dest: None,
-                kind: proto_msg::event::Kind::OldFd as i32,
-                data: vec![fd.to_ne_bytes().to_vec()],
-                meta: vec![]
-            }).unwrap();
+                // Disconnecting client
+                self.drop_connection(fd, connection);
+            }
         }
 
         Ok(())
@@ -463,74 +1001,53 @@ impl Server {
     fn handle_new_stream_event_node(&mut self, fd: i32) -> Result<(), ServerError> {
         log::debug!("Handling `new_stream_event` from node");
 
-        let stream = self.nodes.get_mut(&fd).unwrap();
+        let connection = self.nodes.get_mut(&fd).ok_or(ServerError::PeerGone(fd))?;
 
         // Getting sent events
-        let events = utils::read_events(stream).unwrap();
-        if events.len() != 0 {
-            log::debug!("Received new {}-event message from the node", events.len());
-
-            for event in events {
-                // Adding fd to event's meta information
-                let mut meta = event.meta;
-                meta.insert(0, fd.to_ne_bytes().to_vec());
+        let received = match connection.read_events() {
+            Ok(received) => received,
+            Err(error) => {
+                log::warn!("Dropping node {:?}: {}", connection.get_ref().peer_addr(), error);
+                Received { events: vec![], closed: true }
+            }
+        };
 
-                let event = proto_msg::Event {
-                    dir: Some(proto_msg::event::Dir::Incoming as i32),
-                    dest: Some(proto_msg::event::Dest::Node as i32),
-                    kind: event.kind,
-                    data: event.data,
-                    meta
-                };
+        if !received.events.is_empty() {
+            log::debug!("Received new {}-event message from the node", received.events.len());
 
-                self.main_event_channel_tx.send(event).unwrap();
-            }
+            self.forward_node_events(fd, received.events);
         }
 
-        else {
-            log::info!("Node disconnected {}", stream.peer_addr().unwrap());
+        if received.closed {
+            let connection = self.nodes.remove(&fd).ok_or(ServerError::PeerGone(fd))?;
+            self.outgoing_nodes.remove(&fd);
 
-            // Disconnecting node
-            let addr = stream.peer_addr().unwrap();
-            stream.shutdown(Shutdown::Both).unwrap();
-            self.nodes.remove(&fd);
-
-            {
-                let mut nodes_ips = self.nodes_ips.lock().unwrap();
-                nodes_ips.remove(&addr.ip());
+            if let Ok(addr) = connection.get_ref().peer_addr() {
+                log::info!("Node disconnected {}", addr);
             }
+            // Node might be known by several ips, other nodes might share them
+            self.nodes_ips.lock().unwrap().retain(|_, node_fd| *node_fd != fd);
 
-            let nodes_ids_clone = self.nodes_ids.clone();
+            let id_to_del = self.nodes_ids.iter()
+                .find(|(_, node_fd)| fd == **node_fd)
+                .map(|(node_id, _)| *node_id);
 
-            let mut id_to_del = None;
-            for (node_id, node_fd) in nodes_ids_clone.iter() {
-                if fd == *node_fd {
-                    id_to_del = Some(node_id);
-                    break;
-                }
-            }
+            // Notify `node` about old connection
+            if let Some(node_id) = id_to_del {
+                self.nodes_ids.remove(&node_id);
 
-            if let Some(id) = id_to_del {
-                self.nodes_ids.remove(&id);
+                self.bus.send(proto_msg::Event {
+                    dir: Some(proto_msg::event::Dir::Incoming as i32),
+                    dest: Some(proto_msg::event::Dest::Node as i32),
+                    kind: proto_msg::event::Kind::NodeDisconnected as i32,
+                    data: vec![node_id.to_ne_bytes().to_vec()],
+                    meta: vec![],
+                    correlation_id: None
+                });
             }
 
-            // Notify `node` about old connection
-            self.main_event_channel_tx.send(proto_msg::Event {
-                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                dest: Some(proto_msg::event::Dest::Node as i32),
-                kind: proto_msg::event::Kind::NodeDisconnected as i32,
-                data: vec![id_to_del.unwrap().to_ne_bytes().to_vec()],
-                meta: vec![]
-            }).unwrap();
-
-            // Notify `listener` thread about old client
-            self.event_channel_tx.send(proto_msg::Event {
-                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                dest: None,
-                kind: proto_msg::event::Kind::OldFd as i32,
-                data: vec![fd.to_ne_bytes().to_vec()],
-                meta: vec![]
-            }).unwrap();
+            // Disconnecting node
+            self.drop_connection(fd, connection);
         }
 
         Ok(())
@@ -540,31 +1057,30 @@ impl Server {
         log::debug!("Handling `new_stream`");
 
         // Connecting new node
-        let stream = self.stream_channel_rx.recv().unwrap();
-        let addr = stream.peer_addr().unwrap();
+        // Scanner sends the stream before notifying the server
+        let connection = self.stream_channel_rx.try_recv().map_err(|_| ServerError::InternalError)?;
+        let node_id = event::get_u128(&event.data, 0)?;
 
-        log::info!("New node connected {}", addr);
+        // Node might have already gone, connection is closed on return then
+        let addr = connection.get_ref().peer_addr()?;
 
-        let fd = stream.as_raw_fd();
-        self.nodes.insert(fd, stream);
-        {
-            let mut nodes_ips = self.nodes_ips.lock().unwrap();
-            nodes_ips.insert(addr.ip(), fd);
-        }
+        // Same node may be reachable from several ips (e.g. ipv4 and ipv6)
+        if let Some(&known_fd) = self.nodes_ids.get(&node_id) {
+            // Connection is outgoing, this node has initiated it
+            let initiator = self.node_id;
+            if !self.replaces_known_connection(node_id, known_fd, initiator) {
+                log::debug!("Node {} is already connected, dropping connection {}", node_id, addr);
 
-        let bytes = event.data.get(0).unwrap();
-        let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+                let mut nodes_ips = self.nodes_ips.lock().unwrap();
+                nodes_ips.insert(addr.ip(), known_fd);
 
-        self.nodes_ids.insert(node_id, fd);
+                let _ = connection.get_ref().shutdown(Shutdown::Both);
+                return Ok(());
+            }
+        }
 
-        // Notify `node` about new connection
-        self.main_event_channel_tx.send(proto_msg::Event {
-            dir: Some(proto_msg::event::Dir::Incoming as i32),
-            dest: Some(proto_msg::event::Dest::Node as i32),
-            kind: proto_msg::event::Kind::NodeConnected as i32,
-            data: event.data,
-            meta: vec![]
-        }).unwrap();
+        let fd = connection.get_ref().as_raw_fd();
+        self.register_node(fd, connection, addr, node_id, true);
 
         // Notify `listener` thread about new client
         self.event_channel_tx.send(proto_msg::Event {
@@ -572,26 +1088,30 @@ impl Server {
             dest: None,
             kind: proto_msg::event::Kind::NewFd as i32,
             data: vec![fd.to_ne_bytes().to_vec()],
-            meta: vec![]
-        }).unwrap();
+            meta: vec![],
+            correlation_id: None
+        }).map_err(|_| ServerError::InternalError)?;
 
         Ok(())
     }
 
     fn handle_broadcast_event(&mut self, event: proto_msg::Event) -> Result<(), ServerError> {
-        let actual_event = event.data.get(0).unwrap();
+        let actual_event = event::get_field(&event.data, 0)?;
+        let (actual_events, _rem) = event::deserialize(actual_event);
 
-        let bytes = event.data.get(1).unwrap();
-        let nodes_count = utils::i32_from_ne_bytes(bytes).unwrap();
+        let nodes_count = event::get_i32(&event.data, 1)?;
 
-        for i in 0..nodes_count {
-            let index = 2 + i;
-            let bytes = event.data.get(index as usize).unwrap();
-            let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
+        for i in 0..nodes_count.max(0) as usize {
+            let node_id = event::get_u128(&event.data, 2 + i)?;
 
-            if let Some(fd) = self.nodes_ids.get(&node_id) {
-                let mut stream = self.nodes.get(&fd).unwrap();
-                stream.write(actual_event).unwrap();
+            let connection = self.nodes_ids.get(&node_id)
+                .and_then(|fd| self.nodes.get_mut(fd));
+            if let Some(connection) = connection {
+                for actual_event in actual_events.iter() {
+                    if let Err(error) = connection.send(actual_event.clone()) {
+                        log::warn!("Couldn't send event to the node {}: {}", node_id, error);
+                    }
+                }
             }
         }
 
@@ -601,13 +1121,11 @@ impl Server {
     fn handle_approve_transaction(&mut self, event: proto_msg::Event) -> Result<(), ServerError> {
         log::debug!("Handling `approve_transaction`");
 
-        let bytes = event.meta.get(0).unwrap();
-        let node_fd = utils::i32_from_ne_bytes(bytes).unwrap();
+        let node_fd = event::get_i32(&event.meta, 0)?;
+        let meta = event.meta[1..].to_vec();
 
-        let meta = (&event.meta[1..]).to_vec();
-
-        // TODO: Add check for node being already disconnected
-        let mut node_stream = self.nodes.get(&node_fd).unwrap();
+        // Node might have disconnected while transaction was handled
+        let connection = self.nodes.get_mut(&node_fd).ok_or(ServerError::PeerGone(node_fd))?;
 
         // Removing meta information
         let event = proto_msg::Event {
@@ -615,10 +1133,13 @@ impl Server {
             dest: event.dest,
             kind: event.kind,
             data: event.data,
-            meta
+            meta,
+            correlation_id: None
         };
 
-        node_stream.write(&event::serialize(event)).unwrap();
+        if let Err(error) = connection.send(event) {
+            log::warn!("Couldn't send approval to the node: {}", error);
+        }
 
         Ok(())
     }
@@ -626,36 +1147,167 @@ impl Server {
     fn handle_respond_client(&mut self, event: proto_msg::Event) -> Result<(), ServerError> {
         log::debug!("Handling `respond_client`");
 
-        let first_arg = event.meta.get(0).unwrap();
-        let client_fd = utils::i32_from_ne_bytes(first_arg).unwrap();
+        // Response is matched with the request by id, not by fd, that could be reused.
+        // Request waits, until its last response
+        let partial = event::is_partial_response(&event);
+        let request = event.correlation_id
+            .and_then(|id| if partial { self.requests.get(id).copied() } else { self.requests.take(id) })
+            .ok_or(ServerError::UnknownRequest(event.correlation_id))?;
 
-        let meta = (&event.meta[1..]).to_vec();
-
-        if self.clients.contains_key(&client_fd) {
-            let mut client_stream = self.clients.get(&client_fd).unwrap();
-
-            // Removing meta information
+        if let Some(connection) = self.clients.get_mut(&request.fd) {
+            // Client gets back the id it has given, marker tells it, whether more responses follow
+            let meta = if partial { vec![event::PARTIAL_RESPONSE.to_vec()] } else { vec![] };
             let event = proto_msg::Event {
                 dir: event.dir,
                 dest: event.dest,
                 kind: event.kind,
                 data: event.data,
-                meta
+                meta,
+                correlation_id: request.id
             };
 
-            client_stream.write(&event::serialize(event)).unwrap();
+            if let Err(error) = connection.send(event) {
+                log::warn!("Couldn't respond to the client: {}", error);
+            }
         }
 
         Ok(())
     }
 
-    fn t_scanner(server_event_tx: mpsc::Sender<proto_msg::Event>,
-				 server_stream_tx: mpsc::Sender<TcpStream>,
+    fn forward_client_events(&mut self, fd: i32, events: Vec<proto_msg::Event>) {
+        let client_name = self.clients_names.get(&fd).cloned().unwrap_or_default();
+
+        for event in events {
+            // Other kinds are node's commands and responses, client can't pose as the node
+            if !CLIENT_REQUEST_KINDS.iter().any(|kind| *kind as i32 == event.kind) {
+                log::warn!("Client with fd {} sent event of kind {}, that clients can't send", fd, event.kind);
+                continue;
+            }
+
+            // Every request gets node-wide id, response is routed back by it
+            let request_id = self.requests.insert(ClientRequest { fd, id: event.correlation_id });
+
+            // Client's identity is passed, so `plugin manager` can check permissions
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Incoming as i32),
+                dest: Some(proto_msg::event::Dest::PluginMan as i32),
+                kind: event.kind,
+                data: event.data,
+                meta: vec![client_name.as_bytes().to_vec()],
+                correlation_id: Some(request_id)
+            };
+
+            self.bus.send(event);
+        }
+    }
+
+    fn forward_node_events(&mut self, fd: i32, events: Vec<proto_msg::Event>) {
+        for event in events {
+            if event.kind == proto_msg::event::Kind::Shutdown as i32 {
+                log::warn!("Node with fd {} tried to shut the node down", fd);
+                continue;
+            }
+
+            // Adding fd to event's meta information
+            let mut meta = event.meta;
+            meta.insert(0, fd.to_ne_bytes().to_vec());
+
+            let event = proto_msg::Event {
+                dir: Some(proto_msg::event::Dir::Incoming as i32),
+                dest: Some(proto_msg::event::Dest::Node as i32),
+                kind: event.kind,
+                data: event.data,
+                meta,
+                correlation_id: None
+            };
+
+            self.bus.send(event);
+        }
+    }
+
+    // Whether the new connection with the known node, initiated by `initiator`, takes the place of the known one
+    fn replaces_known_connection(&self, node_id: u128, known_fd: i32, initiator: u128) -> bool {
+        let known_initiator = if self.outgoing_nodes.contains(&known_fd) { self.node_id } else { node_id };
+        replaces_connection(self.node_id, node_id, known_initiator, initiator)
+    }
+
+    fn register_node(&mut self, fd: i32, connection: Connection<Stream>, addr: SocketAddr, node_id: u128, outgoing: bool) {
+        self.nodes.insert(fd, connection);
+        if outgoing {
+            self.outgoing_nodes.insert(fd);
+        }
+
+        // Connection, that has lost the tie-break, is closed quietly, node itself stays connected
+        if let Some(known_fd) = self.nodes_ids.insert(node_id, fd) {
+            log::debug!("Connection {} with the node {} replaces connection {}", fd, node_id, known_fd);
+
+            {
+                let mut nodes_ips = self.nodes_ips.lock().unwrap();
+                nodes_ips.values_mut().filter(|node_fd| **node_fd == known_fd).for_each(|node_fd| *node_fd = fd);
+                nodes_ips.insert(addr.ip(), fd);
+            }
+
+            self.outgoing_nodes.remove(&known_fd);
+            if let Some(connection) = self.nodes.remove(&known_fd) {
+                self.drop_connection(known_fd, connection);
+            }
+
+            return;
+        }
+
+        log::info!("New node connected {}", addr);
+        self.nodes_ips.lock().unwrap().insert(addr.ip(), fd);
+
+        // Notify `node` about new connection
+        self.bus.send(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: Some(proto_msg::event::Dest::Node as i32),
+            kind: proto_msg::event::Kind::NodeConnected as i32,
+            data: vec![node_id.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: None
+        });
+    }
+
+    fn flush_connection(&mut self, fd: i32) {
+        let connection = match self.pending.get_mut(&fd) {
+            Some(pending) => Some(&mut pending.connection),
+            None => self.clients.get_mut(&fd).or(self.nodes.get_mut(&fd))
+        };
+
+        if let Some(connection) = connection {
+            if connection.has_pending_writes() {
+                if let Err(error) = connection.flush() {
+                    log::debug!("Couldn't flush connection {}: {}", fd, error);
+                }
+            }
+        }
+    }
+
+    fn drop_connection(&mut self, fd: i32, connection: Connection<Stream>) {
+        let _ = connection.get_ref().shutdown(Shutdown::Both);
+
+        // Notify `listener` thread about old fd
+        self.event_channel_tx.send(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: None,
+            kind: proto_msg::event::Kind::OldFd as i32,
+            data: vec![fd.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: None
+        }).unwrap();
+    }
+
+    fn t_scanner(server_event_tx: EventSender<proto_msg::Event>,
+				 server_stream_tx: mpsc::Sender<Connection<Stream>>,
+                 running: Arc<AtomicBool>,
                  known_nodes_ips: Arc<Mutex<HashMap<IpAddr, i32>>>,
-                 node_id: u128) {
+                 node_id: u128,
+                 tls_client: Option<ClientContext>,
+                 cluster_key: Option<Vec<u8>>) {
         log::debug!("Scanner thread started");
 
-        loop {
+        while running.load(Ordering::Relaxed) {
             let local_ips = utils::get_ipv4_ips();
             for (network, _mask) in utils::get_networks_and_masks().iter() {
                 if !network.is_ipv4() {
@@ -666,6 +1318,10 @@ impl Server {
 
                 // Ping all avaliable IPs (not really, but OK for MVP)
                 for i in 0..255 {
+                    if !running.load(Ordering::Relaxed) {
+                        return;
+                    }
+
                     let ip = IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], i));
                     {
                         let known_nodes_ips = known_nodes_ips.lock().unwrap();
@@ -675,51 +1331,274 @@ impl Server {
                     }
 
                     let socket_address = SocketAddr::new(ip, 32000);
-                    let stream = TcpStream::connect_timeout(
-                        &socket_address,
-                        time::Duration::from_millis(100)
-                    );
-
-                    match stream {
-                        Ok(mut stream) => {
-                            // MarkMeNode
-                            let event = proto_msg::Event {
-                                dir: None,
-                                dest: None,
-                                kind: proto_msg::event::Kind::MarkMeNode as i32,
-                                data: vec![node_id.to_ne_bytes().to_vec()],
-                                meta: vec![]
-                            };
-                            stream.write(&event::serialize(event)).unwrap();
-
-                            let events = utils::read_events(&mut stream).unwrap();
-                            let event = events.get(0).unwrap();
-                            let bytes = event.data.get(0).unwrap();
-                            let node_id = utils::u128_from_ne_bytes(bytes).unwrap();
-
-                            // Send stream of client that responded
-                            server_stream_tx.send(stream).unwrap();
-                            // Notify server, that new node detected
-                            server_event_tx.send(proto_msg::Event {
-                                dir: Some(proto_msg::event::Dir::Incoming as i32),
-                                dest: None,
-                                kind: proto_msg::event::Kind::NewStream as i32,
-                                data: vec![node_id.to_ne_bytes().to_vec()],
-                                meta: vec![]
-                            }).unwrap();
-                        },
-                        Err(_) => {
-                            // Doing nothing, this situation is perfectly OK
+                    Self::connect_node(&socket_address, &server_event_tx, &server_stream_tx,
+                                       node_id, tls_client.as_ref(), cluster_key.as_deref());
+                }
+            }
+        }
+    }
+
+    fn t_multicast_scanner(server_event_tx: EventSender<proto_msg::Event>,
+                           server_stream_tx: mpsc::Sender<Connection<Stream>>,
+                           running: Arc<AtomicBool>,
+                           known_nodes_ips: Arc<Mutex<HashMap<IpAddr, i32>>>,
+                           node_id: u128,
+                           tls_client: Option<ClientContext>,
+                           cluster_key: Option<Vec<u8>>) {
+        log::debug!("Multicast scanner thread started");
+
+        let socket = match UdpSocket::bind((Ipv6Addr::UNSPECIFIED, DISCOVERY_PORT)).and_then(|socket| {
+            socket.set_read_timeout(Some(time::Duration::from_millis(100)))?;
+            socket.set_multicast_loop_v6(false)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => socket,
+            Err(error) => {
+                log::warn!("Couldn't start multicast scanner: {}", error);
+                return;
+            }
+        };
+
+        let mut joined_interfaces = vec![];
+        let mut buf = [0u8; 1024];
+
+        while running.load(Ordering::Relaxed) {
+            // Interfaces might come and go, so joining group on new ones
+            let interfaces = utils::get_ipv6_interfaces();
+            for index in interfaces.iter() {
+                if joined_interfaces.contains(index) {
+                    continue;
+                }
+
+                match socket.join_multicast_v6(&DISCOVERY_GROUP, *index) {
+                    Ok(_) => joined_interfaces.push(*index),
+                    Err(error) => {
+                        log::warn!("Couldn't join multicast group on interface {}: {}", index, error);
+                    }
+                }
+            }
+
+            // Announcing this node on every interface
+            let beacon = event::serialize(proto_msg::Event {
+                dir: None,
+                dest: None,
+                kind: proto_msg::event::Kind::MarkMeNode as i32,
+                data: vec![node_id.to_ne_bytes().to_vec()],
+                meta: vec![],
+                correlation_id: None
+            });
+            for index in interfaces.iter() {
+                let group_address = SocketAddr::V6(std::net::SocketAddrV6::new(DISCOVERY_GROUP, DISCOVERY_PORT, 0, *index));
+                if let Err(error) = socket.send_to(&beacon, group_address) {
+                    log::debug!("Couldn't send beacon on interface {}: {}", index, error);
+                }
+            }
+
+            // Collecting beacons of other nodes
+            let started = time::Instant::now();
+            while started.elapsed() < time::Duration::from_secs(1) && running.load(Ordering::Relaxed) {
+                let (bytes_num, addr) = match socket.recv_from(&mut buf) {
+                    Ok(result) => result,
+                    Err(error) => {
+                        if error.kind() != io::ErrorKind::WouldBlock && error.kind() != io::ErrorKind::TimedOut {
+                            log::warn!("Multicast scanner failed to receive: {}", error);
                         }
+                        continue;
+                    }
+                };
+
+                let (events, _rem) = event::deserialize(&buf[0..bytes_num]);
+                let beacon_node_id = match events.first()
+                    .filter(|event| event.kind == proto_msg::event::Kind::MarkMeNode as i32)
+                    .and_then(|event| event.data.first())
+                    .and_then(|bytes| utils::u128_from_ne_bytes(bytes).ok()) {
+                    Some(id) => id,
+                    None => continue
+                };
+
+                if beacon_node_id == node_id {
+                    continue;
+                }
+
+                {
+                    let known_nodes_ips = known_nodes_ips.lock().unwrap();
+                    if known_nodes_ips.contains_key(&addr.ip()) {
+                        continue;
                     }
                 }
+
+                // Beacon's source address already carries interface index
+                let mut socket_address = addr;
+                socket_address.set_port(32000);
+                Self::connect_node(&socket_address, &server_event_tx, &server_stream_tx,
+                                   node_id, tls_client.as_ref(), cluster_key.as_deref());
+            }
+        }
+    }
+
+    pub fn connect_node(socket_address: &SocketAddr,
+                    server_event_tx: &EventSender<proto_msg::Event>,
+                    server_stream_tx: &mpsc::Sender<Connection<Stream>>,
+                    node_id: u128,
+                    tls_client: Option<&ClientContext>,
+                    cluster_key: Option<&[u8]>) {
+        let stream = TcpStream::connect_timeout(
+            socket_address,
+            time::Duration::from_millis(100)
+        ).and_then(|stream| {
+            // Node must not be able to hang the scanner
+            stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            Stream::connect(stream, tls_client)
+        });
+
+        match stream {
+            Ok(stream) => {
+                let mut connection = Connection::new(stream);
+
+                let remote_node_id = match Self::join_node(&mut connection, node_id, cluster_key) {
+                    Ok(remote_node_id) => remote_node_id,
+                    Err(error) => {
+                        log::warn!("Couldn't join the node {}: {}", socket_address, error);
+                        let _ = connection.get_ref().shutdown(Shutdown::Both);
+                        return;
+                    }
+                };
+
+                // From now on connection is handled by the server
+                if let Err(error) = connection.get_ref().set_nonblocking(true) {
+                    log::warn!("Couldn't set up connection {}: {}", socket_address, error);
+                    return;
+                }
+
+                // Send stream of client that responded
+                if server_stream_tx.send(connection).is_err() {
+                    log::debug!("Server is gone, dropping connection {}", socket_address);
+                    return;
+                }
+                // Notify server, that new node detected
+                let _ = server_event_tx.send(proto_msg::Event {
+                    dir: Some(proto_msg::event::Dir::Incoming as i32),
+                    dest: None,
+                    kind: proto_msg::event::Kind::NewStream as i32,
+                    data: vec![remote_node_id.to_ne_bytes().to_vec()],
+                    meta: vec![],
+                    correlation_id: None
+                });
+            },
+            Err(_) => {
+                // Doing nothing, this situation is perfectly OK
+            }
+        }
+    }
+
+    // Connecting side of the join handshake, see `handle_handshake_event`.
+    // Returns id of the remote node
+    fn join_node(connection: &mut Connection<Stream>, node_id: u128,
+                 cluster_key: Option<&[u8]>) -> Result<u128, CodecError> {
+        let nonce = cluster_key.map(|_| auth::generate_nonce());
+
+        // MarkMeNode (TLS handshake, if any, happens here)
+        let mut data = vec![node_id.to_ne_bytes().to_vec()];
+        data.extend(nonce.clone());
+        connection.send(proto_msg::Event {
+            dir: None,
+            dest: None,
+            kind: proto_msg::event::Kind::MarkMeNode as i32,
+            data,
+            meta: vec![],
+            correlation_id: None
+        })?;
+
+        let received = connection.wait_events()?;
+        let event = received.events.first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"))?;
+        let remote_node_id = event.data.first()
+            .and_then(|bytes| utils::u128_from_ne_bytes(bytes).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed handshake"))?;
+
+        // Connection got back to us, or someone answers with our own id
+        if remote_node_id == node_id {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "remote node has id of this node").into());
+        }
+
+        let (key, nonce) = match (cluster_key, nonce) {
+            (Some(key), Some(nonce)) => (key, nonce),
+            _ => return Ok(remote_node_id)
+        };
+
+        // Checking that remote node knows the cluster key
+        let remote_nonce = event.data.get(1);
+        let remote_tag = event.data.get(2);
+        match (remote_nonce, remote_tag) {
+            (Some(remote_nonce), Some(remote_tag)) => {
+                let transcript = auth::JoinTranscript {
+                    initiator_nonce: &nonce,
+                    responder_nonce: remote_nonce,
+                    initiator_id: node_id,
+                    responder_id: remote_node_id
+                };
+                if !auth::verify_join(key, auth::JoinRole::Responder, &transcript, remote_tag) {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "remote node failed authentication").into());
+                }
+
+                // Answering remote node's challenge
+                let event = proto_msg::Event {
+                    dir: None,
+                    dest: None,
+                    kind: proto_msg::event::Kind::AuthResponse as i32,
+                    data: vec![auth::sign_join(key, auth::JoinRole::Initiator, &transcript)],
+                    meta: vec![],
+                    correlation_id: None
+                };
+                connection.send(event)?;
+            },
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "remote node is not configured with cluster key").into());
             }
         }
+
+        // Waiting for remote node to accept us
+        let received = connection.wait_events()?;
+        match received.events.first() {
+            Some(event) if event.kind == proto_msg::event::Kind::AuthResponse as i32 => Ok(remote_node_id),
+            _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, "rejected by remote node").into())
+        }
     }
 }
 
+// Two nodes may connect to each other at once, then each of them would drop the connection,
+// that the other one has kept. Both keep the one, that was initiated by the node with the lower id
+pub fn replaces_connection(node_id: u128, remote_node_id: u128, known_initiator: u128, initiator: u128) -> bool {
+    initiator != known_initiator && initiator == node_id.min(remote_node_id)
+}
+
 impl From<FSMError> for ServerError {
-    fn from(_: FSMError) -> Self {
+    fn from(error: FSMError) -> Self {
+        log::error!("State machine failed: {}", error);
         ServerError::InternalError
     }
 }
+
+impl From<FieldError> for ServerError {
+    fn from(error: FieldError) -> Self {
+        ServerError::MalformedEvent(error)
+    }
+}
+
+impl From<io::Error> for ServerError {
+    fn from(error: io::Error) -> Self {
+        ServerError::Io(error)
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::MalformedEvent(error) => write!(f, "malformed event: {}", error),
+            ServerError::PeerGone(fd) => write!(f, "connection {} is already closed", fd),
+            ServerError::UnknownRequest(id) => write!(f, "there is no request with id {:?}", id),
+            ServerError::Io(error) => write!(f, "{}", error),
+            ServerError::InternalError => write!(f, "internal error")
+        }
+    }
+}
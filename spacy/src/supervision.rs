@@ -0,0 +1,177 @@
+use std::{
+    fmt,
+    time::{Duration, Instant}
+};
+use common::package::{Manifest, RestartPolicy};
+
+// Plugin manager watches every plugin it has started. Plugin, that exits,
+// is restarted according to its manifest with exponential backoff:
+//
+//   running -> restarting -> running -> ... -> crashed (or exited)
+//
+// Restarts are counted in a row, plugin, that has run long enough, starts over
+
+// Delay before the first restart, it doubles with every following one
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+
+// Plugin, that has run that long, isn't crashing in a loop
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+// How long plugin may run after its connection is closed, before it's killed
+const DISCONNECTED_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PluginStatus {
+    // Plugin is started, but hasn't connected yet
+    Starting,
+    Running,
+    // Plugin has exited and waits for its restart
+    Restarting,
+    // Plugin has failed and won't be restarted
+    Crashed,
+    // Plugin has exited with zero code and won't be restarted
+    Exited
+}
+
+#[derive(Debug, Clone)]
+pub struct Supervision {
+    policy: RestartPolicy,
+    max_restarts: u32,
+    restarts: u32,
+    status: PluginStatus,
+    // Code of the last exit, it's absent, if plugin was killed by a signal
+    exit_code: Option<i32>,
+    // Why plugin has exited last time, e.g. which limit it has violated
+    exit_reason: Option<String>,
+    started_at: Instant,
+    restart_at: Option<Instant>,
+    disconnected_at: Option<Instant>
+}
+
+impl Supervision {
+    pub fn new(manifest: &Manifest, now: Instant) -> Self {
+        Self {
+            policy: manifest.restart,
+            max_restarts: manifest.max_restarts,
+            restarts: 0,
+            status: PluginStatus::Starting,
+            exit_code: None,
+            exit_reason: None,
+            started_at: now,
+            restart_at: None,
+            disconnected_at: None
+        }
+    }
+
+    pub fn launched(&mut self, now: Instant) {
+        self.status = PluginStatus::Starting;
+        self.started_at = now;
+        self.restart_at = None;
+        self.disconnected_at = None;
+    }
+
+    pub fn started(&mut self, now: Instant) {
+        self.status = PluginStatus::Running;
+        self.started_at = now;
+        self.restart_at = None;
+        self.disconnected_at = None;
+    }
+
+    pub fn disconnected(&mut self, now: Instant) {
+        self.disconnected_at.get_or_insert(now);
+    }
+
+    // Plugin, that doesn't exit after closing its connection, must be killed
+    pub fn is_unresponsive(&self, now: Instant) -> bool {
+        self.status == PluginStatus::Running
+            && self.disconnected_at.is_some_and(|disconnected_at| now >= disconnected_at + DISCONNECTED_TIMEOUT)
+    }
+
+    // Decides, whether plugin is restarted. Restart, that has failed, counts as an exit without code
+    pub fn exited(&mut self, exit_code: Option<i32>, now: Instant) -> PluginStatus {
+        let failed = exit_code != Some(0);
+
+        if now.saturating_duration_since(self.started_at) >= STABLE_RUN {
+            self.restarts = 0;
+        }
+
+        let restart = match self.policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => failed,
+            RestartPolicy::Always => true
+        };
+
+        self.exit_code = exit_code;
+        self.disconnected_at = None;
+        self.status = if restart && self.restarts < self.max_restarts {
+            self.restart_at = Some(now + backoff(self.restarts));
+            self.restarts += 1;
+            PluginStatus::Restarting
+        } else if failed {
+            self.restart_at = None;
+            PluginStatus::Crashed
+        } else {
+            self.restart_at = None;
+            PluginStatus::Exited
+        };
+
+        self.status
+    }
+
+    pub fn is_restart_due(&self, now: Instant) -> bool {
+        self.restart_at.is_some_and(|restart_at| now >= restart_at)
+    }
+
+    // When plugin manager has to wake up for this plugin
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.disconnected_at {
+            Some(disconnected_at) if self.status == PluginStatus::Running => Some(disconnected_at + DISCONNECTED_TIMEOUT),
+            _ => self.restart_at
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected_at.is_some()
+    }
+
+    pub fn status(&self) -> PluginStatus {
+        self.status
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub fn set_exit_reason(&mut self, reason: String) {
+        self.exit_reason = Some(reason);
+    }
+
+    pub fn exit_reason(&self) -> Option<&str> {
+        self.exit_reason.as_deref()
+    }
+
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    pub fn max_restarts(&self) -> u32 {
+        self.max_restarts
+    }
+}
+
+pub fn backoff(restarts: u32) -> Duration {
+    RESTART_BACKOFF.saturating_mul(2u32.saturating_pow(restarts)).min(MAX_RESTART_BACKOFF)
+}
+
+impl fmt::Display for PluginStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginStatus::Starting => write!(f, "starting"),
+            PluginStatus::Running => write!(f, "running"),
+            PluginStatus::Restarting => write!(f, "restarting"),
+            PluginStatus::Crashed => write!(f, "crashed"),
+            PluginStatus::Exited => write!(f, "exited")
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::VecDeque,
+    fmt
+};
+use wasmi::{
+    core::TrapCode,
+    Caller, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc
+};
+use common::event::proto_msg;
+
+// WASM plugins run inside the plugin manager instead of their own processes.
+// Module exports its `memory` and `update`, that is called, when events arrive,
+// and imports functions of the `spacy` module, that mirror `SpacyPlugin`:
+//
+//   get_event(buffer, capacity) -> length        takes the next event, see below
+//   shared_memory_push(key, value, length) -> id   returns id, the result will carry
+//   shared_memory_get(key) -> id                   returns id, the value will carry
+//   respond_client(correlation_id, fields, length) fields are encoded as event's data
+//   log(message, length)                           appends a line to the plugin's output
+//
+// Event is written as `kind: i32, correlation_id: u64, data, meta`, where data and meta
+// are `count: u32` fields, each of them `length: u32` bytes, numbers are little endian,
+// correlation id is 0, if event has none. `get_event` returns 0, if there are no events,
+// and the length of the event without taking it, if it doesn't fit into the buffer.
+// Responses to plugin's requests arrive as events of their kinds.
+//
+// Every `update` gets the same amount of fuel, plugin, that runs out of it or of memory, traps
+
+// Name of the module, that host functions are imported from
+const HOST_MODULE: &str = "spacy";
+
+// Limits, that WASM plugins run with, absent one isn't enforced
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WasmLimits {
+    // Fuel of every `update`, roughly the number of executed instructions
+    pub fuel: Option<u64>,
+    pub memory_bytes: Option<u64>
+}
+
+// Compiles and instantiates modules of WASM plugins
+pub struct WasmRuntime {
+    engine: Engine,
+    linker: Linker<Host>,
+    limits: WasmLimits
+}
+
+pub struct WasmPlugin {
+    store: Store<Host>,
+    update: TypedFunc<(), ()>,
+    fuel: Option<u64>
+}
+
+// What host functions have access to
+struct Host {
+    limits: StoreLimits,
+    // Events, that plugin hasn't taken yet
+    events: VecDeque<proto_msg::Event>,
+    // Events, that plugin has sent
+    sent: Vec<proto_msg::Event>,
+    output: Vec<u8>,
+    // Ids of the plugin's own requests
+    next_request_id: u64
+}
+
+#[derive(Debug)]
+pub enum WasmError {
+    // Module isn't valid or doesn't export what plugin must
+    InvalidModule(String),
+    // Plugin has failed, its instance can't be used anymore
+    Trap(String)
+}
+
+impl WasmRuntime {
+    pub fn new(limits: WasmLimits) -> Self {
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(limits.fuel.is_some());
+
+        let engine = Engine::new(&config);
+        let mut linker = Linker::new(&engine);
+        define_host_functions(&mut linker);
+
+        Self { engine, linker, limits }
+    }
+
+    // Start function of the module runs with the fuel of a single `update`
+    pub fn instantiate(&self, module: &[u8]) -> Result<WasmPlugin, WasmError> {
+        let module = Module::new(&self.engine, module)
+            .map_err(|error| WasmError::InvalidModule(error.to_string()))?;
+
+        let mut limits = StoreLimitsBuilder::new().trap_on_grow_failure(true);
+        if let Some(bytes) = self.limits.memory_bytes {
+            limits = limits.memory_size(usize::try_from(bytes).unwrap_or(usize::MAX));
+        }
+
+        let host = Host {
+            limits: limits.build(),
+            events: VecDeque::new(),
+            sent: vec![],
+            output: vec![],
+            next_request_id: 1
+        };
+        let mut store = Store::new(&self.engine, host);
+        store.limiter(|host| &mut host.limits);
+        if let Some(fuel) = self.limits.fuel {
+            let _ = store.set_fuel(fuel);
+        }
+
+        // Module, that imports something else, isn't a plugin
+        let instance = self.linker.instantiate(&mut store, &module)
+            .map_err(|error| WasmError::InvalidModule(error.to_string()))?
+            .start(&mut store)
+            .map_err(trap)?;
+
+        if instance.get_memory(&store, "memory").is_none() {
+            return Err(WasmError::InvalidModule("module doesn't export `memory`".to_string()));
+        }
+        let update = instance.get_typed_func::<(), ()>(&store, "update")
+            .map_err(|_| WasmError::InvalidModule("module doesn't export `update`".to_string()))?;
+
+        Ok(WasmPlugin { store, update, fuel: self.limits.fuel })
+    }
+}
+
+impl WasmPlugin {
+    // Plugin takes the event, when it runs next
+    pub fn push_event(&mut self, event: proto_msg::Event) {
+        self.store.data_mut().events.push_back(event);
+    }
+
+    pub fn has_events(&self) -> bool {
+        !self.store.data().events.is_empty()
+    }
+
+    // Calls `update`, while plugin takes events
+    pub fn run(&mut self) -> Result<(), WasmError> {
+        while self.has_events() {
+            let queued = self.store.data().events.len();
+
+            if let Some(fuel) = self.fuel {
+                let _ = self.store.set_fuel(fuel);
+            }
+            self.update.call(&mut self.store, ()).map_err(trap)?;
+
+            // Plugin, that leaves events, gets them, when the next one arrives
+            if self.store.data().events.len() >= queued {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Events, that plugin has sent, since they were taken last time
+    pub fn take_sent(&mut self) -> Vec<proto_msg::Event> {
+        std::mem::take(&mut self.store.data_mut().sent)
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.store.data_mut().output)
+    }
+}
+
+impl Host {
+    fn request(&mut self, kind: proto_msg::event::Kind, data: Vec<Vec<u8>>) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+
+        self.sent.push(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: None,
+            kind: kind as i32,
+            data,
+            meta: vec![],
+            correlation_id: Some(id)
+        });
+
+        id
+    }
+}
+
+fn define_host_functions(linker: &mut Linker<Host>) {
+    linker.func_wrap(HOST_MODULE, "get_event", |mut caller: Caller<'_, Host>, buffer: i32, capacity: i32| -> Result<i32, wasmi::Error> {
+        let event = match caller.data().events.front() {
+            Some(event) => encode_event(event),
+            None => return Ok(0)
+        };
+        let length = i32::try_from(event.len()).map_err(|_| wasmi::Error::new("event is too large"))?;
+
+        if length > capacity {
+            return Ok(length);
+        }
+
+        write_memory(&mut caller, buffer, &event)?;
+        caller.data_mut().events.pop_front();
+        Ok(length)
+    }).unwrap();
+
+    linker.func_wrap(HOST_MODULE, "shared_memory_push", |mut caller: Caller<'_, Host>, key: i32, value: i32, length: i32| -> Result<i64, wasmi::Error> {
+        let value = read_memory(&caller, value, length)?;
+        let id = caller.data_mut().request(proto_msg::event::Kind::UpdateSharedMemory, vec![key.to_ne_bytes().to_vec(), value]);
+        Ok(id as i64)
+    }).unwrap();
+
+    linker.func_wrap(HOST_MODULE, "shared_memory_get", |mut caller: Caller<'_, Host>, key: i32| -> i64 {
+        caller.data_mut().request(proto_msg::event::Kind::GetFromSharedMemory, vec![key.to_ne_bytes().to_vec()]) as i64
+    }).unwrap();
+
+    linker.func_wrap(HOST_MODULE, "respond_client", |mut caller: Caller<'_, Host>, correlation_id: i64, fields: i32, length: i32| -> Result<(), wasmi::Error> {
+        let fields = read_memory(&caller, fields, length)?;
+        let data = decode_fields(&mut fields.as_slice())
+            .ok_or_else(|| wasmi::Error::new("malformed response's fields"))?;
+
+        caller.data_mut().sent.push(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: None,
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data,
+            meta: vec![],
+            correlation_id: Some(correlation_id as u64)
+        });
+        Ok(())
+    }).unwrap();
+
+    linker.func_wrap(HOST_MODULE, "log", |mut caller: Caller<'_, Host>, message: i32, length: i32| -> Result<(), wasmi::Error> {
+        let message = read_memory(&caller, message, length)?;
+        let output = &mut caller.data_mut().output;
+        output.extend_from_slice(&message);
+        output.push(b'\n');
+        Ok(())
+    }).unwrap();
+}
+
+// Plugin can pass any pointer, range outside of its memory traps
+fn read_memory(caller: &Caller<'_, Host>, pointer: i32, length: i32) -> Result<Vec<u8>, wasmi::Error> {
+    let memory = caller.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmi::Error::new("module doesn't export `memory`"))?;
+
+    let start = pointer as u32 as usize;
+    let end = start.checked_add(length as u32 as usize);
+    end.and_then(|end| memory.data(caller).get(start..end))
+        .map(|bytes| bytes.to_vec())
+        .ok_or_else(|| wasmi::Error::new("out of bounds memory access"))
+}
+
+fn write_memory(caller: &mut Caller<'_, Host>, pointer: i32, bytes: &[u8]) -> Result<(), wasmi::Error> {
+    let memory = caller.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmi::Error::new("module doesn't export `memory`"))?;
+
+    let start = pointer as u32 as usize;
+    let end = start.checked_add(bytes.len());
+    end.and_then(|end| memory.data_mut(caller).get_mut(start..end))
+        .map(|destination| destination.copy_from_slice(bytes))
+        .ok_or_else(|| wasmi::Error::new("out of bounds memory access"))
+}
+
+fn encode_event(event: &proto_msg::Event) -> Vec<u8> {
+    let mut encoded = vec![];
+    encoded.extend_from_slice(&event.kind.to_le_bytes());
+    encoded.extend_from_slice(&event.correlation_id.unwrap_or_default().to_le_bytes());
+    encode_fields(&mut encoded, &event.data);
+    encode_fields(&mut encoded, &event.meta);
+    encoded
+}
+
+fn encode_fields(encoded: &mut Vec<u8>, fields: &[Vec<u8>]) {
+    encoded.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+    for field in fields {
+        encoded.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        encoded.extend_from_slice(field);
+    }
+}
+
+fn decode_fields(encoded: &mut &[u8]) -> Option<Vec<Vec<u8>>> {
+    let count = decode_u32(encoded)?;
+
+    let mut fields = vec![];
+    for _ in 0..count {
+        let length = decode_u32(encoded)? as usize;
+        if encoded.len() < length {
+            return None;
+        }
+
+        let (field, rest) = encoded.split_at(length);
+        fields.push(field.to_vec());
+        *encoded = rest;
+    }
+
+    Some(fields)
+}
+
+fn decode_u32(encoded: &mut &[u8]) -> Option<u32> {
+    let (number, rest) = encoded.split_first_chunk::<4>()?;
+    *encoded = rest;
+    Some(u32::from_le_bytes(*number))
+}
+
+// Limits' violations are named like the ones of plugins' processes
+fn trap(error: wasmi::Error) -> WasmError {
+    let reason = match error.as_trap_code() {
+        Some(TrapCode::OutOfFuel) => "ran out of fuel".to_string(),
+        Some(TrapCode::GrowthOperationLimited) => "memory limit exceeded".to_string(),
+        _ => format!("trapped: {}", error)
+    };
+
+    WasmError::Trap(reason)
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmError::InvalidModule(error) => write!(f, "invalid module: {}", error),
+            WasmError::Trap(reason) => write!(f, "{}", reason)
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use spacy::acl::{Acl, AclError, Action};
+
+const ACL: &str = "
+# Roles
+role admin *
+role calc list send:simple_calc
+role sender send:*
+
+client alice 9c1e6f0a admin
+client bob 41d7a2b3 calc
+client carol 5e0b9d11 sender
+";
+
+fn parse_error_line(content: &str) -> usize {
+    match Acl::parse(content) {
+        Err(AclError::Parse(line_num, _)) => line_num,
+        Err(error) => panic!("unexpected error: {:?}", error),
+        Ok(_) => panic!("ACL is accepted")
+    }
+}
+
+#[test]
+fn malformed_acl_is_rejected() {
+    assert_eq!(parse_error_line("role"), 1);
+    assert_eq!(parse_error_line("role admin fly"), 1);
+    assert_eq!(parse_error_line("role admin send:"), 1);
+    assert_eq!(parse_error_line("role admin *\nclient alice 9c1e6f0a"), 2);
+    assert_eq!(parse_error_line("group admins alice"), 1);
+    assert_eq!(parse_error_line("role admin *\nclient alice 9c1e6f0a admin\nclient bob 9c1e6f0a admin"), 3);
+
+    // Role is checked, once the whole file is read
+    assert_eq!(parse_error_line("client alice 9c1e6f0a admin"), 0);
+}
+
+#[test]
+fn duplicates_are_rejected() {
+    assert_eq!(parse_error_line("role admin *\nrole admin list"), 2);
+    assert_eq!(parse_error_line("role admin *\nclient alice 9c1e6f0a admin\nclient alice 41d7a2b3 admin"), 3);
+}
+
+#[test]
+fn clients_are_authenticated_by_token() {
+    let acl = Acl::parse(ACL).unwrap();
+
+    let client = acl.authenticate(b"41d7a2b3").unwrap();
+    assert_eq!(client.name, "bob");
+    assert_eq!(client.role, "calc");
+
+    assert!(acl.authenticate(b"00000000").is_none());
+    assert!(acl.authenticate(b"").is_none());
+}
+
+#[test]
+fn send_is_allowed_to_named_plugin_or_any() {
+    let acl = Acl::parse(ACL).unwrap();
+
+    assert!(acl.is_allowed(b"bob", &Action::Send(b"simple_calc")));
+    assert!(!acl.is_allowed(b"bob", &Action::Send(b"simple_calc2")));
+    assert!(acl.is_allowed(b"bob", &Action::List));
+    assert!(!acl.is_allowed(b"bob", &Action::Deploy));
+
+    assert!(acl.is_allowed(b"carol", &Action::Send(b"simple_calc")));
+    assert!(acl.is_allowed(b"carol", &Action::Send(b"other")));
+    assert!(!acl.is_allowed(b"carol", &Action::List));
+
+    assert!(acl.is_allowed(b"alice", &Action::Remove));
+}
+
+#[test]
+fn unknown_client_is_allowed_nothing() {
+    let acl = Acl::parse(ACL).unwrap();
+
+    assert!(!acl.is_allowed(b"mallory", &Action::List));
+    assert!(!acl.is_allowed(b"mallory", &Action::Send(b"simple_calc")));
+    assert!(!acl.is_allowed(b"", &Action::List));
+    assert!(!acl.is_allowed(&[0xff, 0xfe], &Action::List));
+}
@@ -0,0 +1,83 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    os::unix::io::AsRawFd,
+    thread, time
+};
+use common::{
+    bus::Bus,
+    codec::Connection,
+    event::proto_msg::{self, event::Kind},
+    utils
+};
+use spacy::{config::Config, plugin_man::PluginMan, server::Server};
+
+// More than `select` can handle (FD_SETSIZE)
+const CLIENTS: usize = 1100;
+
+const TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+fn make_event(kind: Kind, correlation_id: Option<u64>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind: kind as i32,
+        data: vec![],
+        meta: vec![],
+        correlation_id
+    }
+}
+
+#[test]
+fn server_serves_more_than_1024_clients() {
+    // Server and clients live in the same process
+    let limit = utils::raise_fd_limit().unwrap();
+    assert!(limit >= 2 * CLIENTS as u64 + 64, "open files limit {} is too low, raise the hard limit to run the test", limit);
+
+    // Port is free again, once the probe is dropped
+    let addr = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        listen_addrs: vec![addr],
+        plugins_dir: dir.path().join("plugins"),
+        plugin_logs_dir: dir.path().join("logs"),
+        ..Config::default()
+    };
+
+    // Plugin manager answers the clients, node isn't needed for that
+    let bus = Bus::new();
+    Server::new(bus.clone(), 1, &config).start();
+    PluginMan::new(bus, &config).start();
+
+    let started = time::Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        assert!(started.elapsed() < TIMEOUT, "server didn't start");
+        thread::sleep(time::Duration::from_millis(50));
+    }
+
+    let mut clients = vec![];
+    for index in 0..CLIENTS {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut connection = Connection::new(stream);
+        connection.send(make_event(Kind::MarkMeClient, None)).unwrap();
+        connection.send(make_event(Kind::GetPluginList, Some(index as u64))).unwrap();
+        clients.push(connection);
+    }
+
+    // Server's fds are interleaved with the clients' ones, so it serves fds above the limit too
+    assert!(clients.last().unwrap().get_ref().as_raw_fd() >= 1024);
+
+    for (index, connection) in clients.iter_mut().enumerate() {
+        let response = loop {
+            let received = connection.wait_events().unwrap();
+            if let Some(response) = received.events.into_iter().find(|event| event.kind == Kind::RespondClient as i32) {
+                break response;
+            }
+            assert!(!received.closed, "client {} was dropped", index);
+        };
+
+        assert_eq!(response.correlation_id, Some(index as u64));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{net::{SocketAddr, TcpListener}, sync::mpsc, thread, time::Duration};
+use common::{
+    auth::{self, JoinRole, JoinTranscript},
+    codec::Connection,
+    event::{self, proto_msg},
+    reactor::Reactor,
+    utils
+};
+use spacy::server::{self, Server};
+
+const CLUSTER_KEY: &[u8] = b"cluster key";
+
+// Node, that the scanner has found, it answers the join handshake with `node_id`
+fn spawn_node(node_id: u128, cluster_key: Option<&'static [u8]>) -> SocketAddr {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = Connection::new(stream);
+        let joining = connection.wait_events().unwrap().events.remove(0);
+        let joining_id = utils::u128_from_ne_bytes(&joining.data[0]).unwrap();
+
+        let mut data = vec![node_id.to_ne_bytes().to_vec()];
+        let nonce = auth::generate_nonce();
+        if let Some(key) = cluster_key {
+            let transcript = JoinTranscript {
+                initiator_nonce: &joining.data[1],
+                responder_nonce: &nonce,
+                initiator_id: joining_id,
+                responder_id: node_id
+            };
+            data.extend([nonce.clone(), auth::sign_join(key, JoinRole::Responder, &transcript)]);
+        }
+        connection.send(make_event(0, data)).unwrap();
+
+        // Joining node answers the challenge, it's accepted without checking
+        if cluster_key.is_some() {
+            let _ = connection.wait_events();
+            let _ = connection.send(make_event(proto_msg::event::Kind::AuthResponse as i32, vec![]));
+        }
+
+        // Connection is kept, until joining node is done with it
+        let _ = connection.wait_events();
+    });
+
+    addr
+}
+
+fn make_event(kind: i32, data: Vec<Vec<u8>>) -> proto_msg::Event {
+    proto_msg::Event { dir: None, dest: None, kind, data, meta: vec![], correlation_id: None }
+}
+
+// Returns id of the node, that was handed over to the server, if any
+fn connect_node(addr: SocketAddr, node_id: u128, cluster_key: Option<&[u8]>) -> Option<u128> {
+    let reactor = Reactor::new().unwrap();
+    let (event_tx, event_rx) = reactor.channel();
+    let (stream_tx, stream_rx) = mpsc::channel();
+
+    Server::connect_node(&addr, &event_tx, &stream_tx, node_id, None, cluster_key);
+
+    let event = event_rx.recv_timeout(Duration::from_secs(1)).ok()?;
+    assert_eq!(event.kind, proto_msg::event::Kind::NewStream as i32);
+    assert!(stream_rx.try_recv().is_ok(), "connection wasn't handed over with the event");
+
+    Some(event::get_u128(&event.data, 0).unwrap())
+}
+
+#[test]
+fn found_node_is_handed_to_server() {
+    let addr = spawn_node(7, None);
+    assert_eq!(connect_node(addr, 42, None), Some(7));
+}
+
+#[test]
+fn found_node_is_authenticated() {
+    let addr = spawn_node(7, Some(CLUSTER_KEY));
+    assert_eq!(connect_node(addr, 42, Some(CLUSTER_KEY)), Some(7));
+
+    let addr = spawn_node(7, Some(b"dev laptop key"));
+    assert_eq!(connect_node(addr, 42, Some(CLUSTER_KEY)), None);
+}
+
+#[test]
+fn node_with_own_id_is_not_joined() {
+    let addr = spawn_node(42, None);
+    assert_eq!(connect_node(addr, 42, None), None);
+}
+
+#[test]
+fn nodes_connecting_at_once_keep_same_connection() {
+    // Connections are told apart by the node, that has initiated them
+    for (node_id, remote_node_id) in [(1, 2), (2, 1)] {
+        for (known, new) in [(1, 2), (2, 1)] {
+            let kept = if server::replaces_connection(node_id, remote_node_id, known, new) { new } else { known };
+            assert_eq!(kept, 1, "node {} has kept connection of {}, having got {} first", node_id, kept, known);
+        }
+    }
+}
+
+#[test]
+fn second_connection_of_same_initiator_is_dropped() {
+    assert!(!server::replaces_connection(1, 2, 1, 1));
+    assert!(!server::replaces_connection(1, 2, 2, 2));
+}
@@ -0,0 +1,126 @@
+mod node;
+
+use std::{io::Write, net::TcpStream};
+use common::event::proto_msg::{self, event::Kind};
+use node::{connect, make_event, start_node, wait_for, NODE_LOCK};
+
+// Deterministic source of garbage
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.next() as usize % (max_len + 1);
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+
+    fn fields(&mut self, max_num: usize) -> Vec<Vec<u8>> {
+        let num = self.next() as usize % (max_num + 1);
+        (0..num).map(|_| self.bytes(20)).collect()
+    }
+
+    fn dir(&mut self) -> Option<i32> {
+        match self.next() % 4 {
+            0 => None,
+            1 => Some(proto_msg::event::Dir::Incoming as i32),
+            2 => Some(proto_msg::event::Dir::Outcoming as i32),
+            _ => Some(self.next() as i32)
+        }
+    }
+}
+
+#[test]
+fn node_survives_garbage_from_clients() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let node = start_node(&[]);
+    let mut rng = XorShift(0x5eed);
+
+    // Bytes, that aren't frames at all
+    let mut stream = TcpStream::connect(node.addr).unwrap();
+    let _ = stream.write_all(&rng.bytes(64 * 1024));
+    let _ = stream.write_all(&[0xff; 16]);
+    drop(stream);
+
+    // Well-formed frames with whatever inside
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    for _ in 0..500 {
+        let kind = rng.next() as i32 % 30;
+        let mut data = rng.fields(4);
+
+        // Complete `new_plugin` would start a plugin
+        if kind == Kind::NewPlugin as i32 {
+            data.truncate(1);
+        }
+
+        let event = proto_msg::Event {
+            dir: rng.dir(),
+            dest: Some(rng.next() as i32 % 5),
+            kind,
+            data,
+            meta: rng.fields(3),
+            correlation_id: None
+        };
+        if connection.send(event).is_err() {
+            break;
+        }
+    }
+
+    // Node still serves clients
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::GetPluginList as i32, vec![])).unwrap();
+    assert!(wait_for(&mut connection, Kind::RespondClient).is_some());
+}
+
+#[test]
+fn node_survives_garbage_from_nodes() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let node = start_node(&[]);
+    let mut rng = XorShift(0xbad5eed);
+
+    // Joining the cluster as a node
+    let node_id: u128 = 42;
+    let mut connection = connect(&node, Kind::MarkMeNode, vec![node_id.to_ne_bytes().to_vec()]);
+    connection.wait_events().unwrap();
+
+    // Node syncs shared memory with the newcomer and waits for approval
+    assert!(wait_for(&mut connection, Kind::RequestTransaction).is_some());
+
+    let mut events = vec![
+        make_event(Kind::RequestTransaction as i32, vec![]),
+        make_event(Kind::RequestTransaction as i32, vec![vec![1]]),
+        make_event(Kind::ApproveTransaction as i32, vec![vec![1, 2, 3]]),
+        make_event(Kind::CommitTransaction as i32, vec![node_id.to_ne_bytes().to_vec()]),
+        make_event(Kind::CommitTransaction as i32, vec![node_id.to_ne_bytes().to_vec(), vec![9]]),
+        make_event(Kind::CommitTransaction as i32, vec![node_id.to_ne_bytes().to_vec(), 2i32.to_ne_bytes().to_vec()]),
+        make_event(Kind::NodeLeaving as i32, vec![]),
+        make_event(Kind::Shutdown as i32, vec![])
+    ];
+
+    // Events, that nodes send to each other, filled with random data
+    let kinds = [Kind::RequestTransaction, Kind::ApproveTransaction, Kind::CommitTransaction, Kind::NodeLeaving];
+    for _ in 0..500 {
+        let kind = kinds[rng.next() as usize % kinds.len()];
+        events.push(proto_msg::Event {
+            dir: rng.dir(),
+            dest: None,
+            kind: kind as i32,
+            data: rng.fields(5),
+            meta: rng.fields(2),
+            correlation_id: None
+        });
+    }
+
+    for event in events {
+        connection.send(event).unwrap();
+    }
+
+    // Node still performs transactions
+    connection.send(make_event(Kind::ApproveTransaction as i32, vec![node_id.to_ne_bytes().to_vec()])).unwrap();
+    assert!(wait_for(&mut connection, Kind::CommitTransaction).is_some());
+}
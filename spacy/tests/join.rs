@@ -0,0 +1,91 @@
+mod node;
+
+use std::net::TcpStream;
+use common::{
+    auth::{self, JoinRole, JoinTranscript},
+    codec::Connection,
+    event::proto_msg::event::Kind,
+    utils
+};
+use node::{connect, make_event, start_node, wait_for, Node, NODE_LOCK};
+
+const CLUSTER_KEY: &str = "cluster key";
+
+// Sends `mark_me_node` and returns the node's [id, nonce, tag]
+fn join(node: &Node, node_id: u128, nonce: &[u8]) -> (Connection<TcpStream>, u128, Vec<u8>, Vec<u8>) {
+    let mut connection = connect(node, Kind::MarkMeNode, vec![node_id.to_ne_bytes().to_vec(), nonce.to_vec()]);
+    // Node answers with an event of no particular kind
+    let answer = wait_for(&mut connection, Kind::NewStream).expect("node didn't answer `mark_me_node`");
+    let responder_id = utils::u128_from_ne_bytes(&answer.data[0]).unwrap();
+
+    (connection, responder_id, answer.data[1].clone(), answer.data[2].clone())
+}
+
+#[test]
+fn node_knowing_cluster_key_joins() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let node = start_node(&[("SPACY_CLUSTER_KEY", CLUSTER_KEY)]);
+
+    let nonce = auth::generate_nonce();
+    let (mut connection, responder_id, responder_nonce, tag) = join(&node, 42, &nonce);
+    let transcript = JoinTranscript {
+        initiator_nonce: &nonce,
+        responder_nonce: &responder_nonce,
+        initiator_id: 42,
+        responder_id
+    };
+    assert!(auth::verify_join(CLUSTER_KEY.as_bytes(), JoinRole::Responder, &transcript, &tag));
+
+    let tag = auth::sign_join(CLUSTER_KEY.as_bytes(), JoinRole::Initiator, &transcript);
+    connection.send(make_event(Kind::AuthResponse as i32, vec![tag])).unwrap();
+    assert!(wait_for(&mut connection, Kind::AuthResponse).is_some());
+}
+
+#[test]
+fn node_tag_cant_be_replayed_on_other_connection() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let node = start_node(&[("SPACY_CLUSTER_KEY", CLUSTER_KEY)]);
+
+    // Without the key, node's own answers are the only tags to be had
+    let (mut first, _, first_nonce, _) = join(&node, 42, &auth::generate_nonce());
+    let (_second, _, _, second_tag) = join(&node, 42, &first_nonce);
+
+    first.send(make_event(Kind::AuthResponse as i32, vec![second_tag])).unwrap();
+    assert!(wait_for(&mut first, Kind::AuthResponse).is_none());
+}
+
+#[test]
+fn node_claiming_our_id_is_rejected() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let node = start_node(&[("SPACY_CLUSTER_KEY", CLUSTER_KEY)]);
+
+    let (_first, responder_id, first_nonce, _) = join(&node, 42, &auth::generate_nonce());
+
+    // Node would sign the nonce it has given out, if it took itself for the initiator
+    let mut reflected = connect(&node, Kind::MarkMeNode, vec![responder_id.to_ne_bytes().to_vec(), first_nonce]);
+    assert!(wait_for(&mut reflected, Kind::NewStream).is_none());
+}
+
+#[test]
+fn nodes_sharing_ip_both_join() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let node = start_node(&[("SPACY_CLUSTER_KEY", CLUSTER_KEY)]);
+
+    // Both nodes connect from the loopback, second one might as well be the first one restarted
+    let mut joined = vec![];
+    for node_id in [42, 43] {
+        let nonce = auth::generate_nonce();
+        let (mut connection, responder_id, responder_nonce, _) = join(&node, node_id, &nonce);
+        let transcript = JoinTranscript {
+            initiator_nonce: &nonce,
+            responder_nonce: &responder_nonce,
+            initiator_id: node_id,
+            responder_id
+        };
+
+        let tag = auth::sign_join(CLUSTER_KEY.as_bytes(), JoinRole::Initiator, &transcript);
+        connection.send(make_event(Kind::AuthResponse as i32, vec![tag])).unwrap();
+        assert!(wait_for(&mut connection, Kind::AuthResponse).is_some(), "node {} hasn't joined", node_id);
+        joined.push(connection);
+    }
+}
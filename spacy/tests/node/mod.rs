@@ -0,0 +1,167 @@
+// Node, that runs as a separate process, tests talk to it as clients and nodes do
+
+// Each test uses only some of the helpers
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+    thread, time
+};
+use common::{
+    codec::Connection,
+    event::{self, proto_msg::{self, event::Kind}},
+    package,
+    plugin_protocol::{self, ADDR_VAR, TOKEN_VAR},
+    utils
+};
+
+// Node always listens on the same ports, so nodes are started one at a time
+pub static NODE_LOCK: Mutex<()> = Mutex::new(());
+
+pub const TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+pub struct Node {
+    process: Child,
+    pub addr: SocketAddr
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+// Node is reachable only on interface addresses, test can't run without them
+pub fn start_node(envs: &[(&str, &str)]) -> Node {
+    let addr = utils::get_socket_addrs(32000).into_iter()
+        .find(|addr| addr.is_ipv4())
+        .expect("node needs an ipv4 interface to listen on");
+
+    assert!(TcpListener::bind(addr).is_ok(), "node's port {} is busy", addr);
+
+    let process = Command::new(env!("CARGO_BIN_EXE_spacy"))
+        .env_remove("SPACY_TLS_CERT")
+        .env_remove("SPACY_CLUSTER_KEY")
+        .env_remove("SPACY_CLUSTER_KEY_FILE")
+        .env_remove("SPACY_CLIENTS_FILE")
+        .envs(envs.iter().copied())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let node = Node { process, addr };
+
+    let started = time::Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        assert!(started.elapsed() < TIMEOUT, "node didn't start");
+        thread::sleep(time::Duration::from_millis(50));
+    }
+
+    node
+}
+
+// Plugins and their logs are kept in the given directory
+pub fn start_node_in(dir: &Path) -> Node {
+    let plugins_dir = dir.join("plugins");
+    let logs_dir = dir.join("logs");
+    start_node(&[
+        ("SPACY_PLUGINS_DIR", plugins_dir.to_str().unwrap()),
+        ("SPACY_PLUGIN_LOGS_DIR", logs_dir.to_str().unwrap())
+    ])
+}
+
+pub fn connect(node: &Node, kind: Kind, data: Vec<Vec<u8>>) -> Connection<TcpStream> {
+    let stream = TcpStream::connect(node.addr).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let mut connection = Connection::new(stream);
+    connection.send(make_event(kind as i32, data)).unwrap();
+
+    connection
+}
+
+pub fn make_event(kind: i32, data: Vec<Vec<u8>>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind,
+        data,
+        meta: vec![],
+        correlation_id: None
+    }
+}
+
+// Waits for the event of the given kind, skipping others
+pub fn wait_for(connection: &mut Connection<TcpStream>, kind: Kind) -> Option<proto_msg::Event> {
+    let started = time::Instant::now();
+    while started.elapsed() < TIMEOUT {
+        let received = connection.wait_events().ok()?;
+        if let Some(event) = received.events.into_iter().find(|event| event.kind == kind as i32) {
+            return Some(event);
+        }
+
+        if received.closed {
+            return None;
+        }
+    }
+
+    None
+}
+
+// Status of the response, it goes first
+pub fn status(data: &[Vec<u8>]) -> i32 {
+    i32::from_ne_bytes(data[0].as_slice().try_into().unwrap())
+}
+
+pub fn send_to_plugin(connection: &mut Connection<TcpStream>, name: &str) {
+    let plugin_event = make_event(10, vec![]);
+    connection.send(make_event(Kind::NewPluginEvent as i32, vec![name.as_bytes().to_vec(), event::serialize(plugin_event)])).unwrap();
+}
+
+pub fn make_package(dir: &Path, name: &str, code: &str) -> Vec<u8> {
+    make_runtime_package(dir, name, "python", "main.py", code)
+}
+
+pub fn make_runtime_package(dir: &Path, name: &str, runtime: &str, entrypoint: &str, code: impl AsRef<[u8]>) -> Vec<u8> {
+    let package_dir = dir.join(name);
+    fs::create_dir_all(&package_dir).unwrap();
+    fs::write(package_dir.join("manifest.toml"), format!(
+        "name = \"{}\"\nversion = \"1\"\nentrypoint = \"{}\"\nruntime = \"{}\"\nrestart = \"never\"", name, entrypoint, runtime)).unwrap();
+    fs::write(package_dir.join(entrypoint), code).unwrap();
+    package::pack(&package_dir).unwrap()
+}
+
+// Launched process hands its address and token over to the test, which speaks for the plugin
+pub fn make_plugin(dir: &Path, name: &str) -> Vec<u8> {
+    let launch = dir.join("launch");
+    let script = format!("#!/bin/sh\necho \"${} ${}\" > {}\nwhile [ -e {} ]; do sleep 0.1; done\n",
+        ADDR_VAR, TOKEN_VAR, launch.display(), launch.display());
+    make_runtime_package(dir, name, "native", "run", script)
+}
+
+pub fn connect_plugin(dir: &Path) -> Connection<TcpStream> {
+    let launch = dir.join("launch");
+    let started = time::Instant::now();
+    let handed = loop {
+        match fs::read_to_string(&launch) {
+            Ok(handed) if handed.ends_with('\n') => break handed,
+            _ => {
+                assert!(started.elapsed() < TIMEOUT, "plugin wasn't launched");
+                thread::sleep(time::Duration::from_millis(20));
+            }
+        }
+    };
+
+    let (addr, token) = handed.trim().split_once(' ').unwrap();
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    let mut connection = Connection::new(stream);
+    connection.send(plugin_protocol::handshake(token)).unwrap();
+    connection
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use common::{
+    bus::{Bus, Inbox, Policy, QueueConfig},
+    event::proto_msg::{self, event::{Dest, Dir, Kind}},
+    package,
+    reactor::Reactor
+};
+use spacy::{config::Config, plugin_man::PluginMan};
+
+fn make_event(kind: Kind, data: Vec<Vec<u8>>, meta: Vec<Vec<u8>>, correlation_id: u64) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: Some(Dir::Incoming as i32),
+        dest: Some(Dest::PluginMan as i32),
+        kind: kind as i32,
+        data,
+        meta,
+        correlation_id: Some(correlation_id)
+    }
+}
+
+fn take_all(inbox: &Inbox) -> Vec<proto_msg::Event> {
+    std::iter::from_fn(|| inbox.try_recv()).collect()
+}
+
+#[test]
+fn client_cant_answer_in_place_of_node() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("manifest.toml"), "name = \"deployed\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"python\"").unwrap();
+    fs::write(dir.path().join("main.py"), "").unwrap();
+    let package = package::pack(dir.path()).unwrap();
+
+    let bus = Bus::new();
+    let reactor = Reactor::new().unwrap();
+    let config = QueueConfig { capacity: 16, policy: Policy::Drop };
+    let server = bus.register(Dest::Server, config, reactor.notifier()).unwrap();
+    let node = bus.register(Dest::Node, config, reactor.notifier()).unwrap();
+    let mut plugin_man = PluginMan::new(bus, &Config::default());
+
+    // Deployment waits for the node's answer
+    plugin_man.process_event(make_event(Kind::NewPlugin, vec![package, b"all".to_vec()], vec![b"alice".to_vec()], 5)).unwrap();
+    let deployment = take_all(&node).into_iter().find(|event| event.kind == Kind::DeployPlugin as i32).unwrap();
+    let request_id = deployment.correlation_id.unwrap();
+
+    // Server passes client's events with client's name, same id might be given to one of them
+    let forged = make_event(Kind::TransactionSucceeded, vec![0i32.to_ne_bytes().to_vec()], vec![b"mallory".to_vec()], request_id);
+    plugin_man.process_event(forged).unwrap();
+    assert!(take_all(&server).is_empty());
+
+    let answer = make_event(Kind::TransactionFailed, vec![(-7i32).to_ne_bytes().to_vec()], vec![], request_id);
+    plugin_man.process_event(answer).unwrap();
+    let responses = take_all(&server);
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].correlation_id, Some(5));
+    assert_eq!(responses[0].data, vec![(-7i32).to_ne_bytes().to_vec()]);
+}
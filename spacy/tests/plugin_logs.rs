@@ -0,0 +1,64 @@
+use std::fs;
+use spacy::plugin_logs::{self, LogStream, PluginLogs};
+
+fn lines<'a>(lines: impl Iterator<Item = &'a plugin_logs::LogLine>) -> Vec<(LogStream, String)> {
+    lines.map(|line| (line.stream, String::from_utf8_lossy(&line.line).to_string())).collect()
+}
+
+#[test]
+fn output_is_split_into_lines() {
+    let mut logs = PluginLogs::new(10, None);
+
+    logs.push(LogStream::Stdout, b"Received 3/");
+    logs.push(LogStream::Stderr, b"Traceback\n");
+    logs.push(LogStream::Stdout, b"10 results\n\nlast");
+    assert_eq!(logs.next_seq(), 3);
+
+    logs.close(LogStream::Stdout);
+    logs.close(LogStream::Stderr);
+
+    assert_eq!(lines(logs.tail(10)), vec![
+        (LogStream::Stderr, "Traceback".to_string()),
+        (LogStream::Stdout, "Received 3/10 results".to_string()),
+        (LogStream::Stdout, "".to_string()),
+        (LogStream::Stdout, "last".to_string())
+    ]);
+}
+
+#[test]
+fn only_last_lines_are_kept() {
+    let mut logs = PluginLogs::new(3, None);
+    for i in 0..5 {
+        logs.push(LogStream::Stdout, format!("{}\n", i).as_bytes());
+    }
+
+    assert_eq!(lines(logs.tail(10)).len(), 3);
+    assert_eq!(lines(logs.tail(1)), vec![(LogStream::Stdout, "4".to_string())]);
+
+    // Follower, that has fallen behind, gets what is left
+    assert_eq!(lines(logs.since(0)).len(), 3);
+    assert_eq!(lines(logs.since(4)), vec![(LogStream::Stdout, "4".to_string())]);
+    assert_eq!(lines(logs.since(logs.next_seq())).len(), 0);
+}
+
+#[test]
+fn long_lines_are_split() {
+    let mut logs = PluginLogs::new(10, None);
+    logs.push(LogStream::Stdout, &vec![b'a'; plugin_logs::MAX_LINE_LENGTH + 1]);
+    logs.close(LogStream::Stdout);
+
+    let lengths: Vec<usize> = logs.tail(10).map(|line| line.line.len()).collect();
+    assert_eq!(lengths, vec![plugin_logs::MAX_LINE_LENGTH, 1]);
+}
+
+#[test]
+fn lines_are_written_to_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("logs/simple_calc.log");
+
+    let mut logs = PluginLogs::new(10, Some(path.clone()));
+    logs.push(LogStream::Stdout, b"Received 1/10 results\n");
+    logs.push(LogStream::Stderr, b"Traceback\n");
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "stdout: Received 1/10 results\nstderr: Traceback\n");
+}
@@ -0,0 +1,99 @@
+mod node;
+
+use std::{fs, io::Write, time::Duration};
+use common::event::{self, proto_msg::{self, event::Kind}};
+use node::{connect, connect_plugin, make_event, make_plugin, send_to_plugin, start_node_in, status, wait_for, NODE_LOCK};
+
+// Requests, that are more than the plugin manager's queue takes
+const FLOOD_SIZE: u64 = 20000;
+
+fn make_request(kind: Kind, data: Vec<Vec<u8>>, correlation_id: u64) -> proto_msg::Event {
+    proto_msg::Event { correlation_id: Some(correlation_id), ..make_event(kind as i32, data) }
+}
+
+#[test]
+fn plugin_response_outlives_its_flood() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut client = connect(&node, Kind::MarkMeClient, vec![]);
+    client.send(make_event(Kind::NewPlugin as i32, vec![make_plugin(dir.path(), "flood")])).unwrap();
+    let mut plugin = connect_plugin(dir.path());
+    assert_eq!(status(&wait_for(&mut client, Kind::RespondClient).unwrap().data), 0);
+
+    send_to_plugin(&mut client, "flood");
+    // Client's event is of kind 10, it's just passed on
+    let request = wait_for(&mut plugin, Kind::UpdateSharedMemory).unwrap();
+
+    // Response goes first, so it's the oldest of plugin's events, when the queue is full
+    let mut frames = event::serialize(make_request(Kind::RespondClient, vec![b"answer".to_vec()], request.correlation_id.unwrap()));
+    for id in 1..=FLOOD_SIZE {
+        frames.extend(event::serialize(make_request(Kind::UpdateSharedMemory, vec![1i32.to_ne_bytes().to_vec(), vec![]], id)));
+    }
+    plugin.get_mut().write_all(&frames).unwrap();
+
+    let response = wait_for(&mut client, Kind::RespondClient).expect("plugin's response was dropped");
+    assert_eq!(response.data, vec![b"answer".to_vec()]);
+
+    // Requests, that were dropped, are answered right away, not after the timeout
+    assert!(wait_for(&mut plugin, Kind::RequestTimedOut).is_some());
+
+    let _ = fs::remove_file(dir.path().join("launch"));
+}
+
+#[test]
+fn every_event_of_request_is_answered() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut client = connect(&node, Kind::MarkMeClient, vec![]);
+    client.send(make_event(Kind::NewPlugin as i32, vec![make_plugin(dir.path(), "echo")])).unwrap();
+    let mut plugin = connect_plugin(dir.path());
+    assert_eq!(status(&wait_for(&mut client, Kind::RespondClient).unwrap().data), 0);
+
+    let mut events = vec![];
+    for answer in [&b"first"[..], b"second", b"third"] {
+        events.extend(event::serialize(make_event(10, vec![answer.to_vec()])));
+    }
+    client.send(make_event(Kind::NewPluginEvent as i32, vec![b"echo".to_vec(), events])).unwrap();
+
+    // Plugin answers each event with its data, and once more, which is too many
+    let mut received = vec![];
+    while received.len() < 3 {
+        received.extend(plugin.wait_events().unwrap().events);
+    }
+    let id = received[0].correlation_id.unwrap();
+    for event in received.iter().chain(received.first()) {
+        assert_eq!(event.correlation_id, Some(id));
+        plugin.send(make_request(Kind::RespondClient, event.data.clone(), id)).unwrap();
+    }
+
+    for answer in [&b"first"[..], b"second"] {
+        let response = wait_for(&mut client, Kind::RespondClient).unwrap();
+        assert_eq!(response.data, vec![answer.to_vec()]);
+        assert!(event::is_partial_response(&response));
+    }
+
+    let response = wait_for(&mut client, Kind::RespondClient).unwrap();
+    assert_eq!(response.data, vec![b"third".to_vec()]);
+    assert!(!event::is_partial_response(&response));
+
+    // Request is done with, the extra response doesn't reach the client
+    client.get_ref().set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    assert!(wait_for(&mut client, Kind::RespondClient).is_none());
+
+    let _ = fs::remove_file(dir.path().join("launch"));
+}
+
+#[test]
+fn request_without_events_is_rejected() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut client = connect(&node, Kind::MarkMeClient, vec![]);
+    client.send(make_event(Kind::NewPluginEvent as i32, vec![b"echo".to_vec(), vec![]])).unwrap();
+    assert_eq!(status(&wait_for(&mut client, Kind::RespondClient).unwrap().data), -13);
+}
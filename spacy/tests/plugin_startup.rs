@@ -0,0 +1,169 @@
+mod node;
+
+use std::{
+    fs,
+    io::Read,
+    net::TcpStream,
+    path::Path,
+    thread,
+    time::Duration
+};
+use common::{codec::Connection, event::proto_msg::event::Kind, package};
+use node::{
+    connect, make_event, make_package, make_runtime_package, send_to_plugin, start_node_in, status, wait_for,
+    NODE_LOCK, TIMEOUT
+};
+
+#[test]
+fn plugin_exiting_before_handshake_is_reported() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let package = make_package(dir.path(), "broken", "raise SystemExit(3)");
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::NewPlugin as i32, vec![package])).unwrap();
+
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), -10);
+
+    // Name is free again
+    connection.send(make_event(Kind::GetPluginList as i32, vec![])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert!(!response.data.contains(&b"broken".to_vec()));
+}
+
+#[test]
+fn node_serves_clients_while_plugin_starts() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let package = make_package(dir.path(), "slow", "import time\ntime.sleep(20)");
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::NewPlugin as i32, vec![package])).unwrap();
+
+    // Plugin, that hasn't connected, doesn't hold other requests back
+    connection.send(make_event(Kind::GetPluginStatus as i32, vec![b"slow".to_vec()])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), 0);
+    assert_eq!(response.data[1], b"starting");
+
+    // Process, that doesn't know plugin's token, can't pose as the plugin
+    let stream = TcpStream::connect(("127.0.0.1", 32002)).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut impostor = Connection::new(stream);
+    impostor.send(make_event(Kind::MarkMePlugin as i32, vec![b"guessed token".to_vec()])).unwrap();
+    let mut buffer = [0; 16];
+    assert_eq!(impostor.get_mut().read(&mut buffer).unwrap(), 0);
+
+    connection.send(make_event(Kind::GetPluginStatus as i32, vec![b"slow".to_vec()])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(response.data[1], b"starting");
+}
+
+#[test]
+fn native_plugin_is_executed_itself() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    // Python would fail on it right away
+    let package = make_runtime_package(dir.path(), "native", "native", "run", "#!/bin/sh\nsleep 20\n");
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::NewPlugin as i32, vec![package])).unwrap();
+
+    thread::sleep(Duration::from_millis(500));
+    connection.send(make_event(Kind::GetPluginStatus as i32, vec![b"native".to_vec()])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), 0);
+    assert_eq!(response.data[1], b"starting");
+}
+
+// Python plugin, that needs `dependency`, which is vendored with the given code, if there is one
+fn make_dependent_package(dir: &Path, dependency: &str, dependency_code: Option<&str>) -> Vec<u8> {
+    let package_dir = dir.join("dependent");
+    fs::create_dir_all(package_dir.join("deps")).unwrap();
+    fs::write(package_dir.join("manifest.toml"), format!(
+        "name = \"dependent\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"python\"\nrestart = \"never\"\ndependencies = [\"{}\"]",
+        dependency)).unwrap();
+    fs::write(package_dir.join("main.py"), "import time\ntime.sleep(20)").unwrap();
+    if let Some(code) = dependency_code {
+        fs::write(package_dir.join("deps").join(format!("{}.py", dependency)), code).unwrap();
+    }
+    package::pack(&package_dir).unwrap()
+}
+
+#[test]
+fn missing_dependency_is_reported() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::NewPlugin as i32, vec![make_dependent_package(dir.path(), "no_such_module", None)])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), -6);
+}
+
+#[test]
+fn dependency_hanging_on_import_doesnt_block_node() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let package = make_dependent_package(dir.path(), "stuck", Some("import time\ntime.sleep(20)"));
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::NewPlugin as i32, vec![package])).unwrap();
+
+    connection.send(make_event(Kind::GetPluginStatus as i32, vec![b"dependent".to_vec()])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), 0);
+    assert_eq!(response.data[1], b"starting");
+}
+
+// Answers client's event with the given text
+fn make_wasm_package(dir: &Path, answer: &str) -> Vec<u8> {
+    let module = wat::parse_str(format!(r#"
+    (module
+      (import "spacy" "get_event" (func $get_event (param i32 i32) (result i32)))
+      (import "spacy" "respond_client" (func $respond_client (param i64 i32 i32)))
+      (memory (export "memory") 1)
+      (data (i32.const 1024) "\01\00\00\00\{:02x}\00\00\00{}")
+      (func (export "update")
+        (if (i32.eqz (call $get_event (i32.const 0) (i32.const 1024))) (then (return)))
+        (call $respond_client (i64.load (i32.const 4)) (i32.const 1024) (i32.const {}))))
+    "#, answer.len(), answer, answer.len() + 8)).unwrap();
+
+    make_runtime_package(dir, "echo", "wasm", "echo.wasm", module)
+}
+
+fn ask_plugin(connection: &mut Connection<TcpStream>, name: &str) -> Vec<Vec<u8>> {
+    send_to_plugin(connection, name);
+    wait_for(connection, Kind::RespondClient).unwrap().data
+}
+
+#[test]
+fn wasm_plugin_runs_inside_node_and_is_reloaded() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    connection.send(make_event(Kind::NewPlugin as i32, vec![make_wasm_package(&dir.path().join("v1"), "first")])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), 0);
+
+    assert_eq!(ask_plugin(&mut connection, "echo"), vec![b"first".to_vec()]);
+
+    // Same name is taken by the new version without stopping the plugin
+    connection.send(make_event(Kind::NewPlugin as i32, vec![make_wasm_package(&dir.path().join("v2"), "second")])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(status(&response.data), 0);
+
+    assert_eq!(ask_plugin(&mut connection, "echo"), vec![b"second".to_vec()]);
+
+    connection.send(make_event(Kind::GetPluginStatus as i32, vec![b"echo".to_vec()])).unwrap();
+    let response = wait_for(&mut connection, Kind::RespondClient).unwrap();
+    assert_eq!(response.data[1], b"running");
+}
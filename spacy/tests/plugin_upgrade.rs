@@ -0,0 +1,127 @@
+mod node;
+
+use std::{fs, net::TcpStream, path::Path, thread, time::{Duration, Instant}};
+use common::{codec::Connection, event::proto_msg::event::Kind};
+use spacy_plugin_sdk::{Context, Event, Plugin, ADDR_VAR, TOKEN_VAR};
+use node::{
+    connect, make_event, make_runtime_package, send_to_plugin, start_node_in, status, wait_for,
+    NODE_LOCK, TIMEOUT
+};
+
+// Answers every client's event with `answer` after `delay`
+struct Versioned {
+    answer: &'static str,
+    delay: Duration
+}
+
+impl Plugin for Versioned {
+    fn on_event(&mut self, context: &mut Context, event: Event) {
+        if let Some(correlation_id) = event.correlation_id {
+            thread::sleep(self.delay);
+            let _ = context.respond_client(vec![self.answer.as_bytes().to_vec()], correlation_id);
+        }
+    }
+}
+
+// Launched process hands its address and token over to the test, which runs `Versioned` with
+// the SDK on its behalf. Process lives as long as the plugin's connection does
+fn make_plugin(dir: &Path, answer: &'static str, delay: u64) -> Vec<u8> {
+    let launch = dir.join("launch");
+    let script = format!("#!/bin/sh\necho \"${} ${}\" > {}\nwhile [ -e {} ]; do sleep 0.1; done\n",
+        ADDR_VAR, TOKEN_VAR, launch.display(), launch.display());
+    let package = make_runtime_package(dir, "versioned", "native", "run", script);
+
+    thread::spawn(move || {
+        let started = Instant::now();
+        let handed = loop {
+            match fs::read_to_string(&launch) {
+                Ok(handed) if handed.ends_with('\n') => break handed,
+                _ if started.elapsed() > TIMEOUT => return,
+                _ => thread::sleep(Duration::from_millis(20))
+            }
+        };
+
+        let (addr, token) = handed.trim().split_once(' ').unwrap();
+        let mut context = Context::connect(addr, token).unwrap();
+        let _ = context.run(&mut Versioned { answer, delay: Duration::from_secs(delay) });
+        let _ = fs::remove_file(&launch);
+    });
+
+    package
+}
+
+fn request(connection: &mut Connection<TcpStream>, kind: Kind, data: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    connection.send(make_event(kind as i32, data)).unwrap();
+    wait_for(connection, Kind::RespondClient).unwrap().data
+}
+
+// Entrypoint, that is installed, tells which version it is
+fn installed_version(dir: &Path) -> String {
+    fs::read_to_string(dir.join("plugins/versioned/run")).unwrap()
+}
+
+#[test]
+fn previous_version_answers_requests_it_has_taken() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    assert_eq!(status(&request(&mut connection, Kind::NewPlugin, vec![make_plugin(&dir.path().join("v1"), "first", 2)])), 0);
+
+    // Request is taken by the first version, it answers after the upgrade
+    send_to_plugin(&mut connection, "versioned");
+    let upgraded = request(&mut connection, Kind::UpgradePlugin, vec![make_plugin(&dir.path().join("v2"), "second", 0)]);
+    assert_eq!(status(&upgraded), 0);
+    assert_eq!(wait_for(&mut connection, Kind::RespondClient).unwrap().data, vec![b"first".to_vec()]);
+
+    send_to_plugin(&mut connection, "versioned");
+    assert_eq!(wait_for(&mut connection, Kind::RespondClient).unwrap().data, vec![b"second".to_vec()]);
+
+    let response = request(&mut connection, Kind::GetPluginStatus, vec![b"versioned".to_vec()]);
+    assert_eq!(response[1], b"running");
+    assert!(installed_version(dir.path()).contains("/v2/launch"));
+
+    // Previous version exits, once it has drained, its files go with it
+    let started = Instant::now();
+    while dir.path().join("plugins/.previous/versioned").exists() {
+        assert!(started.elapsed() < TIMEOUT, "previous version wasn't stopped");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn failed_upgrade_is_rolled_back() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    assert_eq!(status(&request(&mut connection, Kind::NewPlugin, vec![make_plugin(&dir.path().join("v1"), "first", 0)])), 0);
+
+    // New version exits before the handshake
+    let upgraded = request(&mut connection, Kind::UpgradePlugin, vec![make_runtime_package(&dir.path().join("v2"), "versioned", "native", "run", "#!/bin/sh\nexit 3\n")]);
+    assert_eq!(status(&upgraded), -10);
+
+    send_to_plugin(&mut connection, "versioned");
+    assert_eq!(wait_for(&mut connection, Kind::RespondClient).unwrap().data, vec![b"first".to_vec()]);
+    assert!(installed_version(dir.path()).contains("/v1/launch"));
+
+    let response = request(&mut connection, Kind::GetPluginStatus, vec![b"versioned".to_vec()]);
+    assert_eq!(response[1], b"running");
+    assert_eq!(response[2], 0u32.to_ne_bytes());
+}
+
+#[test]
+fn only_running_plugin_is_upgraded() {
+    let _lock = NODE_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+    let dir = tempfile::tempdir().unwrap();
+    let node = start_node_in(dir.path());
+
+    let mut connection = connect(&node, Kind::MarkMeClient, vec![]);
+    let upgraded = request(&mut connection, Kind::UpgradePlugin, vec![make_plugin(&dir.path().join("v1"), "first", 0)]);
+    assert_eq!(status(&upgraded), -1);
+
+    let response = request(&mut connection, Kind::GetPluginList, vec![]);
+    assert!(!response.contains(&b"versioned".to_vec()));
+}
@@ -0,0 +1,50 @@
+use std::process::{Command, Output, Stdio};
+use spacy::sandbox::{self, Sandbox};
+
+fn run(sandbox: &Sandbox, program: &str, args: &[&str]) -> Output {
+    let mut command = Command::new(program);
+    sandbox.apply(&mut command);
+    command.args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn limits_are_set() {
+    let sandbox = Sandbox { max_fds: Some(64), memory_bytes: Some(512 * 1024 * 1024), ..Sandbox::default() };
+
+    let output = run(&sandbox, "sh", &["-c", "ulimit -n; ulimit -v"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "64\n524288\n");
+}
+
+#[test]
+fn cpu_limit_kills_plugin() {
+    let sandbox = Sandbox { cpu_seconds: Some(1), ..Sandbox::default() };
+
+    let output = run(&sandbox, "sh", &["-c", "while :; do :; done"]);
+    assert_eq!(sandbox::exit_reason(&output.status), "CPU time limit exceeded");
+}
+
+#[test]
+fn forbidden_system_call_kills_plugin() {
+    let sandbox = Sandbox { seccomp: true, ..Sandbox::default() };
+
+    let output = run(&sandbox, "python3", &["-c", "import os; os.chroot('/')"]);
+    assert_eq!(sandbox::exit_reason(&output.status), "forbidden system call");
+
+    let output = run(&sandbox, "python3", &["-c", "import os; print(os.getcwd())"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn environment_is_not_inherited() {
+    std::env::set_var("SPACY_SANDBOX_TEST_SECRET", "secret");
+
+    let output = run(&Sandbox::default(), "sh", &["-c", "echo \"$SPACY_SANDBOX_TEST_SECRET\"; command -v sh"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with('\n'));
+    assert!(stdout.trim().ends_with("sh"));
+}
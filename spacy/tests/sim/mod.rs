@@ -0,0 +1,466 @@
+// Deterministic simulation of a cluster. All nodes run in the test's thread,
+// messages between them go through a single queue ordered by delivery time,
+// and time moves only when the next message is delivered
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+    time::Duration
+};
+use common::{
+    bus::{Bus, Inbox, Policy, QueueConfig},
+    clock::{Clock, ManualClock},
+    event::{self, proto_msg::{self, event::{Dest, Dir, Kind}}},
+    reactor::Reactor
+};
+use spacy::node::Node;
+
+// Safety net against livelocks, no test needs that many deliveries
+const MAX_STEPS: usize = 1_000_000;
+
+#[derive(Clone, Copy)]
+pub struct Faults {
+    // Every message between nodes is delayed by a random time in this range
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+    // Messages on the same connection may overtake each other, TCP never does that
+    pub reorder: bool,
+    // Share of messages between nodes, that are lost (from 0 to 1000)
+    pub drop_per_mille: u64
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            min_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            reorder: false,
+            drop_per_mille: 0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reply {
+    Succeeded,
+    Failed
+}
+
+struct SimNode {
+    id: u128,
+    node: Node,
+    // Whatever node sends to the server and to the plugin manager
+    server: Inbox,
+    plugin_man: Inbox,
+    // Deployed plugins, that plugin manager was told to run
+    running: HashSet<Vec<u8>>,
+    _reactor: Reactor
+}
+
+struct Message {
+    from: usize,
+    to: usize,
+    // Message is lost, if connection it was sent over has changed
+    epoch: Option<u64>,
+    event: proto_msg::Event
+}
+
+struct Write {
+    key: i32,
+    value: Vec<u8>
+}
+
+pub struct Simulation {
+    clock: Arc<ManualClock>,
+    rng: Rng,
+    faults: Faults,
+    nodes: Vec<SimNode>,
+
+    queue: BinaryHeap<Reverse<(u128, u64)>>,
+    messages: HashMap<u64, Message>,
+    next_seq: u64,
+    // When the last message on each connection is delivered, so messages don't overtake each other
+    links_last_delivery: HashMap<(usize, usize), u128>,
+    // Connected nodes and how many times connection between them has changed
+    links: HashSet<(usize, usize)>,
+    links_epochs: HashMap<(usize, usize), u64>,
+
+    next_request_id: u64,
+    writes: HashMap<u64, Write>,
+    replies: HashMap<u64, Vec<Reply>>,
+    // Writes in the order they were committed
+    committed: Vec<u64>
+}
+
+impl Simulation {
+    pub fn new(nodes_num: usize, seed: u64, faults: Faults) -> Self {
+        let clock = Arc::new(ManualClock::new(1_000_000_000));
+
+        let mut nodes = vec![];
+        for _ in 0..nodes_num {
+            // Node ids are taken from the clock, so they have to differ
+            clock.advance(Duration::from_nanos(1));
+
+            let bus = Bus::new();
+            let reactor = Reactor::new().unwrap();
+            let config = QueueConfig { capacity: 1024, policy: Policy::Drop };
+            let server = bus.register(Dest::Server, config, reactor.notifier()).unwrap();
+            let plugin_man = bus.register(Dest::PluginMan, config, reactor.notifier()).unwrap();
+
+            let mut node = Node::with_clock(bus, clock.clone());
+            nodes.push(SimNode { id: node.get_node_id(), node, server, plugin_man, running: HashSet::new(), _reactor: reactor });
+        }
+
+        Self {
+            clock,
+            rng: Rng(seed.max(1)),
+            faults,
+            nodes,
+
+            queue: BinaryHeap::new(),
+            messages: HashMap::new(),
+            next_seq: 0,
+            links_last_delivery: HashMap::new(),
+            links: HashSet::new(),
+            links_epochs: HashMap::new(),
+
+            next_request_id: 0,
+            writes: HashMap::new(),
+            replies: HashMap::new(),
+            committed: vec![]
+        }
+    }
+
+    pub fn nodes_num(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Connects every pair of nodes one by one, as scanners would find them
+    pub fn connect_all(&mut self) {
+        for i in 0..self.nodes.len() {
+            for j in (i + 1)..self.nodes.len() {
+                self.connect(i, j);
+                self.run_until_quiet();
+            }
+        }
+    }
+
+    pub fn connect(&mut self, a: usize, b: usize) {
+        if !self.links.insert(link(a, b)) {
+            return;
+        }
+        *self.links_epochs.entry(link(a, b)).or_default() += 1;
+
+        // Both servers notify their nodes
+        self.send_local(a, Kind::NodeConnected, self.nodes[b].id);
+        self.send_local(b, Kind::NodeConnected, self.nodes[a].id);
+    }
+
+    pub fn disconnect(&mut self, a: usize, b: usize) {
+        if !self.links.remove(&link(a, b)) {
+            return;
+        }
+        // Messages, that are on the way, are lost together with the connection
+        *self.links_epochs.entry(link(a, b)).or_default() += 1;
+
+        self.send_local(a, Kind::NodeDisconnected, self.nodes[b].id);
+        self.send_local(b, Kind::NodeDisconnected, self.nodes[a].id);
+    }
+
+    // Breaks connections between the group and the rest of the cluster
+    pub fn partition(&mut self, group: &[usize]) {
+        for a in group.iter().copied() {
+            for b in (0..self.nodes.len()).filter(|b| !group.contains(b)) {
+                self.disconnect(a, b);
+            }
+        }
+    }
+
+    // Restores all connections one by one
+    pub fn heal(&mut self) {
+        for a in 0..self.nodes.len() {
+            for b in (a + 1)..self.nodes.len() {
+                if !self.links.contains(&link(a, b)) {
+                    self.connect(a, b);
+                    self.run_until_quiet();
+                }
+            }
+        }
+    }
+
+    // Plugin on the node asks to update shared memory. Returns request id
+    pub fn write(&mut self, node: usize, key: i32, value: &[u8]) -> u64 {
+        let request_id = self.request(node, Kind::UpdateSharedMemory, vec![key.to_ne_bytes().to_vec(), value.to_vec()]);
+        self.writes.insert(request_id, Write { key, value: value.to_vec() });
+
+        request_id
+    }
+
+    // Client deploys the plugin through the node. Returns request id
+    pub fn deploy(&mut self, node: usize, name: &str, placement: &str) -> u64 {
+        let package = format!("package of {}", name).into_bytes();
+        self.request(node, Kind::DeployPlugin, vec![name.as_bytes().to_vec(), placement.as_bytes().to_vec(), package])
+    }
+
+    // Client removes the plugin from the cluster. Returns request id
+    pub fn remove_deployment(&mut self, node: usize, name: &str) -> u64 {
+        self.request(node, Kind::RemoveDeployment, vec![name.as_bytes().to_vec()])
+    }
+
+    // Plugin manager's request to the node
+    fn request(&mut self, node: usize, kind: Kind, data: Vec<Vec<u8>>) -> u64 {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+
+        let event = proto_msg::Event {
+            dir: Some(Dir::Outcoming as i32),
+            dest: Some(Dest::Node as i32),
+            kind: kind as i32,
+            data,
+            meta: vec![],
+            correlation_id: Some(request_id)
+        };
+        self.enqueue(Message { from: node, to: node, epoch: None, event }, 0);
+
+        request_id
+    }
+
+    // Delivers messages until there is nothing left
+    pub fn run_until_quiet(&mut self) {
+        let mut steps = 0;
+        while self.step() {
+            steps += 1;
+            assert!(steps < MAX_STEPS, "cluster doesn't settle");
+        }
+    }
+
+    // Delivers messages, that arrive within the given time, then lets nodes see,
+    // that time has passed, so their timeouts fire
+    pub fn run_for(&mut self, duration: Duration) {
+        let deadline = self.clock.now() + duration.as_nanos();
+        while let Some(Reverse((deliver_at, _))) = self.queue.peek() {
+            if *deliver_at > deadline {
+                break;
+            }
+            self.step();
+        }
+        self.clock.set(deadline);
+
+        for node in 0..self.nodes.len() {
+            self.nodes[node].node.process_idle().expect("node failed");
+            self.collect(node);
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        let (deliver_at, seq) = match self.queue.pop() {
+            Some(Reverse(next)) => next,
+            None => return false
+        };
+        let message = self.messages.remove(&seq).unwrap();
+        self.clock.set(deliver_at.max(self.clock.now()));
+
+        if let Some(epoch) = message.epoch {
+            if self.links_epochs[&link(message.from, message.to)] != epoch {
+                return true;
+            }
+        }
+
+        let node = &mut self.nodes[message.to].node;
+        node.process_event(message.event).expect("node failed");
+        node.process_idle().expect("node failed");
+
+        self.collect(message.to);
+        true
+    }
+
+    // Takes what node has sent and routes it the way server and plugin manager would
+    fn collect(&mut self, from: usize) {
+        while let Some(event) = self.nodes[from].server.try_recv() {
+            if event.kind == Kind::BroadcastEvent as i32 {
+                let (events, _rem) = event::deserialize(event::get_field(&event.data, 0).unwrap());
+                let nodes_count = event::get_i32(&event.data, 1).unwrap() as usize;
+
+                for i in 0..nodes_count {
+                    let node_id = event::get_u128(&event.data, 2 + i).unwrap();
+                    let to = self.nodes.iter().position(|node| node.id == node_id).unwrap();
+
+                    for event in events.iter() {
+                        self.send_peer(from, to, event.clone());
+                    }
+                }
+            }
+
+            else if event.kind == Kind::ApproveTransaction as i32 {
+                // Requester is known by its fd, which is its index here
+                let to = event::get_i32(&event.meta, 0).unwrap() as usize;
+                let event = proto_msg::Event { meta: event.meta[1..].to_vec(), ..event };
+
+                self.send_peer(from, to, event);
+            }
+
+            else {
+                panic!("node sent unexpected event to the server: {}", event.kind);
+            }
+        }
+
+        while let Some(event) = self.nodes[from].plugin_man.try_recv() {
+            if event.kind == Kind::StartPlugin as i32 {
+                let name = event::get_field(&event.data, 0).unwrap().to_vec();
+                assert!(self.nodes[from].running.insert(name), "node {} started plugin twice", from);
+                continue;
+            }
+
+            if event.kind == Kind::StopPlugin as i32 {
+                let name = event::get_field(&event.data, 0).unwrap();
+                assert!(self.nodes[from].running.remove(name), "node {} stopped plugin, that isn't running", from);
+                continue;
+            }
+
+            let reply = if event.kind == Kind::TransactionSucceeded as i32 {
+                Reply::Succeeded
+            } else if event.kind == Kind::TransactionFailed as i32 {
+                Reply::Failed
+            } else {
+                continue;
+            };
+
+            let request_id = event.correlation_id.unwrap();
+            self.replies.entry(request_id).or_default().push(reply);
+            if reply == Reply::Succeeded && self.writes.contains_key(&request_id) {
+                self.committed.push(request_id);
+            }
+        }
+    }
+
+    fn send_local(&mut self, to: usize, kind: Kind, node_id: u128) {
+        let event = proto_msg::Event {
+            dir: Some(Dir::Incoming as i32),
+            dest: Some(Dest::Node as i32),
+            kind: kind as i32,
+            data: vec![node_id.to_ne_bytes().to_vec()],
+            meta: vec![],
+            correlation_id: None
+        };
+
+        self.enqueue(Message { from: to, to, epoch: None, event }, 0);
+    }
+
+    fn send_peer(&mut self, from: usize, to: usize, event: proto_msg::Event) {
+        // Server doesn't know nodes, it has no connection to
+        if !self.links.contains(&link(from, to)) {
+            return;
+        }
+        let epoch = self.links_epochs[&link(from, to)];
+
+        if self.rng.below(1000) < self.faults.drop_per_mille {
+            return;
+        }
+
+        // Receiving server puts its fd of the connection first
+        let mut meta = event.meta;
+        meta.insert(0, (from as i32).to_ne_bytes().to_vec());
+        let event = proto_msg::Event {
+            dir: Some(Dir::Incoming as i32),
+            dest: Some(Dest::Node as i32),
+            kind: event.kind,
+            data: event.data,
+            meta,
+            correlation_id: None
+        };
+
+        let min_delay = self.faults.min_delay.as_nanos() as u64;
+        let max_delay = self.faults.max_delay.as_nanos() as u64;
+        let delay = min_delay + self.rng.below(max_delay - min_delay + 1);
+
+        self.enqueue(Message { from, to, epoch: Some(epoch), event }, delay as u128);
+    }
+
+    fn enqueue(&mut self, message: Message, delay: u128) {
+        let mut deliver_at = self.clock.now() + delay;
+
+        if !self.faults.reorder {
+            let last_delivery = self.links_last_delivery.entry((message.from, message.to)).or_default();
+            deliver_at = deliver_at.max(*last_delivery);
+            *last_delivery = deliver_at;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse((deliver_at, seq)));
+        self.messages.insert(seq, message);
+    }
+
+    // All replicas have the same shared memory
+    pub fn assert_converged(&self) {
+        let expected = self.nodes[0].node.get_shared_memory();
+        for (i, node) in self.nodes.iter().enumerate().skip(1) {
+            assert_eq!(node.node.get_shared_memory(), expected, "node {} diverged from node 0", i);
+        }
+    }
+
+    // Every write got exactly one reply
+    pub fn assert_replied_once(&self) {
+        for request_id in self.writes.keys() {
+            let replies = self.replies.get(request_id).map(|replies| replies.as_slice()).unwrap_or_default();
+            assert_eq!(replies.len(), 1, "write {} got replies {:?}", request_id, replies);
+        }
+    }
+
+    // Replicas hold exactly what committed writes have written, in the order of commits
+    pub fn assert_no_lost_writes(&self) {
+        let mut expected = HashMap::new();
+        for request_id in self.committed.iter() {
+            let write = &self.writes[request_id];
+            expected.insert(write.key, write.value.clone());
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            assert_eq!(node.node.get_shared_memory(), &expected, "node {} lost writes", i);
+        }
+    }
+
+    pub fn committed_num(&self) -> usize {
+        self.committed.len()
+    }
+
+    pub fn replies(&self, request_id: u64) -> &[Reply] {
+        self.replies.get(&request_id).map(|replies| replies.as_slice()).unwrap_or_default()
+    }
+
+    // Nodes, that run the deployed plugin
+    pub fn running_on(&self, name: &str) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|i| self.nodes[*i].running.contains(name.as_bytes()))
+            .collect()
+    }
+
+    // All replicas know the same deployments
+    pub fn assert_deployments_converged(&self) {
+        let expected = self.nodes[0].node.get_deployments();
+        for (i, node) in self.nodes.iter().enumerate().skip(1) {
+            assert_eq!(node.node.get_deployments(), expected, "node {} has different deployments", i);
+        }
+    }
+}
+
+fn link(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+// Deterministic source of randomness
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
@@ -0,0 +1,266 @@
+mod sim;
+
+use std::time::Duration;
+use sim::{Faults, Reply, Simulation};
+
+const SEEDS: u64 = 50;
+
+// Plugin manager gives up on requests after it, node has to answer sooner
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Each node writes to its own key and to the shared one
+fn write_round(sim: &mut Simulation, round: u8) {
+    for node in 0..sim.nodes_num() {
+        sim.write(node, node as i32, &[round, node as u8]);
+        sim.write(node, -1, &[round, node as u8]);
+    }
+}
+
+#[test]
+fn cluster_forms_without_faults() {
+    let mut sim = Simulation::new(3, 1, Faults::default());
+    sim.connect_all();
+    sim.assert_converged();
+
+    for round in 0..5 {
+        write_round(&mut sim, round);
+        sim.run_until_quiet();
+    }
+
+    sim.assert_replied_once();
+    sim.assert_converged();
+    sim.assert_no_lost_writes();
+    assert!(sim.committed_num() > 0);
+}
+
+#[test]
+fn sequential_writes_all_succeed() {
+    let mut sim = Simulation::new(4, 2, Faults::default());
+    sim.connect_all();
+
+    for i in 0..20 {
+        sim.write(i % 4, i as i32 % 3, &[i as u8]);
+        sim.run_until_quiet();
+    }
+
+    sim.assert_replied_once();
+    sim.assert_no_lost_writes();
+    assert_eq!(sim.committed_num(), 20);
+}
+
+#[test]
+fn cluster_converges_with_delays() {
+    let faults = Faults {
+        min_delay: Duration::from_micros(100),
+        max_delay: Duration::from_millis(20),
+        ..Faults::default()
+    };
+
+    for seed in 1..=SEEDS {
+        let mut sim = Simulation::new(3, seed, faults);
+        sim.connect_all();
+
+        // Writes overlap with transactions, that are still going on
+        for i in 0..30 {
+            sim.write(i % 3, i as i32 % 4, &[i as u8]);
+            sim.run_for(Duration::from_millis(seed % 7 * 5));
+        }
+        sim.run_until_quiet();
+
+        sim.assert_replied_once();
+        sim.assert_converged();
+        sim.assert_no_lost_writes();
+    }
+}
+
+#[test]
+fn cluster_converges_with_reordering() {
+    let faults = Faults {
+        min_delay: Duration::from_micros(100),
+        max_delay: Duration::from_millis(20),
+        reorder: true,
+        ..Faults::default()
+    };
+
+    for seed in 1..=SEEDS {
+        let mut sim = Simulation::new(3, seed, faults);
+        sim.connect_all();
+
+        // Writes overlap with transactions, that are still going on
+        for i in 0..30 {
+            sim.write(i % 3, i as i32 % 4, &[i as u8]);
+            sim.run_for(Duration::from_millis(seed % 7 * 5));
+        }
+        sim.run_until_quiet();
+
+        sim.assert_replied_once();
+        sim.assert_converged();
+        sim.assert_no_lost_writes();
+    }
+}
+
+#[test]
+fn lost_messages_end_with_one_reply_per_write() {
+    let faults = Faults {
+        min_delay: Duration::from_micros(100),
+        max_delay: Duration::from_millis(5),
+        drop_per_mille: 50,
+        ..Faults::default()
+    };
+
+    for seed in 1..=SEEDS {
+        let mut sim = Simulation::new(3, seed, faults);
+        sim.connect_all();
+
+        for round in 0..3 {
+            write_round(&mut sim, round);
+            sim.run_until_quiet();
+        }
+
+        // Writes, whose messages were lost, fail, once their transactions time out
+        sim.run_for(REQUEST_TIMEOUT);
+        sim.run_until_quiet();
+
+        sim.assert_replied_once();
+    }
+}
+
+#[test]
+fn cluster_converges_after_partition() {
+    for seed in 1..=SEEDS {
+        let mut sim = Simulation::new(4, seed, Faults::default());
+        sim.connect_all();
+
+        sim.partition(&[0, 1]);
+        sim.run_until_quiet();
+
+        write_round(&mut sim, 0);
+        sim.run_until_quiet();
+
+        sim.heal();
+        sim.run_until_quiet();
+
+        // Neither side is a majority, writes fail on both of them
+        sim.assert_replied_once();
+        sim.assert_converged();
+        assert_eq!(sim.committed_num(), 0);
+    }
+}
+
+#[test]
+fn partition_keeps_committed_writes() {
+    for seed in 1..=SEEDS {
+        let mut sim = Simulation::new(4, seed, Faults::default());
+        sim.connect_all();
+
+        // Only the majority commits, its writes survive the sync after the heal
+        sim.partition(&[0]);
+        sim.run_until_quiet();
+
+        write_round(&mut sim, 0);
+        sim.run_until_quiet();
+        assert_eq!(sim.replies(0), &[Reply::Failed]);
+        assert!(sim.committed_num() > 0);
+
+        // Syncs, that collide with membership changes, are retried, once they time out
+        sim.heal();
+        for _ in 0..2 {
+            sim.run_for(REQUEST_TIMEOUT);
+            sim.run_until_quiet();
+        }
+
+        sim.assert_replied_once();
+        sim.assert_no_lost_writes();
+    }
+}
+
+#[test]
+fn deployment_runs_where_placement_says() {
+    let mut sim = Simulation::new(4, 3, Faults::default());
+    sim.connect_all();
+
+    let all = sim.deploy(0, "everywhere", "all");
+    sim.run_until_quiet();
+    let replicated = sim.deploy(1, "replicated", "replicas=2");
+    sim.run_until_quiet();
+
+    assert_eq!(sim.replies(all), &[Reply::Succeeded]);
+    assert_eq!(sim.replies(replicated), &[Reply::Succeeded]);
+    sim.assert_deployments_converged();
+    assert_eq!(sim.running_on("everywhere"), vec![0, 1, 2, 3]);
+    assert_eq!(sim.running_on("replicated").len(), 2);
+
+    // Name is taken by the cluster
+    let duplicate = sim.deploy(2, "replicated", "all");
+    sim.run_until_quiet();
+    assert_eq!(sim.replies(duplicate), &[Reply::Failed]);
+    assert_eq!(sim.running_on("replicated").len(), 2);
+}
+
+#[test]
+fn joining_node_starts_deployed_plugins() {
+    let mut sim = Simulation::new(4, 4, Faults::default());
+    for a in 0..3 {
+        for b in a + 1..3 {
+            sim.connect(a, b);
+            sim.run_until_quiet();
+        }
+    }
+
+    sim.deploy(0, "everywhere", "all");
+    sim.run_until_quiet();
+    assert_eq!(sim.running_on("everywhere"), vec![0, 1, 2]);
+
+    for node in 0..3 {
+        sim.connect(node, 3);
+        sim.run_until_quiet();
+    }
+
+    sim.assert_deployments_converged();
+    assert_eq!(sim.running_on("everywhere"), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn replicas_move_from_gone_node() {
+    for seed in 1..=SEEDS {
+        let mut sim = Simulation::new(4, seed, Faults::default());
+        sim.connect_all();
+
+        sim.deploy(0, "replicated", "replicas=2");
+        sim.run_until_quiet();
+        let running = sim.running_on("replicated");
+        assert_eq!(running.len(), 2);
+
+        // One of the replicas leaves, the rest of the cluster still runs two
+        let gone = running[1];
+        for node in 0..4 {
+            if node != gone {
+                sim.disconnect(node, gone);
+                sim.run_until_quiet();
+            }
+        }
+
+        let left: Vec<usize> = sim.running_on("replicated").into_iter().filter(|node| *node != gone).collect();
+        assert_eq!(left.len(), 2, "seed {}", seed);
+        assert!(left.contains(&running[0]), "seed {}: replica moved without a reason", seed);
+    }
+}
+
+#[test]
+fn removed_deployment_stops_everywhere() {
+    let mut sim = Simulation::new(3, 5, Faults::default());
+    sim.connect_all();
+
+    sim.deploy(0, "everywhere", "all");
+    sim.run_until_quiet();
+    let removed = sim.remove_deployment(2, "everywhere");
+    sim.run_until_quiet();
+
+    assert_eq!(sim.replies(removed), &[Reply::Succeeded]);
+    sim.assert_deployments_converged();
+    assert!(sim.running_on("everywhere").is_empty());
+
+    let unknown = sim.remove_deployment(1, "everywhere");
+    sim.run_until_quiet();
+    assert_eq!(sim.replies(unknown), &[Reply::Failed]);
+}
@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+use common::package::{Manifest, RestartPolicy};
+use spacy::supervision::{self, PluginStatus, Supervision};
+
+fn make_manifest(restart: RestartPolicy, max_restarts: u32) -> Manifest {
+    let mut manifest = Manifest::parse("name = \"db\"\nversion = \"1\"\nentrypoint = \"main.py\"\nruntime = \"python\"").unwrap();
+    manifest.restart = restart;
+    manifest.max_restarts = max_restarts;
+    manifest
+}
+
+#[test]
+fn restarts_follow_policy() {
+    let now = Instant::now();
+
+    let mut never = Supervision::new(&make_manifest(RestartPolicy::Never, 5), now);
+    assert_eq!(never.exited(Some(1), now), PluginStatus::Crashed);
+    assert_eq!(never.exit_code(), Some(1));
+
+    let mut on_failure = Supervision::new(&make_manifest(RestartPolicy::OnFailure, 5), now);
+    assert_eq!(on_failure.exited(Some(0), now), PluginStatus::Exited);
+    let mut on_failure = Supervision::new(&make_manifest(RestartPolicy::OnFailure, 5), now);
+    assert_eq!(on_failure.exited(None, now), PluginStatus::Restarting);
+
+    let mut always = Supervision::new(&make_manifest(RestartPolicy::Always, 5), now);
+    assert_eq!(always.exited(Some(0), now), PluginStatus::Restarting);
+    assert!(!always.is_restart_due(now));
+    assert!(always.is_restart_due(now + supervision::backoff(0)));
+}
+
+#[test]
+fn restarts_are_capped_and_backed_off() {
+    let mut now = Instant::now();
+    let mut supervision = Supervision::new(&make_manifest(RestartPolicy::OnFailure, 3), now);
+
+    for restart in 0..3 {
+        assert_eq!(supervision.exited(Some(1), now), PluginStatus::Restarting);
+        assert_eq!(supervision.next_deadline(), Some(now + supervision::backoff(restart)));
+
+        now += supervision::backoff(restart);
+        supervision.started(now);
+    }
+
+    assert_eq!(supervision.exited(Some(1), now), PluginStatus::Crashed);
+    assert_eq!(supervision.restarts(), 3);
+    assert_eq!(supervision.next_deadline(), None);
+
+    assert!(supervision::backoff(1) > supervision::backoff(0));
+    assert_eq!(supervision::backoff(40), supervision::backoff(100));
+}
+
+#[test]
+fn plugin_running_long_enough_starts_over() {
+    let now = Instant::now();
+    let mut supervision = Supervision::new(&make_manifest(RestartPolicy::Always, 1), now);
+
+    assert_eq!(supervision.exited(Some(1), now), PluginStatus::Restarting);
+    supervision.started(now);
+
+    let later = now + Duration::from_secs(3600);
+    assert_eq!(supervision.exited(Some(1), later), PluginStatus::Restarting);
+    assert_eq!(supervision.restarts(), 1);
+}
+
+#[test]
+fn disconnected_plugin_is_killed_eventually() {
+    let now = Instant::now();
+    let mut supervision = Supervision::new(&make_manifest(RestartPolicy::OnFailure, 5), now);
+
+    // Plugin is running only after it connects
+    assert_eq!(supervision.status(), PluginStatus::Starting);
+    supervision.started(now);
+
+    assert!(!supervision.is_unresponsive(now));
+    supervision.disconnected(now);
+    assert!(!supervision.is_unresponsive(now));
+
+    let deadline = supervision.next_deadline().unwrap();
+    assert!(supervision.is_unresponsive(deadline));
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, OnceLock},
+    thread, time
+};
+use common::{
+    bus::Bus,
+    codec::Connection,
+    event::proto_msg::{self, event::Kind},
+    stream::Stream,
+    tls::{TlsConfig, DEFAULT_SERVER_NAME}
+};
+use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+use spacy::{acl::Acl, config::Config, plugin_man::PluginMan, server::Server};
+
+const TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+const CLIENTS: &str = "role admin *\nclient alice alice-token admin\nclient bob bob-token admin\n";
+
+// Plugin manager listens on a fixed port, so tests share the cluster
+static CLUSTER: OnceLock<Cluster> = OnceLock::new();
+
+// Every certificate gets its own files, tests run in parallel
+static NEXT_CERTIFICATE: AtomicUsize = AtomicUsize::new(0);
+
+struct Cluster {
+    dir: tempfile::TempDir,
+    issuer: Issuer<'static, KeyPair>,
+    ca: String,
+    addr: SocketAddr
+}
+
+impl Cluster {
+    fn get() -> &'static Self {
+        CLUSTER.get_or_init(Self::start)
+    }
+
+    // Node's server with the plugin manager, that answers its clients
+    fn start() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&key).unwrap().pem();
+        let mut cluster = Self { dir, issuer: Issuer::new(params, key), ca, addr: "127.0.0.1:0".parse().unwrap() };
+
+        // Port is free again, once the probe is dropped
+        cluster.addr = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap();
+
+        let tls = cluster.certificate(DEFAULT_SERVER_NAME);
+        let config = Config {
+            tls_server: Some(tls.server_config().unwrap()),
+            tls_client: Some(tls.client_context().unwrap()),
+            acl: Some(Arc::new(Acl::parse(CLIENTS).unwrap())),
+            listen_addrs: vec![cluster.addr],
+            plugins_dir: cluster.dir.path().join("plugins"),
+            plugin_logs_dir: cluster.dir.path().join("logs"),
+            ..Config::default()
+        };
+
+        let bus = Bus::new();
+        Server::new(bus.clone(), 1, &config).start();
+        PluginMan::new(bus, &config).start();
+
+        let started = time::Instant::now();
+        while TcpStream::connect(cluster.addr).is_err() {
+            assert!(started.elapsed() < TIMEOUT, "server didn't start");
+            thread::sleep(time::Duration::from_millis(50));
+        }
+
+        cluster
+    }
+
+    // Certificate signed by the cluster's CA for the given DNS name
+    fn certificate(&self, dns_name: &str) -> TlsConfig {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![dns_name.to_string()]).unwrap()
+            .signed_by(&key, &self.issuer).unwrap();
+        let file_name = NEXT_CERTIFICATE.fetch_add(1, Ordering::Relaxed);
+
+        let config = TlsConfig {
+            cert: self.dir.path().join(format!("{}.crt", file_name)),
+            key: self.dir.path().join(format!("{}.key", file_name)),
+            ca: self.dir.path().join(format!("{}-ca.crt", file_name)),
+            server_name: DEFAULT_SERVER_NAME.to_string()
+        };
+        fs::write(&config.cert, cert.pem()).unwrap();
+        fs::write(&config.key, key.serialize_pem()).unwrap();
+        fs::write(&config.ca, &self.ca).unwrap();
+
+        config
+    }
+
+    fn connect(&self, dns_name: &str, event: proto_msg::Event) -> Connection<Stream> {
+        let context = self.certificate(dns_name).client_context().unwrap();
+        let stream = TcpStream::connect(self.addr).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut connection = Connection::new(Stream::connect(stream, Some(&context)).unwrap());
+        connection.send(event).unwrap();
+        connection
+    }
+}
+
+fn make_event(kind: Kind, data: Vec<Vec<u8>>, correlation_id: Option<u64>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: None,
+        dest: None,
+        kind: kind as i32,
+        data,
+        meta: vec![],
+        correlation_id
+    }
+}
+
+// First events, that the server sends, or `None` if it closes the connection
+fn wait_answer(connection: &mut Connection<Stream>) -> Option<Vec<proto_msg::Event>> {
+    loop {
+        let received = connection.wait_events().ok()?;
+        if !received.events.is_empty() {
+            return Some(received.events);
+        }
+        if received.closed {
+            return None;
+        }
+    }
+}
+
+fn list_plugins(cluster: &Cluster, dns_name: &str, token: &str) -> Option<Vec<proto_msg::Event>> {
+    let mut connection = cluster.connect(dns_name, make_event(Kind::MarkMeClient, vec![token.as_bytes().to_vec()], None));
+    connection.send(make_event(Kind::GetPluginList, vec![], Some(7))).unwrap();
+
+    wait_answer(&mut connection)
+}
+
+#[test]
+fn node_certificate_joins_as_node() {
+    let cluster = Cluster::get();
+
+    let mut connection = cluster.connect(DEFAULT_SERVER_NAME, make_event(Kind::MarkMeNode, vec![42u128.to_ne_bytes().to_vec()], None));
+    let answer = wait_answer(&mut connection).unwrap();
+    assert_eq!(answer[0].data, vec![1u128.to_ne_bytes().to_vec()]);
+}
+
+#[test]
+fn client_certificate_cant_join_as_node() {
+    let cluster = Cluster::get();
+
+    let mut connection = cluster.connect("alice", make_event(Kind::MarkMeNode, vec![42u128.to_ne_bytes().to_vec()], None));
+    assert!(wait_answer(&mut connection).is_none());
+}
+
+#[test]
+fn client_is_named_by_its_certificate() {
+    let cluster = Cluster::get();
+
+    let answer = list_plugins(cluster, "alice", "alice-token").unwrap();
+    assert_eq!(answer[0].kind, Kind::RespondClient as i32);
+    assert_eq!(answer[0].correlation_id, Some(7));
+
+    // Token of another client doesn't work with the certificate
+    assert!(list_plugins(cluster, "alice", "bob-token").is_none());
+
+    // Node's certificate doesn't name a client
+    assert!(list_plugins(cluster, DEFAULT_SERVER_NAME, "alice-token").is_none());
+}
@@ -0,0 +1,125 @@
+use common::event::proto_msg::{self, event::Kind};
+use spacy::wasm_plugin::{WasmError, WasmLimits, WasmPlugin, WasmRuntime};
+
+// Takes the event and answers the client with its data
+const ECHO: &str = r#"
+(module
+  (import "spacy" "get_event" (func $get_event (param i32 i32) (result i32)))
+  (import "spacy" "respond_client" (func $respond_client (param i64 i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "update")
+    (local $length i32)
+    (local.set $length (call $get_event (i32.const 0) (i32.const 65536)))
+    (if (i32.eqz (local.get $length)) (then (return)))
+    ;; Data goes right after the kind and the correlation id, meta after it is ignored
+    (call $respond_client (i64.load (i32.const 4)) (i32.const 12) (i32.sub (local.get $length) (i32.const 12)))))
+"#;
+
+// Stores every event's kind under key 7
+const STORE: &str = r#"
+(module
+  (import "spacy" "get_event" (func $get_event (param i32 i32) (result i32)))
+  (import "spacy" "shared_memory_push" (func $push (param i32 i32 i32) (result i64)))
+  (import "spacy" "log" (func $log (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 1024) "pushed")
+  (func (export "update")
+    (if (i32.eqz (call $get_event (i32.const 0) (i32.const 1024))) (then (return)))
+    (drop (call $push (i32.const 7) (i32.const 0) (i32.const 4)))
+    (call $log (i32.const 1024) (i32.const 6))))
+"#;
+
+fn instantiate(limits: WasmLimits, module: &str) -> Result<WasmPlugin, WasmError> {
+    WasmRuntime::new(limits).instantiate(&wat::parse_str(module).unwrap())
+}
+
+fn make_event(kind: i32, data: Vec<Vec<u8>>, correlation_id: Option<u64>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: Some(proto_msg::event::Dir::Incoming as i32),
+        dest: None,
+        kind,
+        data,
+        meta: vec![b"client".to_vec()],
+        correlation_id
+    }
+}
+
+#[test]
+fn plugin_answers_client() {
+    let mut plugin = instantiate(WasmLimits::default(), ECHO).unwrap();
+
+    plugin.push_event(make_event(10, vec![b"ab".to_vec(), b"c".to_vec()], Some(42)));
+    plugin.push_event(make_event(10, vec![b"d".to_vec()], Some(43)));
+    plugin.run().unwrap();
+    assert!(!plugin.has_events());
+
+    let sent = plugin.take_sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].kind, Kind::RespondClient as i32);
+    assert_eq!(sent[0].data, vec![b"ab".to_vec(), b"c".to_vec()]);
+    assert_eq!(sent[0].correlation_id, Some(42));
+    assert_eq!(sent[1].data, vec![b"d".to_vec()]);
+    assert_eq!(sent[1].correlation_id, Some(43));
+    assert!(plugin.take_sent().is_empty());
+}
+
+#[test]
+fn plugin_makes_requests_and_logs() {
+    let mut plugin = instantiate(WasmLimits::default(), STORE).unwrap();
+
+    plugin.push_event(make_event(10, vec![], None));
+    plugin.run().unwrap();
+
+    let sent = plugin.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].kind, Kind::UpdateSharedMemory as i32);
+    assert_eq!(sent[0].data, vec![7i32.to_ne_bytes().to_vec(), 10i32.to_le_bytes().to_vec()]);
+    assert_eq!(sent[0].correlation_id, Some(1));
+    assert_eq!(plugin.take_output(), b"pushed\n");
+}
+
+#[test]
+fn fuel_limit_stops_plugin() {
+    let module = r#"(module (memory (export "memory") 1) (func (export "update") (loop (br 0))))"#;
+    let mut plugin = instantiate(WasmLimits { fuel: Some(100_000), ..WasmLimits::default() }, module).unwrap();
+
+    plugin.push_event(make_event(10, vec![], None));
+    assert!(matches!(plugin.run(), Err(WasmError::Trap(reason)) if reason == "ran out of fuel"));
+}
+
+#[test]
+fn memory_limit_stops_plugin() {
+    let module = r#"(module (memory (export "memory") 1) (func (export "update") (drop (memory.grow (i32.const 32)))))"#;
+    let mut plugin = instantiate(WasmLimits { memory_bytes: Some(1024 * 1024), ..WasmLimits::default() }, module).unwrap();
+
+    plugin.push_event(make_event(10, vec![], None));
+    assert!(matches!(plugin.run(), Err(WasmError::Trap(reason)) if reason == "memory limit exceeded"));
+}
+
+#[test]
+fn access_outside_of_memory_stops_plugin() {
+    let module = r#"
+    (module
+      (import "spacy" "log" (func $log (param i32 i32)))
+      (memory (export "memory") 1)
+      (func (export "update") (call $log (i32.const 65530) (i32.const 100))))
+    "#;
+    let mut plugin = instantiate(WasmLimits::default(), module).unwrap();
+
+    plugin.push_event(make_event(10, vec![], None));
+    assert!(matches!(plugin.run(), Err(WasmError::Trap(_))));
+}
+
+#[test]
+fn module_must_be_plugin() {
+    let modules = [
+        r#"(module (memory (export "memory") 1))"#,
+        r#"(module (func (export "update")))"#,
+        r#"(module (import "wasi" "fd_write" (func (param i32))) (memory (export "memory") 1) (func (export "update")))"#
+    ];
+
+    for module in modules {
+        assert!(matches!(instantiate(WasmLimits::default(), module), Err(WasmError::InvalidModule(_))), "{}", module);
+    }
+    assert!(matches!(WasmRuntime::new(WasmLimits::default()).instantiate(b"not wasm"), Err(WasmError::InvalidModule(_))));
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::Shutdown,
+    time::{Duration, Instant}
+};
+use common::{codec::{CodecError, Connection}, event::{self, proto_msg}, stream::Stream};
+
+// Connection to the node, that matches responses with requests by their ids.
+// Requests can be sent one after another and their responses taken in any order
+pub struct Client {
+    connection: Connection<Stream>,
+    next_id: u64,
+    // Requests, that wait for their last response. Responses to others are dropped,
+    // so ones, that come late or weren't asked for, don't pile up
+    outstanding: HashSet<u64>,
+    // Responses, that arrived while waiting for others, request might have several of them
+    responses: HashMap<u64, Vec<proto_msg::Event>>,
+    // Responses of requests, that have more to come
+    partial: HashMap<u64, Vec<proto_msg::Event>>,
+    closed: bool
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Codec(CodecError),
+    // Node closed the connection
+    Disconnected
+}
+
+impl Client {
+    // Stream must be in blocking mode
+    pub fn new(connection: Connection<Stream>) -> Self {
+        Self {
+            connection,
+            next_id: 1,
+            outstanding: HashSet::new(),
+            responses: HashMap::new(),
+            partial: HashMap::new(),
+            closed: false
+        }
+    }
+
+    // Sends the request without waiting, returns its id
+    pub fn send_request(&mut self, mut event: proto_msg::Event) -> Result<u64, ClientError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        event.correlation_id = Some(id);
+        self.connection.send(event).map_err(ClientError::Codec)?;
+        self.outstanding.insert(id);
+
+        Ok(id)
+    }
+
+    // Request, that isn't waited for anymore, its responses are dropped, when they come
+    pub fn abandon(&mut self, id: u64) {
+        self.outstanding.remove(&id);
+        self.partial.remove(&id);
+        self.responses.remove(&id);
+    }
+
+    // Takes the last response, if it has already arrived
+    pub fn try_response(&mut self, id: u64) -> Option<proto_msg::Event> {
+        self.try_responses(id).and_then(|mut responses| responses.pop())
+    }
+
+    // Takes all responses in the order they came, once the last one has arrived
+    pub fn try_responses(&mut self, id: u64) -> Option<Vec<proto_msg::Event>> {
+        self.responses.remove(&id)
+    }
+
+    // Waits for the last response to the request. Ok(None) means it didn't come in time,
+    // request can be waited for again or abandoned
+    pub fn wait_response(&mut self, id: u64, timeout: Duration) -> Result<Option<proto_msg::Event>, ClientError> {
+        Ok(self.wait_responses(id, timeout)?.and_then(|mut responses| responses.pop()))
+    }
+
+    // Waits for all responses to the request, e.g. to the one, that passes several events to a plugin
+    pub fn wait_responses(&mut self, id: u64, timeout: Duration) -> Result<Option<Vec<proto_msg::Event>>, ClientError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(responses) = self.responses.remove(&id) {
+                return Ok(Some(responses));
+            }
+
+            if self.closed {
+                return Err(ClientError::Disconnected);
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(None);
+            }
+
+            self.receive(left)?;
+        }
+    }
+
+    // Sends the request and waits for its response
+    pub fn request(&mut self, event: proto_msg::Event, timeout: Duration) -> Result<Option<proto_msg::Event>, ClientError> {
+        let id = self.send_request(event)?;
+        let response = self.wait_response(id, timeout);
+
+        // No one else knows the id, late response would never be taken
+        if !matches!(response, Ok(Some(_))) {
+            self.abandon(id);
+        }
+        response
+    }
+
+    pub fn shutdown(&self) {
+        let _ = self.connection.get_ref().shutdown(Shutdown::Both);
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<(), ClientError> {
+        self.connection.get_ref().set_read_timeout(Some(timeout))
+            .map_err(|error| ClientError::Codec(CodecError::Io(error)))?;
+
+        let received = match self.connection.wait_events() {
+            Ok(received) => received,
+            Err(CodecError::Io(error)) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(()),
+            Err(error) => return Err(ClientError::Codec(error))
+        };
+
+        // Node answers every request with its id, events without one aren't responses
+        for event in received.events {
+            let id = match event.correlation_id {
+                Some(id) if self.outstanding.contains(&id) => id,
+                _ => continue
+            };
+
+            let partial = event::is_partial_response(&event);
+            let mut responses = self.partial.remove(&id).unwrap_or_default();
+            responses.push(event);
+
+            if partial {
+                self.partial.insert(id, responses);
+            } else {
+                self.outstanding.remove(&id);
+                self.responses.insert(id, responses);
+            }
+        }
+
+        self.closed |= received.closed;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Codec(error) => write!(f, "{}", error),
+            ClientError::Disconnected => write!(f, "node closed the connection")
+        }
+    }
+}
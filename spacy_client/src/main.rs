@@ -1,9 +1,19 @@
 use std::{
-    net::{TcpStream, Shutdown},
+    net::{TcpStream, SocketAddr, IpAddr},
     io::Write,
-    fs
+    path::Path,
+    time::Duration,
+    fs, env, process, thread
 };
-use common::{event::{proto_msg, self}, utils};
+use common::{codec::Connection, event::{proto_msg, self}, package, stream::Stream, tls::TlsConfig, utils};
+use spacy_client::{Client, ClientError};
+
+// Node gives up on requests after a minute, so there is no sense to wait longer
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(65);
+
+// How often followed logs are asked for new lines
+const LOGS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const LOGS_TAIL: i32 = 100;
 
 fn get_from_user(greeter: &str) -> String {
     print!("{}", greeter);
@@ -16,45 +26,193 @@ fn get_from_user(greeter: &str) -> String {
     line
 }
 
+// Node address can be passed as the first argument either as an ip
+// (`192.168.1.5`, `fd00::2`) or as a socket address (`[fe80::1%2]:32000`)
+fn get_node_address() -> Result<SocketAddr, String> {
+    if let Some(arg) = env::args().nth(1) {
+        if let Ok(addr) = arg.parse::<SocketAddr>() {
+            return Ok(addr);
+        }
+
+        return arg.parse::<IpAddr>()
+            .map(|ip| SocketAddr::new(ip, 32000))
+            .map_err(|error| format!("Invalid node address `{}`: {}", arg, error));
+    }
+
+    utils::get_socket_addrs(32000).first()
+        .copied()
+        .ok_or_else(|| "No interface address to connect to, pass the node address as an argument".to_string())
+}
+
+// Client can't go on without the node, error is printed and it exits
+fn exit_with(error: impl std::fmt::Display) -> ! {
+    eprintln!("{}", error);
+    process::exit(1);
+}
+
+// Either a directory with `manifest.toml` or an already packed `.tar.gz` from `plugins`
+fn load_package() -> Option<Vec<u8>> {
+    let filename = get_from_user("Package: ");
+    let path = format!("plugins/{}", filename);
+    let package = if Path::new(&path).is_dir() {
+        package::pack(Path::new(&path)).map_err(|error| error.to_string())
+    } else {
+        fs::read(&path).map_err(|error| error.to_string())
+    };
+
+    match package {
+        Ok(package) => Some(package),
+        Err(error) => {
+            println!("Couldn't load the package: {}", error);
+            None
+        }
+    }
+}
+
+// Prints last lines of plugin's output, with `follow` keeps printing new ones,
+// until plugin is removed. Returns error, if node is gone
+fn print_logs(client: &mut Client, name: &str, follow: bool) -> Result<(), ClientError> {
+    let mut since: Option<u64> = None;
+
+    loop {
+        let mut data = vec![name.as_bytes().to_vec(), LOGS_TAIL.to_ne_bytes().to_vec()];
+        if let Some(since) = since {
+            data.push(since.to_ne_bytes().to_vec());
+        }
+
+        let event = proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: None,
+            kind: proto_msg::event::Kind::GetPluginLogs as i32,
+            data,
+            meta: vec![],
+            correlation_id: None
+        };
+
+        let response = match client.request(event, RESPONSE_TIMEOUT)? {
+            Some(response) => response,
+            None => {
+                println!("No response to the logs request");
+                return Ok(());
+            }
+        };
+
+        if event::get_i32(&response.data, 0) != Ok(0) {
+            println!("Plugin `{}` doesn't exist", name);
+            return Ok(());
+        }
+
+        for line in response.data[2..].chunks(2) {
+            if let [stream, line] = line {
+                println!("{} | {}", String::from_utf8_lossy(stream), String::from_utf8_lossy(line));
+            }
+        }
+
+        if !follow {
+            return Ok(());
+        }
+
+        since = event::get_field(&response.data, 1).ok()
+            .and_then(|next_seq| next_seq.try_into().ok())
+            .map(u64::from_ne_bytes);
+        thread::sleep(LOGS_POLL_INTERVAL);
+    }
+}
+
 fn main() {
-    let ips = utils::get_ipv4_ips();
-    let first_ip = ips.get(0).unwrap();
+    let addr = get_node_address().unwrap_or_else(|error| exit_with(error));
+
+    // Client certificate is taken from `SPACY_TLS_CERT`, `SPACY_TLS_KEY` and `SPACY_TLS_CA`
+    let tls_client = match TlsConfig::from_env() {
+        Ok(Some(tls)) => Some(tls.client_context().unwrap_or_else(|error| exit_with(error))),
+        Ok(None) => None,
+        Err(error) => exit_with(error)
+    };
+
+    println!("Connecting to the node {}...", addr);
+    let stream = TcpStream::connect(addr)
+        .and_then(|stream| Stream::connect(stream, tls_client.as_ref()))
+        .unwrap_or_else(|error| exit_with(format!("Couldn't connect to the node {}: {}", addr, error)));
+    let mut connection = Connection::new(stream);
+    // Token identifies the client, if node requires authentication
+    let mut data = vec![];
+    if let Ok(token) = env::var("SPACY_TOKEN") {
+        data.push(token.into_bytes());
+    }
 
-    println!("Connecting to the node...");
-    let mut stream = TcpStream::connect((first_ip.to_owned(), 32000)).unwrap();
     let event = proto_msg::Event {
         dir: None,
         dest: None,
         kind: proto_msg::event::Kind::MarkMeClient as i32,
-        data: vec![],
-        meta: vec![]
+        data,
+        meta: vec![],
+        correlation_id: None
     };
-    stream.write(&event::serialize(event)).unwrap();
+    connection.send(event).unwrap();
+    let mut client = Client::new(connection);
 
     println!("Connected!");
 
     loop {
         let command = get_from_user(": ");
 
+        // `logs <plugin>` prints plugin's output, `logs -f <plugin>` follows it
+        if let Some(args) = command.strip_prefix("logs ") {
+            let (follow, name) = match args.trim().strip_prefix("-f ") {
+                Some(name) => (true, name.trim()),
+                None => (false, args.trim())
+            };
+
+            match print_logs(&mut client, name, follow) {
+                Ok(_) => continue,
+                Err(ClientError::Disconnected) => {
+                    println!("System disconnected");
+                    client.shutdown();
+                    break;
+                },
+                Err(error) => panic!("{}", error)
+            }
+        }
+
         let event = match command.as_str() {
             "new_plugin" => {
-                let filename = get_from_user("Filename: ");
-                let path = format!("plugins/{}", filename);
-                let content = match fs::read_to_string(path) {
-                    Ok(content) => content,
-                    Err(error) => {
-                        panic!("{}", error);
-                    }
+                let package = match load_package() {
+                    Some(package) => package,
+                    None => continue
                 };
 
-                let name = get_from_user("Name: ");
+                // Plugin runs only on this node, unless it's deployed to the cluster
+                let placement = get_from_user("Placement (local, all, replicas=<n>, nodes=<id>,<id>): ");
+                let mut data = vec![package];
+                if !placement.is_empty() {
+                    data.push(placement.into_bytes());
+                }
 
                 let event = proto_msg::Event {
                     dir: Some(proto_msg::event::Dir::Incoming as i32),
                     dest: None,
                     kind: proto_msg::event::Kind::NewPlugin as i32,
-                    data: vec![content.as_bytes().to_vec(), name.as_bytes().to_vec()],
-                    meta: vec![]
+                    data,
+                    meta: vec![],
+                    correlation_id: None
+                };
+
+                event
+            },
+            // Running plugin is replaced with the new version from the package
+            "upgrade_plugin" => {
+                let package = match load_package() {
+                    Some(package) => package,
+                    None => continue
+                };
+
+                let event = proto_msg::Event {
+                    dir: Some(proto_msg::event::Dir::Incoming as i32),
+                    dest: None,
+                    kind: proto_msg::event::Kind::UpgradePlugin as i32,
+                    data: vec![package],
+                    meta: vec![],
+                    correlation_id: None
                 };
 
                 event
@@ -67,7 +225,8 @@ fn main() {
                     dest: None,
                     kind: proto_msg::event::Kind::RemovePlugin as i32,
                     data: vec![name.as_bytes().to_vec()],
-                    meta: vec![]
+                    meta: vec![],
+                    correlation_id: None
                 };
 
                 event
@@ -78,7 +237,22 @@ fn main() {
                     dest: None,
                     kind: proto_msg::event::Kind::GetPluginList as i32,
                     data: vec![],
-                    meta: vec![]
+                    meta: vec![],
+                    correlation_id: None
+                };
+
+                event
+            },
+            "status" => {
+                let name = get_from_user("Name: ");
+
+                let event = proto_msg::Event {
+                    dir: Some(proto_msg::event::Dir::Incoming as i32),
+                    dest: None,
+                    kind: proto_msg::event::Kind::GetPluginStatus as i32,
+                    data: vec![name.as_bytes().to_vec()],
+                    meta: vec![],
+                    correlation_id: None
                 };
 
                 event
@@ -104,7 +278,8 @@ fn main() {
                     dest: None,
                     kind: result.ok().unwrap(),
                     data,
-                    meta: vec![]
+                    meta: vec![],
+                    correlation_id: None
                 };
 
                 let event = proto_msg::Event {
@@ -112,7 +287,8 @@ fn main() {
                     dest: None,
                     kind: proto_msg::event::Kind::NewPluginEvent as i32,
                     data: vec![plugin_name.as_bytes().to_vec(), event::serialize(actual_event)],
-                    meta: vec![]
+                    meta: vec![],
+                    correlation_id: None
                 };
 
                 event
@@ -123,23 +299,27 @@ fn main() {
             }
         };
 
-        stream.write(&event::serialize(event)).unwrap();
-        println!("Done! Waiting for response...");
+        let id = client.send_request(event).unwrap();
+        println!("Request {} is sent! Waiting for response...", id);
 
-        let events = utils::read_events(&mut stream).unwrap();
-        if events.len() != 0 {
-            for event in events {
+        match client.wait_responses(id, RESPONSE_TIMEOUT) {
+            Ok(Some(events)) => for event in events {
                 println!("Kind: {}", event.kind);
                 println!("Data: ");
                 for item in event.data.iter() {
                     println!("{:?}", String::from_utf8_lossy(item));
                 }
-            }
-
-        } else {
-            println!("System disconnected");
-            stream.shutdown(Shutdown::Both).unwrap();
-            break;
+            },
+            Ok(None) => {
+                println!("No response to the request {}", id);
+                client.abandon(id);
+            },
+            Err(ClientError::Disconnected) => {
+                println!("System disconnected");
+                client.shutdown();
+                break;
+            },
+            Err(error) => panic!("{}", error)
         }
     }
 }
@@ -0,0 +1,93 @@
+use std::{net::{TcpListener, TcpStream}, thread, time::Duration};
+use common::{codec::Connection, event::{self, proto_msg}, stream::Stream};
+use spacy_client::Client;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn make_event(data: Vec<Vec<u8>>, meta: Vec<Vec<u8>>, correlation_id: Option<u64>) -> proto_msg::Event {
+    proto_msg::Event { dir: None, dest: None, kind: 13, data, meta, correlation_id }
+}
+
+// Node, that answers every request with `answer`, which gives the responses for the request's id
+fn spawn_node(answer: fn(u64) -> Vec<proto_msg::Event>) -> Client {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = Connection::new(stream);
+
+        while let Ok(received) = connection.wait_events() {
+            for request in received.events {
+                for response in answer(request.correlation_id.unwrap()) {
+                    connection.send(response).unwrap();
+                }
+            }
+
+            if received.closed {
+                return;
+            }
+        }
+    });
+
+    Client::new(Connection::new(Stream::Plain(TcpStream::connect(addr).unwrap())))
+}
+
+#[test]
+fn partial_responses_are_collected_until_last() {
+    let mut client = spawn_node(|id| vec![
+        make_event(vec![b"first".to_vec()], vec![event::PARTIAL_RESPONSE.to_vec()], Some(id)),
+        make_event(vec![b"second".to_vec()], vec![], Some(id))
+    ]);
+
+    let id = client.send_request(make_event(vec![], vec![], None)).unwrap();
+    let responses = client.wait_responses(id, TIMEOUT).unwrap().unwrap();
+    let data: Vec<_> = responses.into_iter().map(|response| response.data).collect();
+    assert_eq!(data, vec![vec![b"first".to_vec()], vec![b"second".to_vec()]]);
+
+    let response = client.request(make_event(vec![], vec![], None), TIMEOUT).unwrap().unwrap();
+    assert_eq!(response.data, vec![b"second".to_vec()]);
+}
+
+#[test]
+fn responses_to_unknown_requests_are_dropped() {
+    // Response, that no one has asked for, comes before the real one
+    let mut client = spawn_node(|id| vec![
+        make_event(vec![], vec![], Some(id + 100)),
+        make_event(vec![], vec![], None),
+        make_event(vec![], vec![], Some(id))
+    ]);
+
+    let id = client.send_request(make_event(vec![], vec![], None)).unwrap();
+    assert!(client.wait_response(id, TIMEOUT).unwrap().is_some());
+    assert!(client.try_response(id + 100).is_none());
+}
+
+#[test]
+fn late_response_to_abandoned_request_is_dropped() {
+    // First request is answered after the client has stopped waiting
+    let mut client = spawn_node(|id| {
+        if id == 1 {
+            thread::sleep(Duration::from_millis(300));
+        }
+        vec![make_event(vec![], vec![], Some(id))]
+    });
+
+    assert!(client.request(make_event(vec![], vec![], None), Duration::from_millis(50)).unwrap().is_none());
+
+    let id = client.send_request(make_event(vec![], vec![], None)).unwrap();
+    assert!(client.wait_response(id, TIMEOUT).unwrap().is_some());
+    assert!(client.try_response(1).is_none());
+}
+
+#[test]
+fn request_waited_for_again_gets_its_response() {
+    let mut client = spawn_node(|id| {
+        thread::sleep(Duration::from_millis(300));
+        vec![make_event(vec![], vec![], Some(id))]
+    });
+
+    let id = client.send_request(make_event(vec![], vec![], None)).unwrap();
+    assert!(client.wait_response(id, Duration::from_millis(50)).unwrap().is_none());
+    assert!(client.wait_response(id, TIMEOUT).unwrap().is_some());
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use common::event;
+use spacy_conformance::{CLIENT_NAME, ECHO, GET, UPDATE};
+use spacy_plugin_sdk::{Context, Event, Plugin};
+
+// Reference plugin of the conformance suite, it's what the suite expects from plugins
+
+struct Reference {
+    // Client's request by the id of the plugin's one
+    pending: HashMap<u64, u64>
+}
+
+impl Plugin for Reference {
+    fn on_event(&mut self, context: &mut Context, event: Event) {
+        let correlation_id = match event.correlation_id {
+            Some(correlation_id) => correlation_id,
+            None => return
+        };
+
+        let result = if event.kind == ECHO {
+            context.respond_client(event.data, correlation_id)
+        }
+
+        else if event.kind == CLIENT_NAME {
+            context.respond_client(event.meta.into_iter().take(1).collect(), correlation_id)
+        }
+
+        else if event.kind == GET {
+            let key = match event::get_i32(&event.data, 0) {
+                Ok(key) => key,
+                Err(_) => return
+            };
+            context.get_shared_memory(key).map(|request_id| {
+                self.pending.insert(request_id, correlation_id);
+            })
+        }
+
+        else if event.kind == UPDATE {
+            let (key, value) = match (event::get_i32(&event.data, 0), event.data.get(1)) {
+                (Ok(key), Some(value)) => (key, value.clone()),
+                _ => return
+            };
+            context.update_shared_memory(key, value).map(|request_id| {
+                self.pending.insert(request_id, correlation_id);
+            })
+        }
+
+        else {
+            Ok(())
+        };
+
+        if let Err(error) = result {
+            eprintln!("Couldn't handle event of kind {}: {}", event.kind, error);
+        }
+    }
+
+    fn on_shared_memory(&mut self, context: &mut Context, request_id: u64, value: Option<Vec<u8>>) {
+        if let Some(client_request) = self.pending.remove(&request_id) {
+            let _ = context.respond_client(value.into_iter().collect(), client_request);
+        }
+    }
+
+    fn on_transaction_result(&mut self, context: &mut Context, request_id: u64, succeeded: bool) {
+        let result = if succeeded { "succeeded" } else { "failed" };
+        if let Some(client_request) = self.pending.remove(&request_id) {
+            let _ = context.respond_client(vec![result.as_bytes().to_vec()], client_request);
+        }
+    }
+
+    fn on_request_timed_out(&mut self, context: &mut Context, request_id: u64) {
+        if let Some(client_request) = self.pending.remove(&request_id) {
+            let _ = context.respond_client(vec![b"timed out".to_vec()], client_request);
+        }
+    }
+}
+
+fn main() {
+    if let Err(error) = spacy_plugin_sdk::run(Reference { pending: HashMap::new() }) {
+        eprintln!("Plugin has failed: {}", error);
+        std::process::exit(1);
+    }
+}
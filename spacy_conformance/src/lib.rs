@@ -0,0 +1,361 @@
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fmt, io, thread,
+    net::{Shutdown, TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant}
+};
+use common::{
+    auth,
+    codec::{CodecError, Connection},
+    event::proto_msg::{self, event::Kind},
+    plugin_protocol
+};
+
+// Conformance suite of the plugin protocol (`common::plugin_protocol`).
+// Every check launches the plugin, that is tested, the way the plugin manager does,
+// and plays the manager's part of the protocol. Plugin, that is tested, must behave
+// like the reference one (`conformance_plugin`) on these kinds of clients' events:
+//
+//   ECHO         responds with the event's data
+//   CLIENT_NAME  responds with the client's name, that is the first field of meta
+//   GET          `[key]`, gets the key from shared memory, responds with the value,
+//                with no data, if the key isn't set, or with `timed out`
+//   UPDATE       `[key, value]`, updates shared memory, responds with
+//                `succeeded`, `failed` or `timed out`
+//
+// Events of other kinds are ignored. Plugin is run as `spacy_conformance <plugin> [arguments...]`
+
+pub const ECHO: i32 = 1000;
+pub const CLIENT_NAME: i32 = 1001;
+pub const GET: i32 = 1002;
+pub const UPDATE: i32 = 1003;
+
+// How long plugin may take to do what check expects
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long plugin may take to exit after its connection is closed
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+const LARGE_FIELD_SIZE: usize = 1024 * 1024;
+
+pub struct Check {
+    pub name: &'static str,
+    run: fn(&mut Session) -> Result<(), CheckError>
+}
+
+pub const CHECKS: &[Check] = &[
+    Check { name: "handshake", run: |_| Ok(()) },
+    Check { name: "echo", run: check_echo },
+    Check { name: "events_in_order", run: check_events_in_order },
+    Check { name: "large_event", run: check_large_event },
+    Check { name: "client_name", run: check_client_name },
+    Check { name: "shared_memory_get", run: check_shared_memory_get },
+    Check { name: "shared_memory_update", run: check_shared_memory_update },
+    Check { name: "request_timeout", run: check_request_timeout },
+    Check { name: "unknown_events_are_ignored", run: check_unknown_events },
+    Check { name: "exits_when_disconnected", run: check_exit }
+];
+
+#[derive(Debug)]
+pub enum CheckError {
+    // Plugin couldn't be launched
+    Io(io::Error),
+    // Plugin has sent something, that isn't a frame
+    Codec(CodecError),
+    // What plugin didn't do in time
+    Timeout(&'static str),
+    // Plugin has broken the protocol
+    Violation(String)
+}
+
+// Plugin, that has connected and presented its token, from the manager's side
+pub struct Session {
+    process: Child,
+    connection: Connection<TcpStream>,
+    // Events, that were received, but not looked at yet
+    received: VecDeque<proto_msg::Event>,
+    next_client_request: u64
+}
+
+impl Check {
+    // Command is the plugin's executable with its arguments
+    pub fn run(&self, command: &[OsString]) -> Result<(), CheckError> {
+        let mut session = Session::start(command)?;
+        (self.run)(&mut session)
+    }
+}
+
+impl Session {
+    // Launches the plugin and waits for its handshake
+    pub fn start(command: &[OsString]) -> Result<Self, CheckError> {
+        let (program, args) = command.split_first()
+            .ok_or_else(|| CheckError::Violation("plugin's command is empty".to_string()))?;
+
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        listener.set_nonblocking(true)?;
+        let token = auth::generate_token();
+
+        let mut process = Command::new(program)
+            .args(args)
+            .env(plugin_protocol::ADDR_VAR, listener.local_addr()?.to_string())
+            .env(plugin_protocol::TOKEN_VAR, &token)
+            .env(plugin_protocol::NAME_VAR, "conformance")
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        let started = Instant::now();
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {},
+                Err(error) => {
+                    Self::kill(&mut process);
+                    return Err(CheckError::Io(error));
+                }
+            }
+
+            if let Ok(Some(status)) = process.try_wait() {
+                return Err(CheckError::Violation(format!("plugin has exited before connecting, {}", status)));
+            }
+            if started.elapsed() >= TIMEOUT {
+                Self::kill(&mut process);
+                return Err(CheckError::Timeout("connect"));
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let prepared = stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(TIMEOUT)));
+        if let Err(error) = prepared {
+            Self::kill(&mut process);
+            return Err(CheckError::Io(error));
+        }
+
+        let mut session = Self {
+            process,
+            connection: Connection::new(stream),
+            received: VecDeque::new(),
+            next_client_request: 1
+        };
+
+        let handshake = session.receive("send the handshake")?;
+        let (presented, _version) = plugin_protocol::parse_handshake(&handshake)
+            .map_err(|error| CheckError::Violation(format!("invalid handshake: {}", error)))?;
+        if presented != token.as_bytes() {
+            return Err(CheckError::Violation("handshake doesn't carry the launch token".to_string()));
+        }
+
+        Ok(session)
+    }
+
+    // Sends client's event, returns the id of client's request
+    pub fn send_client_event(&mut self, kind: i32, data: Vec<Vec<u8>>) -> Result<u64, CheckError> {
+        let id = self.next_client_request;
+        self.next_client_request += 1;
+
+        self.send(kind, data, vec![b"client".to_vec()], Some(id))?;
+        Ok(id)
+    }
+
+    pub fn send(&mut self, kind: i32, data: Vec<Vec<u8>>, meta: Vec<Vec<u8>>, correlation_id: Option<u64>) -> Result<(), CheckError> {
+        self.connection.send(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Incoming as i32),
+            dest: None,
+            kind,
+            data,
+            meta,
+            correlation_id
+        })?;
+
+        Ok(())
+    }
+
+    // Next event, that plugin has sent, `action` tells, what plugin was expected to do
+    pub fn receive(&mut self, action: &'static str) -> Result<proto_msg::Event, CheckError> {
+        while self.received.is_empty() {
+            let received = match self.connection.wait_events() {
+                Ok(received) => received,
+                Err(CodecError::Io(error)) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Err(CheckError::Timeout(action));
+                },
+                Err(error) => return Err(CheckError::Codec(error))
+            };
+
+            self.received.extend(received.events);
+            if received.closed && self.received.is_empty() {
+                return Err(CheckError::Violation(format!("plugin has closed the connection instead of: {}", action)));
+            }
+        }
+
+        Ok(self.received.pop_front().unwrap())
+    }
+
+    // Next event must be of the kind, its correlation id is returned
+    pub fn expect(&mut self, kind: Kind, data: &[Vec<u8>], action: &'static str) -> Result<Option<u64>, CheckError> {
+        let event = self.receive(action)?;
+
+        if event.kind != kind as i32 {
+            return Err(CheckError::Violation(format!("expected {:?} to {}, got {}", kind, action, kind_name(event.kind))));
+        }
+        if event.data != data {
+            return Err(CheckError::Violation(format!("{:?} to {} has data {:?} instead of {:?}", kind, action, event.data, data)));
+        }
+
+        Ok(event.correlation_id)
+    }
+
+    // Plugin's response must answer the client's request
+    pub fn expect_response(&mut self, request_id: u64, data: &[Vec<u8>], action: &'static str) -> Result<(), CheckError> {
+        match self.expect(Kind::RespondClient, data, action)? {
+            Some(id) if id == request_id => Ok(()),
+            id => Err(CheckError::Violation(format!("response to {} has correlation id {:?} instead of {}", action, id, request_id)))
+        }
+    }
+
+    // Plugin's request must carry an id, so it can be answered
+    pub fn expect_request(&mut self, kind: Kind, data: &[Vec<u8>], action: &'static str) -> Result<u64, CheckError> {
+        self.expect(kind, data, action)?
+            .ok_or_else(|| CheckError::Violation(format!("request to {} doesn't have correlation id", action)))
+    }
+
+    fn kill(process: &mut Child) {
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.connection.get_ref().shutdown(Shutdown::Both);
+        Self::kill(&mut self.process);
+    }
+}
+
+fn check_echo(session: &mut Session) -> Result<(), CheckError> {
+    let data = vec![b"hello".to_vec(), vec![], vec![0, 255]];
+    let id = session.send_client_event(ECHO, data.clone())?;
+    session.expect_response(id, &data, "echo")
+}
+
+fn check_events_in_order(session: &mut Session) -> Result<(), CheckError> {
+    let ids = (0..3)
+        .map(|i| session.send_client_event(ECHO, vec![vec![i]]))
+        .collect::<Result<Vec<u64>, CheckError>>()?;
+
+    for (i, id) in ids.into_iter().enumerate() {
+        session.expect_response(id, &[vec![i as u8]], "echo events in order")?;
+    }
+
+    Ok(())
+}
+
+fn check_large_event(session: &mut Session) -> Result<(), CheckError> {
+    let data = vec![vec![7; LARGE_FIELD_SIZE]];
+    let id = session.send_client_event(ECHO, data.clone())?;
+    session.expect_response(id, &data, "echo a large event")
+}
+
+fn check_client_name(session: &mut Session) -> Result<(), CheckError> {
+    session.send(CLIENT_NAME, vec![], vec![b"alice".to_vec()], Some(100))?;
+    session.expect_response(100, &[b"alice".to_vec()], "respond with client's name")
+}
+
+fn check_shared_memory_get(session: &mut Session) -> Result<(), CheckError> {
+    let key = 7i32.to_le_bytes().to_vec();
+
+    let client_request = session.send_client_event(GET, vec![key.clone()])?;
+    let request = session.expect_request(Kind::GetFromSharedMemory, std::slice::from_ref(&key), "get the key")?;
+    session.send(Kind::GetFromSharedMemory as i32, vec![b"value".to_vec()], vec![], Some(request))?;
+    session.expect_response(client_request, &[b"value".to_vec()], "respond with the value")?;
+
+    // Key, that isn't set, has no value
+    let client_request = session.send_client_event(GET, vec![key.clone()])?;
+    let request = session.expect_request(Kind::GetFromSharedMemory, &[key], "get the key again")?;
+    session.send(Kind::GetFromSharedMemory as i32, vec![], vec![], Some(request))?;
+    session.expect_response(client_request, &[], "respond without value")
+}
+
+fn check_shared_memory_update(session: &mut Session) -> Result<(), CheckError> {
+    let key = 7i32.to_le_bytes().to_vec();
+
+    let first_client_request = session.send_client_event(UPDATE, vec![key.clone(), b"first".to_vec()])?;
+    let first = session.expect_request(Kind::UpdateSharedMemory, &[key.clone(), b"first".to_vec()], "update the key")?;
+    let second_client_request = session.send_client_event(UPDATE, vec![key.clone(), b"second".to_vec()])?;
+    let second = session.expect_request(Kind::UpdateSharedMemory, &[key, b"second".to_vec()], "update the key again")?;
+    if first == second {
+        return Err(CheckError::Violation(format!("requests, that wait for responses, have the same id {}", first)));
+    }
+
+    // Results come in any order
+    session.send(Kind::TransactionFailed as i32, vec![], vec![], Some(second))?;
+    session.expect_response(second_client_request, &[b"failed".to_vec()], "report the failed update")?;
+    session.send(Kind::TransactionSucceeded as i32, vec![], vec![], Some(first))?;
+    session.expect_response(first_client_request, &[b"succeeded".to_vec()], "report the succeeded update")
+}
+
+fn check_request_timeout(session: &mut Session) -> Result<(), CheckError> {
+    let key = 7i32.to_le_bytes().to_vec();
+
+    let client_request = session.send_client_event(GET, vec![key.clone()])?;
+    let request = session.expect_request(Kind::GetFromSharedMemory, &[key], "get the key")?;
+    session.send(Kind::RequestTimedOut as i32, vec![], vec![], Some(request))?;
+    session.expect_response(client_request, &[b"timed out".to_vec()], "report the timeout")
+}
+
+fn check_unknown_events(session: &mut Session) -> Result<(), CheckError> {
+    session.send_client_event(999, vec![b"unknown".to_vec()])?;
+    session.send(Kind::TransactionSucceeded as i32, vec![], vec![], Some(12345))?;
+    session.send(Kind::GetFromSharedMemory as i32, vec![b"value".to_vec()], vec![], Some(12346))?;
+    session.send(Kind::RequestTimedOut as i32, vec![], vec![], None)?;
+
+    // Plugin, that has survived, answers the next event first
+    let id = session.send_client_event(ECHO, vec![b"still here".to_vec()])?;
+    session.expect_response(id, &[b"still here".to_vec()], "echo after unknown events")
+}
+
+fn check_exit(session: &mut Session) -> Result<(), CheckError> {
+    session.connection.get_ref().shutdown(Shutdown::Both)?;
+
+    let started = Instant::now();
+    while started.elapsed() < STOP_TIMEOUT {
+        if session.process.try_wait()?.is_some() {
+            return Ok(());
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    Err(CheckError::Timeout("exit after the connection is closed"))
+}
+
+fn kind_name(kind: i32) -> String {
+    match Kind::try_from(kind) {
+        Ok(kind) => format!("{:?}", kind),
+        Err(_) => kind.to_string()
+    }
+}
+
+impl From<io::Error> for CheckError {
+    fn from(error: io::Error) -> Self {
+        CheckError::Io(error)
+    }
+}
+
+impl From<CodecError> for CheckError {
+    fn from(error: CodecError) -> Self {
+        CheckError::Codec(error)
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Io(error) => write!(f, "{}", error),
+            CheckError::Codec(error) => write!(f, "{}", error),
+            CheckError::Timeout(action) => write!(f, "plugin didn't {} in time", action),
+            CheckError::Violation(violation) => write!(f, "{}", violation)
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use std::{env, ffi::OsString, process};
+use spacy_conformance::CHECKS;
+
+// Runs every check against the plugin, that is given with its arguments:
+// `spacy_conformance python3 main.py`
+
+fn main() {
+    let command: Vec<OsString> = env::args_os().skip(1).collect();
+    if command.is_empty() {
+        eprintln!("Usage: spacy_conformance <plugin> [arguments...]");
+        process::exit(2);
+    }
+
+    let mut failed = 0;
+    for check in CHECKS {
+        match check.run(&command) {
+            Ok(_) => println!("{} ... ok", check.name),
+            Err(error) => {
+                println!("{} ... FAILED: {}", check.name, error);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} of {} checks passed", CHECKS.len() - failed, CHECKS.len());
+    if failed > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,42 @@
+use std::ffi::OsString;
+use spacy_conformance::{CheckError, CHECKS};
+
+#[test]
+fn reference_plugin_conforms() {
+    let command = [OsString::from(env!("CARGO_BIN_EXE_conformance_plugin"))];
+
+    for check in CHECKS {
+        if let Err(error) = check.run(&command) {
+            panic!("{} failed: {}", check.name, error);
+        }
+    }
+}
+
+#[test]
+fn plugin_exiting_before_handshake_fails() {
+    let command = ["sh", "-c", "exit 3"].map(OsString::from);
+
+    assert!(matches!(CHECKS[0].run(&command), Err(CheckError::Violation(_))));
+}
+
+#[test]
+fn plugin_ignoring_events_fails() {
+    // Connects and presents the token, but never answers
+    let script = r#"
+import os, socket, struct, time
+host, port = os.environ["SPACY_PLUGIN_MAN_ADDR"].rsplit(":", 1)
+connection = socket.create_connection((host, int(port)))
+token = os.environ["SPACY_PLUGIN_TOKEN"].encode()
+# Kind 31, token and version as `bytes` fields of the protobuf message
+message = b"\x18\x1f" + b"\x22" + bytes([len(token)]) + token + b"\x22\x04" + struct.pack("<I", 1)
+connection.sendall(bytes([len(message)]) + message)
+time.sleep(20)
+"#;
+    let command = ["python3", "-c", script].map(OsString::from);
+
+    let handshake = CHECKS.iter().find(|check| check.name == "handshake").unwrap();
+    assert!(handshake.run(&command).is_ok());
+
+    let echo = CHECKS.iter().find(|check| check.name == "echo").unwrap();
+    assert!(matches!(echo.run(&command), Err(CheckError::Timeout(_))));
+}
@@ -1,18 +1,17 @@
 use std::{
     net::{TcpStream, Shutdown},
-    collections::HashMap,
-    io::Write,
-    os::unix::prelude::AsRawFd
+    os::unix::prelude::AsRawFd,
+    time::Duration
 };
 use common::{
-    fsm::FSM,
-    event::{self, proto_msg}, utils
-};
-use nix::sys::{
-    select::{select, FdSet},
-    time::{TimeVal, TimeValLike}
+    codec::{Connection, Received},
+    fsm::{Fsm, Priority, State},
+    event::proto_msg,
+    plugin_protocol,
+    reactor::Reactor
 };
 use pyo3::{
+    exceptions::PyNotImplementedError,
     prelude::*
 };
 
@@ -22,7 +21,8 @@ use pyo3::{
 struct SpacyKinds {
     pub kind_transaction_succeeded: i32,
     pub kind_transaction_failed: i32,
-    pub kind_get_from_shared_memory: i32
+    pub kind_get_from_shared_memory: i32,
+    pub kind_request_timed_out: i32
 }
 
 #[pymethods]
@@ -32,7 +32,8 @@ impl SpacyKinds {
         Self {
             kind_transaction_succeeded: proto_msg::event::Kind::TransactionSucceeded as i32,
             kind_transaction_failed: proto_msg::event::Kind::TransactionFailed as i32,
-            kind_get_from_shared_memory: proto_msg::event::Kind::GetFromSharedMemory as i32
+            kind_get_from_shared_memory: proto_msg::event::Kind::GetFromSharedMemory as i32,
+            kind_request_timed_out: proto_msg::event::Kind::RequestTimedOut as i32
         }
     }
 
@@ -50,6 +51,11 @@ impl SpacyKinds {
     fn kind_get_from_shared_memory(&mut self) -> i32 {
         self.kind_get_from_shared_memory
     }
+
+    #[getter]
+    fn kind_request_timed_out(&mut self) -> i32 {
+        self.kind_request_timed_out
+    }
 }
 
 #[pyclass]
@@ -58,7 +64,8 @@ impl SpacyKinds {
 struct SpacyEvent {
     pub kind: i32,
     pub data: Vec<Vec<u8>>,
-    pub meta: Vec<Vec<u8>>
+    pub meta: Vec<Vec<u8>>,
+    pub correlation_id: Option<u64>
 }
 
 #[pymethods]
@@ -77,115 +84,165 @@ impl SpacyEvent {
     fn meta(&self) -> Vec<Vec<u8>> {
         self.meta.to_owned()
     }
+
+    // Id of the client's request or of the plugin's own one, this event answers
+    #[getter]
+    fn correlation_id(&self) -> Option<u64> {
+        self.correlation_id
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PluginState {
+    Init,
+    WaitEvent,
+    HandleEvent,
+    Stop
+}
+
+impl State for PluginState {
+    fn name(&self) -> &'static str {
+        match self {
+            PluginState::Init => "init",
+            PluginState::WaitEvent => "wait_event",
+            PluginState::HandleEvent => "handle_event",
+            PluginState::Stop => "stop"
+        }
+    }
 }
 
 #[pyclass(subclass)]
 struct SpacyPlugin {
-    fsm: FSM,
-    stream: TcpStream,
-    event_queue: Vec<SpacyEvent>
+    fsm: Fsm<PluginState, proto_msg::Event>,
+    stream: Connection<TcpStream>,
+    reactor: Reactor,
+    event_queue: Vec<SpacyEvent>,
+    // Ids of the plugin's own requests
+    next_request_id: u64,
+    running: bool
 }
 
 #[pymethods]
 impl SpacyPlugin {
-    // 0 - initialization
-    // 1 - waiting for events
-    // 2 - handle event
-    // 3 - stop
-
     #[new]
     fn new() -> Self {
-        let fsm = FSM::new(0, HashMap::from([
-            (0, vec![1, 3]),
-            (1, vec![2, 3]),
-            (2, vec![1, 3]),
-            (3, vec![])
-        ]));
-
-        // Connecting to the plugin manager
-        let stream = TcpStream::connect(("127.0.0.1", 32002)).unwrap();
+        let fsm = Fsm::new("plugin", PluginState::Init, &[
+            (PluginState::Init, &[PluginState::WaitEvent, PluginState::Stop]),
+            (PluginState::WaitEvent, &[PluginState::HandleEvent, PluginState::Stop]),
+            (PluginState::HandleEvent, &[PluginState::WaitEvent, PluginState::Stop]),
+            (PluginState::Stop, &[])
+        ]);
+
+        // Connecting to the plugin manager, it tells plugins apart by tokens, that it gives them at launch
+        let addr = std::env::var(plugin_protocol::ADDR_VAR)
+            .unwrap_or_else(|_| plugin_protocol::DEFAULT_PLUGIN_MAN_ADDR.to_string());
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut stream = Connection::new(stream);
+        let token = std::env::var(plugin_protocol::TOKEN_VAR).unwrap_or_default();
+        stream.send(plugin_protocol::handshake(&token)).unwrap();
+        stream.get_ref().set_nonblocking(true).unwrap();
+
+        let reactor = Reactor::new().unwrap();
+        reactor.register(stream.get_ref().as_raw_fd()).unwrap();
 
         Self {
             fsm,
             stream,
-            event_queue: vec![]
+            reactor,
+            event_queue: vec![],
+            next_request_id: 1,
+            running: true
         }
     }
 
     fn step(&mut self) {
-        match self.fsm.state {
-            0 => self.init(),
-            1 => self.wait_event(),
-            2 => self.handle_event(),
-            3 => {
+        match self.fsm.state() {
+            PluginState::Init => self.init(),
+            PluginState::WaitEvent => self.wait_event(),
+            PluginState::HandleEvent => self.handle_event(),
+            PluginState::Stop => {
                 self.stop();
                 return;
-            },
-            _ => unreachable!()
+            }
         }
     }
 
     fn init(&mut self) {
-        match self.fsm.transition(1) {
+        match self.fsm.transition(PluginState::WaitEvent) {
             Ok(_) => return,
             Err(_) => panic!(),
         };
     }
 
     fn wait_event(&mut self) {
-        // Checking if there anything to read
-        let mut readfds = FdSet::new();
-        let fd = self.stream.as_raw_fd();
-        readfds.insert(fd);
+        // Writing what socket didn't accept previously
+        if self.stream.has_pending_writes() {
+            let _ = self.stream.flush();
+        }
 
-        let mut timeout = TimeVal::milliseconds(1);
-        let result = select(None, &mut readfds, None, None, &mut timeout);
-        if result.is_err() || !readfds.contains(fd) {
-            return;
+        // Checking if there anything to read
+        match self.reactor.wait(Some(Duration::from_millis(1))) {
+            Ok(fds) if !fds.is_empty() => {},
+            _ => return
         }
 
-        // Reading message until read
-        let events = utils::read_events(&mut self.stream).unwrap();
+        // Reading everything, that is available
+        let received = self.stream.read_events()
+            .unwrap_or(Received { events: vec![], closed: true });
 
-        // If message length is zero, then plugin manager disconnected
-        if events.len() != 0 {
-            for event in events {
-                self.fsm.push_event(event);
-            }
-        } else {
-            self.stream.shutdown(Shutdown::Both).unwrap();
+        for event in received.events {
+            self.fsm.push_event(event, Priority::Plugin);
+        }
+
+        // Plugin manager disconnected
+        if received.closed {
+            let _ = self.stream.get_ref().shutdown(Shutdown::Both);
 
-            match self.fsm.transition(3) {
+            match self.fsm.transition(PluginState::Stop) {
                 Ok(_) => return,
                 Err(_) => panic!(),
             };
         }
 
-        match self.fsm.transition(2) {
+        match self.fsm.transition(PluginState::HandleEvent) {
             Ok(_) => return,
             Err(_) => panic!(),
         };
     }
 
     fn handle_event(&mut self) {
-        let event = self.fsm.pop_front_event().unwrap();
-
-        let spacy_event = SpacyEvent {
-            kind: event.kind,
-            data: event.data,
-            meta: event.meta
-        };
+        // Socket can be ready without a whole event in it, so queue might be empty
+        while let Some(event) = self.fsm.pop_front_event() {
+            let spacy_event = SpacyEvent {
+                kind: event.kind,
+                data: event.data,
+                meta: event.meta,
+                correlation_id: event.correlation_id
+            };
 
-        self.event_queue.push(spacy_event);
+            self.event_queue.push(spacy_event);
+        }
 
-        match self.fsm.transition(1) {
+        match self.fsm.transition(PluginState::WaitEvent) {
             Ok(_) => return,
             Err(_) => panic!(),
         };
     }
 
     fn stop(&mut self) {
-        panic!()
+        if !self.running {
+            return;
+        }
+
+        // Plugin manager is gone or asked to stop, nothing to do anymore
+        let _ = self.reactor.deregister(self.stream.get_ref().as_raw_fd());
+        let _ = self.stream.get_ref().shutdown(Shutdown::Both);
+
+        self.running = false;
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
     }
 
     fn get_event(&mut self) -> Option<SpacyEvent> {
@@ -197,52 +254,85 @@ impl SpacyPlugin {
         }
     }
 
-    fn shared_memory_push(&mut self, key: i32, value: Vec<u8>) {
+    // Returns id, the response will carry
+    fn shared_memory_push(&mut self, key: i32, value: Vec<u8>) -> u64 {
+        let id = self.next_id();
         let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
             dest: None,
             kind: proto_msg::event::Kind::UpdateSharedMemory as i32,
             data: vec![key.to_ne_bytes().to_vec(), value],
-            meta: vec![]
+            meta: vec![],
+            correlation_id: Some(id)
         };
-        self.stream.write(&event::serialize(event)).unwrap();
+        self.stream.send(event).unwrap();
+        id
     }
 
-    fn shared_memory_get(&mut self, key: i32) {
+    // Returns id, the response will carry
+    fn shared_memory_get(&mut self, key: i32) -> u64 {
+        let id = self.next_id();
         let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
             dest: None,
             kind: proto_msg::event::Kind::GetFromSharedMemory as i32,
             data: vec![key.to_ne_bytes().to_vec()],
-            meta: vec![]
+            meta: vec![],
+            correlation_id: Some(id)
         };
-        self.stream.write(&event::serialize(event)).unwrap();
+        self.stream.send(event).unwrap();
+        id
+    }
+
+    // Takes the response to the plugin's request, if it has already arrived
+    fn get_response(&mut self, id: u64) -> Option<SpacyEvent> {
+        // Clients' requests have their own ids, so only responses are looked at
+        let responses = [
+            proto_msg::event::Kind::TransactionSucceeded as i32,
+            proto_msg::event::Kind::TransactionFailed as i32,
+            proto_msg::event::Kind::GetFromSharedMemory as i32,
+            proto_msg::event::Kind::RequestTimedOut as i32
+        ];
+        let position = self.event_queue.iter()
+            .position(|event| event.correlation_id == Some(id) && responses.contains(&event.kind))?;
+
+        Some(self.event_queue.remove(position))
     }
 
-    fn respond_client(&mut self, data: Vec<Vec<u8>>, meta: Vec<Vec<u8>>) {
+    // Id is the one of the client's request
+    fn respond_client(&mut self, data: Vec<Vec<u8>>, correlation_id: u64) {
         let event = proto_msg::Event {
             dir: Some(proto_msg::event::Dir::Outcoming as i32),
             dest: None,
             kind: proto_msg::event::Kind::RespondClient as i32,
             data,
-            meta
+            meta: vec![],
+            correlation_id: Some(correlation_id)
         };
-        self.stream.write(&event::serialize(event)).unwrap();
+        self.stream.send(event).unwrap();
     }
 
-    fn execute(mut _self: PyRefMut<'_, Self>) {
+    fn execute(mut _self: PyRefMut<'_, Self>) -> PyResult<()> {
         // Taking control of execution
-        let gil = Python::acquire_gil();
-        let py = gil.python();
-
-        // Calling object method
-        let obj = _self.into_py(py);
-        obj.call_method1(py, "update", ()).unwrap();
+        Python::with_gil(|py| {
+            // Calling object method, its exception goes to the caller
+            let obj = _self.into_py(py);
+            obj.call_method1(py, "update", ())?;
+            Ok(())
+        })
     }
 
     // This method must be overwritten by the plugin
-    fn update(&self, _event: SpacyEvent) -> SpacyEvent {
-        unimplemented!();
+    fn update(&self, _event: SpacyEvent) -> PyResult<SpacyEvent> {
+        Err(PyNotImplementedError::new_err("plugin must override `update`"))
+    }
+}
+
+impl SpacyPlugin {
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        id
     }
 }
 
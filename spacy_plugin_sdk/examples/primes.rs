@@ -0,0 +1,49 @@
+use spacy_plugin_sdk::{Context, Event, Plugin};
+
+// Counts primes in the range, that client sends as `<from>%<to>`:
+// `new_event`, `primes`, `10`, `1%1000000`
+
+const COUNT_PRIMES: i32 = 10;
+
+struct Primes;
+
+impl Plugin for Primes {
+    fn on_event(&mut self, context: &mut Context, event: Event) {
+        let correlation_id = match event.correlation_id {
+            Some(correlation_id) => correlation_id,
+            None => return
+        };
+
+        let response = if event.kind != COUNT_PRIMES {
+            "Unknown event".to_string()
+        } else {
+            match parse_range(&event.data) {
+                Some((from, to)) => count_primes(from, to).to_string(),
+                None => "Failed to parse arguments".to_string()
+            }
+        };
+
+        if let Err(error) = context.respond_client(vec![response.into_bytes()], correlation_id) {
+            eprintln!("Couldn't respond the client: {}", error);
+        }
+    }
+}
+
+fn parse_range(data: &[Vec<u8>]) -> Option<(u64, u64)> {
+    let from = std::str::from_utf8(data.first()?).ok()?.trim().parse().ok()?;
+    let to = std::str::from_utf8(data.get(1)?).ok()?.trim().parse().ok()?;
+    Some((from, to))
+}
+
+fn count_primes(from: u64, to: u64) -> usize {
+    (from.max(2)..=to)
+        .filter(|num| (2..).take_while(|i| i * i <= *num).all(|i| num % i != 0))
+        .count()
+}
+
+fn main() {
+    if let Err(error) = spacy_plugin_sdk::run(Primes) {
+        eprintln!("Plugin failed: {}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    env,
+    net::{TcpStream, ToSocketAddrs, Shutdown}
+};
+use common::{
+    codec::Connection,
+    event::proto_msg,
+    plugin_protocol
+};
+
+pub use common::{
+    codec::CodecError,
+    plugin_protocol::{ADDR_VAR, DEFAULT_PLUGIN_MAN_ADDR, TOKEN_VAR}
+};
+
+// SDK for plugins, that are native executables (`runtime = "native"` in the manifest).
+// Plugin implements `Plugin`, `run` connects to the plugin manager, that has launched
+// the process, and calls plugin's methods, as events arrive:
+//
+//   struct Echo;
+//
+//   impl Plugin for Echo {
+//       fn on_event(&mut self, context: &mut Context, event: Event) {
+//           if let Some(id) = event.correlation_id {
+//               let _ = context.respond_client(event.data, id);
+//           }
+//       }
+//   }
+//
+//   fn main() {
+//       spacy_plugin_sdk::run(Echo).unwrap();
+//   }
+//
+// Plugin runs until the plugin manager closes the connection or plugin calls `Context::stop`.
+// Protocol, that the SDK speaks, is described in `common::plugin_protocol`
+
+// Client's event, that was sent to the plugin
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: i32,
+    pub data: Vec<Vec<u8>>,
+    // Name of the client, that has sent the event, goes first
+    pub meta: Vec<Vec<u8>>,
+    // Id of the client's request, response must carry it back
+    pub correlation_id: Option<u64>
+}
+
+pub trait Plugin {
+    fn on_event(&mut self, context: &mut Context, event: Event);
+
+    // Value of the key, that was asked for with `Context::get_shared_memory`, it's absent, if key isn't set
+    fn on_shared_memory(&mut self, _context: &mut Context, _request_id: u64, _value: Option<Vec<u8>>) {}
+
+    // Result of the update, that was requested with `Context::update_shared_memory`
+    fn on_transaction_result(&mut self, _context: &mut Context, _request_id: u64, _succeeded: bool) {}
+
+    // Request wasn't answered in time
+    fn on_request_timed_out(&mut self, _context: &mut Context, _request_id: u64) {}
+}
+
+// Plugin's side of the connection with the plugin manager
+pub struct Context {
+    connection: Connection<TcpStream>,
+    // Ids of the plugin's own requests
+    next_request_id: u64,
+    running: bool
+}
+
+impl Context {
+    // Connects and presents the token, so the plugin manager knows, which plugin it is
+    pub fn connect(addr: impl ToSocketAddrs, token: &str) -> Result<Self, CodecError> {
+        let mut connection = Connection::new(TcpStream::connect(addr)?);
+        connection.send(plugin_protocol::handshake(token))?;
+
+        Ok(Self { connection, next_request_id: 1, running: true })
+    }
+
+    // Returns id, the result will carry
+    pub fn update_shared_memory(&mut self, key: i32, value: Vec<u8>) -> Result<u64, CodecError> {
+        self.request(proto_msg::event::Kind::UpdateSharedMemory, vec![key.to_ne_bytes().to_vec(), value])
+    }
+
+    // Returns id, the value will carry
+    pub fn get_shared_memory(&mut self, key: i32) -> Result<u64, CodecError> {
+        self.request(proto_msg::event::Kind::GetFromSharedMemory, vec![key.to_ne_bytes().to_vec()])
+    }
+
+    // Id is the one of the client's request
+    pub fn respond_client(&mut self, data: Vec<Vec<u8>>, correlation_id: u64) -> Result<(), CodecError> {
+        self.connection.send(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: None,
+            kind: proto_msg::event::Kind::RespondClient as i32,
+            data,
+            meta: vec![],
+            correlation_id: Some(correlation_id)
+        })
+    }
+
+    // Plugin stops after the current event
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    // Calls plugin's methods, until the connection is closed or plugin stops
+    pub fn run<P: Plugin>(&mut self, plugin: &mut P) -> Result<(), CodecError> {
+        while self.running {
+            let received = self.connection.wait_events()?;
+
+            for event in received.events {
+                self.dispatch(plugin, event);
+                if !self.running {
+                    break;
+                }
+            }
+
+            if received.closed {
+                self.running = false;
+            }
+        }
+
+        let _ = self.connection.get_ref().shutdown(Shutdown::Both);
+        Ok(())
+    }
+
+    fn dispatch<P: Plugin>(&mut self, plugin: &mut P, event: proto_msg::Event) {
+        // Responses carry ids of the plugin's requests, clients' events carry ids of clients' ones
+        let response_id = event.correlation_id.unwrap_or_default();
+
+        if event.kind == proto_msg::event::Kind::GetFromSharedMemory as i32 {
+            plugin.on_shared_memory(self, response_id, event.data.into_iter().next());
+        }
+
+        else if event.kind == proto_msg::event::Kind::TransactionSucceeded as i32 {
+            plugin.on_transaction_result(self, response_id, true);
+        }
+
+        else if event.kind == proto_msg::event::Kind::TransactionFailed as i32 {
+            plugin.on_transaction_result(self, response_id, false);
+        }
+
+        else if event.kind == proto_msg::event::Kind::RequestTimedOut as i32 {
+            plugin.on_request_timed_out(self, response_id);
+        }
+
+        else {
+            plugin.on_event(self, Event {
+                kind: event.kind,
+                data: event.data,
+                meta: event.meta,
+                correlation_id: event.correlation_id
+            });
+        }
+    }
+
+    fn request(&mut self, kind: proto_msg::event::Kind, data: Vec<Vec<u8>>) -> Result<u64, CodecError> {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+
+        self.connection.send(proto_msg::Event {
+            dir: Some(proto_msg::event::Dir::Outcoming as i32),
+            dest: None,
+            kind: kind as i32,
+            data,
+            meta: vec![],
+            correlation_id: Some(id)
+        })?;
+
+        Ok(id)
+    }
+}
+
+// Runs the plugin, that the plugin manager has launched
+pub fn run<P: Plugin>(mut plugin: P) -> Result<(), CodecError> {
+    let addr = env::var(ADDR_VAR).unwrap_or_else(|_| DEFAULT_PLUGIN_MAN_ADDR.to_string());
+    let token = env::var(TOKEN_VAR).unwrap_or_default();
+    Context::connect(addr, &token)?.run(&mut plugin)
+}
@@ -0,0 +1,78 @@
+use std::{
+    net::TcpListener,
+    thread,
+    time::Duration
+};
+use common::{
+    codec::Connection,
+    event::proto_msg::{self, event::Kind}
+};
+use spacy_plugin_sdk::{Context, Event, Plugin};
+
+// Answers client's event with the value of the shared memory's key, that client has named
+struct Lookup {
+    // Client's request by the id of the plugin's one
+    pending: Vec<(u64, u64)>
+}
+
+impl Plugin for Lookup {
+    fn on_event(&mut self, context: &mut Context, event: Event) {
+        let key = i32::from_ne_bytes(event.data[0].as_slice().try_into().unwrap());
+        let request_id = context.get_shared_memory(key).unwrap();
+        self.pending.push((request_id, event.correlation_id.unwrap()));
+    }
+
+    fn on_shared_memory(&mut self, context: &mut Context, request_id: u64, value: Option<Vec<u8>>) {
+        let position = self.pending.iter().position(|(id, _)| *id == request_id).unwrap();
+        let (_, client_request) = self.pending.remove(position);
+        context.respond_client(vec![value.unwrap_or_default()], client_request).unwrap();
+    }
+}
+
+fn make_event(kind: Kind, data: Vec<Vec<u8>>, correlation_id: Option<u64>) -> proto_msg::Event {
+    proto_msg::Event {
+        dir: Some(proto_msg::event::Dir::Incoming as i32),
+        dest: None,
+        kind: kind as i32,
+        data,
+        meta: vec![b"client".to_vec()],
+        correlation_id
+    }
+}
+
+#[test]
+fn plugin_speaks_plugin_manager_protocol() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let plugin = thread::spawn(move || {
+        let mut context = Context::connect(addr, "launch token").unwrap();
+        context.run(&mut Lookup { pending: vec![] })
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut connection = Connection::new(stream);
+
+    // Plugin introduces itself first
+    let handshake = connection.wait_events().unwrap().events.remove(0);
+    assert_eq!(handshake.kind, Kind::MarkMePlugin as i32);
+    assert_eq!(handshake.data, vec![b"launch token".to_vec(), 1u32.to_le_bytes().to_vec()]);
+
+    // Client's event makes the plugin ask for the key
+    connection.send(make_event(Kind::NewPluginEvent, vec![7i32.to_ne_bytes().to_vec()], Some(42))).unwrap();
+    let request = connection.wait_events().unwrap().events.remove(0);
+    assert_eq!(request.kind, Kind::GetFromSharedMemory as i32);
+    assert_eq!(request.data, vec![7i32.to_ne_bytes().to_vec()]);
+
+    // Value goes back to the client
+    connection.send(make_event(Kind::GetFromSharedMemory, vec![b"value".to_vec()], request.correlation_id)).unwrap();
+    let response = connection.wait_events().unwrap().events.remove(0);
+    assert_eq!(response.kind, Kind::RespondClient as i32);
+    assert_eq!(response.data, vec![b"value".to_vec()]);
+    assert_eq!(response.correlation_id, Some(42));
+
+    // Closed connection stops the plugin
+    drop(connection);
+    assert!(plugin.join().unwrap().is_ok());
+}